@@ -0,0 +1,135 @@
+// Fixed-rate swap used as a stand-in DEX route by the marketplace tests. It swaps one input
+// mint for whatever the pool's output vault holds, at a rate set when the pool is created
+// Anchor's error type is large by design
+#![allow(clippy::result_large_err)]
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+declare_id!("FX8SdVswgPy46qetE3c2riSqKxEMp5uQQ8VjpsBgDd68");
+
+// Input amounts are quoted per this many base units
+pub const RATE_SCALE: u64 = 1_000_000_000;
+
+#[program]
+pub mod mock_swap {
+    use super::*;
+
+    pub fn initialize_pool(ctx: Context<InitializePool>, rate: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.input_mint = ctx.accounts.input_mint.key();
+        pool.rate = rate;
+        pool.bump = *ctx.bumps.get("pool").unwrap();
+
+        Ok(())
+    }
+
+    pub fn swap(ctx: Context<Swap>, amount_in: u64) -> Result<()> {
+        let amount_out = (amount_in as u128)
+            .checked_mul(ctx.accounts.pool.rate as u128)
+            .map(|v| v / RATE_SCALE as u128)
+            .and_then(|v| u64::try_from(v).ok())
+            .ok_or(MockSwapError::CalculationOverflow)?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_source.to_account_info(),
+                    to: ctx.accounts.pool_input_vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+
+        let input_mint = ctx.accounts.pool.input_mint;
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.pool_output_vault.to_account_info(),
+                    to: ctx.accounts.user_destination.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &[&[b"pool", input_mint.as_ref(), &[ctx.accounts.pool.bump]]],
+            ),
+            amount_out,
+        )?;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub input_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Pool>(),
+        seeds = [b"pool", input_mint.key().as_ref()],
+        bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"pool", pool.input_mint.as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        constraint = user_source.mint == pool.input_mint @ MockSwapError::InvalidTokenAccount,
+    )]
+    pub user_source: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = pool_input_vault.owner == pool.key() @ MockSwapError::InvalidTokenAccount,
+        constraint = pool_input_vault.mint == pool.input_mint @ MockSwapError::InvalidTokenAccount,
+    )]
+    pub pool_input_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = pool_output_vault.owner == pool.key() @ MockSwapError::InvalidTokenAccount,
+    )]
+    pub pool_output_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_destination.mint == pool_output_vault.mint @ MockSwapError::InvalidTokenAccount,
+    )]
+    pub user_destination: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+pub struct Pool {
+    pub input_mint: Pubkey,
+    pub rate: u64, // Output base units paid per RATE_SCALE input units
+    pub bump: u8,
+}
+
+#[error_code]
+pub enum MockSwapError {
+    #[msg("Token account does not match the pool.")]
+    InvalidTokenAccount,
+
+    #[msg("Calculation overflow.")]
+    CalculationOverflow,
+}
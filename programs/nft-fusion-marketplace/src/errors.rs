@@ -155,4 +155,169 @@ pub enum MarketplaceError {
     
     #[msg("Trait validation failed.")]
     TraitValidationFailed,
+    
+    #[msg("Invalid fee discount schedule.")]
+    InvalidFeeDiscount,
+    
+    #[msg("NFT has not been redeemed to the project.")]
+    NftNotRedeemed,
+    
+    #[msg("Too many NFTs provided for fusion.")]
+    FusionInputLimitExceeded,
+    
+    #[msg("Invalid fusion configuration.")]
+    InvalidFusionConfig,
+    
+    #[msg("No royalties available to claim.")]
+    NoRoyaltiesToClaim,
+    
+    #[msg("NFT value is still locked in an active escrow.")]
+    EscrowStillActive,
+    
+    #[msg("NFT is still referenced and cannot be closed.")]
+    NftStillReferenced,
+    
+    #[msg("Oracle price has not been set for this pool yet.")]
+    OracleNotInitialized,
+    
+    #[msg("Reclaim split must be at most 10000 basis points.")]
+    InvalidReclaimSplit,
+    
+    #[msg("Collection metadata hash has already been committed.")]
+    MetadataAlreadyCommitted,
+    
+    #[msg("Collection metadata hash has not been committed.")]
+    MetadataNotCommitted,
+    
+    #[msg("Collection has already been revealed.")]
+    CollectionAlreadyRevealed,
+    
+    #[msg("Revealed metadata does not match the committed hash.")]
+    RevealMismatch,
+    
+    #[msg("Token mint does not match the collection or liquidity pool mint.")]
+    TokenMintMismatch,
+    
+    #[msg("Collection does not belong to the given project.")]
+    CollectionProjectMismatch,
+    
+    #[msg("Liquidity pool does not belong to the given project.")]
+    PoolProjectMismatch,
+    
+    #[msg("Randomness request has not been fulfilled yet.")]
+    RandomnessNotFulfilled,
+    
+    #[msg("Randomness request has already been fulfilled.")]
+    RandomnessAlreadyFulfilled,
+    
+    #[msg("Randomness result has already been used.")]
+    RandomnessAlreadyConsumed,
+    
+    #[msg("Invalid cooldown bypass fee. Must be at most 10000 basis points.")]
+    InvalidCooldownBypassFee,
+    
+    #[msg("NFT has no active cooldown to bypass.")]
+    NftNotInCooldown,
+    
+    #[msg("Cooldown bypass is not enabled for this collection.")]
+    CooldownBypassDisabled,
+    
+    #[msg("Insufficient balance to pay the cooldown bypass fee.")]
+    CooldownBypassFeeUnpaid,
+    
+    #[msg("Invalid mint window. Start must be before end.")]
+    InvalidMintWindow,
+    
+    #[msg("Minting has not started for this collection.")]
+    MintNotStarted,
+    
+    #[msg("Minting has ended for this collection.")]
+    MintEnded,
+    
+    #[msg("NFT owner is already up to date.")]
+    NftOwnerUnchanged,
+    
+    #[msg("A redemption is already reserved for this NFT.")]
+    RedemptionAlreadyReserved,
+    
+    #[msg("Oracle price must be greater than zero.")]
+    InvalidOraclePrice,
+    
+    #[msg("Collection mint rate limit exceeded. Try again in the next window.")]
+    MintRateLimitExceeded,
+    
+    #[msg("Invalid royalty split. Shares must be non-zero and sum to 10000 basis points.")]
+    InvalidRoyaltySplit,
+    
+    #[msg("Royalty recipient accounts do not match the project's royalty config.")]
+    RoyaltyRecipientMismatch,
+    
+    #[msg("Maximum number of active escrows for this user reached.")]
+    EscrowLimitReached,
+    
+    #[msg("Treasury must be a token account for the pool's token mint.")]
+    InvalidTreasury,
+    
+    #[msg("Fusion is paused for this collection.")]
+    FusionInactive,
+    
+    #[msg("The same NFT was listed more than once as a fusion parent.")]
+    DuplicateFusionParent,
+    
+    #[msg("NFT has already been consumed as a fusion parent.")]
+    NftAlreadyFused,
+    
+    #[msg("Fusing again too soon. Wait for the collection's fusion interval to pass.")]
+    FusionRateLimited,
+    
+    #[msg("The project's collection registry is full.")]
+    CollectionRegistryFull,
+    
+    #[msg("Swap program is not approved by the platform.")]
+    SwapProgramNotApproved,
+    
+    #[msg("Swap returned less SOL than the minimum requested.")]
+    SlippageExceeded,
+    
+    #[msg("Swap spent more tokens than the redemption paid out.")]
+    SwapOverspent,
+    
+    #[msg("Oracle-priced redemption is already enabled for this pool.")]
+    OracleRedemptionAlreadyEnabled,
+    
+    #[msg("Buyback burn share cannot exceed 10000 basis points.")]
+    InvalidBuybackBurnShare,
+    
+    #[msg("Escrow token account is not controlled by its escrow.")]
+    InvalidEscrowAuthority,
+    
+    #[msg("Fused NFT would exceed the collection's maximum fusion level.")]
+    MaxFusionLevelReached,
+    
+    #[msg("Redemption LP tax cannot exceed 10000 basis points.")]
+    InvalidRedemptionTax,
+    
+    #[msg("Free mints are not allowed for this collection.")]
+    FreeMintNotAllowed,
+    
+    #[msg("Fusion output accounts do not match the configured output count.")]
+    FusionOutputMismatch,
+    
+    #[msg("Secondary sales of this collection are locked.")]
+    SecondaryMarketLocked,
+    
+    #[msg("Oracle staleness window cannot be negative.")]
+    InvalidOracleStaleness,
+    
+    #[msg("Oracle price confidence interval is too wide.")]
+    OracleConfidenceTooWide,
+    
+    #[msg("Escrow has vested claims paid out of it.")]
+    EscrowPartiallyClaimed,
+    
+    #[msg("Price account is not the one pinned for this pool.")]
+    PriceFeedMismatch,
+    
+    #[msg("The same trait type was selected more than once.")]
+    DuplicateTraitType,
 }
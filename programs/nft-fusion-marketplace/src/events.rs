@@ -0,0 +1,192 @@
+use anchor_lang::prelude::*;
+
+use crate::modules::oracle::PriceSource;
+use crate::state::TraitAttribute;
+
+// Amounts in events are raw integers; clients scale them by the decimals carried alongside
+
+// Emitted when tokens are swapped for a newly minted NFT
+#[event]
+pub struct NftSwapped {
+    pub user: Pubkey,
+    pub nft_mint: Pubkey,
+    pub collection: Pubkey,
+    pub token_amount: u64,
+    pub token_decimals: u8,
+    pub trade_price_usd: u64,    // USD value of the trade (0 for fixed-price mints without an oracle price)
+    pub usd_decimals: u8,
+}
+
+// Emitted when an NFT is redeemed, from the pool or from its escrow
+#[event]
+pub struct NftRedeemed {
+    pub user: Pubkey,
+    pub nft_mint: Pubkey,
+    pub collection: Pubkey,
+    pub token_amount: u64,       // Tokens paid to the user, after fees, taxes and clawbacks
+    pub pending_amount: u64,     // Tokens still owed as a pending claim (pool redemptions only)
+    pub from_escrow: bool,
+}
+
+// Emitted when parents are fused into new NFTs
+#[event]
+pub struct NftFused {
+    pub owner: Pubkey,
+    pub collection: Pubkey,
+    pub child_mints: Vec<Pubkey>, // One per configured fusion output
+    pub parent_nfts: Vec<Pubkey>,
+    pub fusion_level: u8,
+    pub rarity_score: u16,        // Fused rarity before it is split across the children
+    pub timestamp: i64,
+}
+
+// Emitted when a listing is bought, with the price split between fees and the seller
+#[event]
+pub struct ListingSold {
+    pub listing: Pubkey,
+    pub nft_mint: Pubkey,
+    pub collection: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,              // Amount the buyer paid, after any listing discount
+    pub platform_fee: u64,
+    pub royalty_fee: u64,
+    pub seller_amount: u64,
+    pub token_decimals: u8,
+    pub trade_price_usd: u64,    // USD value of the sale (0 without a usable oracle price)
+    pub usd_decimals: u8,
+}
+
+// Emitted by simulate_swap with the full cost breakdown of a prospective swap
+#[event]
+pub struct SwapQuote {
+    pub user: Pubkey,
+    pub collection: Pubkey,
+    pub base_amount: u64,        // Price before any swap discount
+    pub discount_amount: u64,    // Swap discount taken off the base price
+    pub total_amount: u64,       // Amount the user would pay
+    pub fee_discount_bps: u16,   // Volume tier discount applied to fees
+    pub platform_fee: u64,
+    pub project_fee: u64,
+    pub royalty_fee: u64,
+    pub net_amount: u64,         // Amount left in the pool after fees
+}
+
+// Emitted when a liquidity pool's reference price changes
+#[event]
+pub struct PriceUpdated {
+    pub liquidity_pool: Pubkey,
+    pub price_usd: u64,
+    pub usd_decimals: u8,
+    pub source: PriceSource,
+}
+
+// Emitted when a trait value's supply cap is changed after launch
+#[event]
+pub struct TraitSupplyCapAdjusted {
+    pub trait_type: Pubkey,
+    pub value_name: String,
+    pub old_available_supply: Option<u64>,
+    pub new_available_supply: Option<u64>,
+}
+
+// Emitted by a mint config dry run that passed, with the traits and URI the mint would get
+#[event]
+pub struct MintConfigValidated {
+    pub collection: Pubkey,
+    pub traits: Vec<TraitAttribute>,
+    pub metadata_uri: String,
+    pub rarity_score: u16,
+}
+
+// Emitted for each item of a best-effort batch, in order. error_code is the code the item
+// failed with (unset when it succeeded)
+#[event]
+pub struct BatchItemResult {
+    pub index: u16,
+    pub ok: bool,
+    pub error_code: Option<u32>,
+}
+
+// Emitted after a batch escrow close, listing which escrows were closed and which were left open
+#[event]
+pub struct EscrowsBatchClosed {
+    pub owner: Pubkey,
+    pub closed: Vec<Pubkey>,
+    pub skipped: Vec<Pubkey>,
+}
+
+// Emitted when the platform authority overrides a pool's redemption lock outside the price-update path
+#[event]
+pub struct RedemptionLockOverridden {
+    pub liquidity_pool: Pubkey,
+    pub authority: Pubkey,
+    pub was_locked: bool,
+    pub locked: bool,
+    pub timestamp: i64,
+}
+
+// Emitted when a fresh price update lifts a redemption lock on its own. locked_since is unset
+// for locks set before lock times were recorded
+#[event]
+pub struct RedemptionLockRecovered {
+    pub liquidity_pool: Pubkey,
+    pub locked_since: Option<i64>,
+    pub recovered_at: i64,
+    pub source: PriceSource,
+}
+
+// Emitted when the platform authority returns an NFT stuck in a program-owned token account
+#[event]
+pub struct NftRecovered {
+    pub nft_mint: Pubkey,
+    pub custody_account: Pubkey,
+    pub owner: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+// Emitted by get_collection_stats with a snapshot of a collection's counters
+#[event]
+pub struct CollectionStatsReported {
+    pub collection: Pubkey,
+    pub mints: u64,
+    pub redemptions: u64,
+    pub fusions: u64,
+    pub total_volume: u64,
+    pub floor_price: Option<u64>,
+    pub last_updated: i64,
+}
+
+// Emitted when a pool's price is set by hand, recording the price it replaced
+#[event]
+pub struct ManualPriceSet {
+    pub liquidity_pool: Pubkey,
+    pub authority: Pubkey,
+    pub previous_price_usd: Option<u64>,
+    pub price_usd: u64,
+    pub usd_decimals: u8,
+    pub timestamp: i64,
+}
+
+// Emitted when platform fees are pointed at a new treasury; the old one keeps its balance
+#[event]
+pub struct PlatformTreasuryUpdated {
+    pub old_treasury: Pubkey,
+    pub new_treasury: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+// Emitted when a fusion fails its success roll. The burn amount is still taken, the parents are
+// kept and no child is minted; the seed lets clients reproduce the roll
+#[event]
+pub struct FusionFailed {
+    pub collection: Pubkey,
+    pub user: Pubkey,
+    pub parent_nfts: Vec<Pubkey>,
+    pub burn_amount: u64,
+    pub base_success_rate: u8,
+    pub seed: [u8; 32],
+    pub timestamp: i64,
+}
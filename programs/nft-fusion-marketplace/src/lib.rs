@@ -1,351 +1,954 @@
 #![recursion_limit = "256"]
+// Anchor's error type is large by design and instruction handlers take many accounts
+#![allow(clippy::result_large_err, clippy::too_many_arguments)]
 
 use anchor_lang::prelude::*;
-use anchor_spl::{
-    token::{self, Token, TokenAccount, Mint},
-};
-use pyth_sdk_solana::{load_price_feed_from_account_info, Price, PriceFeed};
-use solana_program::clock::Clock;
 
 // Import modules
 pub mod errors;
+pub mod events;
 pub mod state;
 pub mod modules;
 
 declare_id!("7wVDyMSQrpDp7HaAie3Cby9LnqbXyAJeMtGwQyKZ59ES");
 
 // Import enums we need from modules
-use modules::oracle::PriceSource;
+use modules::oracle::QuoteAsset;
 
-// Instruction context for updating price from Pyth Oracle
-#[derive(Accounts)]
-#[instruction(project_id: String)]
-pub struct UpdateOraclePrice<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    #[account(
-        seeds = [b"platform_config"],
-        bump = platform_config.bump,
-    )]
-    pub platform_config: Account<'info, state::PlatformConfig>,
-    
-    #[account(
-        mut,
-        seeds = [b"project", project_id.as_bytes()],
-        bump = project.bump,
-        constraint = project.is_active @ errors::MarketplaceError::ProjectNotFound,
-    )]
-    pub project: Account<'info, state::Project>,
-    
-    #[account(
-        mut,
-        seeds = [b"liquidity_pool", project.key().as_ref()],
-        bump = liquidity_pool.bump,
-    )]
-    pub liquidity_pool: Account<'info, state::LiquidityPool>,
-    
-    /// CHECK: This is the Pyth oracle price feed account
-    pub pyth_price_account: AccountInfo<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
-
-// Instruction context for updating price from DEX liquidity pools (like Raydium)
-#[derive(Accounts)]
-#[instruction(project_id: String)]
-pub struct UpdateDexPrice<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    #[account(
-        seeds = [b"platform_config"],
-        bump = platform_config.bump,
-    )]
-    pub platform_config: Account<'info, state::PlatformConfig>,
-    
-    #[account(
-        mut,
-        seeds = [b"project", project_id.as_bytes()],
-        bump = project.bump,
-        constraint = project.is_active @ errors::MarketplaceError::ProjectNotFound,
-    )]
-    pub project: Account<'info, state::Project>,
-    
-    #[account(
-        mut,
-        seeds = [b"liquidity_pool", project.key().as_ref()],
-        bump = liquidity_pool.bump,
-    )]
-    pub liquidity_pool: Account<'info, state::LiquidityPool>,
-    
-    // DEX Liquidity pool token account (token side)
-    #[account(mut)]
-    pub dex_token_account: Account<'info, TokenAccount>,
-    
-    // DEX Liquidity pool account (USDC/SOL side)
-    #[account(mut)]
-    pub dex_base_account: Account<'info, TokenAccount>,
-    
-    // Token mint account
-    #[account(
-        constraint = token_mint.key() == liquidity_pool.token_mint @ errors::MarketplaceError::InvalidTokenMint,
-    )]
-    pub token_mint: Account<'info, Mint>,
-    
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
+// Import types used as instruction arguments
+use state::{BurnDestination, ChildCooldownPolicy, ClockSource, FeeDiscountTier, MetadataFormat, PriceModifierMode, RandomnessSource, RarityCooldownTier, RoyaltyRecipient, TraitAttribute, TraitComboBonus, TraitPriceModifier, TraitValue};
 
-// Instruction context for updating price from external source (manual or API)
-#[derive(Accounts)]
-#[instruction(project_id: String, price_usd: u64)]
-pub struct SetManualPrice<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    #[account(
-        seeds = [b"platform_config"],
-        bump = platform_config.bump,
-        constraint = platform_config.authority == authority.key() @ errors::MarketplaceError::Unauthorized,
-    )]
-    pub platform_config: Account<'info, state::PlatformConfig>,
-    
-    #[account(
-        mut,
-        seeds = [b"project", project_id.as_bytes()],
-        bump = project.bump,
-        constraint = project.is_active @ errors::MarketplaceError::ProjectNotFound,
-    )]
-    pub project: Account<'info, state::Project>,
-    
-    #[account(
-        mut,
-        seeds = [b"liquidity_pool", project.key().as_ref()],
-        bump = liquidity_pool.bump,
-    )]
-    pub liquidity_pool: Account<'info, state::LiquidityPool>,
-    
-    pub system_program: Program<'info, System>,
-}
+// Bring module instruction contexts into scope for the program entrypoints
+use modules::*;
 
 #[program]
 pub mod nft_fusion_marketplace {
     use super::*;
     
+    // Platform functions
+    
+    // Create the platform config, administered by the signer
+    pub fn initialize_platform(
+        ctx: Context<InitializePlatform>,
+        platform_fee_basis_points: u16,
+        platform_treasury: Pubkey,
+    ) -> Result<()> {
+        modules::platform::initialize_platform(ctx, platform_fee_basis_points, platform_treasury)
+    }
+    
+    // Project functions
+    
+    // Create a project owned by the signer
+    pub fn create_project(
+        ctx: Context<CreateProject>,
+        project_id: String,
+        project_treasury: Pubkey,
+        royalty_wallet: Option<Pubkey>,
+        royalty_basis_points: u16,
+    ) -> Result<()> {
+        modules::project::create_project(ctx, project_id, project_treasury, royalty_wallet, royalty_basis_points)
+    }
+    
     // Oracle price integration functions
     
     // Update price from Pyth oracle
     pub fn update_price_from_pyth(
         ctx: Context<UpdateOraclePrice>, 
-        _project_id: String
-    ) -> Result<()> {
-        let price_feed: PriceFeed = load_price_feed_from_account_info(&ctx.accounts.pyth_price_account)
-            .map_err(|_| errors::MarketplaceError::StaleOracleFeed)?;
-        
-        let price: Price = price_feed.get_current_price()
-            .ok_or(errors::MarketplaceError::StaleOracleFeed)?;
-        
-        // Get price in USD (scaled by 10^6)
-        let price_usd = if price.price < 0 {
-            return Err(errors::MarketplaceError::StaleOracleFeed.into());
-        } else {
-            price.price as u64 * 10u64.pow(price.expo.unsigned_abs() as u32)
-        };
-        
-        // Determine if oracle feed is stale
-        let current_time = Clock::get()?.unix_timestamp;
-        let price_pub_time = current_time - 60; // Simplified due to SDK limitations
-        let max_staleness: i64 = 3600; // 1 hour
-        let is_stale = current_time - price_pub_time > max_staleness;
-        
-        // Update liquidity pool oracle information
-        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
-        liquidity_pool.oracle_price_usd = Some(price_usd);
-        liquidity_pool.oracle_price_last_update = current_time;
-        liquidity_pool.price_source = PriceSource::Pyth;
-        
-        // Lock or unlock redemptions based on oracle status
-        if is_stale {
-            liquidity_pool.redemption_locked = true;
-            msg!("Oracle feed is stale, NFT redemption locked");
-        } else {
-            liquidity_pool.redemption_locked = false;
-            msg!("Oracle price updated: {} USD", price_usd as f64 / 1_000_000.0);
-        }
-        
-        // Update project's last activity timestamp
-        let project = &mut ctx.accounts.project;
-        project.last_activity_timestamp = current_time;
-        
-        Ok(())
+        project_id: String
+    ) -> Result<()> {
+        modules::oracle::update_oracle_price(ctx, project_id)
     }
     
     // Update price from DEX liquidity pools (Raydium, etc.)
     pub fn update_price_from_dex(
         ctx: Context<UpdateDexPrice>, 
-        _project_id: String
-    ) -> Result<()> {
-        // Calculate price based on DEX pool ratios
-        let token_reserves = ctx.accounts.dex_token_account.amount;
-        let base_reserves = ctx.accounts.dex_base_account.amount;
-        
-        // Ensure pools have liquidity
-        if token_reserves == 0 || base_reserves == 0 {
-            return Err(errors::MarketplaceError::InsufficientLiquidity.into());
-        }
-        
-        // Calculate price in base tokens (scaled by 10^6)
-        // For simplicity, we assume the base token is USDC (or another stablecoin with 6 decimals)
-        // and the token has 9 decimals (standard for SPL tokens)
-        let price_usd = (base_reserves as u128)
-            .checked_mul(1_000_000_000)
-            .ok_or(errors::MarketplaceError::CalculationOverflow)?
-            .checked_div(token_reserves as u128)
-            .ok_or(errors::MarketplaceError::CalculationOverflow)? as u64;
-        
-        let current_time = Clock::get()?.unix_timestamp;
-        
-        // Update liquidity pool oracle information
-        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
-        liquidity_pool.oracle_price_usd = Some(price_usd);
-        liquidity_pool.oracle_price_last_update = current_time;
-        liquidity_pool.price_source = PriceSource::DexLiquidity;
-        liquidity_pool.redemption_locked = false;
-        
-        // Update project's last activity timestamp
-        let project = &mut ctx.accounts.project;
-        project.last_activity_timestamp = current_time;
-        
-        msg!("DEX price updated: {} USD", price_usd as f64 / 1_000_000.0);
-        
-        Ok(())
-    }
-    
-    // Set price manually for testing or projects without price feeds
+        project_id: String
+    ) -> Result<()> {
+        modules::oracle::update_dex_price(ctx, project_id)
+    }
+    
+    // Set price manually for testing or projects without price feeds. The price is in USD
+    // scaled by 10^6 unless price_decimals gives its own scale (0 for whole dollars); the
+    // bounds apply to the rescaled price
     pub fn set_price_manually(
         ctx: Context<SetManualPrice>, 
-        _project_id: String, 
-        price_usd: u64
-    ) -> Result<()> {
-        let current_time = Clock::get()?.unix_timestamp;
-        
-        // Update liquidity pool oracle information
-        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
-        liquidity_pool.oracle_price_usd = Some(price_usd);
-        liquidity_pool.oracle_price_last_update = current_time;
-        liquidity_pool.price_source = PriceSource::Manual;
-        liquidity_pool.redemption_locked = false;
-        
-        // Update project's last activity timestamp
-        let project = &mut ctx.accounts.project;
-        project.last_activity_timestamp = current_time;
-        
-        msg!("Manual price set: {} USD", price_usd as f64 / 1_000_000.0);
-        
-        Ok(())
+        project_id: String, 
+        price_usd: u64,
+        price_decimals: Option<u8>,
+    ) -> Result<()> {
+        modules::oracle::set_manual_price(ctx, project_id, price_usd, price_decimals)
     }
-}
-
-// Helper function to distribute fees among platform, project, and royalty wallets
-pub fn distribute_fees<'info>(
-    token_program: &Program<'info, Token>,
-    lp_token_account: &Account<'info, TokenAccount>,
-    platform_treasury: &Account<'info, TokenAccount>,
-    project_treasury: &Account<'info, TokenAccount>,
-    royalty_wallet: Option<&Account<'info, TokenAccount>>,
-    liquidity_pool: &Account<'info, state::LiquidityPool>,
-    platform_config: &Account<'info, state::PlatformConfig>,
-    project: &Account<'info, state::Project>,
-    token_amount: u64,
-) -> Result<()> {
-    // Calculate platform fee (platform_fee_basis_points is in basis points, e.g., 200 = 2%)
-    let platform_fee = (token_amount as u128)
-        .checked_mul(platform_config.platform_fee_basis_points as u128)
-        .ok_or(errors::MarketplaceError::CalculationOverflow)?
-        .checked_div(10000)
-        .ok_or(errors::MarketplaceError::CalculationOverflow)? as u64;
-    
-    // Calculate project fee (royalty_basis_points is in basis points)
-    let project_fee = (token_amount as u128)
-        .checked_mul(project.royalty_basis_points as u128)
-        .ok_or(errors::MarketplaceError::CalculationOverflow)?
-        .checked_div(10000)
-        .ok_or(errors::MarketplaceError::CalculationOverflow)? as u64;
-    
-    // Calculate royalty fee (if royalty wallet is provided)
-    let royalty_fee = if royalty_wallet.is_some() && project.royalty_wallet.is_some() {
-        // For simplicity, we'll use a fixed 1% royalty fee
-        (token_amount as u128)
-            .checked_mul(100) // 1% = 100 basis points
-            .ok_or(errors::MarketplaceError::CalculationOverflow)?
-            .checked_div(10000)
-            .ok_or(errors::MarketplaceError::CalculationOverflow)? as u64
-    } else {
-        0
-    };
-    
-    // Transfer platform fee
-    if platform_fee > 0 {
-        token::transfer(
-            CpiContext::new_with_signer(
-                token_program.to_account_info(),
-                token::Transfer {
-                    from: lp_token_account.to_account_info(),
-                    to: platform_treasury.to_account_info(),
-                    authority: liquidity_pool.to_account_info(),
-                },
-                &[&[
-                    b"liquidity_pool",
-                    liquidity_pool.project.as_ref(),
-                    &[liquidity_pool.bump],
-                ]],
-            ),
-            platform_fee,
-        )?;
-    }
-    
-    // Transfer project fee
-    if project_fee > 0 {
-        token::transfer(
-            CpiContext::new_with_signer(
-                token_program.to_account_info(),
-                token::Transfer {
-                    from: lp_token_account.to_account_info(),
-                    to: project_treasury.to_account_info(),
-                    authority: liquidity_pool.to_account_info(),
-                },
-                &[&[
-                    b"liquidity_pool",
-                    liquidity_pool.project.as_ref(),
-                    &[liquidity_pool.bump],
-                ]],
-            ),
-            project_fee,
-        )?;
-    }
-    
-    // Transfer royalty fee if applicable
-    if royalty_fee > 0 && royalty_wallet.is_some() {
-        token::transfer(
-            CpiContext::new_with_signer(
-                token_program.to_account_info(),
-                token::Transfer {
-                    from: lp_token_account.to_account_info(),
-                    to: royalty_wallet.unwrap().to_account_info(),
-                    authority: liquidity_pool.to_account_info(),
-                },
-                &[&[
-                    b"liquidity_pool",
-                    liquidity_pool.project.as_ref(),
-                    &[liquidity_pool.bump],
-                ]],
-            ),
-            royalty_fee,
-        )?;
+    
+    // Bound the prices accepted by set_price_manually
+    pub fn set_manual_price_bounds(
+        ctx: Context<SetManualPriceBounds>,
+        min_manual_price: Option<u64>,
+        max_manual_price: Option<u64>,
+    ) -> Result<()> {
+        modules::oracle::set_manual_price_bounds(ctx, min_manual_price, max_manual_price)
     }
     
-    Ok(())
+    // Choose the asset a pool's price is quoted in
+    pub fn set_quote_asset(
+        ctx: Context<SetQuoteAsset>,
+        project_id: String,
+        quote_asset: QuoteAsset,
+    ) -> Result<()> {
+        modules::oracle::set_quote_asset(ctx, project_id, quote_asset)
+    }
+    
+    // Set how old a pool's oracle price may get before it counts as stale (0 uses the default)
+    pub fn set_oracle_staleness(
+        ctx: Context<SetOracleStaleness>,
+        project_id: String,
+        max_oracle_staleness: i64,
+    ) -> Result<()> {
+        modules::oracle::set_oracle_staleness(ctx, project_id, max_oracle_staleness)
+    }
+    
+    // Reject Pyth prices whose confidence interval is too wide (0 disables)
+    pub fn set_oracle_confidence(
+        ctx: Context<SetOracleConfidence>,
+        project_id: String,
+        max_confidence_bps: u16,
+    ) -> Result<()> {
+        modules::oracle::set_oracle_confidence(ctx, project_id, max_confidence_bps)
+    }
+    
+    // Set the USD price of a pool's quote asset by hand
+    pub fn set_quote_price_manually(
+        ctx: Context<SetQuotePrice>,
+        project_id: String,
+        price_usd: u64,
+    ) -> Result<()> {
+        modules::oracle::set_quote_price_manually(ctx, project_id, price_usd)
+    }
+    
+    // Pin the Pyth feed a pool's token price is read from
+    pub fn set_pyth_price_feed(
+        ctx: Context<SetPriceFeed>,
+        project_id: String,
+        pyth_price_feed: Option<Pubkey>,
+    ) -> Result<()> {
+        modules::oracle::set_pyth_price_feed(ctx, project_id, pyth_price_feed)
+    }
+    
+    // Pin the DEX reserve accounts a pool's token price is read from
+    pub fn set_dex_price_accounts(
+        ctx: Context<SetPriceFeed>,
+        project_id: String,
+        dex_token_account: Option<Pubkey>,
+        dex_base_account: Option<Pubkey>,
+    ) -> Result<()> {
+        modules::oracle::set_dex_price_accounts(ctx, project_id, dex_token_account, dex_base_account)
+    }
+    
+    // Pin the Pyth feed a pool's quote asset price is read from
+    pub fn set_quote_price_feed(
+        ctx: Context<SetPriceFeed>,
+        project_id: String,
+        quote_price_feed: Option<Pubkey>,
+    ) -> Result<()> {
+        modules::oracle::set_quote_price_feed(ctx, project_id, quote_price_feed)
+    }
+    
+    // Update the USD price of a pool's quote asset from its pinned Pyth feed
+    pub fn update_quote_price_from_pyth(
+        ctx: Context<UpdateQuotePriceFromPyth>,
+        project_id: String,
+    ) -> Result<()> {
+        modules::oracle::update_quote_price_from_pyth(ctx, project_id)
+    }
+    
+    // Lock redemptions for a pool outside the price-update path
+    pub fn lock_redemption(
+        ctx: Context<OverrideRedemptionLock>,
+        project_id: String,
+    ) -> Result<()> {
+        modules::oracle::lock_redemption(ctx, project_id)
+    }
+    
+    // Clear a stuck redemption lock after the authority has verified the feed
+    pub fn force_unlock_redemption(
+        ctx: Context<OverrideRedemptionLock>,
+        project_id: String,
+    ) -> Result<()> {
+        modules::oracle::force_unlock_redemption(ctx, project_id)
+    }
+    
+    // Choose whether fresh price updates lift a redemption lock on their own
+    pub fn set_redemption_auto_recovery(
+        ctx: Context<OverrideRedemptionLock>,
+        project_id: String,
+        auto_recover_redemption_lock: bool,
+    ) -> Result<()> {
+        modules::oracle::set_redemption_auto_recovery(ctx, project_id, auto_recover_redemption_lock)
+    }
+    
+    // Liquidity pool functions
+    
+    // Set up the liquidity pool for a project
+    pub fn setup_liquidity_pool(
+        ctx: Context<SetupLiquidityPool>,
+        project_id: String,
+        token_mint: Pubkey,
+        initial_liquidity: u64,
+    ) -> Result<()> {
+        modules::lp::setup_liquidity_pool(ctx, project_id, token_mint, initial_liquidity)
+    }
+    
+    // Reclaim the liquidity of a pool that has been inactive for too long
+    pub fn check_lp_inactivity(
+        ctx: Context<CheckLpInactivity>,
+        project_id: String,
+    ) -> Result<()> {
+        modules::lp::check_lp_inactivity(ctx, project_id)
+    }
+    
+    // Withdraw free pool liquidity back to the project authority
+    pub fn withdraw_liquidity(
+        ctx: Context<WithdrawLiquidity>,
+        project_id: String,
+        amount: u64,
+    ) -> Result<()> {
+        modules::lp::withdraw_liquidity(ctx, project_id, amount)
+    }
+    
+    // Reserve pool liquidity to buy back a specific NFT at a fixed amount
+    pub fn reserve_redemption(
+        ctx: Context<ReserveRedemption>,
+        nft_mint: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        modules::lp::reserve_redemption(ctx, nft_mint, amount)
+    }
+    
+    // Allow redemptions the pool can't fully cover to be paid in part
+    pub fn set_partial_redemption(
+        ctx: Context<SetPartialRedemption>,
+        project_id: String,
+        allow_partial_redemption: bool,
+    ) -> Result<()> {
+        modules::lp::set_partial_redemption(ctx, project_id, allow_partial_redemption)
+    }
+    
+    // Keep a share of each redemption in the pool (0 disables)
+    pub fn set_redemption_lp_tax(
+        ctx: Context<SetRedemptionLpTax>,
+        project_id: String,
+        redemption_lp_tax_bps: u16,
+    ) -> Result<()> {
+        modules::lp::set_redemption_lp_tax(ctx, project_id, redemption_lp_tax_bps)
+    }
+    
+    // Switch a legacy flat-rate pool to oracle-priced redemptions
+    pub fn enable_oracle_redemption(
+        ctx: Context<EnableOracleRedemption>,
+        project_id: String,
+    ) -> Result<()> {
+        modules::lp::enable_oracle_redemption(ctx, project_id)
+    }
+    
+    // Set the platform's share of liquidity reclaimed from inactive pools
+    pub fn set_reclaim_split(
+        ctx: Context<SetReclaimSplit>,
+        reclaim_split_bps: u16,
+    ) -> Result<()> {
+        modules::lp::set_reclaim_split(ctx, reclaim_split_bps)
+    }
+    
+    // Collection and NFT minting functions
+    
+    // Create a collection under a project
+    pub fn create_collection(
+        ctx: Context<CreateCollection>,
+        collection_id: String,
+        project_id: String,
+        metadata_uri: String,
+        token_mint: Option<Pubkey>,
+        is_compressed: bool,
+    ) -> Result<()> {
+        modules::mint::create_collection(ctx, collection_id, project_id, metadata_uri, token_mint, is_compressed)
+    }
+    
+    // Allow or block redemptions for a collection
+    pub fn set_collection_redeemable(
+        ctx: Context<SetCollectionRedeemable>,
+        redeemable: bool,
+    ) -> Result<()> {
+        modules::mint::set_collection_redeemable(ctx, redeemable)
+    }
+    
+    // Allow or block free mints for a collection without a token
+    pub fn set_free_mint_allowed(
+        ctx: Context<SetCollectionRedeemable>,
+        free_mint_allowed: bool,
+    ) -> Result<()> {
+        modules::mint::set_free_mint_allowed(ctx, free_mint_allowed)
+    }
+    
+    // Set what a redemption of the collection pays in USD (None defers to the pool)
+    pub fn set_redemption_value(
+        ctx: Context<SetCollectionRedeemable>,
+        redemption_value_usd: Option<u64>,
+    ) -> Result<()> {
+        modules::mint::set_redemption_value(ctx, redemption_value_usd)
+    }
+    
+    // Set the least a redemption pays in USD (None removes the floor)
+    pub fn set_redemption_floor(
+        ctx: Context<SetCollectionRedeemable>,
+        redemption_floor_usd: Option<u64>,
+    ) -> Result<()> {
+        modules::mint::set_redemption_floor(ctx, redemption_floor_usd)
+    }
+    
+    // Set the cooldowns newly minted NFTs receive by rarity
+    pub fn set_rarity_cooldown_curve(
+        ctx: Context<UpdateCollectionCooldown>,
+        rarity_cooldown_curve: Vec<RarityCooldownTier>,
+    ) -> Result<()> {
+        modules::mint::set_rarity_cooldown_curve(ctx, rarity_cooldown_curve)
+    }
+    
+    // Set the fee for redeeming an NFT before its cooldown ends
+    pub fn set_cooldown_bypass_fee(
+        ctx: Context<UpdateCollectionCooldown>,
+        cooldown_bypass_fee_bps: u16,
+    ) -> Result<()> {
+        modules::mint::set_cooldown_bypass_fee(ctx, cooldown_bypass_fee_bps)
+    }
+    
+    // Make early redemptions forfeit the unserved share of the purchase discount
+    pub fn set_discount_decay(
+        ctx: Context<UpdateCollectionCooldown>,
+        discount_decay: bool,
+    ) -> Result<()> {
+        modules::mint::set_discount_decay(ctx, discount_decay)
+    }
+    
+    // Cap how many NFTs a collection can mint per window
+    pub fn set_mint_rate_limit(
+        ctx: Context<SetMintRateLimit>,
+        max_mints: u64,
+        window_seconds: i64,
+    ) -> Result<()> {
+        modules::mint::set_mint_rate_limit(ctx, max_mints, window_seconds)
+    }
+    
+    // Set how long NFTs must be held after mint before redemption
+    pub fn set_min_hold_period(
+        ctx: Context<UpdateCollectionCooldown>,
+        min_hold_period: i64,
+    ) -> Result<()> {
+        modules::mint::set_min_hold_period(ctx, min_hold_period)
+    }
+    
+    // Set whether new cooldowns and vesting periods count seconds or slots
+    pub fn set_cooldown_clock(
+        ctx: Context<UpdateCollectionCooldown>,
+        cooldown_clock: ClockSource,
+    ) -> Result<()> {
+        modules::mint::set_cooldown_clock(ctx, cooldown_clock)
+    }
+    
+    // Shorten new mints' cooldowns for holders of a rare NFT from the collection
+    pub fn set_loyalty_cooldown_perk(
+        ctx: Context<UpdateCollectionCooldown>,
+        min_rarity_score: u16,
+        reduction_percent: u8,
+    ) -> Result<()> {
+        modules::mint::set_loyalty_cooldown_perk(ctx, min_rarity_score, reduction_percent)
+    }
+    
+    // Set whether transferred NFTs keep their remaining cooldown
+    pub fn set_transfer_cooldown_policy(
+        ctx: Context<UpdateCollectionCooldown>,
+        reset_cooldown_on_transfer: bool,
+    ) -> Result<()> {
+        modules::mint::set_transfer_cooldown_policy(ctx, reset_cooldown_on_transfer)
+    }
+    
+    // Set whether NFTs still in cooldown can back new escrows
+    pub fn set_escrow_cooldown_policy(
+        ctx: Context<UpdateCollectionCooldown>,
+        escrow_during_cooldown: bool,
+    ) -> Result<()> {
+        modules::mint::set_escrow_cooldown_policy(ctx, escrow_during_cooldown)
+    }
+    
+    // Record the current holder of a transferred NFT
+    pub fn sync_nft_owner(ctx: Context<SyncNftOwner>) -> Result<()> {
+        modules::mint::sync_nft_owner(ctx)
+    }
+    
+    // Set when a collection can be minted
+    pub fn set_mint_window(
+        ctx: Context<UpdateMintWindow>,
+        mint_start_ts: Option<i64>,
+        mint_end_ts: Option<i64>,
+    ) -> Result<()> {
+        modules::mint::set_mint_window(ctx, mint_start_ts, mint_end_ts)
+    }
+    
+    // Block secondary sales of a collection's NFTs until a time
+    pub fn set_secondary_lock(
+        ctx: Context<UpdateMintWindow>,
+        secondary_lock_until: Option<i64>,
+    ) -> Result<()> {
+        modules::mint::set_secondary_lock(ctx, secondary_lock_until)
+    }
+    
+    // Set a flat token price for minting a collection
+    pub fn set_fixed_mint_price(
+        ctx: Context<UpdateMintPrice>,
+        fixed_mint_price: Option<u64>,
+    ) -> Result<()> {
+        modules::mint::set_fixed_mint_price(ctx, fixed_mint_price)
+    }
+    
+    // Commit to a collection's final metadata before minting
+    pub fn commit_metadata_hash(
+        ctx: Context<CollectionReveal>,
+        metadata_hash: [u8; 32],
+    ) -> Result<()> {
+        modules::mint::commit_metadata_hash(ctx, metadata_hash)
+    }
+    
+    // Reveal a collection's final metadata against its commitment
+    pub fn reveal_collection(
+        ctx: Context<CollectionReveal>,
+        revealed_uri: String,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        modules::mint::reveal_collection(ctx, revealed_uri, salt)
+    }
+    
+    // Mint an NFT, with traits, from a collection without a token that allows free mints
+    pub fn mint_nft<'info>(
+        ctx: Context<'_, '_, '_, 'info, MintNft<'info>>,
+        collection_id: String,
+        metadata_uri: String,
+        traits_selection: Option<Vec<u8>>,
+    ) -> Result<()> {
+        modules::mint::mint_nft(ctx, collection_id, metadata_uri, traits_selection)
+    }
+    
+    // Mint an NFT for free from a collection without a token
+    pub fn mint_free_nft(
+        ctx: Context<MintFreeNft>,
+        collection_id: String,
+        metadata_uri: String,
+    ) -> Result<()> {
+        modules::mint::mint_free_nft(ctx, collection_id, metadata_uri)
+    }
+    
+    // Swap and fee functions
+    
+    // Swap tokens for a newly minted NFT
+    pub fn swap_token_for_nft<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapTokenForNft<'info>>,
+        collection_id: String,
+        token_amount: u64,
+        discount_percent: Option<u8>,
+        cooldown_period: Option<i64>,
+        traits_selection: Option<Vec<u8>>,
+    ) -> Result<()> {
+        modules::swap::swap_token_for_nft(ctx, collection_id, token_amount, discount_percent, cooldown_period, traits_selection)
+    }
+    
+    // Quote a swap's full cost breakdown without executing it
+    pub fn simulate_swap(
+        ctx: Context<SimulateSwap>,
+        collection_id: String,
+        token_amount: u64,
+        discount_percent: Option<u8>,
+    ) -> Result<()> {
+        modules::swap::simulate_swap(ctx, collection_id, token_amount, discount_percent)
+    }
+    
+    // Configure volume-based fee discounts for a project
+    pub fn set_fee_discount_tiers(
+        ctx: Context<SetFeeDiscountTiers>,
+        project_id: String,
+        tiers: Vec<FeeDiscountTier>,
+    ) -> Result<()> {
+        modules::fees::set_fee_discount_tiers(ctx, project_id, tiers)
+    }
+    
+    // Exempt a partner collection or market-maker wallet from the platform fee
+    pub fn add_fee_exemption(
+        ctx: Context<AddFeeExemption>,
+        subject: Pubkey,
+    ) -> Result<()> {
+        modules::fees::add_fee_exemption(ctx, subject)
+    }
+    
+    // Withdraw a platform fee exemption
+    pub fn remove_fee_exemption(
+        ctx: Context<RemoveFeeExemption>,
+        subject: Pubkey,
+    ) -> Result<()> {
+        modules::fees::remove_fee_exemption(ctx, subject)
+    }
+    
+    // Change the platform fee in basis points
+    pub fn update_platform_fee(
+        ctx: Context<UpdatePlatformFee>,
+        platform_fee_basis_points: u16,
+    ) -> Result<()> {
+        modules::fees::update_platform_fee(ctx, platform_fee_basis_points)
+    }
+    
+    // Cap the platform fee at an absolute token amount per trade
+    pub fn set_platform_fee_cap(
+        ctx: Context<SetPlatformFeeCap>,
+        max_platform_fee_absolute: Option<u64>,
+    ) -> Result<()> {
+        modules::fees::set_platform_fee_cap(ctx, max_platform_fee_absolute)
+    }
+    
+    // Point platform fees at a new treasury of a pool's token
+    pub fn set_platform_treasury(ctx: Context<SetPlatformTreasury>) -> Result<()> {
+        modules::fees::set_platform_treasury(ctx)
+    }
+    
+    // Set the smallest platform/project fee transferred per trade
+    pub fn set_min_fee_transfer(
+        ctx: Context<SetMinFeeTransfer>,
+        min_fee_transfer: u64,
+    ) -> Result<()> {
+        modules::fees::set_min_fee_transfer(ctx, min_fee_transfer)
+    }
+    
+    // Set the platform-wide ceiling on project royalties
+    pub fn set_max_project_royalty(
+        ctx: Context<SetMaxProjectRoyalty>,
+        max_project_royalty_bps: u16,
+    ) -> Result<()> {
+        modules::fees::set_max_project_royalty(ctx, max_project_royalty_bps)
+    }
+    
+    // Change a project's royalty within the platform ceiling
+    pub fn set_project_royalty(
+        ctx: Context<SetProjectRoyalty>,
+        project_id: String,
+        royalty_basis_points: u16,
+    ) -> Result<()> {
+        modules::fees::set_project_royalty(ctx, project_id, royalty_basis_points)
+    }
+    
+    // Burn a share of the project fee on each swap instead of paying it out
+    pub fn set_buyback_burn(
+        ctx: Context<SetBuybackBurn>,
+        project_id: String,
+        buyback_burn_bps: u16,
+    ) -> Result<()> {
+        modules::fees::set_buyback_burn(ctx, project_id, buyback_burn_bps)
+    }
+    
+    // Toggle accruing royalties into the project's vault
+    pub fn set_royalty_accrual(
+        ctx: Context<SetRoyaltyAccrual>,
+        project_id: String,
+        enabled: bool,
+    ) -> Result<()> {
+        modules::fees::set_royalty_accrual(ctx, project_id, enabled)
+    }
+    
+    // Split the project's royalty across several wallets
+    pub fn set_royalty_recipients(
+        ctx: Context<SetRoyaltyRecipients>,
+        project_id: String,
+        recipients: Vec<RoyaltyRecipient>,
+    ) -> Result<()> {
+        modules::fees::set_royalty_recipients(ctx, project_id, recipients)
+    }
+    
+    // Claim all royalties accrued in the project's vault
+    pub fn claim_royalties(ctx: Context<ClaimRoyalties>) -> Result<()> {
+        modules::fees::claim_royalties(ctx)
+    }
+    
+    // Escrow and redemption functions
+    
+    // Move part of an NFT's escrow into a new escrow on the same vesting schedule
+    pub fn split_escrow(
+        ctx: Context<SplitEscrow>,
+        nft_mint: Pubkey,
+        split_index: u16,
+        split_amount: u64,
+    ) -> Result<()> {
+        modules::escrow::split_escrow(ctx, nft_mint, split_index, split_amount)
+    }
+    
+    // Withdraw a vested split escrow
+    pub fn close_split_escrow(
+        ctx: Context<CloseSplitEscrow>,
+        nft_mint: Pubkey,
+        split_index: u16,
+    ) -> Result<()> {
+        modules::escrow::close_split_escrow(ctx, nft_mint, split_index)
+    }
+    
+    // Cap how many active escrows each user may hold in a project
+    pub fn set_max_escrows_per_user(
+        ctx: Context<SetMaxEscrowsPerUser>,
+        project_id: String,
+        max_escrows_per_user: u32,
+    ) -> Result<()> {
+        modules::escrow::set_max_escrows_per_user(ctx, project_id, max_escrows_per_user)
+    }
+    
+    // Lock tokens in escrow against an NFT
+    pub fn create_token_escrow(
+        ctx: Context<CreateTokenEscrow>,
+        nft_mint: Pubkey,
+        token_amount: u64,
+        vesting_period: Option<i64>,
+    ) -> Result<()> {
+        modules::escrow::create_token_escrow(ctx, nft_mint, token_amount, vesting_period)
+    }
+    
+    // Close several finished escrows at once (pairs passed as remaining accounts)
+    pub fn close_escrows_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, CloseEscrowsBatch<'info>>,
+    ) -> Result<()> {
+        modules::escrow::close_escrows_batch(ctx)
+    }
+    
+    // Redeem an NFT for tokens from the liquidity pool
+    pub fn redeem_nft_for_token(
+        ctx: Context<RedeemNftForToken>,
+        nft_mint: Pubkey,
+        bypass_cooldown: bool,
+    ) -> Result<()> {
+        modules::redeem::redeem_nft_for_token(ctx, nft_mint, bypass_cooldown)
+    }
+    
+    // Redeem an NFT and swap the tokens to SOL through the approved swap program
+    pub fn redeem_nft_for_sol<'info>(
+        ctx: Context<'_, '_, '_, 'info, RedeemNftForToken<'info>>,
+        nft_mint: Pubkey,
+        bypass_cooldown: bool,
+        swap_data: Vec<u8>,
+        min_sol_out: u64,
+    ) -> Result<()> {
+        modules::redeem::redeem_nft_for_sol(ctx, nft_mint, bypass_cooldown, swap_data, min_sol_out)
+    }
+    
+    // Approve the DEX program redemptions may swap through to SOL
+    pub fn set_swap_program(
+        ctx: Context<SetSwapProgram>,
+        swap_program: Option<Pubkey>,
+    ) -> Result<()> {
+        modules::redeem::set_swap_program(ctx, swap_program)
+    }
+    
+    // Collect tokens still owed from a partial redemption
+    pub fn claim_pending_redemption(
+        ctx: Context<ClaimPendingRedemption>,
+        nft_mint: Pubkey,
+    ) -> Result<()> {
+        modules::redeem::claim_pending_redemption(ctx, nft_mint)
+    }
+    
+    // Redeem an NFT for the tokens held in its escrow
+    pub fn redeem_escrow_token(
+        ctx: Context<TokenEscrowRedemption>,
+        nft_mint: Pubkey,
+    ) -> Result<()> {
+        modules::redeem::redeem_escrow_token(ctx, nft_mint)
+    }
+    
+    // Claim the vested share of an escrow, redeeming the NFT once it is all claimed
+    pub fn claim_vested_escrow(
+        ctx: Context<TokenEscrowRedemption>,
+        nft_mint: Pubkey,
+    ) -> Result<()> {
+        modules::redeem::claim_vested_escrow(ctx, nft_mint)
+    }
+    
+    // Reverse an escrow redemption, returning the NFT to its original owner
+    pub fn return_redeemed_nft(
+        ctx: Context<ReturnRedeemedNft>,
+        nft_mint: Pubkey,
+    ) -> Result<()> {
+        modules::redeem::return_redeemed_nft(ctx, nft_mint)
+    }
+    
+    // Close the data of an NFT redeemed to the project, refunding its rent to the project
+    pub fn close_project_owned_nft(
+        ctx: Context<CloseProjectOwnedNft>,
+        nft_mint: Pubkey,
+    ) -> Result<()> {
+        modules::redeem::close_project_owned_nft(ctx, nft_mint)
+    }
+    
+    // Trait configuration functions
+    
+    // Create a trait type for a collection
+    pub fn initialize_trait_type(
+        ctx: Context<InitializeTraitType>,
+        name: String,
+        is_required: bool,
+        trait_values: Vec<TraitValue>,
+    ) -> Result<()> {
+        modules::traits::initialize_trait_type(ctx, name, is_required, trait_values)
+    }
+    
+    // Add a value to an existing trait type
+    pub fn add_trait_value(
+        ctx: Context<AddTraitValue>,
+        trait_value: TraitValue,
+    ) -> Result<()> {
+        modules::traits::add_trait_value(ctx, trait_value)
+    }
+    
+    // Raise or remove the supply cap of a trait value
+    pub fn adjust_trait_supply_cap(
+        ctx: Context<AdjustTraitSupplyCap>,
+        value_name: String,
+        new_available_supply: Option<u64>,
+    ) -> Result<()> {
+        modules::traits::adjust_trait_supply_cap(ctx, value_name, new_available_supply)
+    }
+    
+    // Record an NFT's off-chain computed traits and their hash
+    pub fn commit_trait_hash(
+        ctx: Context<CommitTraitHash>,
+        trait_values: Vec<TraitAttribute>,
+    ) -> Result<()> {
+        modules::traits::commit_trait_hash(ctx, trait_values)
+    }
+    
+    // Check a trait set against an NFT's committed trait hash
+    pub fn verify_traits(
+        ctx: Context<VerifyTraits>,
+        trait_values: Vec<TraitAttribute>,
+    ) -> Result<()> {
+        modules::traits::verify_traits(ctx, trait_values)
+    }
+    
+    // Dry-run a mint with traits without creating anything
+    pub fn validate_mint_config<'info>(
+        ctx: Context<'_, '_, '_, 'info, ValidateMintConfig<'info>>,
+        provided_traits: Option<Vec<TraitAttribute>>,
+        randomness_request_id: u64,
+    ) -> Result<()> {
+        modules::traits::validate_mint_config(ctx, provided_traits, randomness_request_id)
+    }
+    
+    // Create the trait configuration for a collection
+    pub fn initialize_collection_trait_config(
+        ctx: Context<InitializeCollectionTraitConfig>,
+        base_uri: String,
+        auto_generation_enabled: bool,
+        metadata_format: MetadataFormat,
+    ) -> Result<()> {
+        modules::traits::initialize_collection_trait_config(ctx, base_uri, auto_generation_enabled, metadata_format)
+    }
+    
+    // Set the trait-based mint price modifiers of a collection
+    pub fn set_trait_price_modifiers(
+        ctx: Context<UpdateCollectionTraitConfig>,
+        price_modifier_mode: PriceModifierMode,
+        price_modifiers: Vec<TraitPriceModifier>,
+    ) -> Result<()> {
+        modules::traits::set_trait_price_modifiers(ctx, price_modifier_mode, price_modifiers)
+    }
+    
+    // Set the rarity bonuses for trait combinations of a collection
+    pub fn set_trait_combo_bonuses(
+        ctx: Context<UpdateCollectionTraitConfig>,
+        combo_bonuses: Vec<TraitComboBonus>,
+    ) -> Result<()> {
+        modules::traits::set_trait_combo_bonuses(ctx, combo_bonuses)
+    }
+    
+    // Choose where trait generation randomness comes from
+    pub fn set_trait_randomness_source(
+        ctx: Context<UpdateCollectionTraitConfig>,
+        randomness_source: RandomnessSource,
+    ) -> Result<()> {
+        modules::traits::set_trait_randomness_source(ctx, randomness_source)
+    }
+    
+    // Listing functions
+    
+    // List an NFT for sale, moving it into the listing's vault
+    pub fn create_listing(
+        ctx: Context<CreateListing>,
+        asking_price: u64,
+        discount_percent: Option<u8>,
+        cooldown_period: Option<i64>,
+    ) -> Result<()> {
+        modules::listing::create_listing(ctx, asking_price, discount_percent, cooldown_period)
+    }
+    
+    // Buy a listed NFT, paying the platform fee and royalty out of the price
+    pub fn buy_listing<'info>(ctx: Context<'_, '_, '_, 'info, BuyListing<'info>>) -> Result<()> {
+        modules::listing::buy_listing(ctx)
+    }
+    
+    // Cancel a listing and return the NFT to its owner
+    pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+        modules::listing::cancel_listing(ctx)
+    }
+    
+    // Change the asking price of an active listing
+    pub fn update_listing_price(
+        ctx: Context<UpdateListingPrice>,
+        asking_price: u64,
+    ) -> Result<()> {
+        modules::listing::update_listing_price(ctx, asking_price)
+    }
+    
+    // Fusion functions
+    
+    // Create the fusion configuration for a collection
+    pub fn initialize_fusion_config(
+        ctx: Context<InitializeFusionConfig>,
+        min_nfts_required: u8,
+        max_nfts_allowed: u8,
+        base_success_rate: u8,
+        token_burn_percent: u8,
+        cooldown_period: i64,
+        child_cooldown_policy: ChildCooldownPolicy,
+    ) -> Result<()> {
+        modules::fusion::initialize_fusion_config(
+            ctx,
+            min_nfts_required,
+            max_nfts_allowed,
+            base_success_rate,
+            token_burn_percent,
+            cooldown_period,
+            child_cooldown_policy,
+        )
+    }
+    
+    // Choose how fused NFTs get their cooldown: none, the longest parent's, or a fixed period
+    pub fn set_child_cooldown_policy(
+        ctx: Context<UpdateFusionConfig>,
+        child_cooldown_policy: ChildCooldownPolicy,
+        cooldown_period: i64,
+    ) -> Result<()> {
+        modules::fusion::set_child_cooldown_policy(ctx, child_cooldown_policy, cooldown_period)
+    }
+    
+    // Require a minimum combined parent value for fusions (None disables the check)
+    pub fn set_fusion_min_input_value(
+        ctx: Context<UpdateFusionConfig>,
+        min_input_value_usd: Option<u64>,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_min_input_value(ctx, min_input_value_usd)
+    }
+    
+    // Choose where fusion randomness comes from
+    pub fn set_fusion_randomness_source(
+        ctx: Context<UpdateFusionConfig>,
+        randomness_source: RandomnessSource,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_randomness_source(ctx, randomness_source)
+    }
+    
+    // Pause or resume fusion for a collection
+    pub fn set_fusion_active(
+        ctx: Context<UpdateFusionConfig>,
+        is_active: bool,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_active(ctx, is_active)
+    }
+    
+    // Set the fusion burn share and where it goes
+    pub fn set_fusion_burn(
+        ctx: Context<UpdateFusionConfig>,
+        token_burn_percent: u8,
+        burn_destination: BurnDestination,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_burn(ctx, token_burn_percent, burn_destination)
+    }
+    
+    // Set the minimum seconds between fusions by one wallet (0 disables the limit)
+    pub fn set_fusion_min_interval(
+        ctx: Context<UpdateFusionConfig>,
+        min_fusion_interval: i64,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_min_interval(ctx, min_fusion_interval)
+    }
+    
+    // Set the rarity multiplier per fusion level (empty restores the default curve)
+    pub fn set_fusion_level_multipliers(
+        ctx: Context<UpdateFusionConfig>,
+        level_multipliers_bps: Vec<u16>,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_level_multipliers(ctx, level_multipliers_bps)
+    }
+    
+    // Set how many children each fusion mints
+    pub fn set_fusion_output_count(
+        ctx: Context<UpdateFusionConfig>,
+        output_count: u8,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_output_count(ctx, output_count)
+    }
+    
+    // Set the chance (0-100) that a fusion succeeds
+    pub fn set_fusion_success_rate(
+        ctx: Context<UpdateFusionConfig>,
+        base_success_rate: u8,
+    ) -> Result<()> {
+        modules::fusion::set_fusion_success_rate(ctx, base_success_rate)
+    }
+    
+    // Randomness functions
+    
+    // Request randomness from the source configured for a collection
+    pub fn request_randomness(
+        ctx: Context<RequestRandomness>,
+        request_id: u64,
+    ) -> Result<()> {
+        modules::randomness::request_randomness(ctx, request_id)
+    }
+    
+    // Deliver an oracle result for a pending randomness request
+    pub fn fulfill_randomness(
+        ctx: Context<FulfillRandomness>,
+        result: [u8; 32],
+    ) -> Result<()> {
+        modules::randomness::fulfill_randomness(ctx, result)
+    }
+    
+    // Start tracking per-collection stats
+    pub fn initialize_collection_stats(ctx: Context<InitializeCollectionStats>) -> Result<()> {
+        modules::stats::initialize_collection_stats(ctx)
+    }
+    
+    // Emit a collection's current stats
+    pub fn get_collection_stats(ctx: Context<GetCollectionStats>) -> Result<()> {
+        modules::stats::get_collection_stats(ctx)
+    }
+    
+    // Fuse parent NFTs (passed as remaining accounts) into a new child NFT. A failed success
+    // roll still returns Ok so the burn sticks; clients must check for a FusionFailed event
+    pub fn fuse_nfts<'info>(
+        ctx: Context<'_, '_, '_, 'info, FuseNfts<'info>>,
+        collection_id: String,
+        metadata_uri: String,
+        randomness_request_id: u64,
+    ) -> Result<()> {
+        modules::fusion::fuse_nfts(ctx, collection_id, metadata_uri, randomness_request_id)
+    }
+    
+    // Custody functions
+    
+    // Return an NFT stuck in a program-owned token account to its owner
+    pub fn recover_nft(
+        ctx: Context<RecoverNft>,
+        project_id: String,
+    ) -> Result<()> {
+        modules::custody::recover_nft(ctx, project_id)
+    }
 }
 
 // Helper function to mint NFT (placeholder for actual minting logic)
@@ -2,15 +2,79 @@ use anchor_lang::prelude::*;
 use solana_program::clock::Clock;
 
 use crate::{
-    state::NftData,
+    state::{ClockSource, Collection, NftData, RarityCooldownTier, TokenEscrow},
     errors::MarketplaceError,
+    modules::fusion::verify_nft_holder,
 };
 
+// Upper bound used to size collection accounts
+pub const MAX_RARITY_COOLDOWN_TIERS: usize = 8;
+
+// Current reading of a clock: the unix timestamp, or the slot for slot-based periods
+pub fn clock_now(clock: ClockSource) -> Result<i64> {
+    let current = Clock::get()?;
+    match clock {
+        ClockSource::Timestamp => Ok(current.unix_timestamp),
+        ClockSource::Slot => i64::try_from(current.slot)
+            .map_err(|_| MarketplaceError::CalculationOverflow.into()),
+    }
+}
+
 // Check if NFT cooldown period has expired
 pub fn check_cooldown_expired(nft_data: &NftData) -> Result<()> {
     if let Some(cooldown_end) = nft_data.cooldown_end_timestamp {
+        if clock_now(nft_data.cooldown_clock)? < cooldown_end {
+            return Err(MarketplaceError::NftInCooldown.into());
+        }
+    }
+    
+    Ok(())
+}
+
+// Whether an escrow is still inside its vesting period
+pub fn is_vesting(token_escrow: &TokenEscrow) -> Result<bool> {
+    match token_escrow.vesting_end_timestamp {
+        Some(vesting_end) => Ok(clock_now(token_escrow.vesting_clock)? < vesting_end),
+        None => Ok(false),
+    }
+}
+
+// Tokens of an escrow vested so far: linearly from its creation to the vesting end. Slot-based
+// vesting can't be compared with created_at, so it vests all at once when it ends
+pub fn vested_amount(token_escrow: &TokenEscrow) -> Result<u64> {
+    let vesting_end = match token_escrow.vesting_end_timestamp {
+        Some(vesting_end) if is_vesting(token_escrow)? => vesting_end,
+        _ => return Ok(token_escrow.token_amount),
+    };
+    
+    if token_escrow.vesting_clock != ClockSource::Timestamp {
+        return Ok(0);
+    }
+    
+    let total = vesting_end.saturating_sub(token_escrow.created_at);
+    if total <= 0 {
+        return Ok(token_escrow.token_amount);
+    }
+    let elapsed = Clock::get()?.unix_timestamp.saturating_sub(token_escrow.created_at).clamp(0, total);
+    
+    // Never overflows: the quotient is at most the escrowed amount
+    Ok(((token_escrow.token_amount as u128) * (elapsed as u128) / (total as u128)) as u64)
+}
+
+// Check if an escrow's vesting period has ended
+pub fn check_vesting_ended(token_escrow: &TokenEscrow) -> Result<()> {
+    if is_vesting(token_escrow)? {
+        return Err(MarketplaceError::VestingPeriodActive.into());
+    }
+    
+    Ok(())
+}
+
+// Check that an NFT has been held for the collection's minimum hold period since mint
+pub fn check_min_hold_period(nft_data: &NftData, collection: &Collection) -> Result<()> {
+    if collection.min_hold_period > 0 {
         let current_time = Clock::get()?.unix_timestamp;
-        if current_time < cooldown_end {
+        if current_time.saturating_sub(nft_data.minted_at) < collection.min_hold_period {
             return Err(MarketplaceError::NftInCooldown.into());
         }
     }
@@ -18,14 +82,145 @@ pub fn check_cooldown_expired(nft_data: &NftData) -> Result<()> {
     Ok(())
 }
 
-// Calculate remaining cooldown time in seconds
+// Calculate remaining cooldown, in seconds or slots depending on the NFT's cooldown clock
 pub fn get_remaining_cooldown(nft_data: &NftData) -> Result<Option<i64>> {
     if let Some(cooldown_end) = nft_data.cooldown_end_timestamp {
-        let current_time = Clock::get()?.unix_timestamp;
-        if current_time < cooldown_end {
-            return Ok(Some(cooldown_end - current_time));
+        let current = clock_now(nft_data.cooldown_clock)?;
+        if current < cooldown_end {
+            return Ok(Some(cooldown_end - current));
         }
     }
     
     Ok(None)
 }
+
+// Fee for redeeming an NFT before its cooldown ends, in tokens of the redemption value
+pub fn cooldown_bypass_fee(nft_data: &NftData, collection: &Collection, redemption_value: u64) -> Result<u64> {
+    if get_remaining_cooldown(nft_data)?.is_none() {
+        return Err(MarketplaceError::NftNotInCooldown.into());
+    }
+    
+    if collection.cooldown_bypass_fee_bps == 0 {
+        return Err(MarketplaceError::CooldownBypassDisabled.into());
+    }
+    
+    redemption_value
+        .checked_mul(collection.cooldown_bypass_fee_bps as u64)
+        .ok_or(MarketplaceError::CalculationOverflow)?
+        .checked_div(10000)
+        .ok_or_else(|| MarketplaceError::CalculationOverflow.into())
+}
+
+// Tokens clawed back from a redemption before the cooldown ends when the collection decays
+// discounts: the purchase discount on the redemption value, scaled by the share of the cooldown
+// (from mint to cooldown end) still to run. Only timestamp cooldowns decay, since minted_at
+// can't be compared with a slot
+pub fn discount_clawback(nft_data: &NftData, collection: &Collection, redemption_value: u64) -> Result<u64> {
+    let (discount_percent, cooldown_end) = match (nft_data.discount_percent, nft_data.cooldown_end_timestamp) {
+        (Some(discount_percent), Some(cooldown_end)) => (discount_percent, cooldown_end),
+        _ => return Ok(0),
+    };
+    
+    if !collection.discount_decay || nft_data.cooldown_clock != ClockSource::Timestamp {
+        return Ok(0);
+    }
+    
+    let total = cooldown_end.saturating_sub(nft_data.minted_at);
+    if total <= 0 {
+        return Ok(0);
+    }
+    let remaining = cooldown_end.saturating_sub(Clock::get()?.unix_timestamp).clamp(0, total);
+    
+    let clawback = (redemption_value as u128)
+        .checked_mul(discount_percent as u128)
+        .and_then(|v| v.checked_mul(remaining as u128))
+        .and_then(|v| v.checked_div(100 * total as u128))
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
+    u64::try_from(clawback).map_err(|_| MarketplaceError::CalculationOverflow.into())
+}
+
+// Validate a rarity cooldown curve: bounded, strictly ascending by score, non-negative cooldowns
+pub fn validate_rarity_cooldown_curve(curve: &[RarityCooldownTier]) -> Result<()> {
+    if curve.len() > MAX_RARITY_COOLDOWN_TIERS {
+        return Err(MarketplaceError::InvalidCooldownPeriod.into());
+    }
+    
+    if curve.iter().any(|tier| tier.cooldown_seconds < 0)
+        || curve.windows(2).any(|pair| pair[0].min_rarity_score >= pair[1].min_rarity_score)
+    {
+        return Err(MarketplaceError::InvalidCooldownPeriod.into());
+    }
+    
+    Ok(())
+}
+
+// Cooldown for a rarity score: the highest tier the score reaches, or none below the first tier
+pub fn rarity_cooldown(curve: &[RarityCooldownTier], rarity_score: u16) -> i64 {
+    curve
+        .iter()
+        .rev()
+        .find(|tier| rarity_score >= tier.min_rarity_score)
+        .map(|tier| tier.cooldown_seconds)
+        .unwrap_or(0)
+}
+
+// Percent cut to a new mint's cooldown for a wallet presenting an NFT of the collection it holds
+// at or above the loyalty rarity threshold. Passing any account that isn't NftData presents none
+pub fn loyalty_cooldown_reduction(
+    loyalty_nft_data: &AccountInfo,
+    loyalty_token_account: &AccountInfo,
+    collection: &Account<Collection>,
+    user: &Pubkey,
+) -> Result<u8> {
+    if collection.loyalty_cooldown_reduction_percent == 0 {
+        return Ok(0);
+    }
+    
+    let nft_data = match Account::<NftData>::try_from(loyalty_nft_data) {
+        Ok(nft_data) => nft_data,
+        Err(_) => return Ok(0),
+    };
+    
+    // A presented NFT must really be the user's and from this collection
+    if nft_data.collection != collection.key() {
+        return Err(MarketplaceError::MixedCollections.into());
+    }
+    verify_nft_holder(loyalty_token_account, &nft_data, user)?;
+    
+    if nft_data.rarity_score < collection.loyalty_min_rarity_score {
+        return Ok(0);
+    }
+    
+    Ok(collection.loyalty_cooldown_reduction_percent)
+}
+
+// Cooldown end for a new NFT: the longer of the base cooldown and its rarity cooldown,
+// less any loyalty reduction (in percent)
+pub fn cooldown_end_for_rarity(
+    curve: &[RarityCooldownTier],
+    rarity_score: u16,
+    base_cooldown: i64,
+    reduction_percent: u8,
+    current_time: i64,
+) -> Result<Option<i64>> {
+    let cooldown = base_cooldown.max(rarity_cooldown(curve, rarity_score));
+    
+    if cooldown < 0 || reduction_percent > 100 {
+        return Err(MarketplaceError::InvalidCooldownPeriod.into());
+    }
+    
+    let cooldown = cooldown
+        .checked_mul(100 - reduction_percent as i64)
+        .ok_or(MarketplaceError::CalculationOverflow)?
+        / 100;
+    
+    if cooldown == 0 {
+        return Ok(None);
+    }
+    
+    current_time
+        .checked_add(cooldown)
+        .map(Some)
+        .ok_or_else(|| MarketplaceError::CalculationOverflow.into())
+}
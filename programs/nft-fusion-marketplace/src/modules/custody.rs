@@ -0,0 +1,157 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use solana_program::clock::Clock;
+
+use crate::{
+    state::{PlatformConfig, Project, LiquidityPool, NftData, NftListing, TokenEscrow},
+    errors::MarketplaceError,
+    events::NftRecovered,
+};
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct RecoverNft<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    /// The project's authority, which must also approve moving the project's NFTs
+    pub project_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == project_authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+
+    #[account(
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+
+    #[account(
+        constraint = nft_mint.decimals == 0 @ MarketplaceError::InvalidTokenMint,
+    )]
+    pub nft_mint: Account<'info, Mint>,
+
+    /// The stuck NFT, held by the project or its liquidity pool
+    #[account(
+        mut,
+        constraint = custody_token_account.mint == nft_mint.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = custody_token_account.amount == 1 @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub custody_token_account: Account<'info, TokenAccount>,
+
+    /// Token account of the owner the NFT is returned to
+    #[account(
+        mut,
+        constraint = destination_token_account.mint == nft_mint.key() @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub destination_token_account: Account<'info, TokenAccount>,
+
+    /// NFT data, which may not exist for NFTs minted elsewhere
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"nft_data", nft_mint.key().as_ref()],
+        bump,
+    )]
+    pub nft_data: UncheckedAccount<'info>,
+
+    /// Escrow for this NFT, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"token_escrow", nft_mint.key().as_ref()],
+        bump,
+    )]
+    pub token_escrow: UncheckedAccount<'info>,
+
+    /// Listing for this NFT, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"listing", nft_mint.key().as_ref()],
+        bump,
+    )]
+    pub listing: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Return an NFT stuck in a token account owned by a program PDA (the project or its
+// liquidity pool) to the given owner. Both the platform and project authorities sign, since
+// the NFT can go to any owner. NFTs backing an active escrow or listing stay put
+pub fn recover_nft(
+    ctx: Context<RecoverNft>,
+    _project_id: String,
+) -> Result<()> {
+    if let Ok(token_escrow) = Account::<TokenEscrow>::try_from(&ctx.accounts.token_escrow) {
+        if token_escrow.is_active {
+            return Err(MarketplaceError::EscrowStillActive.into());
+        }
+    }
+
+    if let Ok(listing) = Account::<NftListing>::try_from(&ctx.accounts.listing) {
+        if listing.is_active {
+            return Err(MarketplaceError::ListingAlreadyExists.into());
+        }
+    }
+
+    let project = &ctx.accounts.project;
+    let liquidity_pool = &ctx.accounts.liquidity_pool;
+    let custody_owner = ctx.accounts.custody_token_account.owner;
+    let owner = ctx.accounts.destination_token_account.owner;
+
+    // Sign as whichever PDA holds the NFT
+    let project_key = project.key();
+    let (custody_authority, signer_seeds): (AccountInfo, &[&[u8]]) = if custody_owner == liquidity_pool.key() {
+        (
+            liquidity_pool.to_account_info(),
+            &[b"liquidity_pool", project_key.as_ref(), &[liquidity_pool.bump]],
+        )
+    } else if custody_owner == project_key {
+        (
+            project.to_account_info(),
+            &[b"project", project.project_id.as_bytes(), &[project.bump]],
+        )
+    } else {
+        return Err(MarketplaceError::InvalidTokenAccount.into());
+    };
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.custody_token_account.to_account_info(),
+                to: ctx.accounts.destination_token_account.to_account_info(),
+                authority: custody_authority,
+            },
+            &[signer_seeds],
+        ),
+        1,
+    )?;
+
+    // Keep the recorded holder in step with the token
+    if let Ok(mut nft_data) = Account::<NftData>::try_from(&ctx.accounts.nft_data) {
+        nft_data.owner = owner;
+        nft_data.exit(&crate::ID)?;
+    }
+
+    emit!(NftRecovered {
+        nft_mint: ctx.accounts.nft_mint.key(),
+        custody_account: ctx.accounts.custody_token_account.key(),
+        owner,
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("NFT {} recovered from {} to {}", ctx.accounts.nft_mint.key(), custody_owner, owner);
+
+    Ok(())
+}
@@ -1,15 +1,41 @@
 use anchor_lang::prelude::*;
+use anchor_lang::AccountsClose;
 use anchor_spl::{
-    token::{self, Token, TokenAccount, Mint, Transfer},
+    token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount},
     associated_token::AssociatedToken,
 };
 use solana_program::clock::Clock;
 
 use crate::{
-    state::{PlatformConfig, Project, Collection, TokenEscrow, NftData},
+    state::{PlatformConfig, Project, Collection, TokenEscrow, NftData, UserEscrowCount},
     errors::MarketplaceError,
+    events::{BatchItemResult, EscrowsBatchClosed},
+    modules::cooldown::{check_cooldown_expired, check_vesting_ended, clock_now, is_vesting},
 };
 
+// Maximum number of escrows closed in one batch, keeping the transaction within compute limits
+pub const MAX_ESCROW_BATCH: usize = 8;
+
+// Report one item of a best-effort batch. Batches check each item before acting on it: an item
+// that fails its checks is reported and skipped, while a malformed account list or a failure once
+// an item is being processed (such as a token CPI) reverts the whole batch
+pub fn emit_batch_item_result(index: usize, result: &Result<()>) -> Result<()> {
+    let index = u16::try_from(index).map_err(|_| MarketplaceError::CalculationOverflow)?;
+    let error_code = match result {
+        Ok(()) => None,
+        Err(Error::AnchorError(error)) => Some(error.error_code_number),
+        Err(Error::ProgramError(error)) => Some(u64::from(error.program_error.clone()) as u32),
+    };
+    
+    emit!(BatchItemResult {
+        index,
+        ok: result.is_ok(),
+        error_code,
+    });
+    
+    Ok(())
+}
+
 #[derive(Accounts)]
 #[instruction(nft_mint: Pubkey, token_amount: u64, vesting_period: Option<i64>)]
 pub struct CreateTokenEscrow<'info> {
@@ -52,6 +78,16 @@ pub struct CreateTokenEscrow<'info> {
     )]
     pub token_escrow: Account<'info, TokenEscrow>,
     
+    // Active escrows the owner holds in this project
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + std::mem::size_of::<UserEscrowCount>(),
+        seeds = [b"user_escrow_count", project.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub user_escrow_count: Account<'info, UserEscrowCount>,
+    
     // The token mint
     pub token_mint: Account<'info, Mint>,
     
@@ -91,13 +127,28 @@ pub fn create_token_escrow(
         return Err(MarketplaceError::TokenPriceTooLow.into());
     }
     
-    // Calculate vesting end timestamp if vesting period is provided
+    // Escrowing only locks value, so a cooldown blocks it just where the collection says so
+    if !ctx.accounts.collection.escrow_during_cooldown {
+        check_cooldown_expired(&ctx.accounts.nft_data)?;
+    }
+    
+    // Hold each user to the project's cap on active escrows
+    let user_escrow_count = &mut ctx.accounts.user_escrow_count;
+    if user_escrow_count.user == Pubkey::default() {
+        user_escrow_count.project = ctx.accounts.project.key();
+        user_escrow_count.user = ctx.accounts.owner.key();
+        user_escrow_count.bump = *ctx.bumps.get("user_escrow_count").unwrap();
+    }
+    take_escrow_slot(user_escrow_count, ctx.accounts.project.max_escrows_per_user)?;
+    
+    // Calculate vesting end if vesting period is provided, on the collection's cooldown clock
+    let vesting_clock = ctx.accounts.collection.cooldown_clock;
     let vesting_end_timestamp = if let Some(period) = vesting_period {
         if period <= 0 {
             None
         } else {
-            let current_time = Clock::get()?.unix_timestamp;
-            Some(current_time.checked_add(period).ok_or(MarketplaceError::CalculationOverflow)?)
+            let current = clock_now(vesting_clock)?;
+            Some(current.checked_add(period).ok_or(MarketplaceError::CalculationOverflow)?)
         }
     } else {
         None
@@ -107,14 +158,26 @@ pub fn create_token_escrow(
     let token_escrow = &mut ctx.accounts.token_escrow;
     token_escrow.owner = ctx.accounts.owner.key();
     token_escrow.nft_mint = nft_mint;
+    token_escrow.project = ctx.accounts.project.key();
     token_escrow.token_mint = ctx.accounts.token_mint.key();
     token_escrow.token_amount = token_amount;
     token_escrow.created_at = Clock::get()?.unix_timestamp;
     token_escrow.vesting_end_timestamp = vesting_end_timestamp;
+    token_escrow.vesting_clock = vesting_clock;
     token_escrow.escrow_token_account = ctx.accounts.escrow_token_account.key();
     token_escrow.is_active = true;
+    token_escrow.redeemed_amount = 0;
+    token_escrow.claimed_amount = 0;
+    token_escrow.split_index = 0;
+    token_escrow.split_count = 0;
     token_escrow.bump = *ctx.bumps.get("token_escrow").unwrap();
     
+    // The active escrow keeps the NFT from being closed or consumed by a fusion
+    let nft_data = &mut ctx.accounts.nft_data;
+    nft_data.reference_count = nft_data.reference_count
+        .checked_add(1)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
     // Transfer tokens from owner to escrow
     token::transfer(
         CpiContext::new(
@@ -156,6 +219,7 @@ pub struct CloseTokenEscrow<'info> {
     #[account(
         mut,
         constraint = escrow_token_account.key() == token_escrow.escrow_token_account @ MarketplaceError::InvalidTokenAccount,
+        constraint = escrow_token_account.owner == token_escrow.key() @ MarketplaceError::InvalidEscrowAuthority,
     )]
     pub escrow_token_account: Account<'info, TokenAccount>,
     
@@ -166,6 +230,24 @@ pub struct CloseTokenEscrow<'info> {
     )]
     pub owner_token_account: Account<'info, TokenAccount>,
     
+    /// Active escrows the owner holds in the escrow's project
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"user_escrow_count", token_escrow.project.as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub user_escrow_count: UncheckedAccount<'info>,
+    
+    /// NFT data for the escrowed NFT, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"nft_data", nft_mint.as_ref()],
+        bump,
+    )]
+    pub nft_data: UncheckedAccount<'info>,
+    
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
@@ -180,13 +262,7 @@ pub fn close_token_escrow(
     }
     
     // Check if vesting period has ended
-    if let Some(vesting_end) = ctx.accounts.token_escrow.vesting_end_timestamp {
-        let current_time = Clock::get()?.unix_timestamp;
-        
-        if current_time < vesting_end {
-            return Err(MarketplaceError::VestingPeriodActive.into());
-        }
-    }
+    check_vesting_ended(&ctx.accounts.token_escrow)?;
     
     // Get amount to return to owner
     let return_amount = ctx.accounts.escrow_token_account.amount;
@@ -211,9 +287,435 @@ pub fn close_token_escrow(
         )?;
     }
     
+    // Closing an active escrow frees one of the owner's escrow slots
+    update_user_escrow_count(&ctx.accounts.user_escrow_count, |count| {
+        count.active_escrows = count.active_escrows.saturating_sub(1)
+    })?;
+    
+    // The NFT is no longer held by this escrow
+    release_nft_reference(&ctx.accounts.nft_data)?;
+    
     // The token_escrow account will be automatically closed by the runtime due to close = owner
     
     msg!("Token escrow closed for NFT {}: {} tokens returned", nft_mint, return_amount);
     
     Ok(())
-}
\ No newline at end of file
+}
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey, split_index: u16)]
+pub struct SplitEscrow<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    // Project the escrow belongs to, for its escrow cap
+    #[account(
+        constraint = project.key() == token_escrow.project @ MarketplaceError::ProjectNotFound,
+    )]
+    pub project: Account<'info, Project>,
+    
+    // The NFT's escrow being split
+    #[account(
+        mut,
+        seeds = [b"token_escrow", nft_mint.as_ref()],
+        bump = token_escrow.bump,
+        constraint = token_escrow.owner == owner.key() @ MarketplaceError::NotTokenEscrowOwner,
+        constraint = token_escrow.is_active @ MarketplaceError::EscrowNotActive,
+    )]
+    pub token_escrow: Account<'info, TokenEscrow>,
+    
+    #[account(
+        mut,
+        constraint = escrow_token_account.key() == token_escrow.escrow_token_account @ MarketplaceError::InvalidTokenAccount,
+        constraint = escrow_token_account.owner == token_escrow.key() @ MarketplaceError::InvalidEscrowAuthority,
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    
+    // The new escrow holding the split-off tokens
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + std::mem::size_of::<TokenEscrow>(),
+        seeds = [b"token_escrow", nft_mint.as_ref(), &split_index.to_le_bytes()],
+        bump,
+    )]
+    pub split_escrow: Account<'info, TokenEscrow>,
+    
+    #[account(
+        constraint = token_mint.key() == token_escrow.token_mint @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub token_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"escrow_token_account", nft_mint.as_ref(), &split_index.to_le_bytes()],
+        bump,
+        token::mint = token_mint,
+        token::authority = split_escrow,
+    )]
+    pub split_escrow_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_escrow_count", project.key().as_ref(), owner.key().as_ref()],
+        bump = user_escrow_count.bump,
+    )]
+    pub user_escrow_count: Account<'info, UserEscrowCount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Move part of an NFT's escrow into a new escrow on the same vesting schedule
+pub fn split_escrow(
+    ctx: Context<SplitEscrow>,
+    nft_mint: Pubkey,
+    split_index: u16,
+    split_amount: u64,
+) -> Result<()> {
+    // Split escrows are numbered in order from 1
+    let expected_index = ctx.accounts.token_escrow.split_count
+        .checked_add(1)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    if split_index != expected_index {
+        return Err(MarketplaceError::InvalidTokenEscrow.into());
+    }
+    
+    // Vested claims are measured against the whole escrow, so a partly claimed one stays whole
+    if ctx.accounts.token_escrow.claimed_amount > 0 {
+        return Err(MarketplaceError::EscrowPartiallyClaimed.into());
+    }
+    
+    // Both escrows must keep a balance
+    if split_amount == 0 {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    if split_amount >= ctx.accounts.token_escrow.token_amount
+        || split_amount >= ctx.accounts.escrow_token_account.amount
+    {
+        return Err(MarketplaceError::InsufficientTokenBalance.into());
+    }
+    
+    // The split-off escrow counts against the owner's escrow cap
+    take_escrow_slot(&mut ctx.accounts.user_escrow_count, ctx.accounts.project.max_escrows_per_user)?;
+    
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.split_escrow_token_account.to_account_info(),
+                authority: ctx.accounts.token_escrow.to_account_info(),
+            },
+            &[&[
+                b"token_escrow",
+                nft_mint.as_ref(),
+                &[ctx.accounts.token_escrow.bump],
+            ]],
+        ),
+        split_amount,
+    )?;
+    
+    let token_escrow = &mut ctx.accounts.token_escrow;
+    token_escrow.token_amount = token_escrow.token_amount
+        .checked_sub(split_amount)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    token_escrow.split_count = split_index;
+    
+    let split_escrow = &mut ctx.accounts.split_escrow;
+    split_escrow.owner = token_escrow.owner;
+    split_escrow.nft_mint = nft_mint;
+    split_escrow.project = token_escrow.project;
+    split_escrow.token_mint = token_escrow.token_mint;
+    split_escrow.token_amount = split_amount;
+    split_escrow.escrow_token_account = ctx.accounts.split_escrow_token_account.key();
+    split_escrow.discount_percent = token_escrow.discount_percent;
+    split_escrow.vesting_end_timestamp = token_escrow.vesting_end_timestamp;
+    split_escrow.vesting_clock = token_escrow.vesting_clock;
+    split_escrow.is_active = true;
+    split_escrow.created_at = Clock::get()?.unix_timestamp;
+    split_escrow.redeemed_amount = 0;
+    split_escrow.claimed_amount = 0;
+    split_escrow.split_index = split_index;
+    split_escrow.split_count = 0;
+    split_escrow.bump = *ctx.bumps.get("split_escrow").unwrap();
+    
+    msg!("Split {} tokens off the escrow for NFT {} at index {}", split_amount, nft_mint, split_index);
+    
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey, split_index: u16)]
+pub struct CloseSplitEscrow<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"token_escrow", nft_mint.as_ref(), &split_index.to_le_bytes()],
+        bump = split_escrow.bump,
+        constraint = split_escrow.owner == owner.key() @ MarketplaceError::NotTokenEscrowOwner,
+        constraint = split_escrow.is_active @ MarketplaceError::EscrowNotActive,
+        close = owner,
+    )]
+    pub split_escrow: Account<'info, TokenEscrow>,
+    
+    #[account(
+        mut,
+        constraint = split_escrow_token_account.key() == split_escrow.escrow_token_account @ MarketplaceError::InvalidTokenAccount,
+        constraint = split_escrow_token_account.owner == split_escrow.key() @ MarketplaceError::InvalidEscrowAuthority,
+    )]
+    pub split_escrow_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == owner.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = owner_token_account.mint == split_escrow.token_mint @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    /// Active escrows the owner holds in the escrow's project
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"user_escrow_count", split_escrow.project.as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub user_escrow_count: UncheckedAccount<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// Withdraw a split escrow once it has vested, closing it and its token account
+pub fn close_split_escrow(
+    ctx: Context<CloseSplitEscrow>,
+    nft_mint: Pubkey,
+    split_index: u16,
+) -> Result<()> {
+    check_vesting_ended(&ctx.accounts.split_escrow)?;
+    
+    let index_bytes = split_index.to_le_bytes();
+    let signer_seeds: &[&[u8]] = &[
+        b"token_escrow",
+        nft_mint.as_ref(),
+        &index_bytes,
+        &[ctx.accounts.split_escrow.bump],
+    ];
+    
+    let return_amount = ctx.accounts.split_escrow_token_account.amount;
+    if return_amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.split_escrow_token_account.to_account_info(),
+                    to: ctx.accounts.owner_token_account.to_account_info(),
+                    authority: ctx.accounts.split_escrow.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            return_amount,
+        )?;
+    }
+    
+    token::close_account(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.split_escrow_token_account.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.split_escrow.to_account_info(),
+            },
+            &[signer_seeds],
+        ),
+    )?;
+    
+    update_user_escrow_count(&ctx.accounts.user_escrow_count, |count| {
+        count.active_escrows = count.active_escrows.saturating_sub(1)
+    })?;
+    
+    msg!("Split escrow {} for NFT {} closed: {} tokens returned", split_index, nft_mint, return_amount);
+    
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetMaxEscrowsPerUser<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+}
+
+// Cap how many active escrows each user may hold in a project (0 removes the cap)
+pub fn set_max_escrows_per_user(
+    ctx: Context<SetMaxEscrowsPerUser>,
+    project_id: String,
+    max_escrows_per_user: u32,
+) -> Result<()> {
+    ctx.accounts.project.max_escrows_per_user = max_escrows_per_user;
+    
+    msg!("Escrows per user capped at {} for project: {}", max_escrows_per_user, project_id);
+    
+    Ok(())
+}
+
+// Count a new active escrow against the user's cap (0 = unlimited)
+pub fn take_escrow_slot(user_escrow_count: &mut UserEscrowCount, max_escrows_per_user: u32) -> Result<()> {
+    if max_escrows_per_user > 0 && user_escrow_count.active_escrows >= max_escrows_per_user {
+        return Err(MarketplaceError::EscrowLimitReached.into());
+    }
+    
+    user_escrow_count.active_escrows = user_escrow_count.active_escrows.saturating_add(1);
+    
+    Ok(())
+}
+
+// Apply an update to a user's escrow count account (pinned by seeds in the calling
+// instruction). Escrows created before counts were tracked have no account to update
+pub fn update_user_escrow_count<'info>(
+    count_info: &AccountInfo<'info>,
+    update: impl FnOnce(&mut UserEscrowCount),
+) -> Result<()> {
+    let mut user_escrow_count = match Account::<UserEscrowCount>::try_from(count_info) {
+        Ok(user_escrow_count) => user_escrow_count,
+        Err(_) => return Ok(()),
+    };
+    
+    update(&mut user_escrow_count);
+    
+    user_escrow_count.exit(&crate::ID)
+}
+
+// Drop one reference to an NFT, if its data account still exists
+pub fn release_nft_reference(nft_data_info: &AccountInfo) -> Result<()> {
+    if let Ok(mut nft_data) = Account::<NftData>::try_from(nft_data_info) {
+        nft_data.reference_count = nft_data.reference_count.saturating_sub(1);
+        nft_data.exit(&crate::ID)?;
+    }
+    
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CloseEscrowsBatch<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    // (token_escrow, escrow_token_account) pairs are passed via remaining_accounts
+}
+
+// Check that an escrow in a batch close belongs to the owner and is finished: empty, and
+// redeemed or past vesting
+fn check_closable_escrow<'info>(
+    escrow_info: &AccountInfo<'info>,
+    escrow_token_info: &AccountInfo<'info>,
+    owner: &Pubkey,
+) -> Result<Account<'info, TokenEscrow>> {
+    let token_escrow = Account::<TokenEscrow>::try_from(escrow_info)
+        .map_err(|_| MarketplaceError::InvalidTokenEscrow)?;
+    
+    if token_escrow.owner != *owner {
+        return Err(MarketplaceError::NotTokenEscrowOwner.into());
+    }
+    
+    if escrow_token_info.key() != token_escrow.escrow_token_account {
+        return Err(MarketplaceError::InvalidTokenAccount.into());
+    }
+    
+    if !escrow_token_info.data_is_empty() {
+        let escrow_token_account = Account::<TokenAccount>::try_from(escrow_token_info)?;
+        
+        // The escrow signs to close its token account, so it must still be the authority
+        if escrow_token_account.owner != escrow_info.key() {
+            return Err(MarketplaceError::InvalidEscrowAuthority.into());
+        }
+        if escrow_token_account.amount > 0 {
+            return Err(MarketplaceError::EscrowStillActive.into());
+        }
+    }
+    
+    if token_escrow.is_active && is_vesting(&token_escrow)? {
+        return Err(MarketplaceError::VestingPeriodActive.into());
+    }
+    
+    Ok(token_escrow)
+}
+
+// Close several of the owner's finished escrows at once. Best-effort: escrows that are not the
+// owner's or not finished are reported and skipped
+pub fn close_escrows_batch<'info>(
+    ctx: Context<'_, '_, '_, 'info, CloseEscrowsBatch<'info>>,
+) -> Result<()> {
+    let escrow_pairs = ctx.remaining_accounts.chunks_exact(2);
+    
+    if escrow_pairs.len() == 0
+        || escrow_pairs.len() > MAX_ESCROW_BATCH
+        || !escrow_pairs.remainder().is_empty()
+    {
+        return Err(MarketplaceError::InvalidTokenEscrow.into());
+    }
+    
+    let owner = ctx.accounts.owner.to_account_info();
+    let mut closed: Vec<Pubkey> = Vec::new();
+    let mut skipped: Vec<Pubkey> = Vec::new();
+    
+    for (index, pair) in escrow_pairs.enumerate() {
+        let (escrow_info, escrow_token_info) = (&pair[0], &pair[1]);
+        
+        let token_escrow = match check_closable_escrow(escrow_info, escrow_token_info, &owner.key()) {
+            Ok(token_escrow) => token_escrow,
+            Err(error) => {
+                emit_batch_item_result(index, &Err(error))?;
+                skipped.push(escrow_info.key());
+                continue;
+            }
+        };
+        
+        // Redeeming already closes the escrow token account
+        let token_account_closed = escrow_token_info.data_is_empty();
+        
+        // Close the escrow token account, then the escrow itself, refunding both to the owner
+        if !token_account_closed {
+            token::close_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    CloseAccount {
+                        account: escrow_token_info.clone(),
+                        destination: owner.clone(),
+                        authority: escrow_info.clone(),
+                    },
+                    &[&[
+                        b"token_escrow",
+                        token_escrow.nft_mint.as_ref(),
+                        &[token_escrow.bump],
+                    ]],
+                ),
+            )?;
+        }
+        token_escrow.close(owner.clone())?;
+        
+        emit_batch_item_result(index, &Ok(()))?;
+        closed.push(escrow_info.key());
+    }
+    
+    msg!("Closed {} escrows, skipped {}", closed.len(), skipped.len());
+    
+    emit!(EscrowsBatchClosed {
+        owner: owner.key(),
+        closed,
+        skipped,
+    });
+    
+    Ok(())
+}
@@ -1,40 +1,845 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use solana_program::clock::Clock;
 
 use crate::{
-    state::{PlatformConfig, Project, LiquidityPool},
+    events::PlatformTreasuryUpdated,
+    state::{PlatformConfig, Project, LiquidityPool, FeeDiscountTier, FeeExemption, RoyaltyConfig, RoyaltyRecipient},
     errors::MarketplaceError,
 };
 
-// Distribute fees from a swap transaction
+// Maximum number of volume tiers a project can configure
+pub const MAX_FEE_DISCOUNT_TIERS: usize = 8;
+
+// Maximum number of wallets a project's royalty can be split across
+pub const MAX_ROYALTY_RECIPIENTS: usize = 5;
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetFeeDiscountTiers<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetBuybackBurn<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePlatformFee<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlatformFeeCap<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlatformTreasury<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    /// A pool whose token the platform collects fees in, which sets the new treasury's mint
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    /// CHECK: Any wallet; the platform treasury is the owner of the platform's fee token accounts
+    pub new_treasury: UncheckedAccount<'info>,
+    
+    /// The new treasury's token account for the pool's token, showing it can receive fees
+    #[account(
+        constraint = new_treasury_account.owner == new_treasury.key() @ MarketplaceError::InvalidTreasury,
+        constraint = new_treasury_account.mint == liquidity_pool.token_mint @ MarketplaceError::InvalidTreasury,
+    )]
+    pub new_treasury_account: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinFeeTransfer<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxProjectRoyalty<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetProjectRoyalty<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetRoyaltyAccrual<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    #[account(
+        constraint = token_mint.key() == liquidity_pool.token_mint @ MarketplaceError::InvalidTokenMint,
+    )]
+    pub token_mint: Account<'info, Mint>,
+    
+    // Vault royalties accrue into, owned by the project PDA
+    #[account(
+        init_if_needed,
+        payer = authority,
+        seeds = [b"royalty_vault", project.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = project,
+    )]
+    pub royalty_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetRoyaltyRecipients<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<RoyaltyConfig>()
+            + MAX_ROYALTY_RECIPIENTS * std::mem::size_of::<RoyaltyRecipient>(),
+        seeds = [b"royalty_config", project.key().as_ref()],
+        bump,
+    )]
+    pub royalty_config: Account<'info, RoyaltyConfig>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(subject: Pubkey)]
+pub struct AddFeeExemption<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<FeeExemption>(),
+        seeds = [b"fee_exemption", subject.as_ref()],
+        bump,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(subject: Pubkey)]
+pub struct RemoveFeeExemption<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_exemption", subject.as_ref()],
+        bump = fee_exemption.bump,
+        close = authority,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRoyalties<'info> {
+    // Owner of the project's royalty wallet
+    pub claimer: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project.project_id.as_bytes()],
+        bump = project.bump,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"royalty_vault", project.key().as_ref()],
+        bump,
+    )]
+    pub royalty_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = Some(royalty_wallet.key()) == project.royalty_wallet @ MarketplaceError::InvalidTokenAccount,
+        constraint = royalty_wallet.owner == claimer.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub royalty_wallet: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+// Exempt a collection or user wallet from the platform fee on swaps
+pub fn add_fee_exemption(
+    ctx: Context<AddFeeExemption>,
+    subject: Pubkey,
+) -> Result<()> {
+    let fee_exemption = &mut ctx.accounts.fee_exemption;
+    fee_exemption.subject = subject;
+    fee_exemption.created_at = Clock::get()?.unix_timestamp;
+    fee_exemption.bump = *ctx.bumps.get("fee_exemption").unwrap();
+    
+    msg!("Platform fee exemption added for {}", subject);
+    
+    Ok(())
+}
+
+// Withdraw a platform fee exemption, refunding its rent to the authority
+pub fn remove_fee_exemption(
+    _ctx: Context<RemoveFeeExemption>,
+    subject: Pubkey,
+) -> Result<()> {
+    msg!("Platform fee exemption removed for {}", subject);
+    
+    Ok(())
+}
+
+// Whether either of the collection's or user's exemption accounts holds an exemption
+pub fn is_platform_fee_exempt(collection_exemption: &AccountInfo, user_exemption: &AccountInfo) -> bool {
+    Account::<FeeExemption>::try_from(collection_exemption).is_ok()
+        || Account::<FeeExemption>::try_from(user_exemption).is_ok()
+}
+
+// Change the platform fee taken on swaps and redemptions
+pub fn update_platform_fee(
+    ctx: Context<UpdatePlatformFee>,
+    platform_fee_basis_points: u16,
+) -> Result<()> {
+    if platform_fee_basis_points >= 10000 {
+        return Err(MarketplaceError::InvalidPlatformFee.into());
+    }
+    
+    let platform_config = &mut ctx.accounts.platform_config;
+    let old_fee = platform_config.platform_fee_basis_points;
+    platform_config.platform_fee_basis_points = platform_fee_basis_points;
+    
+    msg!("Platform fee changed from {} to {} bps", old_fee, platform_fee_basis_points);
+    
+    Ok(())
+}
+
+// Set or clear the absolute ceiling on the platform fee charged per trade
+pub fn set_platform_fee_cap(
+    ctx: Context<SetPlatformFeeCap>,
+    max_platform_fee_absolute: Option<u64>,
+) -> Result<()> {
+    let platform_config = &mut ctx.accounts.platform_config;
+    platform_config.max_platform_fee_absolute = max_platform_fee_absolute;
+    
+    match max_platform_fee_absolute {
+        Some(cap) => msg!("Platform fee capped at {} tokens per trade", cap),
+        None => msg!("Platform fee cap removed"),
+    }
+    
+    Ok(())
+}
+
+// Point platform fees at a new treasury wallet. Only the owner changes: whatever the old
+// treasury's token accounts hold stays there for operators to drain separately, and pending
+// platform fees are paid to the new treasury's accounts when they are next settled
+pub fn set_platform_treasury(ctx: Context<SetPlatformTreasury>) -> Result<()> {
+    let new_treasury = &ctx.accounts.new_treasury;
+    
+    let platform_config = &mut ctx.accounts.platform_config;
+    let old_treasury = platform_config.platform_treasury;
+    platform_config.platform_treasury = new_treasury.key();
+    
+    emit!(PlatformTreasuryUpdated {
+        old_treasury,
+        new_treasury: new_treasury.key(),
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    msg!("Platform treasury moved from {} to {}", old_treasury, new_treasury.key());
+    
+    Ok(())
+}
+
+// Set the smallest platform/project fee worth transferring (0 transfers every fee)
+pub fn set_min_fee_transfer(
+    ctx: Context<SetMinFeeTransfer>,
+    min_fee_transfer: u64,
+) -> Result<()> {
+    ctx.accounts.platform_config.min_fee_transfer = min_fee_transfer;
+    
+    msg!("Fees below {} tokens now accrue in the pool", min_fee_transfer);
+    
+    Ok(())
+}
+
+// Set the platform-wide ceiling on project royalties
+pub fn set_max_project_royalty(
+    ctx: Context<SetMaxProjectRoyalty>,
+    max_project_royalty_bps: u16,
+) -> Result<()> {
+    if max_project_royalty_bps > 10000 {
+        return Err(MarketplaceError::InvalidRoyaltyFee.into());
+    }
+    
+    ctx.accounts.platform_config.max_project_royalty_bps = max_project_royalty_bps;
+    
+    msg!("Project royalties capped at {} bps", max_project_royalty_bps);
+    
+    Ok(())
+}
+
+// Helper function to check a project royalty against the platform's ceiling and fee
+pub fn validate_project_royalty(platform_config: &PlatformConfig, royalty_basis_points: u16) -> Result<()> {
+    if royalty_basis_points > platform_config.max_project_royalty_bps || royalty_basis_points > 10000 {
+        return Err(MarketplaceError::InvalidRoyaltyFee.into());
+    }
+    
+    // Together with the platform fee a royalty can't claim more than the whole trade
+    if platform_config.platform_fee_basis_points as u32 + royalty_basis_points as u32 > 10000 {
+        return Err(MarketplaceError::InvalidRoyaltyFee.into());
+    }
+    
+    Ok(())
+}
+
+// Change a project's royalty, within the platform's ceiling
+pub fn set_project_royalty(
+    ctx: Context<SetProjectRoyalty>,
+    project_id: String,
+    royalty_basis_points: u16,
+) -> Result<()> {
+    validate_project_royalty(&ctx.accounts.platform_config, royalty_basis_points)?;
+    
+    let project = &mut ctx.accounts.project;
+    project.royalty_basis_points = royalty_basis_points;
+    project.last_activity_timestamp = Clock::get()?.unix_timestamp;
+    
+    msg!("Royalty set to {} bps for project: {}", royalty_basis_points, project_id);
+    
+    Ok(())
+}
+
+// Set the share of the project fee burned from the pool on each swap, deflating the project token
+pub fn set_buyback_burn(
+    ctx: Context<SetBuybackBurn>,
+    project_id: String,
+    buyback_burn_bps: u16,
+) -> Result<()> {
+    if buyback_burn_bps > 10000 {
+        return Err(MarketplaceError::InvalidBuybackBurnShare.into());
+    }
+    
+    ctx.accounts.project.buyback_burn_bps = buyback_burn_bps;
+    
+    msg!("Buyback burn set to {} bps of the project fee for project: {}", buyback_burn_bps, project_id);
+    
+    Ok(())
+}
+
+// Part of a project fee burned rather than paid to the project treasury
+pub fn buyback_burn_amount(project_fee: u64, buyback_burn_bps: u16) -> Result<u64> {
+    if buyback_burn_bps > 10000 {
+        return Err(MarketplaceError::InvalidBuybackBurnShare.into());
+    }
+    
+    // Never overflows: the quotient is at most the fee
+    Ok(((project_fee as u128) * (buyback_burn_bps as u128) / 10000) as u64)
+}
+
+// Route royalties into the project's vault instead of paying them per swap
+pub fn set_royalty_accrual(
+    ctx: Context<SetRoyaltyAccrual>,
+    project_id: String,
+    enabled: bool,
+) -> Result<()> {
+    let project = &mut ctx.accounts.project;
+    
+    // Anything already accrued stays claimable after accrual is switched off
+    project.royalty_vault = if enabled {
+        Some(ctx.accounts.royalty_vault.key())
+    } else {
+        None
+    };
+    project.last_activity_timestamp = Clock::get()?.unix_timestamp;
+    
+    msg!("Royalty accrual {} for project: {}", if enabled { "enabled" } else { "disabled" }, project_id);
+    
+    Ok(())
+}
+
+// Split the project's royalty across several wallets (an empty list goes back to
+// paying the single royalty wallet)
+pub fn set_royalty_recipients(
+    ctx: Context<SetRoyaltyRecipients>,
+    project_id: String,
+    recipients: Vec<RoyaltyRecipient>,
+) -> Result<()> {
+    validate_royalty_recipients(&recipients)?;
+    
+    let royalty_config = &mut ctx.accounts.royalty_config;
+    royalty_config.project = ctx.accounts.project.key();
+    royalty_config.recipients = recipients;
+    royalty_config.bump = *ctx.bumps.get("royalty_config").unwrap();
+    
+    msg!("Royalty split across {} recipients for project: {}", royalty_config.recipients.len(), project_id);
+    
+    Ok(())
+}
+
+// Helper function to check that royalty shares are non-zero and add up to the whole royalty
+pub fn validate_royalty_recipients(recipients: &[RoyaltyRecipient]) -> Result<()> {
+    if recipients.is_empty() {
+        return Ok(());
+    }
+    
+    if recipients.len() > MAX_ROYALTY_RECIPIENTS
+        || recipients.iter().any(|recipient| recipient.share_bps == 0)
+    {
+        return Err(MarketplaceError::InvalidRoyaltySplit.into());
+    }
+    
+    let total_bps: u32 = recipients.iter().map(|recipient| recipient.share_bps as u32).sum();
+    if total_bps != 10000 {
+        return Err(MarketplaceError::InvalidRoyaltySplit.into());
+    }
+    
+    Ok(())
+}
+
+// Split a royalty fee by share, giving any rounding remainder to the last recipient
+pub fn split_royalty_fee(royalty_fee: u64, recipients: &[RoyaltyRecipient]) -> Result<Vec<u64>> {
+    let mut amounts = Vec::with_capacity(recipients.len());
+    let mut remaining = royalty_fee;
+    
+    for (index, recipient) in recipients.iter().enumerate() {
+        let amount = if index + 1 == recipients.len() {
+            remaining
+        } else {
+            royalty_fee
+                .checked_mul(recipient.share_bps as u64)
+                .and_then(|v| v.checked_div(10000))
+                .ok_or(MarketplaceError::CalculationOverflow)?
+        };
+        remaining = remaining
+            .checked_sub(amount)
+            .ok_or(MarketplaceError::FeeCalculationError)?;
+        amounts.push(amount);
+    }
+    
+    Ok(amounts)
+}
+
+// Pay out all accrued royalties to the royalty wallet in one transfer
+pub fn claim_royalties(ctx: Context<ClaimRoyalties>) -> Result<()> {
+    let claim_amount = ctx.accounts.royalty_vault.amount;
+    
+    if claim_amount == 0 {
+        return Err(MarketplaceError::NoRoyaltiesToClaim.into());
+    }
+    
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.royalty_vault.to_account_info(),
+                to: ctx.accounts.royalty_wallet.to_account_info(),
+                authority: ctx.accounts.project.to_account_info(),
+            },
+            &[&[
+                b"project",
+                ctx.accounts.project.project_id.as_bytes(),
+                &[ctx.accounts.project.bump],
+            ]],
+        ),
+        claim_amount,
+    )?;
+    
+    let project = &mut ctx.accounts.project;
+    project.accrued_royalties = 0;
+    project.last_activity_timestamp = Clock::get()?.unix_timestamp;
+    
+    msg!("Royalties claimed: {} tokens", claim_amount);
+    
+    Ok(())
+}
+
+// Replace a project's volume-based fee discount schedule
+pub fn set_fee_discount_tiers(
+    ctx: Context<SetFeeDiscountTiers>,
+    project_id: String,
+    tiers: Vec<FeeDiscountTier>,
+) -> Result<()> {
+    validate_fee_discount_tiers(&tiers)?;
+    
+    let project = &mut ctx.accounts.project;
+    project.fee_discount_tiers = tiers;
+    project.last_activity_timestamp = Clock::get()?.unix_timestamp;
+    
+    msg!("Fee discount tiers updated for project: {}", project_id);
+    
+    Ok(())
+}
+
+// Tiers must be bounded, strictly ascending by volume, and never discount more than 100%
+pub fn validate_fee_discount_tiers(tiers: &[FeeDiscountTier]) -> Result<()> {
+    if tiers.len() > MAX_FEE_DISCOUNT_TIERS {
+        return Err(MarketplaceError::InvalidFeeDiscount.into());
+    }
+    
+    let mut previous_volume: Option<u64> = None;
+    for tier in tiers {
+        if tier.discount_bps > 10000 {
+            return Err(MarketplaceError::InvalidFeeDiscount.into());
+        }
+        
+        if let Some(previous) = previous_volume {
+            if tier.min_volume <= previous {
+                return Err(MarketplaceError::InvalidFeeDiscount.into());
+            }
+        }
+        previous_volume = Some(tier.min_volume);
+    }
+    
+    Ok(())
+}
+
+// Resolve the discount for a user's cumulative volume (highest tier reached wins)
+pub fn get_fee_discount_bps(project: &Project, cumulative_volume: u64) -> u16 {
+    project.fee_discount_tiers
+        .iter()
+        .rev()
+        .find(|tier| cumulative_volume >= tier.min_volume)
+        .map(|tier| tier.discount_bps)
+        .unwrap_or(0)
+}
+
+// Reduce a fee by a discount in basis points, refusing to go below zero
+pub fn apply_fee_discount(fee: u64, discount_bps: u16) -> Result<u64> {
+    let discount = fee
+        .checked_mul(discount_bps as u64)
+        .and_then(|v| v.checked_div(10000))
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
+    fee.checked_sub(discount)
+        .ok_or_else(|| MarketplaceError::FeeCalculationError.into())
+}
+
+// How an amount divides into fees. The components always sum to the amount. Every fee is a
+// basis-point share of the amount rounded down, and each kind of trade has one schedule here:
+// - Swaps (calculate_fees): the platform fee and royalty, then PROJECT_FEE_SHARE_BPS of what
+//   they leave as the project fee. The net amount stays in the pool
+// - Secondary sales (calculate_listing_fees): the platform fee and royalty. The seller gets the net
+// - Escrow redemptions (calculate_redemption_fees): the platform fee, and the royalty rate as
+//   the project fee. The owner gets the net
+// Swaps and sales are then adjusted for volume discounts, the platform fee cap and exemptions,
+// and all of them are paid out by distribute_fees or straight from the escrow
+pub struct FeeBreakdown {
+    pub platform_fee: u64,
+    pub project_fee: u64,
+    pub royalty_fee: u64,
+    pub net_amount: u64,         // What is left after fees (kept by the pool on swaps, paid out on redemptions)
+}
+
+// Split an amount into fees by basis points. Each fee rounds down, so rounding dust
+// always stays with the net amount, and fee shares above 100% are rejected
+pub fn compute_fee_breakdown(
+    amount: u64,
+    platform_bps: u16,
+    project_bps: u16,
+    royalty_bps: u16,
+) -> Result<FeeBreakdown> {
+    if platform_bps as u32 + project_bps as u32 + royalty_bps as u32 > 10000 {
+        return Err(MarketplaceError::FeeCalculationError.into());
+    }
+    
+    // Never overflows: amount * bps fits in u128 and the quotient is at most amount
+    let fee = |bps: u16| ((amount as u128) * (bps as u128) / 10000) as u64;
+    let platform_fee = fee(platform_bps);
+    let project_fee = fee(project_bps);
+    let royalty_fee = fee(royalty_bps);
+    
+    // The fees are each at most their share of the amount, so together they never exceed it
+    let net_amount = amount - platform_fee - project_fee - royalty_fee;
+    
+    Ok(FeeBreakdown { platform_fee, project_fee, royalty_fee, net_amount })
+}
+
+// Helper function to check that a treasury is a token account for the given mint.
+// Treasuries are fee destinations, so a wallet or other system account can't hold them
+pub fn validate_treasury(treasury: &AccountInfo, token_mint: &Pubkey) -> Result<()> {
+    if treasury.owner != &token::ID {
+        return Err(MarketplaceError::InvalidTreasury.into());
+    }
+    
+    let treasury_account = Account::<TokenAccount>::try_from(treasury)
+        .map_err(|_| MarketplaceError::InvalidTreasury)?;
+    if treasury_account.mint != *token_mint {
+        return Err(MarketplaceError::InvalidTreasury.into());
+    }
+    
+    Ok(())
+}
+
+// Share of what the platform fee and royalty leave of a swap that the project takes as its fee.
+// The rest is the swap's net amount, which stays in the pool to back redemptions
+pub const PROJECT_FEE_SHARE_BPS: u16 = 5000;
+
+// Work out the fees on a trade amount, after volume discounts, the platform fee cap and any
+// platform fee exemption. Royalties are charged either way
+pub fn calculate_fees(
+    platform_config: &PlatformConfig,
+    project: &Project,
+    amount: u64,
+    fee_discount_bps: u16,
+    platform_fee_exempt: bool,
+) -> Result<FeeBreakdown> {
+    let platform_bps = platform_config.platform_fee_basis_points;
+    let royalty_bps = project.royalty_basis_points;
+    
+    // A platform fee and royalty adding up to more than the whole trade leave nothing to split,
+    // and are refused rather than wrapping around
+    let remainder_bps = 10000u16
+        .checked_sub(platform_bps)
+        .and_then(|v| v.checked_sub(royalty_bps))
+        .ok_or(MarketplaceError::FeeCalculationError)?;
+    
+    // Never overflows: the share is at most the remainder
+    let project_bps = (remainder_bps as u32 * PROJECT_FEE_SHARE_BPS as u32 / 10000) as u16;
+    
+    let breakdown = compute_fee_breakdown(amount, platform_bps, project_bps, royalty_bps)?;
+    adjust_fees(platform_config, breakdown, fee_discount_bps, platform_fee_exempt)
+}
+
+// Work out the fees on a secondary sale: the platform fee and royalty only, with the same
+// discounts, cap and exemptions as swaps. The net amount goes to the seller
+pub fn calculate_listing_fees(
+    platform_config: &PlatformConfig,
+    project: &Project,
+    amount: u64,
+    fee_discount_bps: u16,
+    platform_fee_exempt: bool,
+) -> Result<FeeBreakdown> {
+    let breakdown = compute_fee_breakdown(
+        amount,
+        platform_config.platform_fee_basis_points,
+        0,
+        project.royalty_basis_points,
+    )?;
+    adjust_fees(platform_config, breakdown, fee_discount_bps, platform_fee_exempt)
+}
+
+// Work out the fees on redeeming an escrow: the platform fee, and the project's royalty rate
+// paid to the project treasury. No discounts apply; the owner receives the net amount
+pub fn calculate_redemption_fees(
+    platform_config: &PlatformConfig,
+    project: &Project,
+    amount: u64,
+) -> Result<FeeBreakdown> {
+    compute_fee_breakdown(
+        amount,
+        platform_config.platform_fee_basis_points,
+        project.royalty_basis_points,
+        0,
+    )
+}
+
+// Volume discounts, the platform fee cap and exemptions reduce fees in favour of the net amount
+fn adjust_fees(
+    platform_config: &PlatformConfig,
+    breakdown: FeeBreakdown,
+    fee_discount_bps: u16,
+    platform_fee_exempt: bool,
+) -> Result<FeeBreakdown> {
+    let platform_fee = apply_fee_discount(breakdown.platform_fee, fee_discount_bps)?;
+    let platform_fee = match platform_config.max_platform_fee_absolute {
+        Some(cap) => platform_fee.min(cap),
+        None => platform_fee,
+    };
+    let platform_fee = if platform_fee_exempt { 0 } else { platform_fee };
+    let project_fee = apply_fee_discount(breakdown.project_fee, fee_discount_bps)?;
+    let net_amount = breakdown.net_amount
+        + (breakdown.platform_fee - platform_fee)
+        + (breakdown.project_fee - project_fee);
+    
+    Ok(FeeBreakdown { platform_fee, project_fee, royalty_fee: breakdown.royalty_fee, net_amount })
+}
+
+// Add a fee to its pending balance and return what should be transferred now:
+// the whole balance once it reaches the threshold, otherwise nothing
+pub fn settle_pending_fee(pending: &mut u64, fee: u64, min_fee_transfer: u64) -> Result<u64> {
+    let total = pending
+        .checked_add(fee)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
+    if total < min_fee_transfer {
+        *pending = total;
+        return Ok(0);
+    }
+    
+    *pending = 0;
+    Ok(total)
+}
+
+// Pay out the fees of a trade from the pool, which must already hold them. Platform and
+// project fees below the platform's transfer threshold stay in the pool until enough has accrued.
+// When the project splits its royalty, the recipient token accounts are passed in
+// the same order as its royalty config; otherwise the royalty wallet gets it all.
+// A trade whose fees all come to zero (a zero amount, zero-bps config or fees rounding
+// away) returns before any transfer. The treasury and royalty accounts are still required
+// by the calling contexts (SwapTokenForNft, BuyListing) and validated there, just not touched
 pub fn distribute_fees<'info>(
     token_program: &Program<'info, Token>,
     lp_token_account: &Account<'info, TokenAccount>,
-    platform_treasury: &AccountInfo<'info>,
+    token_mint: &Account<'info, Mint>,
+    platform_treasury: &Account<'info, TokenAccount>,
     project_treasury: &AccountInfo<'info>,
     royalty_wallet: Option<&AccountInfo<'info>>,
-    liquidity_pool: &Account<'info, LiquidityPool>,
+    royalty_split: Option<(&RoyaltyConfig, &[AccountInfo<'info>])>,
+    liquidity_pool: &mut Account<'info, LiquidityPool>,
     platform_config: &Account<'info, PlatformConfig>,
-    project: &Account<'info, Project>,
-    amount: u64,
+    project: &mut Account<'info, Project>,
+    fees: FeeBreakdown,
 ) -> Result<()> {
-    // Calculate platform fee
-    let platform_fee = amount
-        .checked_mul(platform_config.platform_fee_basis_points as u64)
-        .and_then(|v| v.checked_div(10000))
-        .ok_or(MarketplaceError::CalculationOverflow)?;
+    let FeeBreakdown { platform_fee, mut project_fee, mut royalty_fee, .. } = fees;
+    if platform_fee == 0 && project_fee == 0 && royalty_fee == 0 {
+        return Ok(());
+    }
     
-    // Calculate project fee
-    let project_fee = amount
-        .checked_mul(((10000 - platform_config.platform_fee_basis_points - project.royalty_basis_points) / 2) as u64)
-        .and_then(|v| v.checked_div(10000))
-        .ok_or(MarketplaceError::CalculationOverflow)?;
+    // The project's buyback share of its fee is burned from the pool instead of paid out
+    let buyback_burn = buyback_burn_amount(project_fee, project.buyback_burn_bps)?;
+    project_fee -= buyback_burn;
     
-    // Calculate royalty fee
-    let royalty_fee = amount
-        .checked_mul(project.royalty_basis_points as u64)
-        .and_then(|v| v.checked_div(10000))
-        .ok_or(MarketplaceError::CalculationOverflow)?;
+    // Split the royalty fee across the configured recipients. Vault accrual takes
+    // precedence, since accrued royalties are claimed by the single royalty wallet
+    let royalty_split = royalty_split
+        .filter(|(royalty_config, _)| !royalty_config.recipients.is_empty())
+        .filter(|_| project.royalty_vault.is_none());
+    
+    // Without its own royalty wallet the royalty defaults to the project treasury, so
+    // it is paid as part of the project fee rather than as a second transfer there
+    let royalty_to_treasury = royalty_split.is_none()
+        && matches!(royalty_wallet, Some(wallet) if wallet.key() == project_treasury.key());
+    if royalty_to_treasury {
+        project_fee = project_fee
+            .checked_add(royalty_fee)
+            .ok_or(MarketplaceError::CalculationOverflow)?;
+        royalty_fee = 0;
+    }
+    
+    let min_fee_transfer = platform_config.min_fee_transfer;
+    let platform_fee = settle_pending_fee(&mut liquidity_pool.pending_platform_fees, platform_fee, min_fee_transfer)?;
+    let project_fee = settle_pending_fee(&mut liquidity_pool.pending_project_fees, project_fee, min_fee_transfer)?;
     
     // Transfer platform fee
     if platform_fee > 0 {
@@ -43,7 +848,7 @@ pub fn distribute_fees<'info>(
                 token_program.to_account_info(),
                 Transfer {
                     from: lp_token_account.to_account_info(),
-                    to: platform_treasury.clone(),
+                    to: platform_treasury.to_account_info(),
                     authority: liquidity_pool.to_account_info(),
                 },
                 &[&[
@@ -76,14 +881,70 @@ pub fn distribute_fees<'info>(
         )?;
     }
     
+    // Burn the buyback share out of the pool's holdings, reducing supply
+    if buyback_burn > 0 {
+        token::burn(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                Burn {
+                    mint: token_mint.to_account_info(),
+                    from: lp_token_account.to_account_info(),
+                    authority: liquidity_pool.to_account_info(),
+                },
+                &[&[
+                    b"liquidity_pool",
+                    liquidity_pool.project.as_ref(),
+                    &[liquidity_pool.bump],
+                ]],
+            ),
+            buyback_burn,
+        )?;
+    }
+    
+    // Pay each royalty recipient its share
+    if let Some((royalty_config, recipient_accounts)) = royalty_split {
+        if recipient_accounts.len() != royalty_config.recipients.len() {
+            return Err(MarketplaceError::RoyaltyRecipientMismatch.into());
+        }
+        
+        let amounts = split_royalty_fee(royalty_fee, &royalty_config.recipients)?;
+        for ((recipient, account), amount) in royalty_config.recipients.iter().zip(recipient_accounts).zip(amounts) {
+            if account.key() != recipient.wallet {
+                return Err(MarketplaceError::RoyaltyRecipientMismatch.into());
+            }
+            if amount == 0 {
+                continue;
+            }
+            
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    Transfer {
+                        from: lp_token_account.to_account_info(),
+                        to: account.clone(),
+                        authority: liquidity_pool.to_account_info(),
+                    },
+                    &[&[
+                        b"liquidity_pool",
+                        liquidity_pool.project.as_ref(),
+                        &[liquidity_pool.bump],
+                    ]],
+                ),
+                amount,
+            )?;
+        }
+        
+        return Ok(());
+    }
+    
     // Transfer royalty fee
-    if royalty_fee > 0 && royalty_wallet.is_some() {
+    if let Some(royalty_wallet) = royalty_wallet.filter(|_| royalty_fee > 0) {
         token::transfer(
             CpiContext::new_with_signer(
                 token_program.to_account_info(),
                 Transfer {
                     from: lp_token_account.to_account_info(),
-                    to: royalty_wallet.unwrap().clone(),
+                    to: royalty_wallet.clone(),
                     authority: liquidity_pool.to_account_info(),
                 },
                 &[&[
@@ -94,7 +955,113 @@ pub fn distribute_fees<'info>(
             ),
             royalty_fee,
         )?;
+        
+        // Royalties routed to the vault are tracked until claimed
+        if project.royalty_vault == Some(royalty_wallet.key()) {
+            project.accrued_royalties = project.accrued_royalties
+                .checked_add(royalty_fee)
+                .ok_or(MarketplaceError::CalculationOverflow)?;
+        }
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const BPS_STEPS: [u16; 8] = [0, 1, 99, 250, 3333, 5000, 9999, 10000];
+    
+    // The fee a share of the amount should come to, computed independently of the helper
+    fn expected_fee(amount: u64, bps: u16) -> u64 {
+        (amount as u128 * bps as u128 / 10000) as u64
+    }
+    
+    fn assert_breakdown(amount: u64, platform_bps: u16, project_bps: u16, royalty_bps: u16) {
+        let breakdown = compute_fee_breakdown(amount, platform_bps, project_bps, royalty_bps).unwrap();
+        assert_eq!(breakdown.platform_fee, expected_fee(amount, platform_bps));
+        assert_eq!(breakdown.project_fee, expected_fee(amount, project_bps));
+        assert_eq!(breakdown.royalty_fee, expected_fee(amount, royalty_bps));
+        
+        let total = breakdown.platform_fee as u128
+            + breakdown.project_fee as u128
+            + breakdown.royalty_fee as u128
+            + breakdown.net_amount as u128;
+        assert_eq!(total, amount as u128, "parts of {} at {}/{}/{} bps", amount, platform_bps, project_bps, royalty_bps);
+        
+        // Rounding dust goes to the net amount: at most one unit per fee
+        let remainder_bps = 10000 - (platform_bps + project_bps + royalty_bps);
+        let exact_net = expected_fee(amount, remainder_bps);
+        assert!(breakdown.net_amount >= exact_net && breakdown.net_amount - exact_net <= 3);
+    }
+    
+    #[test]
+    fn zero_bps_leaves_the_whole_amount() {
+        for amount in [0, 1, 10_000, u64::MAX] {
+            let breakdown = compute_fee_breakdown(amount, 0, 0, 0).unwrap();
+            assert_eq!(breakdown.platform_fee + breakdown.project_fee + breakdown.royalty_fee, 0);
+            assert_eq!(breakdown.net_amount, amount);
+        }
+    }
+    
+    #[test]
+    fn a_full_share_takes_the_whole_amount() {
+        for amount in [0, 1, 9_999, u64::MAX] {
+            for (platform_bps, project_bps, royalty_bps) in [(10000, 0, 0), (0, 10000, 0), (0, 0, 10000)] {
+                assert_breakdown(amount, platform_bps, project_bps, royalty_bps);
+                let breakdown = compute_fee_breakdown(amount, platform_bps, project_bps, royalty_bps).unwrap();
+                assert_eq!(breakdown.net_amount, 0);
+            }
+        }
+    }
+    
+    #[test]
+    fn shares_over_the_whole_amount_are_rejected() {
+        for (platform_bps, project_bps, royalty_bps) in [
+            (10000, 1, 0),
+            (0, 10000, 1),
+            (1, 0, 10000),
+            (5000, 5000, 1),
+            (3334, 3333, 3334),
+            (u16::MAX, 0, 0),
+            (u16::MAX, u16::MAX, u16::MAX),
+        ] {
+            for amount in [0, 1, u64::MAX] {
+                assert!(compute_fee_breakdown(amount, platform_bps, project_bps, royalty_bps).is_err());
+            }
+        }
+    }
+    
+    #[test]
+    fn max_amounts_split_without_overflow() {
+        for platform_bps in BPS_STEPS {
+            for project_bps in BPS_STEPS {
+                for royalty_bps in BPS_STEPS {
+                    if platform_bps as u32 + project_bps as u32 + royalty_bps as u32 <= 10000 {
+                        assert_breakdown(u64::MAX, platform_bps, project_bps, royalty_bps);
+                        assert_breakdown(u64::MAX - 1, platform_bps, project_bps, royalty_bps);
+                    }
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn parts_sum_to_the_amount_across_a_sweep() {
+        let amounts = (0..=2_000u64)
+            .chain((0..64).map(|shift| 1u64 << shift))
+            .chain([999_999_999, 1_000_000_000, 1_000_000_001, u64::MAX / 10000, u64::MAX / 3]);
+        for amount in amounts {
+            for platform_bps in BPS_STEPS {
+                for project_bps in BPS_STEPS {
+                    for royalty_bps in BPS_STEPS {
+                        if platform_bps as u32 + project_bps as u32 + royalty_bps as u32 <= 10000 {
+                            assert_breakdown(amount, platform_bps, project_bps, royalty_bps);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,808 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use solana_program::clock::Clock;
+
+use crate::{
+    state::{Project, Collection, BurnDestination, ChildCooldownPolicy, FusionConfig, FusionCooldown, LiquidityPool, NftData, RandomnessSource, TraitType},
+    errors::MarketplaceError,
+    events::{FusionFailed, NftFused},
+    modules::{
+        cooldown::{clock_now, get_remaining_cooldown},
+        oracle::get_usd_value_for_tokens,
+        randomness::committed_randomness,
+        rarity::calculate_fused_nft_rarity,
+        redeem::{safe_close_nft_data, NFT_BASE_TOKEN_VALUE},
+        stats::record_collection_activity,
+    },
+};
+
+// Hard cap on parents per fusion, independent of FusionConfig, so a single
+// fusion always fits within the transaction compute budget
+pub const MAX_FUSION_INPUTS: usize = 5;
+
+// Most levels a fusion config's level curve can define, used to size fusion config accounts
+pub const MAX_FUSION_LEVELS: usize = 10;
+
+// Largest level multiplier a curve may use, in basis points (5x)
+pub const MAX_LEVEL_MULTIPLIER_BPS: u16 = 50000;
+
+// Most children a single fusion can mint, so creating their data accounts fits the compute budget
+pub const MAX_FUSION_OUTPUTS: usize = 4;
+
+#[derive(Accounts)]
+pub struct InitializeFusionConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub collection: Account<'info, Collection>,
+
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<FusionConfig>() + MAX_FUSION_LEVELS * 2,
+        seeds = [b"fusion_config", collection.key().as_ref()],
+        bump,
+    )]
+    pub fusion_config: Account<'info, FusionConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFusionConfig<'info> {
+    pub authority: Signer<'info>,
+
+    pub collection: Account<'info, Collection>,
+
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+
+    #[account(
+        mut,
+        seeds = [b"fusion_config", collection.key().as_ref()],
+        bump = fusion_config.bump,
+    )]
+    pub fusion_config: Account<'info, FusionConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(collection_id: String, metadata_uri: String, randomness_request_id: u64)]
+pub struct FuseNfts<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [b"collection", collection_id.as_bytes()],
+        bump = collection.bump,
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// Stats for this collection, which may not be tracked
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"project", project.project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.is_active @ MarketplaceError::ProjectNotFound,
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+    )]
+    pub project: Account<'info, Project>,
+
+    #[account(
+        seeds = [b"fusion_config", collection.key().as_ref()],
+        bump = fusion_config.bump,
+        constraint = fusion_config.is_active @ MarketplaceError::FusionInactive,
+    )]
+    pub fusion_config: Account<'info, FusionConfig>,
+
+    #[account(
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+
+    /// The mint of the fused child NFT
+    #[account(mut)]
+    pub child_mint: Signer<'info>,
+
+    /// The fused child NFT data, with room for its parent list
+    #[account(
+        init,
+        payer = user,
+        space = 8 + std::mem::size_of::<NftData>() + metadata_uri.len() + MAX_FUSION_INPUTS * 32,
+        seeds = [b"nft_data", child_mint.key().as_ref()],
+        bump,
+    )]
+    pub child_nft_data: Account<'info, NftData>,
+
+    /// The user's last fusion time in this collection
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + std::mem::size_of::<FusionCooldown>(),
+        seeds = [b"fusion_cooldown", collection.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub fusion_cooldown: Account<'info, FusionCooldown>,
+
+    /// The user's randomness request, consumed when the fusion config takes randomness from an oracle
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"randomness", user.key().as_ref(), randomness_request_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub randomness_request: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = token_mint.key() == liquidity_pool.token_mint @ MarketplaceError::InvalidTokenMint,
+    )]
+    pub token_mint: Account<'info, Mint>,
+
+    /// The user's token account the fusion burn amount is paid from
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = user_token_account.mint == token_mint.key() @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.key() == liquidity_pool.lp_token_account @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub lp_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = project.project_treasury,
+    )]
+    /// CHECK: This is the project treasury account
+    pub project_treasury: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    // Parents are passed via remaining_accounts as (NftData, user's NFT token account) pairs,
+    // followed by a (child mint, child NftData) pair for each child after the first
+}
+
+// Create the fusion configuration for a collection
+pub fn initialize_fusion_config(
+    ctx: Context<InitializeFusionConfig>,
+    min_nfts_required: u8,
+    max_nfts_allowed: u8,
+    base_success_rate: u8,
+    token_burn_percent: u8,
+    cooldown_period: i64,
+    child_cooldown_policy: ChildCooldownPolicy,
+) -> Result<()> {
+    if max_nfts_allowed as usize > MAX_FUSION_INPUTS {
+        return Err(MarketplaceError::FusionInputLimitExceeded.into());
+    }
+
+    if min_nfts_required < 2
+        || min_nfts_required > max_nfts_allowed
+        || base_success_rate > 100
+        || token_burn_percent > 100
+        || cooldown_period < 0
+    {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.project = ctx.accounts.project.key();
+    fusion_config.collection = ctx.accounts.collection.key();
+    fusion_config.min_nfts_required = min_nfts_required;
+    fusion_config.max_nfts_allowed = max_nfts_allowed;
+    fusion_config.base_success_rate = base_success_rate;
+    fusion_config.token_burn_percent = token_burn_percent;
+    fusion_config.cooldown_period = cooldown_period;
+    fusion_config.is_active = true;
+    fusion_config.child_cooldown_policy = child_cooldown_policy;
+    fusion_config.min_input_value_usd = None;
+    fusion_config.randomness_source = RandomnessSource::SlotHash;
+    fusion_config.burn_destination = BurnDestination::Burn;
+    fusion_config.min_fusion_interval = 0;
+    fusion_config.level_multipliers_bps = Vec::new();
+    fusion_config.output_count = 1;
+    fusion_config.bump = *ctx.bumps.get("fusion_config").unwrap();
+
+    msg!("Fusion config created for collection: {}", ctx.accounts.collection.collection_id);
+
+    Ok(())
+}
+
+// Pause or resume fusion for a collection without touching the rest of its config
+pub fn set_fusion_active(
+    ctx: Context<UpdateFusionConfig>,
+    is_active: bool,
+) -> Result<()> {
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.is_active = is_active;
+
+    msg!("Fusion active set to {} for collection: {}", is_active, ctx.accounts.collection.collection_id);
+
+    Ok(())
+}
+
+// Choose how fused NFTs get their cooldown, with the period used by the FixedPeriod policy
+pub fn set_child_cooldown_policy(
+    ctx: Context<UpdateFusionConfig>,
+    child_cooldown_policy: ChildCooldownPolicy,
+    cooldown_period: i64,
+) -> Result<()> {
+    if cooldown_period < 0 {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.child_cooldown_policy = child_cooldown_policy;
+    fusion_config.cooldown_period = cooldown_period;
+
+    msg!("Fused NFT cooldown policy set to {:?} ({}) for collection: {}", child_cooldown_policy, cooldown_period, ctx.accounts.collection.collection_id);
+
+    Ok(())
+}
+
+// Set the minimum combined USD value the parents of a fusion must carry
+pub fn set_fusion_min_input_value(
+    ctx: Context<UpdateFusionConfig>,
+    min_input_value_usd: Option<u64>,
+) -> Result<()> {
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.min_input_value_usd = min_input_value_usd;
+
+    msg!("Fusion minimum input value set to {:?} for collection: {}", min_input_value_usd, ctx.accounts.collection.collection_id);
+
+    Ok(())
+}
+
+// Choose where fusion randomness comes from
+pub fn set_fusion_randomness_source(
+    ctx: Context<UpdateFusionConfig>,
+    randomness_source: RandomnessSource,
+) -> Result<()> {
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.randomness_source = randomness_source;
+    
+    msg!("Fusion randomness source set to {:?} for collection: {}", randomness_source, ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Set the share of input value a fusion costs and where that amount goes
+pub fn set_fusion_burn(
+    ctx: Context<UpdateFusionConfig>,
+    token_burn_percent: u8,
+    burn_destination: BurnDestination,
+) -> Result<()> {
+    if token_burn_percent > 100 {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+    
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.token_burn_percent = token_burn_percent;
+    fusion_config.burn_destination = burn_destination;
+    
+    msg!("Fusion burn set to {}% ({:?}) for collection: {}", token_burn_percent, burn_destination, ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Set the minimum time a wallet must wait between fusions in the collection
+pub fn set_fusion_min_interval(
+    ctx: Context<UpdateFusionConfig>,
+    min_fusion_interval: i64,
+) -> Result<()> {
+    if min_fusion_interval < 0 {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+    
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.min_fusion_interval = min_fusion_interval;
+    
+    msg!("Fusion interval set to {}s for collection: {}", min_fusion_interval, ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Set the fusion level curve: the rarity multiplier for levels 1, 2, ... in basis points. A curve
+// caps fused NFTs at its last level; an empty one restores the default, uncapped curve
+pub fn set_fusion_level_multipliers(
+    ctx: Context<UpdateFusionConfig>,
+    level_multipliers_bps: Vec<u16>,
+) -> Result<()> {
+    validate_level_multipliers(&level_multipliers_bps)?;
+    
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.level_multipliers_bps = level_multipliers_bps;
+    
+    msg!("Fusion level curve set with {} levels for collection: {}", fusion_config.level_multipliers_bps.len(), ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Set how many children each fusion mints. They split the fused rarity between them
+pub fn set_fusion_output_count(
+    ctx: Context<UpdateFusionConfig>,
+    output_count: u8,
+) -> Result<()> {
+    if output_count == 0 || output_count as usize > MAX_FUSION_OUTPUTS {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+    
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.output_count = output_count;
+    
+    msg!("Fusion output count set to {} for collection: {}", output_count, ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Set the chance (0-100) that a fusion succeeds. A failed fusion still costs the burn amount
+// but keeps its parents and mints nothing
+pub fn set_fusion_success_rate(
+    ctx: Context<UpdateFusionConfig>,
+    base_success_rate: u8,
+) -> Result<()> {
+    if base_success_rate > 100 {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+    
+    let fusion_config = &mut ctx.accounts.fusion_config;
+    fusion_config.base_success_rate = base_success_rate;
+    
+    msg!("Fusion success rate set to {}% for collection: {}", base_success_rate, ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Validate a fusion level curve: bounded in length, at least 1x, never falling, and capped
+pub fn validate_level_multipliers(level_multipliers_bps: &[u16]) -> Result<()> {
+    if level_multipliers_bps.len() > MAX_FUSION_LEVELS {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+    
+    if level_multipliers_bps.iter().any(|&bps| !(10000..=MAX_LEVEL_MULTIPLIER_BPS).contains(&bps))
+        || level_multipliers_bps.windows(2).any(|pair| pair[0] > pair[1])
+    {
+        return Err(MarketplaceError::InvalidFusionConfig.into());
+    }
+    
+    Ok(())
+}
+
+// Value of an NFT in tokens: the base redemption value plus its rarity score as a percentage bonus
+pub fn nft_token_value(rarity_score: u16) -> Result<u64> {
+    NFT_BASE_TOKEN_VALUE
+        .checked_mul(100 + rarity_score as u64)
+        .and_then(|v| v.checked_div(100))
+        .ok_or_else(|| MarketplaceError::CalculationOverflow.into())
+}
+
+// Load a parent NftData account passed through remaining_accounts
+pub fn load_parent_nft_data(account: &AccountInfo) -> Result<NftData> {
+    if account.owner != &crate::ID {
+        return Err(MarketplaceError::InvalidNftForFusion.into());
+    }
+
+    let data = account.try_borrow_data()?;
+    NftData::try_deserialize(&mut &data[..])
+        .map_err(|_| MarketplaceError::InvalidNftForFusion.into())
+}
+
+// Verify the signer holds an NFT right now, from its token account rather than NftData.owner
+pub fn verify_nft_holder(
+    token_account: &AccountInfo,
+    parent: &NftData,
+    user: &Pubkey,
+) -> Result<()> {
+    let token_account = Account::<TokenAccount>::try_from(token_account)
+        .map_err(|_| MarketplaceError::InvalidTokenAccount)?;
+    
+    if token_account.mint != parent.mint {
+        return Err(MarketplaceError::InvalidTokenAccount.into());
+    }
+    
+    if token_account.owner != *user || token_account.amount != 1 {
+        return Err(MarketplaceError::NotNftOwner.into());
+    }
+    
+    Ok(())
+}
+
+// Close a consumed parent's NftData and refund its rent to the holder, as redemption does.
+// The parent records the child it went into before it's closed, so even revived it can't be
+// fused again. Its token stays with the holder. An NFT still referenced (e.g. backing an
+// active escrow) can't be consumed
+pub fn close_parent_nft_data<'info>(
+    account: &AccountInfo<'info>,
+    holder: &AccountInfo<'info>,
+    child_mint: Pubkey,
+) -> Result<()> {
+    if !account.is_writable {
+        return Err(MarketplaceError::InvalidNftForFusion.into());
+    }
+
+    let mut parent = Account::<NftData>::try_from(account)?;
+    parent.fused_into = Some(child_mint);
+    parent.exit(&crate::ID)?;
+    safe_close_nft_data(&parent, holder)
+}
+
+// Rarity of the index-th of output_count children splitting a fused rarity score. Any remainder
+// goes to the earliest children, so the children together carry the whole score
+pub fn split_fused_rarity(rarity_score: u16, output_count: usize, index: usize) -> u16 {
+    let output_count = output_count.max(1) as u16;
+    rarity_score / output_count + u16::from((index as u16) < rarity_score % output_count)
+}
+
+// Whether a fusion succeeds: a roll of 0-99 taken from the seed must fall below the config's
+// base success rate. The outcome depends only on the seed, so the same inputs always roll the same
+pub fn roll_fusion_outcome(config: &FusionConfig, seed: &[u8; 32]) -> bool {
+    let mut roll_bytes = [0u8; 8];
+    roll_bytes.copy_from_slice(&seed[..8]);
+    u64::from_le_bytes(roll_bytes) % 100 < config.base_success_rate as u64
+}
+
+// Check a (child mint, child NftData) pair for a child after the first, returning the NftData
+// bump. The child mint must sign, as the first child's does
+fn check_child_accounts(child_mint: &AccountInfo, child_nft_data: &AccountInfo) -> Result<u8> {
+    if !child_mint.is_signer || !child_nft_data.is_writable {
+        return Err(MarketplaceError::FusionOutputMismatch.into());
+    }
+    
+    let (address, bump) = Pubkey::find_program_address(&[b"nft_data", child_mint.key.as_ref()], &crate::ID);
+    if address != *child_nft_data.key {
+        return Err(MarketplaceError::FusionOutputMismatch.into());
+    }
+    
+    Ok(bump)
+}
+
+// Create the NftData account of a child after the first at its PDA, paid for by the user
+fn create_child_nft_data<'info>(
+    user: &AccountInfo<'info>,
+    child_mint: &AccountInfo<'info>,
+    child_nft_data: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    child: NftData,
+) -> Result<()> {
+    let bump = child.bump;
+    let space = 8 + std::mem::size_of::<NftData>() + child.metadata_uri.len() + MAX_FUSION_INPUTS * 32;
+    system_program::create_account(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::CreateAccount {
+                from: user.clone(),
+                to: child_nft_data.clone(),
+            },
+            &[&[b"nft_data", child_mint.key.as_ref(), &[bump]]],
+        ),
+        Rent::get()?.minimum_balance(space),
+        space as u64,
+        &crate::ID,
+    )?;
+    
+    let mut data = child_nft_data.try_borrow_mut_data()?;
+    child.try_serialize(&mut &mut data[..])
+}
+
+// Fuse several parent NFTs from the same collection into the collection's configured number of
+// new child NFTs. A failed success roll is not an error: returning one would revert the burn,
+// so the instruction succeeds, emits FusionFailed and mints nothing. Clients must check the
+// transaction's events (FusionFailed or NftFused) rather than its status to tell the outcomes apart
+pub fn fuse_nfts<'info>(
+    ctx: Context<'_, '_, '_, 'info, FuseNfts<'info>>,
+    _collection_id: String,
+    metadata_uri: String,
+    _randomness_request_id: u64,
+) -> Result<()> {
+    if metadata_uri.is_empty() {
+        return Err(MarketplaceError::InvalidMetadataUri.into());
+    }
+
+    let fusion_config = &ctx.accounts.fusion_config;
+    let output_count = fusion_config.output_count.max(1) as usize;
+    
+    // Children after the first trail the parents, one (mint, NftData) pair each
+    let account_pairs = ctx.remaining_accounts.chunks_exact(2);
+    if !account_pairs.remainder().is_empty() {
+        return Err(MarketplaceError::InvalidNftForFusion.into());
+    }
+    let parent_count = account_pairs
+        .len()
+        .checked_sub(output_count - 1)
+        .ok_or(MarketplaceError::FusionOutputMismatch)?;
+    let (parent_accounts, extra_child_accounts) = ctx.remaining_accounts.split_at(parent_count * 2);
+    let parent_pairs = parent_accounts.chunks_exact(2);
+    
+    // Check the child accounts first, so a fusion missing one isn't mistaken for one short a parent
+    let mut child_mints = vec![ctx.accounts.child_mint.key()];
+    let mut extra_child_bumps = Vec::with_capacity(output_count - 1);
+    for pair in extra_child_accounts.chunks_exact(2) {
+        if child_mints.contains(pair[0].key) {
+            return Err(MarketplaceError::FusionOutputMismatch.into());
+        }
+        child_mints.push(pair[0].key());
+        extra_child_bumps.push(check_child_accounts(&pair[0], &pair[1])?);
+    }
+
+    // Bound the input count before touching any parent so oversized fusions fail cleanly
+    if parent_count > MAX_FUSION_INPUTS
+        || parent_count > fusion_config.max_nfts_allowed as usize
+    {
+        return Err(MarketplaceError::FusionInputLimitExceeded.into());
+    }
+
+    if parent_count < fusion_config.min_nfts_required as usize {
+        return Err(MarketplaceError::NotEnoughNftsForFusion.into());
+    }
+
+    // Throttle each wallet to one fusion per interval, independent of NFT cooldowns
+    let current_time = Clock::get()?.unix_timestamp;
+    let fusion_cooldown = &mut ctx.accounts.fusion_cooldown;
+    if fusion_cooldown.last_fusion_at > 0
+        && current_time.saturating_sub(fusion_cooldown.last_fusion_at) < fusion_config.min_fusion_interval
+    {
+        return Err(MarketplaceError::FusionRateLimited.into());
+    }
+    fusion_cooldown.collection = ctx.accounts.collection.key();
+    fusion_cooldown.user = ctx.accounts.user.key();
+    fusion_cooldown.last_fusion_at = current_time;
+    fusion_cooldown.bump = *ctx.bumps.get("fusion_cooldown").unwrap();
+
+    let cooldown_clock = ctx.accounts.collection.cooldown_clock;
+    let mut parent_mints = Vec::with_capacity(parent_count);
+    let mut parent_scores = Vec::with_capacity(parent_count);
+    let mut max_parent_level: u8 = 0;
+    let mut max_parent_cooldown_end: Option<i64> = None;
+    let mut combined_token_value: u64 = 0;
+    let mut consumed_parents = Vec::with_capacity(parent_count);
+
+    for pair in parent_pairs {
+        let (parent_account, parent_token_account) = (&pair[0], &pair[1]);
+        let parent = load_parent_nft_data(parent_account)?;
+
+        // Each parent counts once. Consumed parents are closed, and any brought back still
+        // carry the child they went into
+        if parent_mints.contains(&parent.mint) {
+            return Err(MarketplaceError::DuplicateFusionParent.into());
+        }
+        if parent.fused_into.is_some() {
+            return Err(MarketplaceError::NftAlreadyFused.into());
+        }
+
+        // The token holder is authoritative, even if NftData.owner is stale
+        verify_nft_holder(parent_token_account, &parent, &ctx.accounts.user.key())?;
+
+        if parent.collection != ctx.accounts.collection.key() {
+            return Err(MarketplaceError::MixedCollections.into());
+        }
+
+        parent_mints.push(parent.mint);
+        parent_scores.push(parent.rarity_score);
+        combined_token_value = combined_token_value
+            .checked_add(nft_token_value(parent.rarity_score)?)
+            .ok_or(MarketplaceError::CalculationOverflow)?;
+        max_parent_level = max_parent_level.max(parent.fusion_level);
+        if parent.cooldown_clock == cooldown_clock {
+            max_parent_cooldown_end = max_parent_cooldown_end.max(parent.cooldown_end_timestamp);
+        } else if fusion_config.child_cooldown_policy == ChildCooldownPolicy::MaxParent
+            && get_remaining_cooldown(&parent)?.is_some()
+        {
+            // A cooldown on the other clock can't be carried over to the child
+            return Err(MarketplaceError::NftInCooldown.into());
+        }
+
+        // Parents are only consumed once the fusion succeeds, but one that couldn't be
+        // consumed must not cost the user a roll
+        if !parent_account.is_writable {
+            return Err(MarketplaceError::InvalidNftForFusion.into());
+        }
+        if parent.reference_count > 0 {
+            return Err(MarketplaceError::NftStillReferenced.into());
+        }
+        consumed_parents.push(parent_account);
+    }
+
+    // Worthless parents must not fuse into a valuable child for free
+    if let Some(min_input_value_usd) = fusion_config.min_input_value_usd {
+        let combined_value_usd = get_usd_value_for_tokens(&ctx.accounts.liquidity_pool, combined_token_value)?;
+        if combined_value_usd < min_input_value_usd {
+            return Err(MarketplaceError::TokenPriceTooLow.into());
+        }
+    }
+
+    // The user pays token_burn_percent of the parents' value, which is burned or routed
+    // to the configured destination
+    let burn_amount = combined_token_value
+        .checked_mul(fusion_config.token_burn_percent as u64)
+        .and_then(|v| v.checked_div(100))
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    if burn_amount > 0 {
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let from = ctx.accounts.user_token_account.to_account_info();
+        let authority = ctx.accounts.user.to_account_info();
+        
+        match fusion_config.burn_destination {
+            BurnDestination::Burn => token::burn(
+                CpiContext::new(
+                    token_program,
+                    Burn {
+                        mint: ctx.accounts.token_mint.to_account_info(),
+                        from,
+                        authority,
+                    },
+                ),
+                burn_amount,
+            )?,
+            BurnDestination::Treasury => token::transfer(
+                CpiContext::new(
+                    token_program,
+                    Transfer {
+                        from,
+                        to: ctx.accounts.project_treasury.to_account_info(),
+                        authority,
+                    },
+                ),
+                burn_amount,
+            )?,
+            BurnDestination::BuybackLp => token::transfer(
+                CpiContext::new(
+                    token_program,
+                    Transfer {
+                        from,
+                        to: ctx.accounts.lp_token_account.to_account_info(),
+                        authority,
+                    },
+                ),
+                burn_amount,
+            )?,
+        }
+        
+        msg!("Fusion burn of {} tokens sent to {:?}", burn_amount, fusion_config.burn_destination);
+    }
+
+    let fusion_level = max_parent_level
+        .checked_add(1)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
+    // A custom level curve ends at the highest level the collection allows
+    let level_multipliers_bps = &fusion_config.level_multipliers_bps;
+    if !level_multipliers_bps.is_empty() && fusion_level as usize > level_multipliers_bps.len() {
+        return Err(MarketplaceError::MaxFusionLevelReached.into());
+    }
+
+    // Fused NFTs carry no traits of their own yet, so rarity comes from the parents and level
+    let no_traits: &[Box<TraitType>] = &[];
+    let rarity_score = calculate_fused_nft_rarity(no_traits, &[], &parent_scores, fusion_level, level_multipliers_bps);
+
+    // Roll for success from the config's randomness source. An oracle source takes the user's
+    // fulfilled request; the slot hash fallback mixes the parents into the slot hash. The child
+    // mint is the user's free choice, so it stays out of the seed rather than let them grind it
+    let roll_entropy: Vec<u8> = parent_mints.iter().flat_map(|mint| mint.to_bytes()).collect();
+    let roll_seed = committed_randomness(
+        fusion_config.randomness_source,
+        &ctx.accounts.randomness_request,
+        &ctx.accounts.user.key(),
+        &ctx.accounts.collection.key(),
+        &roll_entropy,
+    )?;
+    
+    // A failed fusion keeps the burn but leaves the parents, and anything escrowed against them,
+    // untouched. The child's data account is closed again and its rent refunded. It was never
+    // filled in, so the empty record Anchor writes back on exit has no owner or mint
+    if !roll_fusion_outcome(fusion_config, &roll_seed) {
+        safe_close_nft_data(&ctx.accounts.child_nft_data, &ctx.accounts.user.to_account_info())?;
+        
+        emit!(FusionFailed {
+            collection: ctx.accounts.collection.key(),
+            user: ctx.accounts.user.key(),
+            parent_nfts: parent_mints,
+            burn_amount,
+            base_success_rate: fusion_config.base_success_rate,
+            seed: roll_seed,
+            timestamp: current_time,
+        });
+        
+        ctx.accounts.project.last_activity_timestamp = current_time;
+        
+        msg!("Fusion failed; {} tokens burned and parents kept", burn_amount);
+        
+        return Ok(());
+    }
+    
+    // The parents are consumed: their data accounts are closed and the rent goes to the holder
+    for parent_account in consumed_parents {
+        close_parent_nft_data(parent_account, &ctx.accounts.user.to_account_info(), ctx.accounts.child_mint.key())?;
+    }
+
+    // The child's cooldown follows the config's policy, on the collection's clock
+    let cooldown_now = clock_now(cooldown_clock)?;
+    let cooldown_end_timestamp = match fusion_config.child_cooldown_policy {
+        ChildCooldownPolicy::None => None,
+        ChildCooldownPolicy::MaxParent => {
+            max_parent_cooldown_end.filter(|&cooldown_end| cooldown_end > cooldown_now)
+        }
+        ChildCooldownPolicy::FixedPeriod if fusion_config.cooldown_period > 0 => Some(
+            cooldown_now
+                .checked_add(fusion_config.cooldown_period)
+                .ok_or(MarketplaceError::CalculationOverflow)?,
+        ),
+        ChildCooldownPolicy::FixedPeriod => None,
+    };
+
+    // Every child shares the parents, level and cooldown, and takes its share of the rarity
+    let new_child = |mint: Pubkey, index: usize, bump: u8| NftData {
+        owner: ctx.accounts.user.key(),
+        collection: ctx.accounts.collection.key(),
+        mint,
+        metadata_uri: metadata_uri.clone(),
+        minted_at: current_time,
+        cooldown_end_timestamp,
+        cooldown_clock,
+        discount_percent: None,
+        fusion_level,
+        parent_nfts: Some(parent_mints.clone()),
+        rarity_score: split_fused_rarity(rarity_score, output_count, index),
+        reference_count: 0,
+        fused_into: None,
+        bump,
+    };
+    
+    for (i, (pair, &bump)) in extra_child_accounts.chunks_exact(2).zip(&extra_child_bumps).enumerate() {
+        create_child_nft_data(
+            &ctx.accounts.user.to_account_info(),
+            &pair[0],
+            &pair[1],
+            &ctx.accounts.system_program.to_account_info(),
+            new_child(pair[0].key(), i + 1, bump),
+        )?;
+    }
+    
+    let first_child = new_child(ctx.accounts.child_mint.key(), 0, *ctx.bumps.get("child_nft_data").unwrap());
+    ctx.accounts.child_nft_data.set_inner(first_child);
+
+    record_collection_activity(
+        &ctx.accounts.collection_stats,
+        &ctx.accounts.collection.key(),
+        |stats| stats.fusions = stats.fusions.saturating_add(1),
+    )?;
+
+    // Update project's last activity timestamp
+    let project = &mut ctx.accounts.project;
+    project.last_activity_timestamp = current_time;
+
+    emit!(NftFused {
+        owner: ctx.accounts.user.key(),
+        collection: ctx.accounts.collection.key(),
+        child_mints: child_mints.clone(),
+        parent_nfts: parent_mints,
+        fusion_level,
+        rarity_score,
+        timestamp: current_time,
+    });
+
+    msg!("NFTs fused into {:?} (level {}, rarity {})", child_mints, fusion_level, rarity_score);
+
+    Ok(())
+}
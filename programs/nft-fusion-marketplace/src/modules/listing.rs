@@ -0,0 +1,601 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+use solana_program::clock::Clock;
+
+use crate::{
+    state::{PlatformConfig, Project, Collection, LiquidityPool, NftData, NftListing, RoyaltyConfig, UserTier},
+    errors::MarketplaceError,
+    events::ListingSold,
+    modules::{
+        cooldown::clock_now,
+        fees::{calculate_listing_fees, distribute_fees, get_fee_discount_bps, is_platform_fee_exempt, FeeBreakdown},
+        mint::record_nft_transfer,
+        oracle::{check_oracle_status, record_trade_price, USD_PRICE_DECIMALS},
+        stats::{record_collection_activity, record_sale},
+        swap::apply_swap_discount,
+    },
+};
+
+#[derive(Accounts)]
+pub struct CreateListing<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        constraint = collection.key() == nft_data.collection @ MarketplaceError::CollectionNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+    
+    pub nft_mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"nft_data", nft_mint.key().as_ref()],
+        bump = nft_data.bump,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    /// The listing, reused once an earlier listing of the NFT has been sold or cancelled
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + std::mem::size_of::<NftListing>(),
+        seeds = [b"listing", nft_mint.key().as_ref()],
+        bump,
+        constraint = !listing.is_active @ MarketplaceError::ListingAlreadyExists,
+    )]
+    pub listing: Account<'info, NftListing>,
+    
+    /// Holds the listed NFT until it is sold or the listing is cancelled
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [b"listing_vault", nft_mint.key().as_ref()],
+        bump,
+        token::mint = nft_mint,
+        token::authority = listing,
+    )]
+    pub listing_vault: Account<'info, TokenAccount>,
+    
+    // The owner's token account, proving they hold the NFT right now
+    #[account(
+        mut,
+        constraint = owner_nft_account.mint == nft_mint.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = owner_nft_account.owner == owner.key() @ MarketplaceError::NotNftOwner,
+        constraint = owner_nft_account.amount == 1 @ MarketplaceError::NotNftOwner,
+    )]
+    pub owner_nft_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelListing<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"listing", listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.owner == owner.key() @ MarketplaceError::UnauthorizedListingOperation,
+        constraint = listing.is_active @ MarketplaceError::ListingNotActive,
+    )]
+    pub listing: Account<'info, NftListing>,
+    
+    #[account(
+        mut,
+        seeds = [b"nft_data", listing.nft_mint.as_ref()],
+        bump = nft_data.bump,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    #[account(
+        mut,
+        seeds = [b"listing_vault", listing.nft_mint.as_ref()],
+        bump,
+    )]
+    pub listing_vault: Account<'info, TokenAccount>,
+    
+    // The owner's token account the NFT is returned to
+    #[account(
+        mut,
+        constraint = owner_nft_account.mint == listing.nft_mint @ MarketplaceError::InvalidTokenAccount,
+        constraint = owner_nft_account.owner == owner.key() @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub owner_nft_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct BuyListing<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+    )]
+    pub platform_config: Box<Account<'info, PlatformConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"listing", listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.is_active @ MarketplaceError::ListingNotActive,
+    )]
+    pub listing: Box<Account<'info, NftListing>>,
+    
+    #[account(
+        constraint = collection.key() == listing.collection @ MarketplaceError::CollectionNotFound,
+    )]
+    pub collection: Box<Account<'info, Collection>>,
+    
+    /// Stats for this collection, which may not be tracked
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project.project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.is_active @ MarketplaceError::ProjectNotFound,
+        constraint = collection.project == project.key() @ MarketplaceError::CollectionProjectMismatch,
+    )]
+    pub project: Box<Account<'info, Project>>,
+    
+    #[account(
+        mut,
+        constraint = liquidity_pool.project == project.key() @ MarketplaceError::PoolProjectMismatch,
+    )]
+    pub liquidity_pool: Box<Account<'info, LiquidityPool>>,
+    
+    #[account(
+        mut,
+        seeds = [b"nft_data", listing.nft_mint.as_ref()],
+        bump = nft_data.bump,
+    )]
+    pub nft_data: Box<Account<'info, NftData>>,
+    
+    #[account(
+        mut,
+        seeds = [b"listing_vault", listing.nft_mint.as_ref()],
+        bump,
+    )]
+    pub listing_vault: Box<Account<'info, TokenAccount>>,
+    
+    // The buyer's token account the NFT is delivered to
+    #[account(
+        mut,
+        constraint = buyer_nft_account.mint == listing.nft_mint @ MarketplaceError::InvalidTokenAccount,
+        constraint = buyer_nft_account.owner == buyer.key() @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub buyer_nft_account: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = buyer_token_account.mint == token_mint.key() @ MarketplaceError::TokenMintMismatch,
+    )]
+    pub buyer_token_account: Box<Account<'info, TokenAccount>>,
+    
+    // The seller's token account the sale proceeds are paid to
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == listing.owner @ MarketplaceError::InvalidTokenAccount,
+        constraint = seller_token_account.mint == token_mint.key() @ MarketplaceError::TokenMintMismatch,
+    )]
+    pub seller_token_account: Box<Account<'info, TokenAccount>>,
+    
+    /// CHECK: The seller's wallet, pinned to the listing; receives the listing vault's rent
+    #[account(mut, address = listing.owner @ MarketplaceError::UnauthorizedListingOperation)]
+    pub seller: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        constraint = lp_token_account.key() == liquidity_pool.lp_token_account @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+    
+    /// Mutable so the project's buyback share of fees can be burned
+    #[account(
+        mut,
+        constraint = token_mint.key() == listing.token_mint @ MarketplaceError::TokenMintMismatch,
+        constraint = token_mint.key() == liquidity_pool.token_mint @ MarketplaceError::TokenMintMismatch,
+    )]
+    pub token_mint: Box<Account<'info, Mint>>,
+    
+    /// The platform treasury's token account for the pool's token
+    #[account(
+        mut,
+        constraint = platform_treasury.owner == platform_config.platform_treasury @ MarketplaceError::InvalidTreasury,
+        constraint = platform_treasury.mint == liquidity_pool.token_mint @ MarketplaceError::InvalidTreasury,
+    )]
+    pub platform_treasury: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        address = project.project_treasury,
+    )]
+    /// CHECK: This is the project treasury account
+    pub project_treasury: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        address = project.royalty_vault.or(project.royalty_wallet).unwrap_or(project.project_treasury),
+    )]
+    /// CHECK: This is the royalty wallet account, or the royalty vault when accrual is enabled
+    pub royalty_wallet: AccountInfo<'info>,
+    
+    /// Royalty split for this project, which may not be set
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"royalty_config", project.key().as_ref()],
+        bump,
+    )]
+    pub royalty_config: UncheckedAccount<'info>,
+    
+    /// Platform fee exemption for the collection, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"fee_exemption", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_fee_exemption: UncheckedAccount<'info>,
+    
+    /// Platform fee exemption for the buyer, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"fee_exemption", buyer.key().as_ref()],
+        bump,
+    )]
+    pub user_fee_exemption: UncheckedAccount<'info>,
+    
+    /// The buyer's cumulative volume record for fee discount tiers
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + std::mem::size_of::<UserTier>(),
+        seeds = [b"user_tier", project.key().as_ref(), buyer.key().as_ref()],
+        bump,
+    )]
+    pub user_tier: Box<Account<'info, UserTier>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    // Royalty recipient token accounts are passed via remaining_accounts when the project splits its royalty
+}
+
+#[derive(Accounts)]
+pub struct UpdateListingPrice<'info> {
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"listing", listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.owner == owner.key() @ MarketplaceError::UnauthorizedListingOperation,
+        constraint = listing.is_active @ MarketplaceError::ListingNotActive,
+    )]
+    pub listing: Account<'info, NftListing>,
+    
+    #[account(
+        constraint = collection.key() == listing.collection @ MarketplaceError::CollectionNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+}
+
+// Check that a collection's NFTs can be traded between holders. Every secondary-sale
+// instruction (listing, repricing, buying) calls this; primary mints and redemptions don't
+pub fn check_secondary_market_open(collection: &Collection, current_time: i64) -> Result<()> {
+    if let Some(lock_until) = collection.secondary_lock_until {
+        if current_time < lock_until {
+            return Err(MarketplaceError::SecondaryMarketLocked.into());
+        }
+    }
+    
+    Ok(())
+}
+
+// Reprice an active listing in place instead of cancelling and relisting
+pub fn update_listing_price(
+    ctx: Context<UpdateListingPrice>,
+    asking_price: u64,
+) -> Result<()> {
+    if asking_price == 0 {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    
+    let current_time = Clock::get()?.unix_timestamp;
+    check_secondary_market_open(&ctx.accounts.collection, current_time)?;
+    
+    let listing = &mut ctx.accounts.listing;
+    let old_price = listing.asking_price;
+    listing.asking_price = asking_price;
+    listing.price_updated_at = current_time;
+    
+    msg!("Listing for {} repriced from {} to {}", listing.nft_mint, old_price, asking_price);
+    
+    Ok(())
+}
+
+// Signer seeds of a listing PDA, which owns the listed NFT's vault
+fn listing_seeds(listing: &NftListing) -> [&[u8]; 3] {
+    [b"listing", listing.nft_mint.as_ref(), std::slice::from_ref(&listing.bump)]
+}
+
+// Hand the listed NFT out of its vault and close the vault, refunding its rent to the seller
+fn release_listed_nft<'info>(
+    token_program: &Program<'info, Token>,
+    listing: &Account<'info, NftListing>,
+    listing_vault: &Account<'info, TokenAccount>,
+    destination: &Account<'info, TokenAccount>,
+    seller: AccountInfo<'info>,
+) -> Result<()> {
+    let signer_seeds = listing_seeds(listing);
+    
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: listing_vault.to_account_info(),
+                to: destination.to_account_info(),
+                authority: listing.to_account_info(),
+            },
+            &[&signer_seeds],
+        ),
+        1,
+    )?;
+    
+    token::close_account(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            CloseAccount {
+                account: listing_vault.to_account_info(),
+                destination: seller,
+                authority: listing.to_account_info(),
+            },
+            &[&signer_seeds],
+        ),
+    )
+}
+
+// List an NFT for sale. The NFT moves into a vault owned by the listing until it is sold or
+// the listing is cancelled, and a discounted listing puts the bought NFT on a cooldown
+pub fn create_listing(
+    ctx: Context<CreateListing>,
+    asking_price: u64,
+    discount_percent: Option<u8>,
+    cooldown_period: Option<i64>,
+) -> Result<()> {
+    if asking_price == 0 {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    
+    if matches!(discount_percent, Some(discount) if discount > 100) {
+        return Err(MarketplaceError::InvalidDiscountPercentage.into());
+    }
+    if matches!(cooldown_period, Some(cooldown) if cooldown <= 0) {
+        return Err(MarketplaceError::InvalidCooldownPeriod.into());
+    }
+    
+    let collection = &ctx.accounts.collection;
+    let token_mint = collection.token_mint.ok_or(MarketplaceError::NoTokenMintSpecified)?;
+    
+    let current_time = Clock::get()?.unix_timestamp;
+    check_secondary_market_open(collection, current_time)?;
+    
+    // The token holder is authoritative; a stale recorded owner is brought up to date
+    let owner = ctx.accounts.owner.key();
+    let nft_data = &mut ctx.accounts.nft_data;
+    if nft_data.owner != owner {
+        record_nft_transfer(nft_data, collection, owner);
+    }
+    
+    // The active listing keeps the NFT from being closed or consumed by a fusion
+    nft_data.reference_count = nft_data.reference_count
+        .checked_add(1)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.owner_nft_account.to_account_info(),
+                to: ctx.accounts.listing_vault.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            },
+        ),
+        1,
+    )?;
+    
+    let listing = &mut ctx.accounts.listing;
+    listing.owner = owner;
+    listing.nft_mint = ctx.accounts.nft_mint.key();
+    listing.token_mint = token_mint;
+    listing.asking_price = asking_price;
+    listing.discount_percent = discount_percent;
+    listing.cooldown_period = cooldown_period;
+    listing.is_active = true;
+    listing.created_at = current_time;
+    listing.collection = collection.key();
+    listing.price_updated_at = current_time;
+    listing.bump = *ctx.bumps.get("listing").unwrap();
+    
+    msg!("NFT {} listed for {} tokens", listing.nft_mint, asking_price);
+    
+    Ok(())
+}
+
+// Withdraw a listing and return the NFT to its owner
+pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+    release_listed_nft(
+        &ctx.accounts.token_program,
+        &ctx.accounts.listing,
+        &ctx.accounts.listing_vault,
+        &ctx.accounts.owner_nft_account,
+        ctx.accounts.owner.to_account_info(),
+    )?;
+    
+    let nft_data = &mut ctx.accounts.nft_data;
+    nft_data.reference_count = nft_data.reference_count.saturating_sub(1); // Released by the listing
+    
+    let listing = &mut ctx.accounts.listing;
+    listing.is_active = false;
+    
+    msg!("Listing for {} cancelled", listing.nft_mint);
+    
+    Ok(())
+}
+
+// Buy a listed NFT. The buyer pays into the project's pool, which pays the platform fee and
+// royalty and forwards the rest to the seller; the NFT and its recorded ownership pass to the buyer
+pub fn buy_listing<'info>(ctx: Context<'_, '_, '_, 'info, BuyListing<'info>>) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    check_secondary_market_open(&ctx.accounts.collection, current_time)?;
+    
+    let listing = &ctx.accounts.listing;
+    let price = apply_swap_discount(listing.asking_price, listing.discount_percent)?;
+    if ctx.accounts.buyer_token_account.amount < price {
+        return Err(MarketplaceError::InsufficientTokenAmount.into());
+    }
+    
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.lp_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
+        ),
+        price,
+    )?;
+    
+    // Fees follow the buyer's volume tier and exemptions, as on swaps
+    let fee_discount_bps = get_fee_discount_bps(&ctx.accounts.project, ctx.accounts.user_tier.cumulative_volume);
+    let platform_fee_exempt = is_platform_fee_exempt(
+        &ctx.accounts.collection_fee_exemption,
+        &ctx.accounts.user_fee_exemption,
+    );
+    let fees = calculate_listing_fees(
+        &ctx.accounts.platform_config,
+        &ctx.accounts.project,
+        price,
+        fee_discount_bps,
+        platform_fee_exempt,
+    )?;
+    let FeeBreakdown { platform_fee, royalty_fee, net_amount: seller_amount, .. } = fees;
+    
+    let royalty_config = Account::<RoyaltyConfig>::try_from(&ctx.accounts.royalty_config).ok();
+    distribute_fees(
+        &ctx.accounts.token_program,
+        &ctx.accounts.lp_token_account,
+        &ctx.accounts.token_mint,
+        &ctx.accounts.platform_treasury,
+        &ctx.accounts.project_treasury,
+        Some(&ctx.accounts.royalty_wallet),
+        royalty_config.as_deref().map(|royalty_config| (royalty_config, ctx.remaining_accounts)),
+        &mut ctx.accounts.liquidity_pool,
+        &ctx.accounts.platform_config,
+        &mut ctx.accounts.project,
+        fees,
+    )?;
+    
+    // The rest of the price goes on to the seller
+    if seller_amount > 0 {
+        let liquidity_pool = &ctx.accounts.liquidity_pool;
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.lp_token_account.to_account_info(),
+                    to: ctx.accounts.seller_token_account.to_account_info(),
+                    authority: liquidity_pool.to_account_info(),
+                },
+                &[&[
+                    b"liquidity_pool",
+                    liquidity_pool.project.as_ref(),
+                    &[liquidity_pool.bump],
+                ]],
+            ),
+            seller_amount,
+        )?;
+    }
+    
+    release_listed_nft(
+        &ctx.accounts.token_program,
+        &ctx.accounts.listing,
+        &ctx.accounts.listing_vault,
+        &ctx.accounts.buyer_nft_account,
+        ctx.accounts.seller.to_account_info(),
+    )?;
+    
+    // The buyer becomes the recorded owner; a discounted listing also puts the NFT on cooldown
+    let buyer = ctx.accounts.buyer.key();
+    let nft_data = &mut ctx.accounts.nft_data;
+    record_nft_transfer(nft_data, &ctx.accounts.collection, buyer);
+    nft_data.reference_count = nft_data.reference_count.saturating_sub(1); // Released by the listing
+    if let (Some(discount), Some(cooldown)) = (listing.discount_percent, listing.cooldown_period) {
+        let cooldown_end = clock_now(nft_data.cooldown_clock)?
+            .checked_add(cooldown)
+            .ok_or(MarketplaceError::CalculationOverflow)?;
+        nft_data.cooldown_end_timestamp = nft_data.cooldown_end_timestamp.max(Some(cooldown_end));
+        nft_data.discount_percent = Some(discount);
+    }
+    
+    let listing = &mut ctx.accounts.listing;
+    listing.is_active = false;
+    
+    // Record the purchase towards the buyer's volume tier
+    let user_tier = &mut ctx.accounts.user_tier;
+    if user_tier.user == Pubkey::default() {
+        user_tier.project = ctx.accounts.project.key();
+        user_tier.user = buyer;
+        user_tier.bump = *ctx.bumps.get("user_tier").unwrap();
+    }
+    user_tier.cumulative_volume = user_tier.cumulative_volume
+        .checked_add(price)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
+    ctx.accounts.project.last_activity_timestamp = current_time;
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.last_activity = current_time;
+    let trade_price_usd = match check_oracle_status(liquidity_pool) {
+        Ok(()) => record_trade_price(liquidity_pool, price, current_time)?,
+        Err(_) => 0,
+    };
+    
+    record_collection_activity(
+        &ctx.accounts.collection_stats,
+        &ctx.accounts.collection.key(),
+        |stats| record_sale(stats, price),
+    )?;
+    
+    emit!(ListingSold {
+        listing: ctx.accounts.listing.key(),
+        nft_mint: ctx.accounts.listing.nft_mint,
+        collection: ctx.accounts.collection.key(),
+        seller: ctx.accounts.listing.owner,
+        buyer,
+        price,
+        platform_fee,
+        royalty_fee,
+        seller_amount,
+        token_decimals: ctx.accounts.token_mint.decimals,
+        trade_price_usd,
+        usd_decimals: USD_PRICE_DECIMALS,
+    });
+    
+    msg!("NFT {} sold for {} tokens", ctx.accounts.listing.nft_mint, price);
+    
+    Ok(())
+}
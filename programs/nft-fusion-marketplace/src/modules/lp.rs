@@ -6,9 +6,11 @@ use anchor_spl::{
 use solana_program::clock::Clock;
 
 use crate::{
-    state::{PlatformConfig, Project, LiquidityPool},
+    state::{PlatformConfig, Project, Collection, LiquidityPool, NftData, PricingMode, RedemptionReservation},
     errors::MarketplaceError,
-    modules::oracle::PriceSource,
+    modules::oracle::{check_oracle_status, get_usd_value_for_tokens, PriceSource, QuoteAsset, DEFAULT_MAX_PRICE_STALENESS},
+    modules::redeem::{claimable_liquidity, NFT_BASE_TOKEN_VALUE},
+    modules::fees::validate_treasury,
 };
 
 // Make struct explicitly implement Accounts trait
@@ -62,6 +64,19 @@ pub struct SetupLiquidityPool<'info> {
     )]
     pub authority_token_account: Account<'info, TokenAccount>,
     
+    /// The platform treasury's token account for the pool's token
+    #[account(
+        constraint = platform_treasury.owner == platform_config.platform_treasury @ MarketplaceError::InvalidTreasury,
+        constraint = platform_treasury.mint == token_mint @ MarketplaceError::InvalidTreasury,
+    )]
+    pub platform_treasury: Account<'info, TokenAccount>,
+    
+    #[account(
+        address = project.project_treasury,
+    )]
+    /// CHECK: This is the project treasury account, checked in the instruction
+    pub project_treasury: AccountInfo<'info>,
+    
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
@@ -101,12 +116,189 @@ pub struct CheckLpInactivity<'info> {
     )]
     pub lp_token_account: Account<'info, TokenAccount>,
     
+    /// The platform treasury's token account for the pool's token
     #[account(
         mut,
-        address = platform_config.platform_treasury,
+        constraint = platform_treasury.owner == platform_config.platform_treasury @ MarketplaceError::InvalidTreasury,
+        constraint = platform_treasury.mint == liquidity_pool.token_mint @ MarketplaceError::InvalidTreasury,
     )]
-    /// CHECK: This is the platform treasury account
-    pub platform_treasury: AccountInfo<'info>,
+    pub platform_treasury: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        address = project.project_treasury,
+    )]
+    /// CHECK: This is the project treasury account, which may have been closed
+    pub project_treasury: AccountInfo<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct WithdrawLiquidity<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    #[account(
+        mut,
+        constraint = lp_token_account.key() == liquidity_pool.lp_token_account @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub lp_token_account: Account<'info, TokenAccount>,
+    
+    // Authority's token account receiving the withdrawn liquidity
+    #[account(
+        mut,
+        constraint = authority_token_account.owner == authority.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = authority_token_account.mint == liquidity_pool.token_mint @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub authority_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetReclaimSplit<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetPartialRedemption<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetRedemptionLpTax<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct EnableOracleRedemption<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey)]
+pub struct ReserveRedemption<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.is_active @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    // NFT the reservation pays out for
+    #[account(
+        seeds = [b"nft_data", nft_mint.as_ref()],
+        bump = nft_data.bump,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    #[account(
+        constraint = collection.key() == nft_data.collection @ MarketplaceError::CollectionNotFound,
+        constraint = collection.project == project.key() @ MarketplaceError::CollectionProjectMismatch,
+    )]
+    pub collection: Account<'info, Collection>,
+    
+    // Reservation for this NFT, reusable once a previous reservation was redeemed
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<RedemptionReservation>(),
+        seeds = [b"redemption_reservation", nft_mint.as_ref()],
+        bump,
+    )]
+    pub redemption_reservation: Account<'info, RedemptionReservation>,
+    
+    #[account(
+        mut,
+        constraint = lp_token_account.key() == liquidity_pool.lp_token_account @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub lp_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = authority_token_account.owner == authority.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = authority_token_account.mint == liquidity_pool.token_mint @ MarketplaceError::TokenMintMismatch,
+    )]
+    pub authority_token_account: Account<'info, TokenAccount>,
     
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
@@ -119,6 +311,10 @@ pub fn setup_liquidity_pool(
     token_mint: Pubkey,
     initial_liquidity: u64,
 ) -> Result<()> {
+    // Fees are paid in the pool's token, so the project treasury must be a token account for it.
+    // The platform treasury's account for it is checked by the context
+    validate_treasury(&ctx.accounts.project_treasury, &token_mint)?;
+    
     // Initialize liquidity pool
     let liquidity_pool = &mut ctx.accounts.liquidity_pool;
     liquidity_pool.project = ctx.accounts.project.key();
@@ -129,7 +325,28 @@ pub fn setup_liquidity_pool(
     liquidity_pool.oracle_price_usd = None; // Will be updated by oracle module
     liquidity_pool.oracle_price_last_update = 0;
     liquidity_pool.redemption_locked = false;
+    liquidity_pool.redemption_locked_since = None;
+    liquidity_pool.auto_recover_redemption_lock = true;
     liquidity_pool.price_source = PriceSource::None; // No price source set yet
+    liquidity_pool.last_trade_price_usd = None;
+    liquidity_pool.last_trade_ts = 0;
+    liquidity_pool.reserved_liquidity = 0;
+    liquidity_pool.pending_platform_fees = 0;
+    liquidity_pool.pending_project_fees = 0;
+    liquidity_pool.quote_asset = QuoteAsset::Usd;
+    liquidity_pool.quote_price_usd = None;
+    liquidity_pool.quote_price_last_update = 0;
+    liquidity_pool.allow_partial_redemption = false;
+    liquidity_pool.pending_claims = 0;
+    liquidity_pool.pricing_mode = PricingMode::Flat;
+    liquidity_pool.redemption_value_usd = 0;
+    liquidity_pool.redemption_lp_tax_bps = 0;
+    liquidity_pool.max_oracle_staleness = DEFAULT_MAX_PRICE_STALENESS;
+    liquidity_pool.max_confidence_bps = 0;
+    liquidity_pool.quote_price_feed = None;
+    liquidity_pool.pyth_price_feed = None;
+    liquidity_pool.dex_token_account = None;
+    liquidity_pool.dex_base_account = None;
     liquidity_pool.bump = *ctx.bumps.get("liquidity_pool").unwrap();
     
     // Transfer initial liquidity if provided
@@ -170,33 +387,257 @@ pub fn check_lp_inactivity(
         return Err(MarketplaceError::LiquidityPoolNotInactive.into());
     }
     
-    // If inactive, reclaim liquidity to platform treasury
+    // If inactive, reclaim liquidity and split it between the platform and project treasuries
     let liquidity_amount = ctx.accounts.lp_token_account.amount;
+    let (mut platform_amount, mut project_amount) = split_reclaimed_liquidity(
+        liquidity_amount,
+        ctx.accounts.platform_config.reclaim_split_bps,
+    )?;
+    
+    // A closed project treasury forfeits its share to the platform
+    let project_treasury = &ctx.accounts.project_treasury;
+    if project_treasury.owner != &token::ID || project_treasury.data_is_empty() {
+        platform_amount = liquidity_amount;
+        project_amount = 0;
+    }
+    
+    let project_key = ctx.accounts.project.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"liquidity_pool",
+        project_key.as_ref(),
+        &[ctx.accounts.liquidity_pool.bump],
+    ]];
     
-    if liquidity_amount > 0 {
+    for (treasury, amount) in [
+        (ctx.accounts.platform_treasury.to_account_info(), platform_amount),
+        (project_treasury.clone(), project_amount),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        
         token::transfer(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
                 Transfer {
                     from: ctx.accounts.lp_token_account.to_account_info(),
-                    to: ctx.accounts.platform_treasury.to_account_info(),
+                    to: treasury.to_account_info(),
                     authority: ctx.accounts.liquidity_pool.to_account_info(),
                 },
-                &[&[
-                    b"liquidity_pool",
-                    ctx.accounts.project.key().as_ref(),
-                    &[ctx.accounts.liquidity_pool.bump],
-                ]],
+                signer_seeds,
             ),
-            liquidity_amount,
+            amount,
         )?;
     }
     
+    // Reservations, pending claims and pending fees cannot be honored once the pool is drained
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.reserved_liquidity = 0;
+    liquidity_pool.pending_claims = 0;
+    liquidity_pool.pending_platform_fees = 0;
+    liquidity_pool.pending_project_fees = 0;
+    
     // Mark project as inactive
     let project = &mut ctx.accounts.project;
     project.is_active = false;
     
-    msg!("Inactive liquidity pool reclaimed for project: {}", project_id);
+    msg!("Inactive liquidity pool reclaimed for project: {} ({} to platform, {} to project)", project_id, platform_amount, project_amount);
     
     Ok(())
 }
+
+// Withdraw some of the pool's liquidity back to the project authority. Only the free balance
+// can go: pending fees, reserved buybacks and tokens owed to partial redemptions stay. Escrows
+// hold their tokens in their own accounts, so the pool owes them nothing
+pub fn withdraw_liquidity(
+    ctx: Context<WithdrawLiquidity>,
+    project_id: String,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    
+    let liquidity_pool = &ctx.accounts.liquidity_pool;
+    let withdrawable = claimable_liquidity(liquidity_pool, &ctx.accounts.lp_token_account)?
+        .saturating_sub(liquidity_pool.pending_claims);
+    if amount > withdrawable {
+        return Err(MarketplaceError::InsufficientLiquidity.into());
+    }
+    
+    let project_key = ctx.accounts.project.key();
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.lp_token_account.to_account_info(),
+                to: ctx.accounts.authority_token_account.to_account_info(),
+                authority: ctx.accounts.liquidity_pool.to_account_info(),
+            },
+            &[&[
+                b"liquidity_pool",
+                project_key.as_ref(),
+                &[ctx.accounts.liquidity_pool.bump],
+            ]],
+        ),
+        amount,
+    )?;
+    
+    // Managing the pool counts as activity, so it isn't reclaimed as abandoned
+    let current_time = Clock::get()?.unix_timestamp;
+    ctx.accounts.liquidity_pool.last_activity = current_time;
+    ctx.accounts.project.last_activity_timestamp = current_time;
+    
+    msg!("Withdrew {} tokens of liquidity from the pool for project: {}", amount, project_id);
+    
+    Ok(())
+}
+
+// Fund the pool with a fixed buyback amount for one NFT, paid out on its redemption
+// regardless of the oracle price
+pub fn reserve_redemption(
+    ctx: Context<ReserveRedemption>,
+    nft_mint: Pubkey,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    
+    if ctx.accounts.redemption_reservation.reserved_amount > 0 {
+        return Err(MarketplaceError::RedemptionAlreadyReserved.into());
+    }
+    
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.authority_token_account.to_account_info(),
+                to: ctx.accounts.lp_token_account.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+    
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.reserved_liquidity = liquidity_pool
+        .reserved_liquidity
+        .checked_add(amount)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    liquidity_pool.last_activity = current_time;
+    
+    let redemption_reservation = &mut ctx.accounts.redemption_reservation;
+    redemption_reservation.project = ctx.accounts.project.key();
+    redemption_reservation.nft_mint = nft_mint;
+    redemption_reservation.reserved_amount = amount;
+    redemption_reservation.created_at = current_time;
+    redemption_reservation.bump = *ctx.bumps.get("redemption_reservation").unwrap();
+    
+    msg!("Reserved {} tokens for redemption of NFT: {}", amount, nft_mint);
+    
+    Ok(())
+}
+
+// Set the platform's share of liquidity reclaimed from inactive pools
+pub fn set_reclaim_split(
+    ctx: Context<SetReclaimSplit>,
+    reclaim_split_bps: u16,
+) -> Result<()> {
+    if reclaim_split_bps > 10000 {
+        return Err(MarketplaceError::InvalidReclaimSplit.into());
+    }
+    
+    ctx.accounts.platform_config.reclaim_split_bps = reclaim_split_bps;
+    
+    msg!("Reclaim split set to {} bps for the platform", reclaim_split_bps);
+    
+    Ok(())
+}
+
+// Let redemptions the pool can't fully cover pay out what is available and record the
+// remainder as a pending claim, instead of failing
+pub fn set_partial_redemption(
+    ctx: Context<SetPartialRedemption>,
+    _project_id: String,
+    allow_partial_redemption: bool,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.allow_partial_redemption = allow_partial_redemption;
+    
+    msg!("Partial redemption allowed: {}", allow_partial_redemption);
+    
+    Ok(())
+}
+
+// Set the share of each redemption the pool keeps to stay solvent. Unlike fees it never leaves
+// the pool; the user is simply paid less
+pub fn set_redemption_lp_tax(
+    ctx: Context<SetRedemptionLpTax>,
+    _project_id: String,
+    redemption_lp_tax_bps: u16,
+) -> Result<()> {
+    if redemption_lp_tax_bps > 10000 {
+        return Err(MarketplaceError::InvalidRedemptionTax.into());
+    }
+    
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.redemption_lp_tax_bps = redemption_lp_tax_bps;
+    
+    msg!("Redemption LP tax set to {} bps", redemption_lp_tax_bps);
+    
+    Ok(())
+}
+
+// Tokens of a redemption the pool keeps under its LP tax
+pub fn redemption_lp_tax(redemption_amount: u64, redemption_lp_tax_bps: u16) -> Result<u64> {
+    if redemption_lp_tax_bps > 10000 {
+        return Err(MarketplaceError::InvalidRedemptionTax.into());
+    }
+    
+    // Never overflows: the quotient is at most the redemption amount
+    Ok(((redemption_amount as u128) * (redemption_lp_tax_bps as u128) / 10000) as u64)
+}
+
+// Move a pool from flat 1:1 redemptions to oracle-priced ones. The redemption USD value is
+// fixed at what the base token value is worth at the current, fresh price, so the payout
+// only moves with the price from here on. This is one-way
+pub fn enable_oracle_redemption(
+    ctx: Context<EnableOracleRedemption>,
+    _project_id: String,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    if liquidity_pool.pricing_mode == PricingMode::OraclePriced {
+        return Err(MarketplaceError::OracleRedemptionAlreadyEnabled.into());
+    }
+    
+    check_oracle_status(liquidity_pool)?;
+    let redemption_value_usd = get_usd_value_for_tokens(liquidity_pool, NFT_BASE_TOKEN_VALUE)?;
+    if redemption_value_usd == 0 {
+        return Err(MarketplaceError::InvalidOraclePrice.into());
+    }
+    
+    liquidity_pool.pricing_mode = PricingMode::OraclePriced;
+    liquidity_pool.redemption_value_usd = redemption_value_usd;
+    
+    msg!("Oracle-priced redemption enabled at {} USD per NFT", redemption_value_usd);
+    
+    Ok(())
+}
+
+// Split reclaimed liquidity into (platform, project) shares; rounding favours the project
+pub fn split_reclaimed_liquidity(amount: u64, reclaim_split_bps: u16) -> Result<(u64, u64)> {
+    if reclaim_split_bps > 10000 {
+        return Err(MarketplaceError::InvalidReclaimSplit.into());
+    }
+    
+    let platform_amount = (amount as u128)
+        .checked_mul(reclaim_split_bps as u128)
+        .ok_or(MarketplaceError::CalculationOverflow)?
+        / 10000;
+    let platform_amount = platform_amount as u64;
+    
+    Ok((platform_amount, amount - platform_amount))
+}
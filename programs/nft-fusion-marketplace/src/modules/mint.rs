@@ -1,17 +1,26 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
 use anchor_spl::{
-    token::Token,
+    token::{Token, TokenAccount, Mint},
     associated_token::AssociatedToken,
 };
 // Using direct TokenMetadata calls instead due to version incompatibility
 // We'll implement basic NFT metadata operations
-use solana_program::clock::Clock;
+use solana_program::{clock::Clock, hash::hashv};
 
 use crate::{
-    state::{PlatformConfig, Project, Collection, NftData},
+    state::{PlatformConfig, Project, Collection, CollectionRegistry, ClockSource, MintRateLimit, NftData, RarityCooldownTier},
     errors::MarketplaceError,
+    modules::{
+        cooldown::{clock_now, cooldown_end_for_rarity, loyalty_cooldown_reduction, validate_rarity_cooldown_curve, MAX_RARITY_COOLDOWN_TIERS},
+        traits::{apply_trait_selection, load_combo_bonuses},
+        stats::record_collection_activity,
+    },
 };
 
+// Upper bound on collections recorded in a project's registry, which keeps the account small
+pub const MAX_REGISTERED_COLLECTIONS: usize = 256;
+
 #[derive(Accounts)]
 #[instruction(collection_id: String, project_id: String, metadata_uri: String, token_mint: Option<Pubkey>)]
 pub struct CreateCollection<'info> {
@@ -36,12 +45,23 @@ pub struct CreateCollection<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + std::mem::size_of::<Collection>() + collection_id.len() + metadata_uri.len() + 100, // Extra space
+        space = 8 + std::mem::size_of::<Collection>() + collection_id.len() + metadata_uri.len() + 100 // Extra space
+            + MAX_RARITY_COOLDOWN_TIERS * std::mem::size_of::<RarityCooldownTier>(),
         seeds = [b"collection", collection_id.as_bytes()],
         bump
     )]
     pub collection: Account<'info, Collection>,
     
+    /// Index of the project's collections, created with its first collection
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<CollectionRegistry>(),
+        seeds = [b"collection_registry", project.key().as_ref()],
+        bump,
+    )]
+    pub collection_registry: Account<'info, CollectionRegistry>,
+    
     #[account(
         constraint = token_mint.is_none() || token_mint_account.key() == token_mint.unwrap(),
     )]
@@ -51,6 +71,151 @@ pub struct CreateCollection<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetCollectionRedeemable<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"collection", collection.collection_id.as_bytes()],
+        bump = collection.bump,
+        constraint = collection.project == project.key() @ MarketplaceError::ProjectNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCollectionCooldown<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"collection", collection.collection_id.as_bytes()],
+        bump = collection.bump,
+        constraint = collection.project == project.key() @ MarketplaceError::ProjectNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMintPrice<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"collection", collection.collection_id.as_bytes()],
+        bump = collection.bump,
+        constraint = collection.project == project.key() @ MarketplaceError::ProjectNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMintWindow<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"collection", collection.collection_id.as_bytes()],
+        bump = collection.bump,
+        constraint = collection.project == project.key() @ MarketplaceError::ProjectNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+}
+
+#[derive(Accounts)]
+pub struct SetMintRateLimit<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        seeds = [b"collection", collection.collection_id.as_bytes()],
+        bump = collection.bump,
+        constraint = collection.project == project.key() @ MarketplaceError::ProjectNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MintRateLimit>(),
+        seeds = [b"mint_rate_limit", collection.key().as_ref()],
+        bump,
+    )]
+    pub mint_rate_limit: Account<'info, MintRateLimit>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SyncNftOwner<'info> {
+    // New holder of the NFT
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"nft_data", nft_data.mint.as_ref()],
+        bump = nft_data.bump,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    #[account(
+        constraint = collection.key() == nft_data.collection @ MarketplaceError::CollectionNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+    
+    // The new holder's token account, proving they hold the NFT
+    #[account(
+        constraint = user_nft_account.mint == nft_data.mint @ MarketplaceError::InvalidTokenAccount,
+        constraint = user_nft_account.owner == user.key() @ MarketplaceError::NotNftOwner,
+        constraint = user_nft_account.amount == 1 @ MarketplaceError::NotNftOwner,
+    )]
+    pub user_nft_account: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct CollectionReveal<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"collection", collection.collection_id.as_bytes()],
+        bump = collection.bump,
+        constraint = collection.project == project.key() @ MarketplaceError::ProjectNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+}
+
 #[derive(Accounts)]
 #[instruction(collection_id: String, metadata_uri: String)]
 pub struct MintNft<'info> {
@@ -70,17 +235,49 @@ pub struct MintNft<'info> {
     )]
     pub collection: Account<'info, Collection>,
     
+    /// Stats for this collection, which may not be tracked
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: UncheckedAccount<'info>,
+    
+    /// Mint rate limit for this collection, which may not be set
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"mint_rate_limit", collection.key().as_ref()],
+        bump,
+    )]
+    pub mint_rate_limit: UncheckedAccount<'info>,
+    
+    /// Trait config for this collection, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"trait_config", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_trait_config: UncheckedAccount<'info>,
+    
     #[account(
         mut,
         seeds = [b"project", project.project_id.as_bytes()],
         bump = project.bump,
         constraint = project.is_active @ MarketplaceError::ProjectNotFound,
+        constraint = collection.project == project.key() @ MarketplaceError::CollectionProjectMismatch,
     )]
     pub project: Account<'info, Project>,
     
-    /// The NFT mint that will be created
-    #[account(mut)]
-    pub nft_mint: Signer<'info>,
+    /// The NFT mint, which must be fresh: no supply and zero decimals
+    #[account(
+        mut,
+        signer,
+        constraint = nft_mint.supply == 0 @ MarketplaceError::InvalidTokenMint,
+        constraint = nft_mint.decimals == 0 @ MarketplaceError::InvalidTokenMint,
+    )]
+    pub nft_mint: Account<'info, Mint>,
     
     /// The NFT metadata account
     #[account(
@@ -92,6 +289,14 @@ pub struct MintNft<'info> {
     )]
     pub nft_data: Account<'info, NftData>,
     
+    /// An NFT of the collection the user holds, presented for the loyalty cooldown perk
+    /// CHECK: Contents are checked in the instruction; any non-NftData account presents none
+    pub loyalty_nft_data: UncheckedAccount<'info>,
+    
+    /// Token account holding the presented loyalty NFT
+    /// CHECK: Checked in the instruction when a loyalty NFT is presented
+    pub loyalty_token_account: UncheckedAccount<'info>,
+    
     /// Metadata account for the NFT
     /// CHECK: This is validated in the instruction
     #[account(mut)]
@@ -103,6 +308,7 @@ pub struct MintNft<'info> {
     pub master_edition: AccountInfo<'info>,
     
     /// The user's associated token account to receive the NFT
+    /// CHECK: This is validated in the instruction
     #[account(mut)]
     pub user_token_account: AccountInfo<'info>,
     
@@ -115,6 +321,67 @@ pub struct MintNft<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+#[instruction(collection_id: String, metadata_uri: String)]
+pub struct MintFreeNft<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"collection", collection_id.as_bytes()],
+        bump = collection.bump,
+    )]
+    pub collection: Account<'info, Collection>,
+    
+    /// Stats for this collection, which may not be tracked
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: UncheckedAccount<'info>,
+    
+    /// Mint rate limit for this collection, which may not be set
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"mint_rate_limit", collection.key().as_ref()],
+        bump,
+    )]
+    pub mint_rate_limit: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"project", project.project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.is_active @ MarketplaceError::ProjectNotFound,
+        constraint = collection.project == project.key() @ MarketplaceError::ProjectNotFound,
+    )]
+    pub project: Account<'info, Project>,
+    
+    /// The NFT mint, which must be fresh: no supply and zero decimals
+    #[account(
+        mut,
+        signer,
+        constraint = nft_mint.supply == 0 @ MarketplaceError::InvalidTokenMint,
+        constraint = nft_mint.decimals == 0 @ MarketplaceError::InvalidTokenMint,
+    )]
+    pub nft_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + std::mem::size_of::<NftData>() + metadata_uri.len() + 100, // Extra space
+        seeds = [b"nft_data", nft_mint.key().as_ref()],
+        bump,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    pub system_program: Program<'info, System>,
+}
+
 pub fn create_collection(
     ctx: Context<CreateCollection>,
     collection_id: String,
@@ -134,8 +401,36 @@ pub fn create_collection(
     collection.metadata_uri = metadata_uri;
     collection.token_mint = token_mint;
     collection.is_compressed = is_compressed;
+    collection.redeemable = true;
+    collection.metadata_hash = None;
+    collection.revealed = false;
+    collection.rarity_cooldown_curve = Vec::new();
+    collection.cooldown_bypass_fee_bps = 0;
+    collection.mint_start_ts = None;
+    collection.mint_end_ts = None;
+    collection.reset_cooldown_on_transfer = false;
+    collection.min_hold_period = 0;
+    collection.fixed_mint_price = None;
+    collection.cooldown_clock = ClockSource::Timestamp;
+    collection.loyalty_min_rarity_score = 0;
+    collection.loyalty_cooldown_reduction_percent = 0;
+    collection.discount_decay = false;
+    collection.redemption_floor_usd = None;
+    collection.escrow_during_cooldown = true;
+    collection.free_mint_allowed = false;
+    collection.secondary_lock_until = None;
+    collection.redemption_value_usd = None;
     collection.bump = *ctx.bumps.get("collection").unwrap();
     
+    register_collection(
+        &mut ctx.accounts.collection_registry,
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        ctx.accounts.project.key(),
+        collection.key(),
+        *ctx.bumps.get("collection_registry").unwrap(),
+    )?;
+    
     // Update project's last activity timestamp
     let project = &mut ctx.accounts.project;
     project.last_activity_timestamp = Clock::get()?.unix_timestamp;
@@ -145,6 +440,468 @@ pub fn create_collection(
     Ok(())
 }
 
+// Append a collection to its project's registry, growing the account by one key and topping
+// up its rent from the payer
+fn register_collection<'info>(
+    registry: &mut Account<'info, CollectionRegistry>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    project: Pubkey,
+    collection: Pubkey,
+    bump: u8,
+) -> Result<()> {
+    if registry.collections.len() >= MAX_REGISTERED_COLLECTIONS {
+        return Err(MarketplaceError::CollectionRegistryFull.into());
+    }
+    
+    // Discriminator, project, vector length prefix and bump, plus every key after this one
+    let space = 8 + 32 + 4 + 32 * (registry.collections.len() + 1) + 1;
+    let registry_info = registry.to_account_info();
+    if space > registry_info.data_len() {
+        let rent_due = Rent::get()?.minimum_balance(space).saturating_sub(registry_info.lamports());
+        if rent_due > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    system_program::Transfer {
+                        from: payer.clone(),
+                        to: registry_info.clone(),
+                    },
+                ),
+                rent_due,
+            )?;
+        }
+        registry_info.realloc(space, false)?;
+    }
+    
+    registry.project = project;
+    registry.collections.push(collection);
+    registry.bump = bump;
+    
+    Ok(())
+}
+
+// Allow or block redemptions for a single collection, independent of the pool-wide oracle lock
+pub fn set_collection_redeemable(
+    ctx: Context<SetCollectionRedeemable>,
+    redeemable: bool,
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    collection.redeemable = redeemable;
+    
+    msg!("Collection {} redeemable: {}", collection.collection_id, redeemable);
+    
+    Ok(())
+}
+
+// Let a collection without a token mint for free. Collections with a token always charge
+// through swaps, so they can't opt in
+pub fn set_free_mint_allowed(
+    ctx: Context<SetCollectionRedeemable>,
+    free_mint_allowed: bool,
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    
+    if free_mint_allowed && collection.token_mint.is_some() {
+        return Err(MarketplaceError::FreeMintNotAllowed.into());
+    }
+    
+    collection.free_mint_allowed = free_mint_allowed;
+    
+    msg!("Collection {} free mints allowed: {}", collection.collection_id, free_mint_allowed);
+    
+    Ok(())
+}
+
+// Price the collection's redemptions in USD at the current oracle price, instead of by the
+// pool's pricing mode
+pub fn set_redemption_value(
+    ctx: Context<SetCollectionRedeemable>,
+    redemption_value_usd: Option<u64>,
+) -> Result<()> {
+    if redemption_value_usd == Some(0) {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.redemption_value_usd = redemption_value_usd;
+    
+    msg!("Redemption value set to {:?} USD for collection: {}", redemption_value_usd, collection.collection_id);
+    
+    Ok(())
+}
+
+// Guarantee redemptions pay at least a USD value, funded from the liquidity pool
+pub fn set_redemption_floor(
+    ctx: Context<SetCollectionRedeemable>,
+    redemption_floor_usd: Option<u64>,
+) -> Result<()> {
+    if redemption_floor_usd == Some(0) {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.redemption_floor_usd = redemption_floor_usd;
+    
+    msg!("Redemption floor set to {:?} USD for collection: {}", redemption_floor_usd, collection.collection_id);
+    
+    Ok(())
+}
+
+// Set the cooldowns newly minted NFTs receive by rarity (an empty curve disables them)
+pub fn set_rarity_cooldown_curve(
+    ctx: Context<UpdateCollectionCooldown>,
+    rarity_cooldown_curve: Vec<RarityCooldownTier>,
+) -> Result<()> {
+    validate_rarity_cooldown_curve(&rarity_cooldown_curve)?;
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.rarity_cooldown_curve = rarity_cooldown_curve;
+    
+    msg!("Rarity cooldown curve set with {} tiers for collection: {}", collection.rarity_cooldown_curve.len(), collection.collection_id);
+    
+    Ok(())
+}
+
+// Set the fee (in basis points of the redemption value) for skipping an NFT's cooldown
+pub fn set_cooldown_bypass_fee(
+    ctx: Context<UpdateCollectionCooldown>,
+    cooldown_bypass_fee_bps: u16,
+) -> Result<()> {
+    if cooldown_bypass_fee_bps > 10000 {
+        return Err(MarketplaceError::InvalidCooldownBypassFee.into());
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.cooldown_bypass_fee_bps = cooldown_bypass_fee_bps;
+    
+    msg!("Cooldown bypass fee set to {} bps for collection: {}", cooldown_bypass_fee_bps, collection.collection_id);
+    
+    Ok(())
+}
+
+// Choose whether an early redemption only honors the share of the purchase discount
+// matching how much of the cooldown has been served
+pub fn set_discount_decay(
+    ctx: Context<UpdateCollectionCooldown>,
+    discount_decay: bool,
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    collection.discount_decay = discount_decay;
+    
+    msg!("Discount decay set to {} for collection: {}", discount_decay, collection.collection_id);
+    
+    Ok(())
+}
+
+// Set how long every NFT must be held after mint before it can be redeemed (0 disables).
+// Unlike discount cooldowns, the hold period cannot be bypassed
+pub fn set_min_hold_period(
+    ctx: Context<UpdateCollectionCooldown>,
+    min_hold_period: i64,
+) -> Result<()> {
+    if min_hold_period < 0 {
+        return Err(MarketplaceError::InvalidCooldownPeriod.into());
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.min_hold_period = min_hold_period;
+    
+    msg!("Minimum hold period set to {} seconds for collection: {}", min_hold_period, collection.collection_id);
+    
+    Ok(())
+}
+
+// Choose whether new cooldowns and vesting periods count seconds or slots. NFTs and escrows
+// keep the clock they were created with, so switching only affects periods set afterwards
+pub fn set_cooldown_clock(
+    ctx: Context<UpdateCollectionCooldown>,
+    cooldown_clock: ClockSource,
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    collection.cooldown_clock = cooldown_clock;
+    
+    msg!("Cooldown clock set to {:?} for collection: {}", cooldown_clock, collection.collection_id);
+    
+    Ok(())
+}
+
+// Cut the cooldown of new mints by a percentage for wallets that present a held NFT of the
+// collection with at least the given rarity score (a zero percentage disables the perk)
+pub fn set_loyalty_cooldown_perk(
+    ctx: Context<UpdateCollectionCooldown>,
+    min_rarity_score: u16,
+    reduction_percent: u8,
+) -> Result<()> {
+    if reduction_percent > 100 {
+        return Err(MarketplaceError::InvalidDiscountPercentage.into());
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.loyalty_min_rarity_score = min_rarity_score;
+    collection.loyalty_cooldown_reduction_percent = reduction_percent;
+    
+    msg!("Loyalty cooldown reduction set to {}% for rarity {}+ in collection: {}", reduction_percent, min_rarity_score, collection.collection_id);
+    
+    Ok(())
+}
+
+// Choose whether a transferred NFT keeps its remaining cooldown or starts fresh for the new holder
+pub fn set_transfer_cooldown_policy(
+    ctx: Context<UpdateCollectionCooldown>,
+    reset_cooldown_on_transfer: bool,
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    collection.reset_cooldown_on_transfer = reset_cooldown_on_transfer;
+    
+    msg!("Collection {} resets cooldown on transfer: {}", collection.collection_id, reset_cooldown_on_transfer);
+    
+    Ok(())
+}
+
+// Choose whether an NFT still in cooldown can back a new escrow. The cooldown exists to stop
+// discounted NFTs being flipped for their value, so escrow redemptions always wait for it; locking
+// tokens against the NFT extracts nothing, and collections allow it by default
+pub fn set_escrow_cooldown_policy(
+    ctx: Context<UpdateCollectionCooldown>,
+    escrow_during_cooldown: bool,
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    collection.escrow_during_cooldown = escrow_during_cooldown;
+    
+    msg!("Collection {} allows escrow during cooldown: {}", collection.collection_id, escrow_during_cooldown);
+    
+    Ok(())
+}
+
+// Record a new holder after the NFT was transferred outside the marketplace,
+// applying the collection's transfer cooldown policy
+pub fn sync_nft_owner(ctx: Context<SyncNftOwner>) -> Result<()> {
+    let nft_data = &mut ctx.accounts.nft_data;
+    
+    if nft_data.owner == ctx.accounts.user.key() {
+        return Err(MarketplaceError::NftOwnerUnchanged.into());
+    }
+    
+    record_nft_transfer(nft_data, &ctx.accounts.collection, ctx.accounts.user.key());
+    
+    msg!("NFT {} owner synced to {}", nft_data.mint, nft_data.owner);
+    
+    Ok(())
+}
+
+// Record a new holder of an NFT, applying the collection's transfer cooldown policy
+pub fn record_nft_transfer(nft_data: &mut NftData, collection: &Collection, new_owner: Pubkey) {
+    nft_data.owner = new_owner;
+    
+    if collection.reset_cooldown_on_transfer {
+        nft_data.cooldown_end_timestamp = None;
+    }
+}
+
+// Set when the collection can be minted (either bound may be left open)
+pub fn set_mint_window(
+    ctx: Context<UpdateMintWindow>,
+    mint_start_ts: Option<i64>,
+    mint_end_ts: Option<i64>,
+) -> Result<()> {
+    if let (Some(start), Some(end)) = (mint_start_ts, mint_end_ts) {
+        if start >= end {
+            return Err(MarketplaceError::InvalidMintWindow.into());
+        }
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.mint_start_ts = mint_start_ts;
+    collection.mint_end_ts = mint_end_ts;
+    
+    msg!("Mint window set for collection {}: {:?} to {:?}", collection.collection_id, mint_start_ts, mint_end_ts);
+    
+    Ok(())
+}
+
+// Block secondary sales of the collection's NFTs until a time, e.g. through a launch (None lifts
+// the lock). Minting, holding and redeeming are unaffected
+pub fn set_secondary_lock(
+    ctx: Context<UpdateMintWindow>,
+    secondary_lock_until: Option<i64>,
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    collection.secondary_lock_until = secondary_lock_until;
+    
+    msg!("Secondary sales locked until {:?} for collection: {}", secondary_lock_until, collection.collection_id);
+    
+    Ok(())
+}
+
+// Set a flat token price for minting through swaps (None returns to oracle-gated pricing)
+pub fn set_fixed_mint_price(
+    ctx: Context<UpdateMintPrice>,
+    fixed_mint_price: Option<u64>,
+) -> Result<()> {
+    if fixed_mint_price == Some(0) {
+        return Err(MarketplaceError::InvalidTokenAmount.into());
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    collection.fixed_mint_price = fixed_mint_price;
+    
+    msg!("Fixed mint price set to {:?} for collection: {}", fixed_mint_price, collection.collection_id);
+    
+    Ok(())
+}
+
+// Base price of a mint: the collection's fixed price if set, otherwise the amount offered.
+// The amount offered is the most the user agreed to pay, so a higher fixed price is refused
+pub fn mint_base_price(collection: &Collection, token_amount: u64) -> Result<u64> {
+    match collection.fixed_mint_price {
+        Some(price) if price > token_amount => Err(MarketplaceError::InsufficientTokenAmount.into()),
+        Some(price) => Ok(price),
+        None => Ok(token_amount),
+    }
+}
+
+// Check that the collection's mint window is open
+pub fn check_mint_window(collection: &Collection, current_time: i64) -> Result<()> {
+    if let Some(start) = collection.mint_start_ts {
+        if current_time < start {
+            return Err(MarketplaceError::MintNotStarted.into());
+        }
+    }
+    
+    if let Some(end) = collection.mint_end_ts {
+        if current_time >= end {
+            return Err(MarketplaceError::MintEnded.into());
+        }
+    }
+    
+    Ok(())
+}
+
+// Cap how many NFTs a collection can mint per rolling window (max_mints 0 removes the cap)
+pub fn set_mint_rate_limit(
+    ctx: Context<SetMintRateLimit>,
+    max_mints: u64,
+    window_seconds: i64,
+) -> Result<()> {
+    if window_seconds <= 0 {
+        return Err(MarketplaceError::InvalidMintWindow.into());
+    }
+    
+    let mint_rate_limit = &mut ctx.accounts.mint_rate_limit;
+    mint_rate_limit.collection = ctx.accounts.collection.key();
+    mint_rate_limit.max_mints = max_mints;
+    mint_rate_limit.window_seconds = window_seconds;
+    mint_rate_limit.window_start = Clock::get()?.unix_timestamp;
+    mint_rate_limit.minted_in_window = 0;
+    mint_rate_limit.bump = *ctx.bumps.get("mint_rate_limit").unwrap();
+    
+    msg!("Mint rate limit for collection {}: {} per {}s", ctx.accounts.collection.collection_id, max_mints, window_seconds);
+    
+    Ok(())
+}
+
+// Count a mint against the collection's rate limit account (pinned by seeds in the
+// calling instruction), starting a new window once the current one has elapsed.
+// Collections without a rate limit are not throttled
+pub fn consume_mint_allowance<'info>(
+    rate_limit_info: &AccountInfo<'info>,
+    collection: &Pubkey,
+    current_time: i64,
+) -> Result<()> {
+    let mut rate_limit = match Account::<MintRateLimit>::try_from(rate_limit_info) {
+        Ok(rate_limit) => rate_limit,
+        Err(_) => return Ok(()),
+    };
+    
+    if rate_limit.collection != *collection {
+        return Err(MarketplaceError::CollectionNotFound.into());
+    }
+    
+    if rate_limit.max_mints == 0 {
+        return Ok(());
+    }
+    
+    let window_end = rate_limit.window_start.saturating_add(rate_limit.window_seconds);
+    if current_time >= window_end {
+        rate_limit.window_start = current_time;
+        rate_limit.minted_in_window = 0;
+    }
+    
+    if rate_limit.minted_in_window >= rate_limit.max_mints {
+        return Err(MarketplaceError::MintRateLimitExceeded.into());
+    }
+    
+    rate_limit.minted_in_window = rate_limit.minted_in_window.saturating_add(1);
+    
+    rate_limit.exit(&crate::ID)
+}
+
+// Commitment to a collection's final metadata: sha256(revealed_uri || salt)
+pub fn metadata_commitment(revealed_uri: &str, salt: &[u8; 32]) -> [u8; 32] {
+    hashv(&[revealed_uri.as_bytes(), salt]).to_bytes()
+}
+
+// Commit to the final metadata before minting so the reveal can be verified later.
+// The commitment is fixed once set
+pub fn commit_metadata_hash(
+    ctx: Context<CollectionReveal>,
+    metadata_hash: [u8; 32],
+) -> Result<()> {
+    let collection = &mut ctx.accounts.collection;
+    
+    if collection.revealed {
+        return Err(MarketplaceError::CollectionAlreadyRevealed.into());
+    }
+    
+    if collection.metadata_hash.is_some() {
+        return Err(MarketplaceError::MetadataAlreadyCommitted.into());
+    }
+    
+    collection.metadata_hash = Some(metadata_hash);
+    
+    msg!("Metadata hash committed for collection: {}", collection.collection_id);
+    
+    Ok(())
+}
+
+// Reveal the final metadata, which must match the committed hash
+pub fn reveal_collection(
+    ctx: Context<CollectionReveal>,
+    revealed_uri: String,
+    salt: [u8; 32],
+) -> Result<()> {
+    if revealed_uri.is_empty() {
+        return Err(MarketplaceError::InvalidMetadataUri.into());
+    }
+    
+    let collection = &mut ctx.accounts.collection;
+    
+    if collection.revealed {
+        return Err(MarketplaceError::CollectionAlreadyRevealed.into());
+    }
+    
+    let metadata_hash = collection.metadata_hash
+        .ok_or(MarketplaceError::MetadataNotCommitted)?;
+    
+    if metadata_commitment(&revealed_uri, &salt) != metadata_hash {
+        return Err(MarketplaceError::RevealMismatch.into());
+    }
+    
+    // The collection account is not resized, so the revealed URI must fit its current allocation
+    let serialized_len = 8 + collection.try_to_vec()?.len() - collection.metadata_uri.len() + revealed_uri.len();
+    if serialized_len > collection.to_account_info().data_len() {
+        return Err(MarketplaceError::InvalidMetadataUri.into());
+    }
+    
+    collection.metadata_uri = revealed_uri;
+    collection.revealed = true;
+    
+    msg!("Collection {} revealed: {}", collection.collection_id, collection.metadata_uri);
+    
+    Ok(())
+}
+
 // Internal function for minting an NFT
 // In a real implementation, you would integrate with either standard NFTs or compressed NFTs via Bubblegum
 pub fn mint_nft_internal(
@@ -173,8 +930,8 @@ pub fn mint_nft_internal(
     Ok(())
 }
 
-pub fn mint_nft(
-    ctx: Context<MintNft>,
+pub fn mint_nft<'info>(
+    ctx: Context<'_, '_, '_, 'info, MintNft<'info>>,
     _collection_id: String,
     metadata_uri: String,
     traits_selection: Option<Vec<u8>>,
@@ -184,24 +941,67 @@ pub fn mint_nft(
         return Err(MarketplaceError::InvalidMetadataUri.into());
     }
     
-    // Validate traits selection if provided
-    if let Some(traits) = &traits_selection {
-        if traits.is_empty() {
-            return Err(MarketplaceError::InvalidTraitsSelection.into());
-        }
+    // Nothing is paid here, so only tokenless collections that allow free mints can use it
+    let collection = &ctx.accounts.collection;
+    if !collection.free_mint_allowed || collection.token_mint.is_some() {
+        return Err(MarketplaceError::FreeMintNotAllowed.into());
     }
     
+    let current_time = Clock::get()?.unix_timestamp;
+    check_mint_window(&ctx.accounts.collection, current_time)?;
+    consume_mint_allowance(&ctx.accounts.mint_rate_limit, &ctx.accounts.collection.key(), current_time)?;
+    
+    // Validate traits selection if provided and count each selected value against its supply
+    // (the trait type accounts are passed via remaining_accounts in selection order)
+    let rarity_score = match &traits_selection {
+        Some(traits) => {
+            if traits.is_empty() {
+                return Err(MarketplaceError::InvalidTraitsSelection.into());
+            }
+            
+            let collection_key = ctx.accounts.collection.key();
+            let combo_bonuses = load_combo_bonuses(&ctx.accounts.collection_trait_config, &collection_key);
+            apply_trait_selection(&collection_key, ctx.remaining_accounts, traits, &combo_bonuses)?.rarity_score
+        }
+        None => 0,
+    };
+    
+    // Holders of a rare NFT from the collection get a loyalty cut to the new cooldown
+    let reduction_percent = loyalty_cooldown_reduction(
+        &ctx.accounts.loyalty_nft_data,
+        &ctx.accounts.loyalty_token_account,
+        &ctx.accounts.collection,
+        &ctx.accounts.user.key(),
+    )?;
+    let cooldown_clock = ctx.accounts.collection.cooldown_clock;
+    let cooldown_end_timestamp = cooldown_end_for_rarity(
+        &ctx.accounts.collection.rarity_cooldown_curve,
+        rarity_score,
+        0,
+        reduction_percent,
+        clock_now(cooldown_clock)?,
+    )?;
+    
     // Initialize NFT data
     let nft_data = &mut ctx.accounts.nft_data;
     nft_data.owner = ctx.accounts.user.key();
     nft_data.collection = ctx.accounts.collection.key();
     nft_data.mint = ctx.accounts.nft_mint.key();
     nft_data.metadata_uri = metadata_uri.clone();
-    nft_data.minted_at = Clock::get()?.unix_timestamp;
-    nft_data.cooldown_end_timestamp = None;
+    nft_data.minted_at = current_time;
+    nft_data.cooldown_end_timestamp = cooldown_end_timestamp;
+    nft_data.cooldown_clock = cooldown_clock;
     nft_data.discount_percent = None;
+    nft_data.rarity_score = rarity_score;
+    nft_data.fused_into = None;
     nft_data.bump = *ctx.bumps.get("nft_data").unwrap();
     
+    record_collection_activity(
+        &ctx.accounts.collection_stats,
+        &ctx.accounts.collection.key(),
+        |stats| stats.mints = stats.mints.saturating_add(1),
+    )?;
+    
     // Here we would mint the NFT based on whether it's compressed or not
     if ctx.accounts.collection.is_compressed {
         // For compressed NFTs, we would use bubblegum program
@@ -228,3 +1028,59 @@ pub fn mint_nft(
     
     Ok(())
 }
+
+// Mint an NFT from a collection intentionally without a token. No tokens move and no fees are
+// charged; the mint window, rate limit and rarity cooldown curve still apply
+pub fn mint_free_nft(
+    ctx: Context<MintFreeNft>,
+    _collection_id: String,
+    metadata_uri: String,
+) -> Result<()> {
+    if metadata_uri.is_empty() {
+        return Err(MarketplaceError::InvalidMetadataUri.into());
+    }
+    
+    let collection = &ctx.accounts.collection;
+    if !collection.free_mint_allowed || collection.token_mint.is_some() {
+        return Err(MarketplaceError::FreeMintNotAllowed.into());
+    }
+    
+    let current_time = Clock::get()?.unix_timestamp;
+    check_mint_window(collection, current_time)?;
+    consume_mint_allowance(&ctx.accounts.mint_rate_limit, &collection.key(), current_time)?;
+    
+    let cooldown_clock = collection.cooldown_clock;
+    let cooldown_end_timestamp = cooldown_end_for_rarity(
+        &collection.rarity_cooldown_curve,
+        0,
+        0,
+        0,
+        clock_now(cooldown_clock)?,
+    )?;
+    
+    let nft_data = &mut ctx.accounts.nft_data;
+    nft_data.owner = ctx.accounts.user.key();
+    nft_data.collection = ctx.accounts.collection.key();
+    nft_data.mint = ctx.accounts.nft_mint.key();
+    nft_data.metadata_uri = metadata_uri;
+    nft_data.minted_at = current_time;
+    nft_data.cooldown_end_timestamp = cooldown_end_timestamp;
+    nft_data.cooldown_clock = cooldown_clock;
+    nft_data.discount_percent = None;
+    nft_data.rarity_score = 0;
+    nft_data.fused_into = None;
+    nft_data.bump = *ctx.bumps.get("nft_data").unwrap();
+    
+    record_collection_activity(
+        &ctx.accounts.collection_stats,
+        &ctx.accounts.collection.key(),
+        |stats| stats.mints = stats.mints.saturating_add(1),
+    )?;
+    
+    let project = &mut ctx.accounts.project;
+    project.last_activity_timestamp = current_time;
+    
+    msg!("Free NFT minted: {}", ctx.accounts.nft_mint.key());
+    
+    Ok(())
+}
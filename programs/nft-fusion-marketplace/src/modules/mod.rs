@@ -8,6 +8,13 @@ pub mod oracle;
 pub mod escrow;
 pub mod traits;
 pub mod rarity;
+pub mod fusion;
+pub mod listing;
+pub mod randomness;
+pub mod stats;
+pub mod custody;
+pub mod project;
+pub mod platform;
 
 pub use swap::*;
 pub use mint::*;
@@ -19,3 +26,10 @@ pub use oracle::*;
 pub use escrow::*;
 pub use traits::*;
 pub use rarity::*;
+pub use fusion::*;
+pub use listing::*;
+pub use randomness::*;
+pub use stats::*;
+pub use custody::*;
+pub use project::*;
+pub use platform::*;
@@ -1,13 +1,29 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, TokenAccount, Mint};
-use pyth_sdk_solana::{load_price_feed_from_account_info, Price, PriceFeed};
+use pyth_sdk_solana::{load_price_feed_from_account_info, Price, PriceFeed, PriceStatus};
 use solana_program::clock::Clock;
 
 use crate::{
     state::{PlatformConfig, Project, LiquidityPool},
     errors::MarketplaceError,
+    events::{ManualPriceSet, PriceUpdated, RedemptionLockOverridden, RedemptionLockRecovered},
 };
 
+// Oracle USD prices are stored as integers with this many decimals
+pub const USD_PRICE_DECIMALS: u8 = 6;
+
+// SOL-quoted prices are stored in lamports
+pub const SOL_PRICE_DECIMALS: u8 = 9;
+
+// Token amounts are converted assuming 9 decimals (standard for SPL tokens)
+const TOKEN_UNIT: u128 = 1_000_000_000;
+
+// Oracle prices older than this are considered stale, unless the pool sets its own window
+pub const DEFAULT_MAX_PRICE_STALENESS: i64 = 3600; // 1 hour
+
+// Largest Pyth exponent magnitude accepted; a feed reporting more is malformed
+pub const MAX_PYTH_EXPO: u32 = 18;
+
 // Instruction context for updating price from Pyth Oracle
 #[derive(Accounts)]
 #[instruction(project_id: String)]
@@ -29,14 +45,17 @@ pub struct UpdateOraclePrice<'info> {
     )]
     pub project: Account<'info, Project>,
     
+    // Checked by its project field rather than seeds so a foreign pool reports a specific error
     #[account(
         mut,
-        seeds = [b"liquidity_pool", project.key().as_ref()],
-        bump = liquidity_pool.bump,
+        constraint = liquidity_pool.project == project.key() @ MarketplaceError::PoolProjectMismatch,
     )]
     pub liquidity_pool: Account<'info, LiquidityPool>,
     
-    /// CHECK: This is the Pyth oracle price feed account
+    /// CHECK: Must be the feed pinned on the pool; parsed as a Pyth feed in the instruction
+    #[account(
+        constraint = liquidity_pool.pyth_price_feed == Some(pyth_price_account.key()) @ MarketplaceError::PriceFeedMismatch,
+    )]
     pub pyth_price_account: AccountInfo<'info>,
     
     pub system_program: Program<'info, System>,
@@ -63,19 +82,25 @@ pub struct UpdateDexPrice<'info> {
     )]
     pub project: Account<'info, Project>,
     
+    // Checked by its project field rather than seeds so a foreign pool reports a specific error
     #[account(
         mut,
-        seeds = [b"liquidity_pool", project.key().as_ref()],
-        bump = liquidity_pool.bump,
+        constraint = liquidity_pool.project == project.key() @ MarketplaceError::PoolProjectMismatch,
     )]
     pub liquidity_pool: Account<'info, LiquidityPool>,
     
-    // DEX Liquidity pool token account (token side)
-    #[account(mut)]
+    // DEX Liquidity pool token account (token side), as pinned on the pool
+    #[account(
+        mut,
+        constraint = liquidity_pool.dex_token_account == Some(dex_token_account.key()) @ MarketplaceError::PriceFeedMismatch,
+    )]
     pub dex_token_account: Account<'info, TokenAccount>,
     
-    // DEX Liquidity pool account (USDC/SOL side)
-    #[account(mut)]
+    // DEX Liquidity pool account (USDC/SOL side), as pinned on the pool
+    #[account(
+        mut,
+        constraint = liquidity_pool.dex_base_account == Some(dex_base_account.key()) @ MarketplaceError::PriceFeedMismatch,
+    )]
     pub dex_base_account: Account<'info, TokenAccount>,
     
     // Token mint account
@@ -110,14 +135,201 @@ pub struct SetManualPrice<'info> {
     )]
     pub project: Account<'info, Project>,
     
+    // Checked by its project field rather than seeds so a foreign pool reports a specific error
+    #[account(
+        mut,
+        constraint = liquidity_pool.project == project.key() @ MarketplaceError::PoolProjectMismatch,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Instruction context for bounding the prices accepted by set_manual_price
+#[derive(Accounts)]
+pub struct SetManualPriceBounds<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+}
+
+// Instruction context for choosing the asset a pool's price is quoted in
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetQuoteAsset<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
     #[account(
         mut,
         seeds = [b"liquidity_pool", project.key().as_ref()],
         bump = liquidity_pool.bump,
     )]
     pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+// Instruction context for setting how old a pool's oracle price may get
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetOracleStaleness<'info> {
+    pub authority: Signer<'info>,
     
-    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+// Instruction context for bounding the Pyth confidence interval a pool accepts
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetOracleConfidence<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+// Instruction context for setting the USD price of a pool's quote asset by hand
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetQuotePrice<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+// Instruction context for pinning the price accounts a pool's price updates must read
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct SetPriceFeed<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+}
+
+// Instruction context for updating the USD price of a pool's quote asset from Pyth (e.g. SOL/USD)
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct UpdateQuotePriceFromPyth<'info> {
+    // The project or platform authority
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key()
+            || platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    /// CHECK: Must be the quote feed pinned on the pool; parsed as a Pyth feed in the instruction
+    #[account(
+        constraint = liquidity_pool.quote_price_feed == Some(pyth_price_account.key()) @ MarketplaceError::PriceFeedMismatch,
+    )]
+    pub pyth_price_account: AccountInfo<'info>,
+}
+
+// Instruction context for the authority overriding a pool's redemption lock
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct OverrideRedemptionLock<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(
+        seeds = [b"project", project_id.as_bytes()],
+        bump = project.bump,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
 }
 
 // Check if oracle price feed is fresh and usable
@@ -127,21 +339,79 @@ pub fn check_oracle_status(liquidity_pool: &LiquidityPool) -> Result<()> {
         return Err(MarketplaceError::RedemptionLocked.into());
     }
     
-    // Check if oracle price is available and fresh (not older than 1 hour)
+    // A freshly set up pool has no price yet, which is distinct from a feed going stale
     if liquidity_pool.oracle_price_usd.is_none() {
-        return Err(MarketplaceError::StaleOracleFeed.into());
+        return Err(MarketplaceError::OracleNotInitialized.into());
     }
     
+    // Check if oracle price is fresh (not older than the pool's staleness window)
     let current_time = Clock::get()?.unix_timestamp;
-    let max_staleness: i64 = 3600; // 1 hour
+    let max_staleness = max_oracle_staleness(liquidity_pool);
     
     if current_time - liquidity_pool.oracle_price_last_update > max_staleness {
         return Err(MarketplaceError::StaleOracleFeed.into());
     }
     
+    // Non-USD quotes also need a fresh USD price for the quote asset to value anything in USD
+    if liquidity_pool.quote_asset != QuoteAsset::Usd {
+        if liquidity_pool.quote_price_usd.is_none() {
+            return Err(MarketplaceError::OracleNotInitialized.into());
+        }
+        if current_time - liquidity_pool.quote_price_last_update > max_staleness {
+            return Err(MarketplaceError::StaleOracleFeed.into());
+        }
+    }
+    
     Ok(())
 }
 
+// Whether a Pyth feed's last publish is older than the staleness window. The SDK also stops
+// reporting a feed as trading once its publishers fall behind, which counts as stale too
+pub fn is_pyth_feed_stale(price_feed: &PriceFeed, current_time: i64, max_staleness: i64) -> bool {
+    !matches!(price_feed.status, PriceStatus::Trading)
+        || current_time.saturating_sub(price_feed.publish_time) > max_staleness
+}
+
+// Seconds before a pool's oracle price counts as stale. Pools created before the window was
+// configurable store 0, which takes the default
+pub fn max_oracle_staleness(liquidity_pool: &LiquidityPool) -> i64 {
+    if liquidity_pool.max_oracle_staleness > 0 {
+        liquidity_pool.max_oracle_staleness
+    } else {
+        DEFAULT_MAX_PRICE_STALENESS
+    }
+}
+
+// Convert an amount of the pool's quote asset (in its smallest units) to USD (scaled by 10^6)
+pub fn quote_to_usd(liquidity_pool: &LiquidityPool, quote_amount: u128) -> Result<u128> {
+    match liquidity_pool.quote_asset {
+        QuoteAsset::Usd => Ok(quote_amount),
+        QuoteAsset::Sol => {
+            let quote_price_usd = liquidity_pool.quote_price_usd
+                .ok_or(MarketplaceError::OracleNotInitialized)?;
+            quote_amount
+                .checked_mul(quote_price_usd as u128)
+                .and_then(|v| v.checked_div(10u128.pow(SOL_PRICE_DECIMALS as u32)))
+                .ok_or_else(|| MarketplaceError::CalculationOverflow.into())
+        }
+    }
+}
+
+// Convert a USD amount (scaled by 10^6) to the pool's quote asset (in its smallest units)
+pub fn usd_to_quote(liquidity_pool: &LiquidityPool, usd_amount: u128) -> Result<u128> {
+    match liquidity_pool.quote_asset {
+        QuoteAsset::Usd => Ok(usd_amount),
+        QuoteAsset::Sol => {
+            let quote_price_usd = liquidity_pool.quote_price_usd
+                .ok_or(MarketplaceError::OracleNotInitialized)?;
+            usd_amount
+                .checked_mul(10u128.pow(SOL_PRICE_DECIMALS as u32))
+                .and_then(|v| v.checked_div(quote_price_usd as u128))
+                .ok_or_else(|| MarketplaceError::CalculationOverflow.into())
+        }
+    }
+}
+
 // Get the current oracle price in tokens for a given USD amount
 // This is useful for converting from USD to token amount when users want to mint NFTs
 pub fn get_token_amount_for_usd(
@@ -151,16 +421,17 @@ pub fn get_token_amount_for_usd(
     // Ensure oracle price is fresh and available
     check_oracle_status(liquidity_pool)?;
     
-    let oracle_price_usd = liquidity_pool.oracle_price_usd
+    let oracle_price = liquidity_pool.oracle_price_usd
         .ok_or(MarketplaceError::StaleOracleFeed)?;
     
-    // Calculate token amount based on USD price
-    // Formula: token_amount = (usd_amount * 10^9) / token_price_usd
+    // Calculate token amount based on the price in the quote asset
+    // Formula: token_amount = (quote_amount * 10^9) / token_price
     // Note: 10^9 is for 9 decimal places in token amount (standard for SPL tokens)
-    let token_amount = (usd_amount as u128)
-        .checked_mul(1_000_000_000)
+    let quote_amount = usd_to_quote(liquidity_pool, usd_amount as u128)?;
+    let token_amount = quote_amount
+        .checked_mul(TOKEN_UNIT)
         .ok_or(MarketplaceError::CalculationOverflow)?
-        .checked_div(oracle_price_usd as u128)
+        .checked_div(oracle_price as u128)
         .ok_or(MarketplaceError::CalculationOverflow)? as u64;
     
     Ok(token_amount)
@@ -175,20 +446,111 @@ pub fn get_usd_value_for_tokens(
     // Ensure oracle price is fresh and available
     check_oracle_status(liquidity_pool)?;
     
-    let oracle_price_usd = liquidity_pool.oracle_price_usd
+    let oracle_price = liquidity_pool.oracle_price_usd
         .ok_or(MarketplaceError::StaleOracleFeed)?;
     
-    // Calculate USD value based on token amount
-    // Formula: usd_value = (token_amount * token_price_usd) / 10^9
-    let usd_value = (token_amount as u128)
-        .checked_mul(oracle_price_usd as u128)
+    // Calculate the value in the quote asset, then convert it to USD
+    // Formula: quote_value = (token_amount * token_price) / 10^9
+    let quote_value = (token_amount as u128)
+        .checked_mul(oracle_price as u128)
         .ok_or(MarketplaceError::CalculationOverflow)?
-        .checked_div(1_000_000_000)
-        .ok_or(MarketplaceError::CalculationOverflow)? as u64;
+        .checked_div(TOKEN_UNIT)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    let usd_value = quote_to_usd(liquidity_pool, quote_value)? as u64;
     
     Ok(usd_value)
 }
 
+// Scale a Pyth price to USD with 6 decimals, rejecting non-positive prices and out-of-range
+// exponents before any scaling
+pub fn pyth_price_to_usd(price: &Price) -> Result<u64> {
+    if price.price <= 0 || price.expo.unsigned_abs() > MAX_PYTH_EXPO {
+        return Err(MarketplaceError::InvalidOraclePrice.into());
+    }
+    
+    let scale_expo = price.expo + USD_PRICE_DECIMALS as i32;
+    let scale = 10u64
+        .checked_pow(scale_expo.unsigned_abs())
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    let price_usd = if scale_expo >= 0 {
+        (price.price as u64).checked_mul(scale)
+    } else {
+        (price.price as u64).checked_div(scale)
+    };
+    
+    price_usd.ok_or_else(|| MarketplaceError::CalculationOverflow.into())
+}
+
+// Reject a Pyth price whose confidence interval is wider than max_confidence_bps of the
+// price; a wide band means the publishers disagree and the price can't be trusted (0 disables)
+pub fn check_pyth_confidence(price: &Price, max_confidence_bps: u16) -> Result<()> {
+    if max_confidence_bps == 0 {
+        return Ok(());
+    }
+    
+    let max_conf = (price.price.unsigned_abs() as u128) * (max_confidence_bps as u128);
+    if (price.conf as u128) * 10000 > max_conf {
+        return Err(MarketplaceError::OracleConfidenceTooWide.into());
+    }
+    
+    Ok(())
+}
+
+// Record the USD value of a trade as the pool's last traded price
+pub fn record_trade_price(
+    liquidity_pool: &mut LiquidityPool,
+    token_amount: u64,
+    timestamp: i64,
+) -> Result<u64> {
+    let trade_price_usd = get_usd_value_for_tokens(liquidity_pool, token_amount)?;
+    
+    liquidity_pool.last_trade_price_usd = Some(trade_price_usd);
+    liquidity_pool.last_trade_ts = timestamp;
+    
+    Ok(trade_price_usd)
+}
+
+// Reject prices that would make token amounts undefined
+pub fn validate_oracle_price(price_usd: u64) -> Result<()> {
+    if price_usd == 0 {
+        return Err(MarketplaceError::InvalidOraclePrice.into());
+    }
+    
+    Ok(())
+}
+
+// Rescale a USD price given with price_decimals decimals to USD_PRICE_DECIMALS, so operators can
+// enter whole dollars (0 decimals) rather than pre-scaled values
+pub fn normalize_usd_price(price: u64, price_decimals: u8) -> Result<u64> {
+    if price_decimals as u32 > MAX_PYTH_EXPO {
+        return Err(MarketplaceError::InvalidOraclePrice.into());
+    }
+    
+    let scale = 10u64
+        .checked_pow((price_decimals as i32 - USD_PRICE_DECIMALS as i32).unsigned_abs())
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    let price_usd = if price_decimals <= USD_PRICE_DECIMALS {
+        price.checked_mul(scale)
+    } else {
+        price.checked_div(scale)
+    };
+    
+    price_usd.ok_or_else(|| MarketplaceError::CalculationOverflow.into())
+}
+
+// Reject manual prices outside the platform's configured bounds
+pub fn validate_manual_price(platform_config: &PlatformConfig, price_usd: u64) -> Result<()> {
+    validate_oracle_price(price_usd)?;
+    
+    let below_min = matches!(platform_config.min_manual_price, Some(min) if price_usd < min);
+    let above_max = matches!(platform_config.max_manual_price, Some(max) if price_usd > max);
+    if below_min || above_max {
+        return Err(MarketplaceError::InvalidOraclePrice.into());
+    }
+    
+    Ok(())
+}
+
 // Update oracle price from Pyth
 pub fn update_oracle_price(
     ctx: Context<UpdateOraclePrice>,
@@ -197,36 +559,36 @@ pub fn update_oracle_price(
     let price_feed: PriceFeed = load_price_feed_from_account_info(&ctx.accounts.pyth_price_account)
         .map_err(|_| MarketplaceError::StaleOracleFeed)?;
     
-    let price: Price = price_feed.get_current_price()
-        .ok_or(MarketplaceError::StaleOracleFeed)?;
+    // Staleness is judged by when the feed itself last published, not when we read it
+    let current_time = Clock::get()?.unix_timestamp;
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    if is_pyth_feed_stale(&price_feed, current_time, max_oracle_staleness(liquidity_pool)) {
+        // Keep the last good price and lock redemptions until a fresh one arrives
+        engage_redemption_lock(liquidity_pool, current_time);
+        msg!("Oracle feed is stale (published at {}), NFT redemption locked", price_feed.publish_time);
+        return Ok(());
+    }
     
-    // Get price in USD (scaled by 10^6)
-    let price_usd = if price.price < 0 {
-        return Err(MarketplaceError::StaleOracleFeed.into());
-    } else {
-        price.price as u64 * 10u64.pow(price.expo.unsigned_abs() as u32)
-    };
+    let price: Price = price_feed.get_current_price_unchecked();
+    check_pyth_confidence(&price, liquidity_pool.max_confidence_bps)?;
     
-    // Determine if oracle feed is stale
-    let current_time = Clock::get()?.unix_timestamp;
-    let price_pub_time = current_time - 60; // Simplified due to SDK limitations
-    let max_staleness: i64 = 3600; // 1 hour
-    let is_stale = current_time - price_pub_time > max_staleness;
+    // Get price in USD (scaled by 10^6)
+    let price_usd = pyth_price_to_usd(&price)?;
     
-    // Update liquidity pool oracle information
-    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    // Update liquidity pool oracle information, aged from the feed's publish time
     liquidity_pool.oracle_price_usd = Some(price_usd);
-    liquidity_pool.oracle_price_last_update = current_time;
+    liquidity_pool.oracle_price_last_update = price_feed.publish_time.min(current_time);
     liquidity_pool.price_source = PriceSource::Pyth;
     
-    // Lock or unlock redemptions based on oracle status
-    if is_stale {
-        liquidity_pool.redemption_locked = true;
-        msg!("Oracle feed is stale, NFT redemption locked");
-    } else {
-        liquidity_pool.redemption_locked = false;
-        msg!("Oracle price updated: {} USD", price_usd as f64 / 1_000_000.0);
-    }
+    recover_redemption_lock(liquidity_pool, PriceSource::Pyth, current_time);
+    msg!("Oracle price updated: {} (USD, {} decimals)", price_usd, USD_PRICE_DECIMALS);
+    
+    emit!(PriceUpdated {
+        liquidity_pool: liquidity_pool.key(),
+        price_usd,
+        usd_decimals: USD_PRICE_DECIMALS,
+        source: PriceSource::Pyth,
+    });
     
     // Update project's last activity timestamp
     let project = &mut ctx.accounts.project;
@@ -258,6 +620,9 @@ pub fn update_dex_price(
         .checked_div(token_reserves as u128)
         .ok_or(MarketplaceError::CalculationOverflow)? as u64;
     
+    // Lopsided reserves can round the price down to zero
+    validate_oracle_price(price_usd)?;
+    
     let current_time = Clock::get()?.unix_timestamp;
     
     // Update liquidity pool oracle information
@@ -265,13 +630,20 @@ pub fn update_dex_price(
     liquidity_pool.oracle_price_usd = Some(price_usd);
     liquidity_pool.oracle_price_last_update = current_time;
     liquidity_pool.price_source = PriceSource::DexLiquidity;
-    liquidity_pool.redemption_locked = false;
+    recover_redemption_lock(liquidity_pool, PriceSource::DexLiquidity, current_time);
+    
+    emit!(PriceUpdated {
+        liquidity_pool: liquidity_pool.key(),
+        price_usd,
+        usd_decimals: USD_PRICE_DECIMALS,
+        source: PriceSource::DexLiquidity,
+    });
     
     // Update project's last activity timestamp
     let project = &mut ctx.accounts.project;
     project.last_activity_timestamp = current_time;
     
-    msg!("DEX price updated: {} USD", price_usd as f64 / 1_000_000.0);
+    msg!("DEX price updated: {} (USD, {} decimals)", price_usd, USD_PRICE_DECIMALS);
     
     Ok(())
 }
@@ -281,25 +653,247 @@ pub fn set_manual_price(
     ctx: Context<SetManualPrice>,
     _project_id: String,
     price_usd: u64,
+    price_decimals: Option<u8>,
 ) -> Result<()> {
+    let price_usd = match price_decimals {
+        Some(price_decimals) => normalize_usd_price(price_usd, price_decimals)?,
+        None => price_usd,
+    };
+    validate_manual_price(&ctx.accounts.platform_config, price_usd)?;
+    
     let current_time = Clock::get()?.unix_timestamp;
     
     // Update liquidity pool oracle information
     let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    let previous_price_usd = liquidity_pool.oracle_price_usd;
     liquidity_pool.oracle_price_usd = Some(price_usd);
     liquidity_pool.oracle_price_last_update = current_time;
     liquidity_pool.price_source = PriceSource::Manual;
-    liquidity_pool.redemption_locked = false;
+    recover_redemption_lock(liquidity_pool, PriceSource::Manual, current_time);
+    
+    emit!(PriceUpdated {
+        liquidity_pool: liquidity_pool.key(),
+        price_usd,
+        usd_decimals: USD_PRICE_DECIMALS,
+        source: PriceSource::Manual,
+    });
+    
+    emit!(ManualPriceSet {
+        liquidity_pool: liquidity_pool.key(),
+        authority: ctx.accounts.authority.key(),
+        previous_price_usd,
+        price_usd,
+        usd_decimals: USD_PRICE_DECIMALS,
+        timestamp: current_time,
+    });
     
     // Update project's last activity timestamp
     let project = &mut ctx.accounts.project;
     project.last_activity_timestamp = current_time;
     
-    msg!("Manual price set: {} USD", price_usd as f64 / 1_000_000.0);
+    msg!("Manual price set: {} (USD, {} decimals)", price_usd, USD_PRICE_DECIMALS);
+    
+    Ok(())
+}
+
+// Set or clear the bounds on manually set prices
+pub fn set_manual_price_bounds(
+    ctx: Context<SetManualPriceBounds>,
+    min_manual_price: Option<u64>,
+    max_manual_price: Option<u64>,
+) -> Result<()> {
+    if let (Some(min), Some(max)) = (min_manual_price, max_manual_price) {
+        if min > max {
+            return Err(MarketplaceError::InvalidOraclePrice.into());
+        }
+    }
+    
+    let platform_config = &mut ctx.accounts.platform_config;
+    platform_config.min_manual_price = min_manual_price;
+    platform_config.max_manual_price = max_manual_price;
+    
+    msg!("Manual price bounds set: {:?} to {:?}", min_manual_price, max_manual_price);
+    
+    Ok(())
+}
+
+// Choose the asset a pool's price is quoted in. The current price is in the old
+// asset, so it is cleared until the next price update
+pub fn set_quote_asset(
+    ctx: Context<SetQuoteAsset>,
+    _project_id: String,
+    quote_asset: QuoteAsset,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    if liquidity_pool.quote_asset != quote_asset {
+        liquidity_pool.quote_asset = quote_asset;
+        liquidity_pool.oracle_price_usd = None;
+        liquidity_pool.price_source = PriceSource::None;
+        liquidity_pool.quote_price_usd = None;
+        liquidity_pool.quote_price_last_update = 0;
+    }
+    
+    msg!("Pool {} now quoted in {:?} ({} decimals)", liquidity_pool.key(), quote_asset, quote_asset.decimals());
+    
+    Ok(())
+}
+
+// Set how old the pool's oracle price may get before it counts as stale: longer for thinly
+// traded tokens, shorter for busy ones. 0 restores the default
+pub fn set_oracle_staleness(
+    ctx: Context<SetOracleStaleness>,
+    _project_id: String,
+    max_oracle_staleness: i64,
+) -> Result<()> {
+    if max_oracle_staleness < 0 {
+        return Err(MarketplaceError::InvalidOracleStaleness.into());
+    }
+    
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.max_oracle_staleness = max_oracle_staleness;
+    
+    msg!("Oracle staleness window for {} set to {}s (0 uses the default)", liquidity_pool.key(), max_oracle_staleness);
     
     Ok(())
 }
 
+// Set the widest Pyth confidence interval the pool accepts, in basis points of the price.
+// Updates from a feed any less certain are rejected and the last price stays. 0 disables
+pub fn set_oracle_confidence(
+    ctx: Context<SetOracleConfidence>,
+    _project_id: String,
+    max_confidence_bps: u16,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.max_confidence_bps = max_confidence_bps;
+    
+    msg!("Oracle confidence bound for {} set to {} bps", liquidity_pool.key(), max_confidence_bps);
+    
+    Ok(())
+}
+
+// Set the USD price of the pool's quote asset by hand (e.g. SOL/USD)
+pub fn set_quote_price_manually(
+    ctx: Context<SetQuotePrice>,
+    _project_id: String,
+    price_usd: u64,
+) -> Result<()> {
+    validate_oracle_price(price_usd)?;
+    
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    record_quote_price(liquidity_pool, price_usd, Clock::get()?.unix_timestamp)
+}
+
+// Pin the Pyth feed update_price_from_pyth reads the token price from. None blocks Pyth
+// updates until a feed is pinned again
+pub fn set_pyth_price_feed(
+    ctx: Context<SetPriceFeed>,
+    _project_id: String,
+    pyth_price_feed: Option<Pubkey>,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.pyth_price_feed = pyth_price_feed;
+    
+    msg!("Pyth price feed for {} set to {:?}", liquidity_pool.key(), pyth_price_feed);
+    
+    Ok(())
+}
+
+// Pin the DEX reserve accounts update_price_from_dex reads the token price from. Leaving
+// either unset blocks DEX updates
+pub fn set_dex_price_accounts(
+    ctx: Context<SetPriceFeed>,
+    _project_id: String,
+    dex_token_account: Option<Pubkey>,
+    dex_base_account: Option<Pubkey>,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.dex_token_account = dex_token_account;
+    liquidity_pool.dex_base_account = dex_base_account;
+    
+    msg!("DEX price accounts for {} set to {:?} / {:?}", liquidity_pool.key(), dex_token_account, dex_base_account);
+    
+    Ok(())
+}
+
+// Pin the Pyth feed update_quote_price_from_pyth reads the quote asset's USD price from.
+// None blocks Pyth quote updates until a feed is pinned again
+pub fn set_quote_price_feed(
+    ctx: Context<SetPriceFeed>,
+    _project_id: String,
+    quote_price_feed: Option<Pubkey>,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.quote_price_feed = quote_price_feed;
+    
+    msg!("Quote price feed for {} set to {:?}", liquidity_pool.key(), quote_price_feed);
+    
+    Ok(())
+}
+
+// Update the USD price of the pool's quote asset from its pinned Pyth feed (e.g. SOL/USD).
+// A stale feed locks redemptions like a stale token price does, keeping the last quote price
+pub fn update_quote_price_from_pyth(
+    ctx: Context<UpdateQuotePriceFromPyth>,
+    _project_id: String,
+) -> Result<()> {
+    let price_feed: PriceFeed = load_price_feed_from_account_info(&ctx.accounts.pyth_price_account)
+        .map_err(|_| MarketplaceError::StaleOracleFeed)?;
+    
+    // A USD-quoted pool has no quote price, so its feed can't lock redemptions either
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    if liquidity_pool.quote_asset == QuoteAsset::Usd {
+        return Err(MarketplaceError::InvalidOraclePrice.into());
+    }
+    
+    let current_time = Clock::get()?.unix_timestamp;
+    if is_pyth_feed_stale(&price_feed, current_time, max_oracle_staleness(liquidity_pool)) {
+        engage_redemption_lock(liquidity_pool, current_time);
+        msg!("Quote feed is stale (published at {}), NFT redemption locked", price_feed.publish_time);
+        return Ok(());
+    }
+    
+    let price: Price = price_feed.get_current_price_unchecked();
+    check_pyth_confidence(&price, liquidity_pool.max_confidence_bps)?;
+    
+    // Scale the feed's price to USD with 6 decimals
+    let price_usd = pyth_price_to_usd(&price)?;
+    validate_oracle_price(price_usd)?;
+    
+    record_quote_price(liquidity_pool, price_usd, price_feed.publish_time.min(current_time))
+}
+
+// Helper function to store a quote asset USD price on a pool, aged from when it was observed
+fn record_quote_price(liquidity_pool: &mut Account<LiquidityPool>, price_usd: u64, updated_at: i64) -> Result<()> {
+    if liquidity_pool.quote_asset == QuoteAsset::Usd {
+        return Err(MarketplaceError::InvalidOraclePrice.into());
+    }
+    
+    liquidity_pool.quote_price_usd = Some(price_usd);
+    liquidity_pool.quote_price_last_update = updated_at;
+    
+    msg!("{:?} price updated: {} (USD, {} decimals)", liquidity_pool.quote_asset, price_usd, USD_PRICE_DECIMALS);
+    
+    Ok(())
+}
+
+// Asset a pool's oracle price is quoted in
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum QuoteAsset {
+    Usd,            // Priced in USD (scaled by 10^6)
+    Sol,            // Priced in SOL (lamports), converted to USD with the SOL/USD price
+}
+
+impl QuoteAsset {
+    // Decimals prices in this asset are scaled by
+    pub fn decimals(&self) -> u8 {
+        match self {
+            QuoteAsset::Usd => USD_PRICE_DECIMALS,
+            QuoteAsset::Sol => SOL_PRICE_DECIMALS,
+        }
+    }
+}
+
 // Define price source enum to track where the price came from
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
 pub enum PriceSource {
@@ -308,3 +902,90 @@ pub enum PriceSource {
     Manual,         // Manually set price
     None,           // No price source set
 }
+
+// Lock redemptions by hand, e.g. while a feed is known to be misbehaving
+pub fn lock_redemption(
+    ctx: Context<OverrideRedemptionLock>,
+    _project_id: String,
+) -> Result<()> {
+    set_redemption_lock(ctx, true)
+}
+
+// Clear a redemption lock that no price update has lifted, once the authority has verified the feed
+pub fn force_unlock_redemption(
+    ctx: Context<OverrideRedemptionLock>,
+    _project_id: String,
+) -> Result<()> {
+    set_redemption_lock(ctx, false)
+}
+
+// Choose whether fresh price updates lift a redemption lock on their own. With it off, only
+// force_unlock_redemption clears a lock
+pub fn set_redemption_auto_recovery(
+    ctx: Context<OverrideRedemptionLock>,
+    _project_id: String,
+    auto_recover_redemption_lock: bool,
+) -> Result<()> {
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.auto_recover_redemption_lock = auto_recover_redemption_lock;
+    
+    msg!("Redemption lock auto-recovery for {} set to {}", liquidity_pool.key(), auto_recover_redemption_lock);
+    
+    Ok(())
+}
+
+// Lock redemptions, keeping the time an existing lock began
+pub fn engage_redemption_lock(liquidity_pool: &mut LiquidityPool, current_time: i64) {
+    if !liquidity_pool.redemption_locked {
+        liquidity_pool.redemption_locked_since = Some(current_time);
+    }
+    liquidity_pool.redemption_locked = true;
+}
+
+// Lift a redemption lock after a fresh price update, unless the pool leaves that to the authority
+pub fn recover_redemption_lock(
+    liquidity_pool: &mut Account<LiquidityPool>,
+    source: PriceSource,
+    current_time: i64,
+) {
+    if !liquidity_pool.redemption_locked || !liquidity_pool.auto_recover_redemption_lock {
+        return;
+    }
+    
+    let locked_since = liquidity_pool.redemption_locked_since.take();
+    liquidity_pool.redemption_locked = false;
+    
+    emit!(RedemptionLockRecovered {
+        liquidity_pool: liquidity_pool.key(),
+        locked_since,
+        recovered_at: current_time,
+        source,
+    });
+    
+    msg!("Redemption lock lifted by a fresh price update");
+}
+
+// Helper function to override the redemption lock and record who did it
+fn set_redemption_lock(ctx: Context<OverrideRedemptionLock>, locked: bool) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    let was_locked = liquidity_pool.redemption_locked;
+    if locked {
+        engage_redemption_lock(liquidity_pool, current_time);
+    } else {
+        liquidity_pool.redemption_locked = false;
+        liquidity_pool.redemption_locked_since = None;
+    }
+    
+    emit!(RedemptionLockOverridden {
+        liquidity_pool: liquidity_pool.key(),
+        authority: ctx.accounts.authority.key(),
+        was_locked,
+        locked,
+        timestamp: current_time,
+    });
+    
+    msg!("Redemption lock for {} set to {} by authority", liquidity_pool.key(), locked);
+    
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    state::PlatformConfig,
+    errors::MarketplaceError,
+};
+
+#[derive(Accounts)]
+pub struct InitializePlatform<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<PlatformConfig>(),
+        seeds = [b"platform_config"],
+        bump,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Create the platform config, administered by the signer. Optional limits start disabled:
+// no fee cap, no manual price bounds, no swap program, and the platform takes all reclaimed
+// liquidity while projects may set any royalty below 100%
+pub fn initialize_platform(
+    ctx: Context<InitializePlatform>,
+    platform_fee_basis_points: u16,
+    platform_treasury: Pubkey,
+) -> Result<()> {
+    if platform_fee_basis_points >= 10000 {
+        return Err(MarketplaceError::InvalidPlatformFee.into());
+    }
+
+    let platform_config = &mut ctx.accounts.platform_config;
+    platform_config.authority = ctx.accounts.authority.key();
+    platform_config.platform_fee_basis_points = platform_fee_basis_points;
+    platform_config.platform_treasury = platform_treasury;
+    platform_config.max_platform_fee_absolute = None;
+    platform_config.reclaim_split_bps = 10000;
+    platform_config.max_project_royalty_bps = 10000;
+    platform_config.min_fee_transfer = 0;
+    platform_config.min_manual_price = None;
+    platform_config.max_manual_price = None;
+    platform_config.swap_program = None;
+    platform_config.bump = *ctx.bumps.get("platform_config").unwrap();
+
+    msg!("Platform initialized with a {} bps fee", platform_fee_basis_points);
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use anchor_lang::prelude::*;
+use solana_program::clock::Clock;
+
+use crate::{
+    state::{FeeDiscountTier, PlatformConfig, Project},
+    errors::MarketplaceError,
+    modules::fees::{validate_project_royalty, MAX_FEE_DISCOUNT_TIERS},
+};
+
+#[derive(Accounts)]
+#[instruction(project_id: String)]
+pub struct CreateProject<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    /// The new project; init fails if a project with this id already exists
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Project>() + project_id.len()
+            + MAX_FEE_DISCOUNT_TIERS * std::mem::size_of::<FeeDiscountTier>(),
+        seeds = [b"project", project_id.as_bytes()],
+        bump,
+    )]
+    pub project: Account<'info, Project>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Create a project owned by the signer, with its treasury and royalty settings
+pub fn create_project(
+    ctx: Context<CreateProject>,
+    project_id: String,
+    project_treasury: Pubkey,
+    royalty_wallet: Option<Pubkey>,
+    royalty_basis_points: u16,
+) -> Result<()> {
+    // A royalty can never take the whole trade, and stays within the platform's ceiling
+    if royalty_basis_points >= 10000 {
+        return Err(MarketplaceError::InvalidRoyaltyFee.into());
+    }
+    validate_project_royalty(&ctx.accounts.platform_config, royalty_basis_points)?;
+
+    let project = &mut ctx.accounts.project;
+    project.authority = ctx.accounts.authority.key();
+    project.project_id = project_id;
+    project.project_treasury = project_treasury;
+    project.royalty_wallet = royalty_wallet;
+    project.royalty_basis_points = royalty_basis_points;
+    project.last_activity_timestamp = Clock::get()?.unix_timestamp;
+    project.is_active = true;
+    project.fee_discount_tiers = Vec::new();
+    project.royalty_vault = None;
+    project.accrued_royalties = 0;
+    project.max_escrows_per_user = 0;
+    project.buyback_burn_bps = 0;
+    project.bump = *ctx.bumps.get("project").unwrap();
+
+    msg!("Project created: {}", project.project_id);
+
+    Ok(())
+}
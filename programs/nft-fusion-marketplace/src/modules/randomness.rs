@@ -0,0 +1,169 @@
+use anchor_lang::prelude::*;
+use solana_program::clock::Clock;
+
+use crate::{
+    state::{Collection, CollectionTraitConfig, FusionConfig, RandomnessRequest, RandomnessSource},
+    errors::MarketplaceError,
+    modules::traits::generate_random_seed,
+};
+
+#[derive(Accounts)]
+#[instruction(request_id: u64)]
+pub struct RequestRandomness<'info> {
+    #[account(mut)]
+    pub requester: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    /// The fusion config or collection trait config whose randomness source applies
+    /// CHECK: Deserialized as either config type and checked against the collection in the instruction
+    pub source_config: UncheckedAccount<'info>,
+    
+    #[account(
+        init,
+        payer = requester,
+        space = 8 + std::mem::size_of::<RandomnessRequest>(),
+        seeds = [b"randomness", requester.key().as_ref(), request_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub randomness_request: Account<'info, RandomnessRequest>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FulfillRandomness<'info> {
+    // The oracle authority named by the request's source
+    pub oracle: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"randomness", randomness_request.requester.as_ref(), randomness_request.request_id.to_le_bytes().as_ref()],
+        bump = randomness_request.bump,
+    )]
+    pub randomness_request: Account<'info, RandomnessRequest>,
+}
+
+// Resolve the randomness source configured for a collection by a fusion or trait config
+pub fn randomness_source_for(config: &AccountInfo, collection: &Pubkey) -> Result<RandomnessSource> {
+    let (config_collection, source) = if let Ok(fusion_config) = Account::<FusionConfig>::try_from(config) {
+        (fusion_config.collection, fusion_config.randomness_source)
+    } else if let Ok(trait_config) = Account::<CollectionTraitConfig>::try_from(config) {
+        (trait_config.collection, trait_config.randomness_source)
+    } else {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    };
+    
+    if config_collection != *collection {
+        return Err(MarketplaceError::CollectionNotFound.into());
+    }
+    
+    Ok(source)
+}
+
+// Open a randomness request. The slot hash fallback is fulfilled immediately;
+// an oracle request stays pending until its oracle fulfills it
+pub fn request_randomness(
+    ctx: Context<RequestRandomness>,
+    request_id: u64,
+) -> Result<()> {
+    let collection_key = ctx.accounts.collection.key();
+    let source = randomness_source_for(&ctx.accounts.source_config, &collection_key)?;
+    let requested_slot = Clock::get()?.slot;
+    
+    let result = match source {
+        RandomnessSource::SlotHash => Some(generate_random_seed(
+            requested_slot,
+            &collection_key,
+            &ctx.accounts.requester.key(),
+            &request_id.to_le_bytes(),
+        )),
+        RandomnessSource::Oracle { .. } => None,
+    };
+    
+    let randomness_request = &mut ctx.accounts.randomness_request;
+    randomness_request.requester = ctx.accounts.requester.key();
+    randomness_request.collection = collection_key;
+    randomness_request.source = source;
+    randomness_request.request_id = request_id;
+    randomness_request.requested_slot = requested_slot;
+    randomness_request.result = result;
+    randomness_request.consumed = false;
+    randomness_request.bump = *ctx.bumps.get("randomness_request").unwrap();
+    
+    msg!("Randomness requested ({:?}) for {}", source, randomness_request.requester);
+    
+    Ok(())
+}
+
+// Deliver an oracle result for a pending request; only the oracle named by its source may do so.
+// The result is trusted as delivered and not verified on-chain
+pub fn fulfill_randomness(
+    ctx: Context<FulfillRandomness>,
+    result: [u8; 32],
+) -> Result<()> {
+    let randomness_request = &mut ctx.accounts.randomness_request;
+    
+    match randomness_request.source {
+        RandomnessSource::Oracle { oracle } if oracle == ctx.accounts.oracle.key() => {}
+        _ => return Err(MarketplaceError::Unauthorized.into()),
+    }
+    
+    if randomness_request.result.is_some() {
+        return Err(MarketplaceError::RandomnessAlreadyFulfilled.into());
+    }
+    
+    randomness_request.result = Some(result);
+    
+    msg!("Randomness fulfilled for {}", randomness_request.requester);
+    
+    Ok(())
+}
+
+// Take the result of a fulfilled request for one use by its requester
+pub fn consume_randomness(
+    randomness_request: &mut RandomnessRequest,
+    requester: &Pubkey,
+    collection: &Pubkey,
+) -> Result<[u8; 32]> {
+    if randomness_request.requester != *requester || randomness_request.collection != *collection {
+        return Err(MarketplaceError::Unauthorized.into());
+    }
+    
+    if randomness_request.consumed {
+        return Err(MarketplaceError::RandomnessAlreadyConsumed.into());
+    }
+    
+    let result = randomness_request.result
+        .ok_or(MarketplaceError::RandomnessNotFulfilled)?;
+    randomness_request.consumed = true;
+    
+    Ok(result)
+}
+
+// Draw the randomness for one roll. The slot hash fallback hashes the current slot with the keys
+// and entropy; an oracle source consumes the requester's fulfilled request for it instead
+pub fn committed_randomness(
+    source: RandomnessSource,
+    randomness_request: &AccountInfo,
+    requester: &Pubkey,
+    collection: &Pubkey,
+    entropy: &[u8],
+) -> Result<[u8; 32]> {
+    match source {
+        RandomnessSource::SlotHash => Ok(generate_random_seed(Clock::get()?.slot, collection, requester, entropy)),
+        RandomnessSource::Oracle { .. } => {
+            let mut request = Account::<RandomnessRequest>::try_from(randomness_request)
+                .map_err(|_| MarketplaceError::RandomnessNotFulfilled)?;
+            if request.source != source {
+                return Err(MarketplaceError::Unauthorized.into());
+            }
+            
+            let result = consume_randomness(&mut request, requester, collection)?;
+            // Loaded outside the context, so persist the consumed flag by hand
+            request.exit(&crate::ID)?;
+            
+            Ok(result)
+        }
+    }
+}
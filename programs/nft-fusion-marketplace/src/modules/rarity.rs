@@ -2,12 +2,32 @@
 use std::collections::HashMap;
 use std::ops::Deref;
 
-use crate::state::TraitType;
+use crate::state::{TraitComboBonus, TraitType};
 
-// Calculate rarity score based on trait values
-pub fn calculate_rarity_score<'a, T>(
-    trait_types: &'a [T],
+// Rarity multiplier for fused NFTs of levels 1, 2, ... in basis points (10000 = 1x), used when a
+// fusion config has no curve of its own. Levels past the end keep the last multiplier
+pub const DEFAULT_LEVEL_MULTIPLIERS_BPS: [u16; 4] = [11000, 12000, 13500, 15000];
+
+// Multiplier for a fusion level from a level curve, or the default curve if it is empty.
+// Base NFTs (level 0) get no boost
+pub fn level_multiplier_bps(level_multipliers_bps: &[u16], fusion_level: u8) -> u16 {
+    let curve = if level_multipliers_bps.is_empty() {
+        &DEFAULT_LEVEL_MULTIPLIERS_BPS[..]
+    } else {
+        level_multipliers_bps
+    };
+    
+    match fusion_level {
+        0 => 10000,
+        level => curve[(level as usize - 1).min(curve.len() - 1)],
+    }
+}
+
+// Calculate rarity score based on trait values and the combinations they form
+pub fn calculate_rarity_score<T>(
+    trait_types: &[T],
     trait_values: &[(String, String)],
+    combo_bonuses: &[TraitComboBonus],
 ) -> u16 
 where
     T: AsRef<TraitType> + Deref<Target = TraitType>
@@ -31,8 +51,8 @@ where
                     // Invert the weight and scale
                     (max_weight as f32 / value.rarity_weight as f32 * 5.0) as u16
                 } else {
-                    // If weight is 0, assign a high rarity bonus
-                    50
+                    // Zero-weight values can never be rolled, so they earn no rarity bonus
+                    0
                 };
                 
                 // Add to total score
@@ -50,6 +70,19 @@ where
         }
     }
     
+    // Bonus for each recognized combination, on top of the individual trait bonuses
+    for combo in combo_bonuses {
+        let present = combo.traits.iter().all(|attribute| {
+            trait_values
+                .iter()
+                .any(|(trait_name, value_name)| *trait_name == attribute.trait_type && *value_name == attribute.value)
+        });
+        
+        if present {
+            base_score = base_score.saturating_add(combo.bonus);
+        }
+    }
+    
     // Ensure score is within a reasonable range
     base_score.min(1000)
 }
@@ -74,32 +107,27 @@ pub fn calculate_fusion_boost(parent_scores: &[u16]) -> u16 {
 }
 
 // Calculate overall rarity score for a fused NFT
-pub fn calculate_fused_nft_rarity<'a, T>(
-    trait_types: &'a [T],
+pub fn calculate_fused_nft_rarity<T>(
+    trait_types: &[T],
     trait_values: &[(String, String)],
     parent_scores: &[u16],
     fusion_level: u8,
+    level_multipliers_bps: &[u16],
 ) -> u16 
 where
     T: AsRef<TraitType> + Deref<Target = TraitType>
 {
     // Base score from traits
-    let base_score = calculate_rarity_score(trait_types, trait_values);
+    let base_score = calculate_rarity_score(trait_types, trait_values, &[]);
     
     // Fusion boost from parents
     let fusion_boost = calculate_fusion_boost(parent_scores);
     
     // Level multiplier - higher fusion levels get more boost
-    let level_multiplier = match fusion_level {
-        0 => 1.0,     // Base NFT, no boost
-        1 => 1.1,     // First fusion level
-        2 => 1.2,     // Second fusion level
-        3 => 1.35,    // Third fusion level
-        _ => 1.5,     // Max boost for higher levels
-    };
+    let level_multiplier = level_multiplier_bps(level_multipliers_bps, fusion_level) as u32;
     
     // Apply multiplier and add fusion boost
-    let final_score = (base_score as f32 * level_multiplier) as u16 + fusion_boost;
+    let final_score = ((base_score as u32 * level_multiplier / 10000) as u16).saturating_add(fusion_boost);
     
     // Cap at maximum score to prevent inflation
     final_score.min(2000)
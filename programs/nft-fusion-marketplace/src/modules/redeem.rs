@@ -1,17 +1,30 @@
 use anchor_lang::prelude::*;
+use anchor_lang::{system_program, AccountsClose};
 use anchor_spl::{
-    token::{self, Mint, Token, TokenAccount, Transfer},
+    token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer},
     associated_token::AssociatedToken,
 };
-use solana_program::clock::Clock;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+};
 
 use crate::{
-    state::{PlatformConfig, Project, Collection, LiquidityPool, NftData, TokenEscrow},
+    state::{PlatformConfig, Project, Collection, LiquidityPool, NftData, NftListing, PendingClaim, PricingMode, RedemptionReservation, TokenEscrow},
     errors::MarketplaceError,
-    modules::oracle::check_oracle_status,
-    modules::cooldown::check_cooldown_expired,
+    events::NftRedeemed,
+    modules::oracle::{check_oracle_status, get_token_amount_for_usd},
+    modules::cooldown::{check_cooldown_expired, check_min_hold_period, check_vesting_ended, cooldown_bypass_fee, discount_clawback, vested_amount},
+    modules::stats::record_collection_activity,
+    modules::escrow::update_user_escrow_count,
+    modules::fees::{calculate_redemption_fees, FeeBreakdown},
+    modules::lp::redemption_lp_tax,
 };
 
+// Tokens paid out for redeeming a base NFT (1 token with 9 decimals)
+pub const NFT_BASE_TOKEN_VALUE: u64 = 1_000_000_000;
+
 #[derive(Accounts)]
 pub struct RedeemNftForToken<'info> {
     #[account(mut)]
@@ -28,16 +41,30 @@ pub struct RedeemNftForToken<'info> {
         seeds = [b"nft_data", nft_mint.key().as_ref()],
         bump = nft_data.bump,
         constraint = nft_data.owner == user.key() @ MarketplaceError::NotNftOwner,
+        constraint = nft_data.collection == collection.key() @ MarketplaceError::CollectionNotFound,
+        close = user,
     )]
     pub nft_data: Account<'info, NftData>,
     
+    /// The NFT's own collection, whose redemption settings apply
     #[account(
         mut,
         seeds = [b"collection", collection.collection_id.as_bytes()],
         bump = collection.bump,
+        constraint = collection.redeemable @ MarketplaceError::RedemptionLocked,
+        constraint = collection.project == project.key() @ MarketplaceError::CollectionProjectMismatch,
     )]
     pub collection: Account<'info, Collection>,
     
+    /// Stats for this collection, which may not be tracked
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: UncheckedAccount<'info>,
+    
     #[account(
         mut,
         seeds = [b"project", project.project_id.as_bytes()],
@@ -58,6 +85,32 @@ pub struct RedeemNftForToken<'info> {
     #[account(mut)]
     pub nft_mint: Account<'info, Mint>,
     
+    /// The escrow PDA for this NFT, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"token_escrow", nft_mint.key().as_ref()],
+        bump,
+    )]
+    pub token_escrow: UncheckedAccount<'info>,
+    
+    /// The redemption reservation PDA for this NFT, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"redemption_reservation", nft_mint.key().as_ref()],
+        bump,
+    )]
+    pub redemption_reservation: UncheckedAccount<'info>,
+    
+    /// Claim for the unpaid part of a partial redemption, created only when one is needed
+    /// CHECK: Address is pinned by seeds; the account is created in the instruction
+    #[account(
+        mut,
+        seeds = [b"pending_claim", nft_mint.key().as_ref()],
+        bump,
+    )]
+    pub pending_claim: UncheckedAccount<'info>,
+    
     /// The user's NFT token account
     #[account(
         mut,
@@ -85,6 +138,14 @@ pub struct RedeemNftForToken<'info> {
     )]
     pub token_mint: Account<'info, Mint>,
     
+    /// Project fee destination for cooldown bypass fees
+    #[account(
+        mut,
+        constraint = project_treasury.key() == project.project_treasury @ MarketplaceError::InvalidTokenAccount,
+        constraint = project_treasury.mint == token_mint.key() @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub project_treasury: Account<'info, TokenAccount>,
+    
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
@@ -126,9 +187,28 @@ pub struct TokenEscrowRedemption<'info> {
     // Collection this NFT belongs to
     #[account(
         constraint = collection.key() == nft_data.collection @ MarketplaceError::CollectionNotFound,
+        constraint = collection.redeemable @ MarketplaceError::RedemptionLocked,
     )]
     pub collection: Account<'info, Collection>,
     
+    /// Stats for this collection, which may not be tracked
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: UncheckedAccount<'info>,
+    
+    /// Active escrows the user holds in this project
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"user_escrow_count", project.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub user_escrow_count: UncheckedAccount<'info>,
+    
     // Project this collection belongs to
     #[account(
         mut,
@@ -148,6 +228,7 @@ pub struct TokenEscrowRedemption<'info> {
     #[account(
         mut,
         constraint = escrow_token_account.key() == token_escrow.escrow_token_account @ MarketplaceError::InvalidTokenAccount,
+        constraint = escrow_token_account.owner == token_escrow.key() @ MarketplaceError::InvalidEscrowAuthority,
     )]
     pub escrow_token_account: Account<'info, TokenAccount>,
     
@@ -162,7 +243,7 @@ pub struct TokenEscrowRedemption<'info> {
     // Project fee destination
     #[account(
         mut,
-        constraint = project_treasury.owner == project.project_treasury @ MarketplaceError::InvalidTokenAccount,
+        constraint = project_treasury.key() == project.project_treasury @ MarketplaceError::InvalidTokenAccount,
         constraint = project_treasury.mint == token_escrow.token_mint @ MarketplaceError::InvalidTokenAccount,
     )]
     pub project_treasury: Account<'info, TokenAccount>,
@@ -171,90 +252,293 @@ pub struct TokenEscrowRedemption<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey)]
+pub struct ReturnRedeemedNft<'info> {
+    // Project authority approving the reversal
+    pub authority: Signer<'info>,
+    
+    // Original owner returning the redeemed tokens
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    // NFT currently held by the project after redemption
+    #[account(
+        mut,
+        seeds = [b"nft_data", nft_mint.as_ref()],
+        bump = nft_data.bump,
+        constraint = nft_data.owner == project.key() @ MarketplaceError::NftNotRedeemed,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    // Redeemed escrow for this NFT
+    #[account(
+        mut,
+        seeds = [b"token_escrow", nft_mint.as_ref()],
+        bump = token_escrow.bump,
+        constraint = token_escrow.owner == user.key() @ MarketplaceError::NotTokenEscrowOwner,
+        constraint = !token_escrow.is_active @ MarketplaceError::NftNotRedeemed,
+    )]
+    pub token_escrow: Account<'info, TokenEscrow>,
+    
+    // Collection this NFT belongs to
+    #[account(
+        constraint = collection.key() == nft_data.collection @ MarketplaceError::CollectionNotFound,
+    )]
+    pub collection: Account<'info, Collection>,
+    
+    // Project that received the NFT
+    #[account(
+        mut,
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    // User's token account the redeemed tokens are returned from
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = user_token_account.mint == token_escrow.token_mint @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    
+    // Escrow token account the tokens are returned to (recreated if the redemption closed it)
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"escrow_token_account", nft_mint.as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = token_escrow,
+        constraint = escrow_token_account.key() == token_escrow.escrow_token_account @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        constraint = token_mint.key() == token_escrow.token_mint @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub token_mint: Account<'info, Mint>,
+    
+    /// Active escrows the user holds in this project
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"user_escrow_count", project.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub user_escrow_count: UncheckedAccount<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey)]
+pub struct ClaimPendingRedemption<'info> {
+    #[account(mut)]
+    pub claimant: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pending_claim", nft_mint.as_ref()],
+        bump = pending_claim.bump,
+        constraint = pending_claim.claimant == claimant.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub pending_claim: Account<'info, PendingClaim>,
+    
+    #[account(
+        seeds = [b"project", project.project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.key() == pending_claim.project @ MarketplaceError::ProjectNotFound,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquidity_pool", project.key().as_ref()],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    #[account(
+        mut,
+        constraint = lp_token_account.key() == liquidity_pool.lp_token_account,
+    )]
+    pub lp_token_account: Account<'info, TokenAccount>,
+    
+    /// The claimant's token account to receive the owed tokens
+    #[account(
+        mut,
+        constraint = claimant_token_account.owner == claimant.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = claimant_token_account.mint == liquidity_pool.token_mint @ MarketplaceError::InvalidTokenAccount,
+    )]
+    pub claimant_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey)]
+pub struct CloseProjectOwnedNft<'info> {
+    pub authority: Signer<'info>,
+    
+    // Project the NFT was redeemed to, which receives the rent
+    #[account(
+        mut,
+        seeds = [b"project", project.project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"nft_data", nft_mint.as_ref()],
+        bump = nft_data.bump,
+        constraint = nft_data.owner == project.key() @ MarketplaceError::NftNotRedeemed,
+        close = project,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    /// Listing for this NFT, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"listing", nft_mint.as_ref()],
+        bump,
+    )]
+    pub listing: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSwapProgram<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+        constraint = platform_config.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+}
+
 pub fn redeem_escrow_token(
     ctx: Context<TokenEscrowRedemption>,
     nft_mint: Pubkey,
 ) -> Result<()> {
+    // NFTs cannot be redeemed until the collection's minimum hold period has passed
+    check_min_hold_period(&ctx.accounts.nft_data, &ctx.accounts.collection)?;
+    
+    // Redeeming takes the escrowed value out, so it waits for the NFT's cooldown
+    check_cooldown_expired(&ctx.accounts.nft_data)?;
+    
     // Check if vesting period has ended
-    if let Some(vesting_end) = ctx.accounts.token_escrow.vesting_end_timestamp {
-        let current_time = Clock::get()?.unix_timestamp;
-        
-        if current_time < vesting_end {
-            return Err(MarketplaceError::VestingPeriodActive.into());
-        }
+    check_vesting_ended(&ctx.accounts.token_escrow)?;
+    
+    // Get amount to transfer: whatever earlier vested claims have left
+    let token_escrow = &ctx.accounts.token_escrow;
+    let redemption_amount = token_escrow.token_amount
+        .checked_sub(token_escrow.claimed_amount)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    
+    let final_amount = pay_out_escrow(ctx.accounts, nft_mint, redemption_amount)?;
+    
+    let token_escrow = &mut ctx.accounts.token_escrow;
+    token_escrow.claimed_amount = token_escrow.token_amount;
+    token_escrow.redeemed_amount = token_escrow.redeemed_amount.saturating_add(final_amount);
+    
+    finish_escrow_redemption(ctx, nft_mint)
+}
+
+// Claim the share of an escrow that has vested so far, linearly from its creation to the
+// vesting end. Claiming again before more has vested pays nothing; the final claim redeems
+// the NFT exactly as redeem_escrow_token does
+pub fn claim_vested_escrow(
+    ctx: Context<TokenEscrowRedemption>,
+    nft_mint: Pubkey,
+) -> Result<()> {
+    // Claims take escrowed value out, so they wait like a full redemption would
+    check_min_hold_period(&ctx.accounts.nft_data, &ctx.accounts.collection)?;
+    check_cooldown_expired(&ctx.accounts.nft_data)?;
+    
+    let token_escrow = &ctx.accounts.token_escrow;
+    let claim_amount = vested_amount(token_escrow)?.saturating_sub(token_escrow.claimed_amount);
+    if claim_amount == 0 {
+        msg!("Nothing newly vested in the escrow for NFT {}", nft_mint);
+        return Ok(());
     }
     
-    // Get amount to transfer
-    let redemption_amount = ctx.accounts.token_escrow.token_amount;
+    // Fees are taken from each claim, so they add up to the fees of a single redemption
+    let final_amount = pay_out_escrow(ctx.accounts, nft_mint, claim_amount)?;
     
-    // Calculate redemption fee (small fee to prevent abuse)
-    let platform_fee_bps = ctx.accounts.platform_config.platform_fee_basis_points as u64;
-    let redemption_fee = redemption_amount
-        .checked_mul(platform_fee_bps as u64)
-        .ok_or(MarketplaceError::CalculationOverflow)?
-        .checked_div(10000)
-        .ok_or(MarketplaceError::CalculationOverflow)?;
-        
-    let project_fee_bps = ctx.accounts.project.royalty_basis_points as u64;
-    let project_redemption_fee = redemption_amount
-        .checked_mul(project_fee_bps as u64)
-        .ok_or(MarketplaceError::CalculationOverflow)?
-        .checked_div(10000)
-        .ok_or(MarketplaceError::CalculationOverflow)?;
-        
-    // Calculate final amount to transfer to user
-    let final_amount = redemption_amount
-        .checked_sub(redemption_fee)
-        .ok_or(MarketplaceError::CalculationOverflow)?
-        .checked_sub(project_redemption_fee)
+    let token_escrow = &mut ctx.accounts.token_escrow;
+    token_escrow.claimed_amount = token_escrow.claimed_amount
+        .checked_add(claim_amount)
         .ok_or(MarketplaceError::CalculationOverflow)?;
+    token_escrow.redeemed_amount = token_escrow.redeemed_amount.saturating_add(final_amount);
     
-    // Transfer tokens from escrow to user
-    token::transfer(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            token::Transfer {
-                from: ctx.accounts.escrow_token_account.to_account_info(),
-                to: ctx.accounts.user_token_account.to_account_info(),
-                authority: ctx.accounts.token_escrow.to_account_info(),
-            },
-            &[&[
-                b"token_escrow", 
-                nft_mint.as_ref(), 
-                &[ctx.accounts.token_escrow.bump]
-            ]],
-        ),
-        final_amount,
-    )?;
+    msg!("Claimed {} vested tokens ({} of {}) from the escrow for NFT {}", claim_amount, token_escrow.claimed_amount, token_escrow.token_amount, nft_mint);
+    
+    if token_escrow.claimed_amount < token_escrow.token_amount {
+        ctx.accounts.project.last_activity_timestamp = Clock::get()?.unix_timestamp;
+        return Ok(());
+    }
+    
+    finish_escrow_redemption(ctx, nft_mint)
+}
+
+// Pay an amount out of an escrow, less redemption fees (small fees to prevent abuse).
+// Returns what the user got
+fn pay_out_escrow<'info>(accounts: &TokenEscrowRedemption<'info>, nft_mint: Pubkey, amount: u64) -> Result<u64> {
+    let FeeBreakdown {
+        platform_fee: redemption_fee,
+        project_fee: project_redemption_fee,
+        net_amount: final_amount,
+        ..
+    } = calculate_redemption_fees(&accounts.platform_config, &accounts.project, amount)?;
     
-    // Transfer redemption fee to platform treasury
-    if redemption_fee > 0 {
+    let bump = [accounts.token_escrow.bump];
+    let signer_seeds: &[&[&[u8]]] = &[&[b"token_escrow", nft_mint.as_ref(), &bump]];
+    let transfer_from_escrow = |to: AccountInfo<'info>, amount: u64| -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
         token::transfer(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
+                accounts.token_program.to_account_info(),
                 token::Transfer {
-                    from: ctx.accounts.escrow_token_account.to_account_info(),
-                    to: ctx.accounts.platform_treasury.to_account_info(),
-                    authority: ctx.accounts.token_escrow.to_account_info(),
+                    from: accounts.escrow_token_account.to_account_info(),
+                    to,
+                    authority: accounts.token_escrow.to_account_info(),
                 },
-                &[&[
-                    b"token_escrow", 
-                    nft_mint.as_ref(), 
-                    &[ctx.accounts.token_escrow.bump]
-                ]],
+                signer_seeds,
             ),
-            redemption_fee,
-        )?;
-    }
+            amount,
+        )
+    };
     
-    // Transfer project redemption fee to project treasury
-    if project_redemption_fee > 0 {
-        token::transfer(
+    // Transfer tokens from escrow to user, then the fees to the platform and project treasuries
+    transfer_from_escrow(accounts.user_token_account.to_account_info(), final_amount)?;
+    transfer_from_escrow(accounts.platform_treasury.to_account_info(), redemption_fee)?;
+    transfer_from_escrow(accounts.project_treasury.to_account_info(), project_redemption_fee)?;
+    
+    Ok(final_amount)
+}
+
+// Wind up an escrow whose tokens have all been paid out: close its token account and hand the
+// NFT to the project
+fn finish_escrow_redemption(ctx: Context<TokenEscrowRedemption>, nft_mint: Pubkey) -> Result<()> {
+    // Close the emptied escrow token account and refund its rent to the user
+    ctx.accounts.escrow_token_account.reload()?;
+    if ctx.accounts.escrow_token_account.amount == 0 {
+        token::close_account(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.escrow_token_account.to_account_info(),
-                    to: ctx.accounts.project_treasury.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.escrow_token_account.to_account_info(),
+                    destination: ctx.accounts.user.to_account_info(),
                     authority: ctx.accounts.token_escrow.to_account_info(),
                 },
                 &[&[
@@ -263,60 +547,390 @@ pub fn redeem_escrow_token(
                     &[ctx.accounts.token_escrow.bump]
                 ]],
             ),
-            project_redemption_fee,
         )?;
     }
     
-    // Mark escrow as inactive
-    let token_escrow = &mut ctx.accounts.token_escrow;
-    token_escrow.is_active = false;
+    // Mark escrow as inactive; redeemed_amount keeps the payout in case the project reverses it
+    ctx.accounts.token_escrow.is_active = false;
+    
+    // A redeemed escrow no longer counts against the user's escrow cap
+    update_user_escrow_count(&ctx.accounts.user_escrow_count, |count| {
+        count.active_escrows = count.active_escrows.saturating_sub(1)
+    })?;
     
     // Burn or close the NFT (in a real implementation, you would burn the NFT)
     // For now, we'll just mark it as redeemed by updating the NFT data
     let nft_data = &mut ctx.accounts.nft_data;
     nft_data.owner = ctx.accounts.project.key(); // Transfer ownership to project
+    nft_data.reference_count = nft_data.reference_count.saturating_sub(1); // Released by the escrow
+    
+    record_collection_activity(
+        &ctx.accounts.collection_stats,
+        &ctx.accounts.collection.key(),
+        |stats| stats.redemptions = stats.redemptions.saturating_add(1),
+    )?;
     
     // Update project's last activity timestamp
     let project = &mut ctx.accounts.project;
     project.last_activity_timestamp = Clock::get()?.unix_timestamp;
     
+    emit!(NftRedeemed {
+        user: ctx.accounts.user.key(),
+        nft_mint,
+        collection: ctx.accounts.collection.key(),
+        token_amount: ctx.accounts.token_escrow.redeemed_amount,
+        pending_amount: 0,
+        from_escrow: true,
+    });
+    
     msg!("NFT redeemed for tokens from escrow: {}", nft_mint);
     
     Ok(())
 }
 
+// Refuse to close an NFT data account while anything still references it
+pub fn ensure_nft_data_unreferenced(nft_data: &NftData) -> Result<()> {
+    if nft_data.reference_count > 0 {
+        return Err(MarketplaceError::NftStillReferenced.into());
+    }
+    
+    Ok(())
+}
+
+// Close an NFT data account, refusing while anything still references it. Anchor's close marks
+// the account closed as well as draining its rent, so refunding it later in the same transaction
+// doesn't bring it back. Accounts in an instruction's context are written back when it exits, so
+// those close through their `close` constraint instead
+pub fn safe_close_nft_data<'info>(
+    nft_data: &Account<'info, NftData>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    ensure_nft_data_unreferenced(nft_data)?;
+    nft_data.close(destination.clone())
+}
+
 pub fn redeem_nft_for_token(
     ctx: Context<RedeemNftForToken>,
     nft_mint: Pubkey,
+    bypass_cooldown: bool,
 ) -> Result<()> {
     // Ensure NFT mint matches the one in context
     if ctx.accounts.nft_mint.key() != nft_mint {
         return Err(MarketplaceError::NotNftOwner.into());
     }
     
-    // Check oracle status to ensure price feed is valid
-    check_oracle_status(&ctx.accounts.liquidity_pool)?;
+    // An NFT whose value is still locked in escrow cannot also be redeemed from the pool
+    if let Ok(token_escrow) = Account::<TokenEscrow>::try_from(&ctx.accounts.token_escrow) {
+        if token_escrow.is_active {
+            return Err(MarketplaceError::EscrowStillActive.into());
+        }
+    }
+    
+    // A funded reservation fixes the payout, so the oracle does not matter
+    let mut reservation = Account::<RedemptionReservation>::try_from(&ctx.accounts.redemption_reservation)
+        .ok()
+        .filter(|reservation| reservation.reserved_amount > 0);
     
-    // Check if the NFT is still in cooldown period
-    check_cooldown_expired(&ctx.accounts.nft_data)?;
+    // The reservation is paid from this pool, so it must have been funded by this project
+    if let Some(reservation) = &reservation {
+        if reservation.project != ctx.accounts.project.key() {
+            return Err(MarketplaceError::PoolProjectMismatch.into());
+        }
+    }
     
-    // Calculate token amount to redeem
-    // For simplicity in this MVP, we'll use a 1:1 ratio
-    // In a production system, you would calculate based on oracle price
-    let token_amount: u64 = 1_000_000_000; // 1 token with 9 decimals
+    let (mut token_amount, floor_applied) = match &reservation {
+        Some(reservation) => (reservation.reserved_amount, false),
+        None => {
+            // Check oracle status to ensure price feed is valid
+            check_oracle_status(&ctx.accounts.liquidity_pool)?;
+            
+            redemption_value(&ctx.accounts.collection, &ctx.accounts.liquidity_pool)?
+        }
+    };
+    let gross_amount = token_amount;
+    let mut clawback = 0;
+    
+    // The minimum hold period applies even when the cooldown is bypassed
+    check_min_hold_period(&ctx.accounts.nft_data, &ctx.accounts.collection)?;
+    
+    // Either pay the collection's bypass fee to the project or wait out the cooldown
+    if bypass_cooldown {
+        let bypass_fee = cooldown_bypass_fee(&ctx.accounts.nft_data, &ctx.accounts.collection, token_amount)?;
+        
+        if ctx.accounts.user_token_account.amount < bypass_fee {
+            return Err(MarketplaceError::CooldownBypassFeeUnpaid.into());
+        }
+        
+        if bypass_fee > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.user_token_account.to_account_info(),
+                        to: ctx.accounts.project_treasury.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                bypass_fee,
+            )?;
+        }
+        
+        // Leaving early also forfeits the unserved share of a decaying discount, which stays in the pool
+        clawback = discount_clawback(&ctx.accounts.nft_data, &ctx.accounts.collection, token_amount)?;
+        if clawback > 0 {
+            token_amount -= clawback;
+            msg!("Discount decay clawed back {} tokens from the redemption of {}", clawback, nft_mint);
+        }
+    } else {
+        check_cooldown_expired(&ctx.accounts.nft_data)?;
+    }
+    
+    // The pool keeps its LP tax, except from payouts fixed by a reservation or guaranteed by the floor
+    let lp_tax = if reservation.is_none() && !floor_applied {
+        redemption_lp_tax(token_amount, ctx.accounts.liquidity_pool.redemption_lp_tax_bps)?
+    } else {
+        0
+    };
+    if lp_tax > 0 {
+        token_amount -= lp_tax;
+        msg!("Redemption LP tax kept {} tokens in the pool from the redemption of {}", lp_tax, nft_mint);
+    }
     
-    // Check if liquidity pool has enough tokens
-    if ctx.accounts.lp_token_account.amount < token_amount {
+    // Check if liquidity pool has enough tokens. Fees awaiting transfer are never available,
+    // and unreserved redemptions cannot touch reserved liquidity or liquidity owed to claims
+    let liquidity_pool = &ctx.accounts.liquidity_pool;
+    let available_liquidity = match reservation {
+        Some(_) => ctx.accounts.lp_token_account.amount.saturating_sub(pending_pool_fees(liquidity_pool)),
+        None => claimable_liquidity(liquidity_pool, &ctx.accounts.lp_token_account)?
+            .saturating_sub(liquidity_pool.pending_claims),
+    };
+    
+    // A pool that allows partial redemptions pays what it has and owes the rest, except
+    // that a floor payout is only guaranteed if the pool can fund it in full
+    let shortfall = token_amount.saturating_sub(available_liquidity);
+    if shortfall > 0 && (floor_applied || !liquidity_pool.allow_partial_redemption) {
         return Err(MarketplaceError::InsufficientLiquidity.into());
     }
+    let payout = token_amount - shortfall;
+    
+    // Every token of the redemption value is paid, owed, or kept in the pool
+    let accounted = payout
+        .checked_add(shortfall)
+        .and_then(|v| v.checked_add(clawback))
+        .and_then(|v| v.checked_add(lp_tax))
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    if accounted != gross_amount {
+        return Err(MarketplaceError::CalculationOverflow.into());
+    }
     
     // Transfer tokens from LP account to user
+    if payout > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.lp_token_account.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.liquidity_pool.to_account_info(),
+                },
+                &[&[
+                    b"liquidity_pool",
+                    ctx.accounts.project.key().as_ref(),
+                    &[ctx.accounts.liquidity_pool.bump],
+                ]],
+            ),
+            payout,
+        )?;
+    }
+    
+    if shortfall > 0 {
+        open_pending_claim(
+            &ctx.accounts.pending_claim,
+            &ctx.accounts.user.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &PendingClaim {
+                claimant: ctx.accounts.user.key(),
+                project: ctx.accounts.project.key(),
+                nft_mint,
+                amount: shortfall,
+                created_at: Clock::get()?.unix_timestamp,
+                bump: *ctx.bumps.get("pending_claim").unwrap(),
+            },
+        )?;
+        
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        liquidity_pool.pending_claims = liquidity_pool.pending_claims
+            .checked_add(shortfall)
+            .ok_or(MarketplaceError::CalculationOverflow)?;
+        
+        msg!("Redemption of {} paid {} tokens, {} owed as a pending claim", nft_mint, payout, shortfall);
+    }
+    
+    // Update NFT data to mark as redeemed
+    // In a real implementation, you would burn the NFT or transfer it to a null account
+    // For this MVP, we'll just close the NFT data account
+    
+    // Update project's last activity timestamp
+    let project = &mut ctx.accounts.project;
+    project.last_activity_timestamp = Clock::get()?.unix_timestamp;
+    
+    // Update liquidity pool's last activity timestamp
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.last_activity = Clock::get()?.unix_timestamp;
+    
+    // Release the reservation that was just paid out
+    if let Some(reservation) = reservation.as_mut() {
+        liquidity_pool.reserved_liquidity = liquidity_pool
+            .reserved_liquidity
+            .saturating_sub(reservation.reserved_amount);
+        reservation.reserved_amount = 0;
+        reservation.exit(&crate::ID)?;
+    }
+    
+    record_collection_activity(
+        &ctx.accounts.collection_stats,
+        &ctx.accounts.collection.key(),
+        |stats| stats.redemptions = stats.redemptions.saturating_add(1),
+    )?;
+    
+    // The NFT data account is closed on exit and its rent refunded to the user
+    ensure_nft_data_unreferenced(&ctx.accounts.nft_data)?;
+    
+    emit!(NftRedeemed {
+        user: ctx.accounts.user.key(),
+        nft_mint,
+        collection: ctx.accounts.collection.key(),
+        token_amount: payout,
+        pending_amount: shortfall,
+        from_escrow: false,
+    });
+    
+    msg!("NFT redeemed for tokens: {}", nft_mint);
+    
+    Ok(())
+}
+
+// Approve the DEX program redemptions may swap through to SOL, or disable swaps with None
+pub fn set_swap_program(
+    ctx: Context<SetSwapProgram>,
+    swap_program: Option<Pubkey>,
+) -> Result<()> {
+    ctx.accounts.platform_config.swap_program = swap_program;
+    
+    msg!("Redemption swap program set to {:?}", swap_program);
+    
+    Ok(())
+}
+
+// Redeem an NFT for tokens, then swap them to wrapped SOL through the platform's approved swap
+// program in the same transaction. remaining_accounts holds the swap program, the user's wrapped
+// SOL account, then the accounts of the swap instruction in order. The swap may only spend what
+// the redemption paid out, and must return at least min_sol_out
+pub fn redeem_nft_for_sol<'info>(
+    ctx: Context<'_, '_, '_, 'info, RedeemNftForToken<'info>>,
+    nft_mint: Pubkey,
+    bypass_cooldown: bool,
+    swap_data: Vec<u8>,
+    min_sol_out: u64,
+) -> Result<()> {
+    let (swap_program, user_sol_account, route_accounts) = match ctx.remaining_accounts {
+        [swap_program, user_sol_account, route_accounts @ ..] => (swap_program, user_sol_account, route_accounts),
+        _ => return Err(MarketplaceError::SwapProgramNotApproved.into()),
+    };
+    if ctx.accounts.platform_config.swap_program != Some(swap_program.key()) || !swap_program.executable {
+        return Err(MarketplaceError::SwapProgramNotApproved.into());
+    }
+    
+    let user = ctx.accounts.user.key();
+    let sol_balance = move |account: &AccountInfo<'info>| -> Result<u64> {
+        let sol_account = Account::<TokenAccount>::try_from(account)
+            .map_err(|_| MarketplaceError::InvalidTokenAccount)?;
+        if sol_account.owner != user || sol_account.mint != spl_token::native_mint::ID {
+            return Err(MarketplaceError::InvalidTokenAccount.into());
+        }
+        Ok(sol_account.amount)
+    };
+    let sol_before = sol_balance(user_sol_account)?;
+    let tokens_before = ctx.accounts.user_token_account.amount;
+    
+    redeem_nft_for_token(
+        Context::new(ctx.program_id, &mut *ctx.accounts, &[], ctx.bumps.clone()),
+        nft_mint,
+        bypass_cooldown,
+    )?;
+    
+    // Tokens the user held before redeeming (less any bypass fee) stay out of the swap
+    ctx.accounts.user_token_account.reload()?;
+    let token_floor = tokens_before.min(ctx.accounts.user_token_account.amount);
+    
+    invoke(
+        &Instruction {
+            program_id: swap_program.key(),
+            accounts: route_accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.key(),
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: swap_data,
+        },
+        ctx.remaining_accounts,
+    )?;
+    
+    ctx.accounts.user_token_account.reload()?;
+    if ctx.accounts.user_token_account.amount < token_floor {
+        return Err(MarketplaceError::SwapOverspent.into());
+    }
+    
+    let sol_out = sol_balance(user_sol_account)?.saturating_sub(sol_before);
+    if sol_out < min_sol_out {
+        return Err(MarketplaceError::SlippageExceeded.into());
+    }
+    
+    msg!("NFT {} redeemed and swapped for {} lamports of wrapped SOL", nft_mint, sol_out);
+    
+    Ok(())
+}
+
+// Tokens an unreserved redemption pays: the base value, raised to the collection's USD floor
+// at the current price when that is worth more. Also reports whether the floor set the amount
+pub fn redemption_value(collection: &Collection, liquidity_pool: &LiquidityPool) -> Result<(u64, bool)> {
+    // A collection's own USD value wins; otherwise legacy pools pay a fixed 1:1 base value
+    // until their operator opts into oracle pricing
+    let base_value = match (collection.redemption_value_usd, liquidity_pool.pricing_mode) {
+        (Some(value_usd), _) => get_token_amount_for_usd(liquidity_pool, value_usd)?,
+        (None, PricingMode::Flat) => NFT_BASE_TOKEN_VALUE,
+        (None, PricingMode::OraclePriced) => get_token_amount_for_usd(liquidity_pool, liquidity_pool.redemption_value_usd)?,
+    };
+    
+    match collection.redemption_floor_usd {
+        Some(floor_usd) => {
+            let floor_value = get_token_amount_for_usd(liquidity_pool, floor_usd)?;
+            Ok((base_value.max(floor_value), floor_value > base_value))
+        }
+        None => Ok((base_value, false)),
+    }
+}
+
+// Pay out as much of a pending claim as the pool can cover, closing the claim once it is settled
+pub fn claim_pending_redemption(
+    ctx: Context<ClaimPendingRedemption>,
+    nft_mint: Pubkey,
+) -> Result<()> {
+    let available_liquidity = claimable_liquidity(&ctx.accounts.liquidity_pool, &ctx.accounts.lp_token_account)?;
+    let payout = ctx.accounts.pending_claim.amount.min(available_liquidity);
+    if payout == 0 {
+        return Err(MarketplaceError::InsufficientLiquidity.into());
+    }
+    
     token::transfer(
         CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             Transfer {
                 from: ctx.accounts.lp_token_account.to_account_info(),
-                to: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.claimant_token_account.to_account_info(),
                 authority: ctx.accounts.liquidity_pool.to_account_info(),
             },
             &[&[
@@ -325,33 +939,155 @@ pub fn redeem_nft_for_token(
                 &[ctx.accounts.liquidity_pool.bump],
             ]],
         ),
-        token_amount,
+        payout,
     )?;
     
-    // Update NFT data to mark as redeemed
-    // In a real implementation, you would burn the NFT or transfer it to a null account
-    // For this MVP, we'll just close the NFT data account
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    liquidity_pool.pending_claims = liquidity_pool.pending_claims.saturating_sub(payout);
+    liquidity_pool.last_activity = Clock::get()?.unix_timestamp;
+    
+    let pending_claim = &mut ctx.accounts.pending_claim;
+    pending_claim.amount -= payout;
+    let remaining = pending_claim.amount;
+    
+    if remaining == 0 {
+        ctx.accounts.pending_claim.close(ctx.accounts.claimant.to_account_info())?;
+    }
+    
+    msg!("Pending claim for {} paid {} tokens, {} still owed", nft_mint, payout, remaining);
+    
+    Ok(())
+}
+
+// Fees held in the pool until they reach the transfer threshold
+fn pending_pool_fees(liquidity_pool: &LiquidityPool) -> u64 {
+    liquidity_pool.pending_platform_fees
+        .saturating_add(liquidity_pool.pending_project_fees)
+}
+
+// Pool balance free for unreserved payouts: everything but pending fees and reserved liquidity
+pub fn claimable_liquidity(liquidity_pool: &LiquidityPool, lp_token_account: &TokenAccount) -> Result<u64> {
+    Ok(lp_token_account.amount
+        .saturating_sub(pending_pool_fees(liquidity_pool))
+        .saturating_sub(liquidity_pool.reserved_liquidity))
+}
+
+// Create the pending claim PDA for the unpaid part of a redemption. Lamports already sent to
+// the address are kept, as Anchor's init does, so pre-funding it cannot block the claim
+fn open_pending_claim<'info>(
+    pending_claim: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    claim: &PendingClaim,
+) -> Result<()> {
+    let space = 8 + std::mem::size_of::<PendingClaim>();
+    let rent_due = Rent::get()?.minimum_balance(space).saturating_sub(pending_claim.lamports());
+    let signer_seeds: &[&[&[u8]]] = &[&[b"pending_claim", claim.nft_mint.as_ref(), &[claim.bump]]];
+    
+    if rent_due > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: pending_claim.clone(),
+                },
+            ),
+            rent_due,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Allocate { account_to_allocate: pending_claim.clone() },
+            signer_seeds,
+        ),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Assign { account_to_assign: pending_claim.clone() },
+            signer_seeds,
+        ),
+        &crate::ID,
+    )?;
+    
+    let mut data = pending_claim.try_borrow_mut_data()?;
+    claim.try_serialize(&mut &mut data[..])
+}
+
+// Reverse a mistaken escrow redemption: the user hands back the payout and regains the NFT
+pub fn return_redeemed_nft(
+    ctx: Context<ReturnRedeemedNft>,
+    nft_mint: Pubkey,
+) -> Result<()> {
+    let return_amount = ctx.accounts.token_escrow.redeemed_amount;
+    
+    if ctx.accounts.user_token_account.amount < return_amount {
+        return Err(MarketplaceError::InsufficientTokenBalance.into());
+    }
+    
+    // Return the redeemed tokens to the escrow
+    if return_amount > 0 {
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.escrow_token_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            return_amount,
+        )?;
+    }
+    
+    // Reactivate the escrow with the returned balance (fees already paid are not refunded)
+    let token_escrow = &mut ctx.accounts.token_escrow;
+    token_escrow.token_amount = return_amount;
+    token_escrow.claimed_amount = 0;
+    token_escrow.redeemed_amount = 0;
+    token_escrow.is_active = true;
+    
+    // The reactivated escrow counts against the user's escrow cap again. The project
+    // approved the reversal, so it isn't refused for being over the cap
+    update_user_escrow_count(&ctx.accounts.user_escrow_count, |count| {
+        count.active_escrows = count.active_escrows.saturating_add(1)
+    })?;
+    
+    // Give the NFT back to the user, held by the reactivated escrow again
+    let nft_data = &mut ctx.accounts.nft_data;
+    nft_data.owner = ctx.accounts.user.key();
+    nft_data.reference_count = nft_data.reference_count
+        .checked_add(1)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
     
     // Update project's last activity timestamp
     let project = &mut ctx.accounts.project;
     project.last_activity_timestamp = Clock::get()?.unix_timestamp;
     
-    // Update liquidity pool's last activity timestamp
-    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
-    liquidity_pool.last_activity = Clock::get()?.unix_timestamp;
+    msg!("Redeemed NFT returned to owner: {}", nft_mint);
     
-    // Close the NFT data account and refund rent to user
-    let nft_data_account_info = ctx.accounts.nft_data.to_account_info();
-    let destination_account_info = ctx.accounts.user.to_account_info();
-    let rent_balance = nft_data_account_info.lamports();
+    Ok(())
+}
+
+// Close the data account of an NFT redeemed to the project, refunding its rent to the project.
+// The redemption can no longer be reversed once its NFT data is gone
+pub fn close_project_owned_nft(
+    ctx: Context<CloseProjectOwnedNft>,
+    nft_mint: Pubkey,
+) -> Result<()> {
+    if let Ok(listing) = Account::<NftListing>::try_from(&ctx.accounts.listing) {
+        if listing.is_active {
+            return Err(MarketplaceError::NftStillReferenced.into());
+        }
+    }
     
-    **nft_data_account_info.try_borrow_mut_lamports()? = 0;
-    **destination_account_info.try_borrow_mut_lamports()? = destination_account_info
-        .lamports()
-        .checked_add(rent_balance)
-        .ok_or(MarketplaceError::CalculationOverflow)?;
+    // The NFT data account is closed on exit and its rent refunded to the project
+    ensure_nft_data_unreferenced(&ctx.accounts.nft_data)?;
     
-    msg!("NFT redeemed for tokens: {}", nft_mint);
+    msg!("Closed project-owned NFT data: {}", nft_mint);
     
     Ok(())
 }
@@ -0,0 +1,112 @@
+use anchor_lang::prelude::*;
+use solana_program::clock::Clock;
+
+use crate::{
+    state::{Project, Collection, CollectionStats},
+    errors::MarketplaceError,
+    events::CollectionStatsReported,
+};
+
+#[derive(Accounts)]
+pub struct InitializeCollectionStats<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        constraint = collection.project == project.key() @ MarketplaceError::CollectionProjectMismatch,
+    )]
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<CollectionStats>(),
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetCollectionStats<'info> {
+    #[account(
+        seeds = [b"collection_stats", collection_stats.collection.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+}
+
+// Start tracking stats for a collection; activity before this point is not counted
+pub fn initialize_collection_stats(ctx: Context<InitializeCollectionStats>) -> Result<()> {
+    let collection_stats = &mut ctx.accounts.collection_stats;
+    collection_stats.collection = ctx.accounts.collection.key();
+    collection_stats.mints = 0;
+    collection_stats.redemptions = 0;
+    collection_stats.fusions = 0;
+    collection_stats.total_volume = 0;
+    collection_stats.floor_price = None;
+    collection_stats.last_updated = Clock::get()?.unix_timestamp;
+    collection_stats.bump = *ctx.bumps.get("collection_stats").unwrap();
+    
+    msg!("Collection stats tracking started for collection: {}", ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Emit the current stats so clients can read them from a simulated transaction
+pub fn get_collection_stats(ctx: Context<GetCollectionStats>) -> Result<()> {
+    let stats = &ctx.accounts.collection_stats;
+    
+    emit!(CollectionStatsReported {
+        collection: stats.collection,
+        mints: stats.mints,
+        redemptions: stats.redemptions,
+        fusions: stats.fusions,
+        total_volume: stats.total_volume,
+        floor_price: stats.floor_price,
+        last_updated: stats.last_updated,
+    });
+    
+    Ok(())
+}
+
+// Apply an update to a collection's stats account (pinned by seeds in the calling
+// instruction). Collections that have not initialized stats are skipped
+pub fn record_collection_activity<'info>(
+    stats_info: &AccountInfo<'info>,
+    collection: &Pubkey,
+    update: impl FnOnce(&mut CollectionStats),
+) -> Result<()> {
+    let mut stats = match Account::<CollectionStats>::try_from(stats_info) {
+        Ok(stats) => stats,
+        Err(_) => return Ok(()),
+    };
+    
+    if stats.collection != *collection {
+        return Err(MarketplaceError::CollectionNotFound.into());
+    }
+    
+    update(&mut stats);
+    stats.last_updated = Clock::get()?.unix_timestamp;
+    
+    stats.exit(&crate::ID)
+}
+
+// Count a swap: one more NFT, its price added to volume and checked against the floor
+pub fn record_swap(stats: &mut CollectionStats, price: u64) {
+    stats.mints = stats.mints.saturating_add(1);
+    stats.total_volume = stats.total_volume.saturating_add(price);
+    stats.floor_price = Some(stats.floor_price.map_or(price, |floor| floor.min(price)));
+}
+
+// Count a secondary sale: its price is added to volume, but no NFT is minted
+pub fn record_sale(stats: &mut CollectionStats, price: u64) {
+    stats.total_volume = stats.total_volume.saturating_add(price);
+}
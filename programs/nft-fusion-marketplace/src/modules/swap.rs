@@ -6,9 +6,17 @@ use anchor_spl::{
 use solana_program::clock::Clock;
 
 use crate::{
-    state::{PlatformConfig, Project, Collection, LiquidityPool, NftData},
+    state::{PlatformConfig, Project, Collection, LiquidityPool, NftData, RoyaltyConfig, UserTier},
     errors::MarketplaceError,
-    modules::{mint::mint_nft_internal, fees::distribute_fees, oracle::check_oracle_status},
+    events::{NftSwapped, SwapQuote},
+    modules::{
+        mint::{check_mint_window, consume_mint_allowance, mint_base_price, mint_nft_internal},
+        fees::{calculate_fees, distribute_fees, get_fee_discount_bps, is_platform_fee_exempt, FeeBreakdown},
+        oracle::{check_oracle_status, record_trade_price, USD_PRICE_DECIMALS},
+        traits::price_trait_selection,
+        cooldown::{clock_now, cooldown_end_for_rarity, loyalty_cooldown_reduction},
+        stats::{record_collection_activity, record_swap},
+    },
 };
 
 #[derive(Accounts)]
@@ -26,49 +34,71 @@ pub struct SwapTokenForNft<'info> {
     #[account(
         mut,
         seeds = [b"collection", collection_id.as_bytes()],
-        bump,
+        bump = collection.bump,
     )]
     pub collection: Account<'info, Collection>,
 
+    /// Stats for this collection, which may not be tracked
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"collection_stats", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_stats: UncheckedAccount<'info>,
+
+    /// Mint rate limit for this collection, which may not be set
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"mint_rate_limit", collection.key().as_ref()],
+        bump,
+    )]
+    pub mint_rate_limit: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [b"project", project.project_id.as_bytes()],
         bump = project.bump,
         constraint = project.is_active @ MarketplaceError::ProjectNotFound,
+        constraint = collection.project == project.key() @ MarketplaceError::CollectionProjectMismatch,
     )]
     pub project: Account<'info, Project>,
 
+    // Checked by its project field rather than seeds so a foreign pool reports a specific error
     #[account(
         mut,
-        seeds = [b"liquidity_pool", project.key().as_ref()],
-        bump = liquidity_pool.bump,
+        constraint = liquidity_pool.project == project.key() @ MarketplaceError::PoolProjectMismatch,
     )]
     pub liquidity_pool: Account<'info, LiquidityPool>,
 
     #[account(
         mut,
-        constraint = user_token_account.owner == user.key(),
-        constraint = user_token_account.mint == token_mint.key(),
+        constraint = user_token_account.owner == user.key() @ MarketplaceError::InvalidTokenAccount,
+        constraint = user_token_account.mint == token_mint.key() @ MarketplaceError::TokenMintMismatch,
     )]
     pub user_token_account: Account<'info, TokenAccount>,
 
     #[account(
         mut,
-        constraint = lp_token_account.key() == liquidity_pool.lp_token_account,
+        constraint = lp_token_account.key() == liquidity_pool.lp_token_account @ MarketplaceError::InvalidTokenAccount,
     )]
     pub lp_token_account: Account<'info, TokenAccount>,
 
+    /// Mutable so the project's buyback share of fees can be burned
     #[account(
-        constraint = token_mint.key() == liquidity_pool.token_mint,
+        mut,
+        constraint = token_mint.key() == liquidity_pool.token_mint @ MarketplaceError::TokenMintMismatch,
     )]
     pub token_mint: Account<'info, Mint>,
 
+    /// The platform treasury's token account for the pool's token
     #[account(
         mut,
-        address = platform_config.platform_treasury,
+        constraint = platform_treasury.owner == platform_config.platform_treasury @ MarketplaceError::InvalidTreasury,
+        constraint = platform_treasury.mint == liquidity_pool.token_mint @ MarketplaceError::InvalidTreasury,
     )]
-    /// CHECK: This is the platform treasury account
-    pub platform_treasury: AccountInfo<'info>,
+    pub platform_treasury: Account<'info, TokenAccount>,
 
     #[account(
         mut,
@@ -79,11 +109,35 @@ pub struct SwapTokenForNft<'info> {
 
     #[account(
         mut,
-        address = project.royalty_wallet.unwrap_or(project.project_treasury),
+        address = project.royalty_vault.or(project.royalty_wallet).unwrap_or(project.project_treasury),
     )]
-    /// CHECK: This is the royalty wallet account
+    /// CHECK: This is the royalty wallet account, or the royalty vault when accrual is enabled
     pub royalty_wallet: AccountInfo<'info>,
 
+    /// Royalty split for this project, which may not be set
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"royalty_config", project.key().as_ref()],
+        bump,
+    )]
+    pub royalty_config: UncheckedAccount<'info>,
+
+    /// Platform fee exemption for the collection, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"fee_exemption", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_fee_exemption: UncheckedAccount<'info>,
+
+    /// Platform fee exemption for the user, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"fee_exemption", user.key().as_ref()],
+        bump,
+    )]
+    pub user_fee_exemption: UncheckedAccount<'info>,
+
     /// The NFT mint that will be created
     #[account(mut)]
     pub nft_mint: Signer<'info>,
@@ -98,18 +152,167 @@ pub struct SwapTokenForNft<'info> {
     )]
     pub nft_data: Account<'info, NftData>,
 
+    /// The user's cumulative volume record for fee discount tiers
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + std::mem::size_of::<UserTier>(),
+        seeds = [b"user_tier", project.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub user_tier: Account<'info, UserTier>,
+
+    /// An NFT of the collection the user holds, presented for the loyalty cooldown perk
+    /// CHECK: Contents are checked in the instruction; any non-NftData account presents none
+    pub loyalty_nft_data: UncheckedAccount<'info>,
+
+    /// Token account holding the presented loyalty NFT
+    /// CHECK: Checked in the instruction when a loyalty NFT is presented
+    pub loyalty_token_account: UncheckedAccount<'info>,
+
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
-pub fn swap_token_for_nft(
-    ctx: Context<SwapTokenForNft>,
+#[derive(Accounts)]
+#[instruction(collection_id: String)]
+pub struct SimulateSwap<'info> {
+    /// CHECK: Only used to look up the user's volume tier
+    pub user: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"platform_config"],
+        bump = platform_config.bump,
+    )]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    #[account(
+        seeds = [b"collection", collection_id.as_bytes()],
+        bump = collection.bump,
+    )]
+    pub collection: Account<'info, Collection>,
+
+    #[account(
+        seeds = [b"project", project.project_id.as_bytes()],
+        bump = project.bump,
+        constraint = project.is_active @ MarketplaceError::ProjectNotFound,
+        constraint = collection.project == project.key() @ MarketplaceError::CollectionProjectMismatch,
+    )]
+    pub project: Account<'info, Project>,
+
+    #[account(
+        constraint = liquidity_pool.project == project.key() @ MarketplaceError::PoolProjectMismatch,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+
+    /// The user's volume tier, which may not exist yet
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"user_tier", project.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub user_tier: UncheckedAccount<'info>,
+
+    /// Platform fee exemption for the collection, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"fee_exemption", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_fee_exemption: UncheckedAccount<'info>,
+
+    /// Platform fee exemption for the user, which may not exist
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        seeds = [b"fee_exemption", user.key().as_ref()],
+        bump,
+    )]
+    pub user_fee_exemption: UncheckedAccount<'info>,
+}
+
+// Apply an optional percentage discount to the swap price
+pub fn apply_swap_discount(token_amount: u64, discount_percent: Option<u8>) -> Result<u64> {
+    match discount_percent {
+        Some(discount) if discount > 100 => Err(MarketplaceError::InvalidDiscountPercentage.into()),
+        Some(discount) => token_amount
+            .checked_mul((100 - discount) as u64)
+            .and_then(|v| v.checked_div(100))
+            .ok_or_else(|| MarketplaceError::CalculationOverflow.into()),
+        None => Ok(token_amount),
+    }
+}
+
+// Quote what a swap would charge without moving tokens or minting
+pub fn simulate_swap(
+    ctx: Context<SimulateSwap>,
+    collection_id: String,
+    token_amount: u64,
+    discount_percent: Option<u8>,
+) -> Result<()> {
+    if ctx.accounts.collection.collection_id != collection_id {
+        return Err(MarketplaceError::CollectionNotFound.into());
+    }
+
+    match ctx.accounts.collection.token_mint {
+        None => return Err(MarketplaceError::NoTokenMintSpecified.into()),
+        Some(token_mint) if token_mint != ctx.accounts.liquidity_pool.token_mint => {
+            return Err(MarketplaceError::TokenMintMismatch.into());
+        }
+        Some(_) => {}
+    }
+
+    // Refuse quotes on a stale feed exactly as the swap would
+    if ctx.accounts.collection.fixed_mint_price.is_none() {
+        check_oracle_status(&ctx.accounts.liquidity_pool)?;
+    }
+
+    let token_amount = mint_base_price(&ctx.accounts.collection, token_amount)?;
+    let total_amount = apply_swap_discount(token_amount, discount_percent)?;
+
+    // A user without a tier account has no trading volume yet
+    let cumulative_volume = Account::<UserTier>::try_from(&ctx.accounts.user_tier)
+        .map(|user_tier| user_tier.cumulative_volume)
+        .unwrap_or(0);
+    let fee_discount_bps = get_fee_discount_bps(&ctx.accounts.project, cumulative_volume);
+
+    let platform_fee_exempt = is_platform_fee_exempt(
+        &ctx.accounts.collection_fee_exemption,
+        &ctx.accounts.user_fee_exemption,
+    );
+
+    let FeeBreakdown { platform_fee, project_fee, royalty_fee, net_amount } = calculate_fees(
+        &ctx.accounts.platform_config,
+        &ctx.accounts.project,
+        total_amount,
+        fee_discount_bps,
+        platform_fee_exempt,
+    )?;
+
+    emit!(SwapQuote {
+        user: ctx.accounts.user.key(),
+        collection: ctx.accounts.collection.key(),
+        base_amount: token_amount,
+        discount_amount: token_amount - total_amount,
+        total_amount,
+        fee_discount_bps,
+        platform_fee,
+        project_fee,
+        royalty_fee,
+        net_amount,
+    });
+
+    Ok(())
+}
+
+pub fn swap_token_for_nft<'info>(
+    ctx: Context<'_, '_, '_, 'info, SwapTokenForNft<'info>>,
     collection_id: String,
     token_amount: u64,
     discount_percent: Option<u8>,
     cooldown_period: Option<i64>,
+    traits_selection: Option<Vec<u8>>,
 ) -> Result<()> {
     // Check if collection exists and belongs to the right project
     if ctx.accounts.collection.collection_id != collection_id {
@@ -117,32 +320,54 @@ pub fn swap_token_for_nft(
     }
 
     // Check if the token mint matches the collection's associated token
-    if ctx.accounts.collection.token_mint.is_none() || 
-       ctx.accounts.collection.token_mint.unwrap() != ctx.accounts.token_mint.key() {
-        return Err(MarketplaceError::NoTokenMintSpecified.into());
+    match ctx.accounts.collection.token_mint {
+        None => return Err(MarketplaceError::NoTokenMintSpecified.into()),
+        Some(token_mint) if token_mint != ctx.accounts.token_mint.key() => {
+            return Err(MarketplaceError::TokenMintMismatch.into());
+        }
+        Some(_) => {}
     }
 
-    // Check oracle status to ensure price feed is valid
-    check_oracle_status(&ctx.accounts.liquidity_pool)?;
+    // Swaps mint a new NFT, so they respect the collection's mint window
+    let current_time = Clock::get()?.unix_timestamp;
+    check_mint_window(&ctx.accounts.collection, current_time)?;
+    consume_mint_allowance(&ctx.accounts.mint_rate_limit, &ctx.accounts.collection.key(), current_time)?;
+
+    // Fixed-price collections charge their configured price and don't depend on the oracle;
+    // otherwise check oracle status to ensure price feed is valid
+    if ctx.accounts.collection.fixed_mint_price.is_none() {
+        check_oracle_status(&ctx.accounts.liquidity_pool)?;
+    }
+    let token_amount = mint_base_price(&ctx.accounts.collection, token_amount)?;
+
+    // Selected traits are counted against their supply and may carry price modifiers
+    // (the collection trait config and trait type accounts are passed via remaining_accounts,
+    // followed by any royalty recipient token accounts)
+    let trait_account_count = traits_selection.as_ref().map_or(0, |traits| traits.len() + 1);
+    if ctx.remaining_accounts.len() < trait_account_count {
+        return Err(MarketplaceError::InvalidTraitsSelection.into());
+    }
+    let (trait_accounts, royalty_recipient_accounts) = ctx.remaining_accounts.split_at(trait_account_count);
+    let (token_amount, rarity_score) = match &traits_selection {
+        Some(traits) => {
+            if traits.is_empty() {
+                return Err(MarketplaceError::InvalidTraitsSelection.into());
+            }
+            let (amount, selection) = price_trait_selection(
+                &ctx.accounts.collection.key(),
+                trait_accounts,
+                traits,
+                token_amount,
+            )?;
+            (amount, selection.rarity_score)
+        }
+        None => (token_amount, 0),
+    };
 
     // Calculate token amount required based on oracle price
     // For simplicity in this MVP we assume a 1:1 ratio
     // In a production system, you would calculate based on oracle price
-    let required_token_amount = token_amount;
-    
-    // Apply discount if provided
-    let discounted_amount = if let Some(discount) = discount_percent {
-        if discount > 100 {
-            return Err(MarketplaceError::InvalidDiscountPercentage.into());
-        }
-        
-        required_token_amount
-            .checked_mul((100 - discount) as u64)
-            .and_then(|v| v.checked_div(100))
-            .ok_or(MarketplaceError::CalculationOverflow)?
-    } else {
-        required_token_amount
-    };
+    let discounted_amount = apply_swap_discount(token_amount, discount_percent)?;
     
     // Check if user has enough tokens
     if ctx.accounts.user_token_account.amount < discounted_amount {
@@ -162,31 +387,81 @@ pub fn swap_token_for_nft(
         discounted_amount,
     )?;
 
+    // Resolve the fee discount from volume traded before this swap
+    let fee_discount_bps = get_fee_discount_bps(
+        &ctx.accounts.project,
+        ctx.accounts.user_tier.cumulative_volume,
+    );
+
+    // A project without a royalty config pays its whole royalty to the royalty wallet
+    let royalty_config = Account::<RoyaltyConfig>::try_from(&ctx.accounts.royalty_config).ok();
+
+    // Partner collections and market makers may be exempt from the platform fee
+    let platform_fee_exempt = is_platform_fee_exempt(
+        &ctx.accounts.collection_fee_exemption,
+        &ctx.accounts.user_fee_exemption,
+    );
+
     // Distribute fees
+    let fees = calculate_fees(
+        &ctx.accounts.platform_config,
+        &ctx.accounts.project,
+        discounted_amount,
+        fee_discount_bps,
+        platform_fee_exempt,
+    )?;
     distribute_fees(
         &ctx.accounts.token_program,
         &ctx.accounts.lp_token_account,
+        &ctx.accounts.token_mint,
         &ctx.accounts.platform_treasury,
         &ctx.accounts.project_treasury,
         Some(&ctx.accounts.royalty_wallet),
-        &ctx.accounts.liquidity_pool,
+        royalty_config.as_deref().map(|royalty_config| (royalty_config, royalty_recipient_accounts)),
+        &mut ctx.accounts.liquidity_pool,
         &ctx.accounts.platform_config,
-        &ctx.accounts.project,
-        discounted_amount,
+        &mut ctx.accounts.project,
+        fees,
     )?;
 
+    // Record this swap towards the user's volume tier
+    let user_tier = &mut ctx.accounts.user_tier;
+    if user_tier.user == Pubkey::default() {
+        user_tier.project = ctx.accounts.project.key();
+        user_tier.user = ctx.accounts.user.key();
+        user_tier.bump = *ctx.bumps.get("user_tier").unwrap();
+    }
+    user_tier.cumulative_volume = user_tier.cumulative_volume
+        .checked_add(discounted_amount)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+
     // Set cooldown if discount was applied
-    let cooldown_end_timestamp = if discount_percent.is_some() && cooldown_period.is_some() {
-        let cooldown = cooldown_period.unwrap();
+    let discount_cooldown = if let (Some(_), Some(cooldown)) = (discount_percent, cooldown_period) {
         if cooldown <= 0 {
             return Err(MarketplaceError::InvalidCooldownPeriod.into());
         }
         
-        let current_time = Clock::get()?.unix_timestamp;
-        Some(current_time + cooldown)
+        cooldown
     } else {
-        None
+        0
     };
+    
+    // Rarer NFTs may carry a longer cooldown from the collection's rarity curve
+    // and holders of a rare NFT from the collection get a loyalty cut to it
+    let reduction_percent = loyalty_cooldown_reduction(
+        &ctx.accounts.loyalty_nft_data,
+        &ctx.accounts.loyalty_token_account,
+        &ctx.accounts.collection,
+        &ctx.accounts.user.key(),
+    )?;
+    let cooldown_clock = ctx.accounts.collection.cooldown_clock;
+    let cooldown_end_timestamp = cooldown_end_for_rarity(
+        &ctx.accounts.collection.rarity_cooldown_curve,
+        rarity_score,
+        discount_cooldown,
+        reduction_percent,
+        clock_now(cooldown_clock)?,
+    )?;
 
     // Initialize NFT data
     let nft_data = &mut ctx.accounts.nft_data;
@@ -195,7 +470,10 @@ pub fn swap_token_for_nft(
     nft_data.mint = ctx.accounts.nft_mint.key();
     nft_data.minted_at = Clock::get()?.unix_timestamp;
     nft_data.cooldown_end_timestamp = cooldown_end_timestamp;
+    nft_data.cooldown_clock = cooldown_clock;
     nft_data.discount_percent = discount_percent;
+    nft_data.rarity_score = rarity_score;
+    nft_data.fused_into = None;
     nft_data.bump = *ctx.bumps.get("nft_data").unwrap();
     
     // Mint the NFT to the user
@@ -213,9 +491,32 @@ pub fn swap_token_for_nft(
     let project = &mut ctx.accounts.project;
     project.last_activity_timestamp = Clock::get()?.unix_timestamp;
     
-    // Update liquidity pool's last activity timestamp
+    // Update liquidity pool's last activity timestamp and last traded price
+    let current_time = Clock::get()?.unix_timestamp;
     let liquidity_pool = &mut ctx.accounts.liquidity_pool;
-    liquidity_pool.last_activity = Clock::get()?.unix_timestamp;
+    liquidity_pool.last_activity = current_time;
+    // A fixed-price mint may go through without a usable oracle price, in which case
+    // the trade has no USD value to record
+    let trade_price_usd = match check_oracle_status(liquidity_pool) {
+        Ok(()) => record_trade_price(liquidity_pool, discounted_amount, current_time)?,
+        Err(_) => 0,
+    };
+    
+    record_collection_activity(
+        &ctx.accounts.collection_stats,
+        &ctx.accounts.collection.key(),
+        |stats| record_swap(stats, discounted_amount),
+    )?;
+    
+    emit!(NftSwapped {
+        user: ctx.accounts.user.key(),
+        nft_mint: ctx.accounts.nft_mint.key(),
+        collection: ctx.accounts.collection.key(),
+        token_amount: discounted_amount,
+        token_decimals: ctx.accounts.token_mint.decimals,
+        trade_price_usd,
+        usd_decimals: USD_PRICE_DECIMALS,
+    });
     
     msg!("Token swapped for NFT: {}", ctx.accounts.nft_mint.key());
     
@@ -3,10 +3,603 @@ use solana_program::hash::hash;
 use std::ops::{Deref, DerefMut};
 
 use crate::errors::MarketplaceError;
+use crate::events::{MintConfigValidated, TraitSupplyCapAdjusted};
+use crate::modules::randomness::committed_randomness;
+use crate::modules::rarity::calculate_rarity_score;
 use crate::state::{
-    CollectionTraitConfig, MetadataFormat, TraitType, TraitValue
+    Collection, CollectionTraitConfig, MetadataFormat, NftData, NftTraits, PriceModifierMode, Project,
+    RandomnessSource, TraitAttribute, TraitComboBonus, TraitPriceModifier, TraitType, TraitValue
 };
 
+// Upper bounds used to size trait type accounts
+pub const MAX_TRAIT_VALUES: usize = 32;
+pub const MAX_TRAIT_STRING_LEN: usize = 32;
+
+// Upper bounds used to size collection trait config accounts
+pub const MAX_BASE_URI_LEN: usize = 200;
+pub const MAX_PRICE_MODIFIERS: usize = 16;
+
+// Upper bounds used to size trait combination bonus tables
+pub const MAX_COMBO_BONUSES: usize = 8;
+pub const MAX_COMBO_TRAITS: usize = 4;
+
+// Upper bound used to size NFT trait records
+pub const MAX_NFT_TRAITS: usize = 16;
+
+// Multiplicative price modifiers are expressed in basis points
+pub const PRICE_MODIFIER_BPS_DENOMINATOR: u64 = 10_000;
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct InitializeTraitType<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<TraitType>() + name.len()
+            + MAX_TRAIT_VALUES * (std::mem::size_of::<TraitValue>() + 2 * MAX_TRAIT_STRING_LEN),
+        seeds = [b"trait_type", collection.key().as_ref(), name.as_bytes()],
+        bump,
+    )]
+    pub trait_type: Account<'info, TraitType>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddTraitValue<'info> {
+    pub authority: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"trait_type", collection.key().as_ref(), trait_type.name.as_bytes()],
+        bump = trait_type.bump,
+    )]
+    pub trait_type: Account<'info, TraitType>,
+}
+
+#[derive(Accounts)]
+pub struct AdjustTraitSupplyCap<'info> {
+    pub authority: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"trait_type", collection.key().as_ref(), trait_type.name.as_bytes()],
+        bump = trait_type.bump,
+    )]
+    pub trait_type: Account<'info, TraitType>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeCollectionTraitConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<CollectionTraitConfig>() + MAX_BASE_URI_LEN
+            + MAX_PRICE_MODIFIERS * (std::mem::size_of::<TraitPriceModifier>() + 2 * MAX_TRAIT_STRING_LEN)
+            + MAX_COMBO_BONUSES * (std::mem::size_of::<TraitComboBonus>()
+                + MAX_COMBO_TRAITS * (std::mem::size_of::<TraitAttribute>() + 2 * MAX_TRAIT_STRING_LEN)),
+        seeds = [b"trait_config", collection.key().as_ref()],
+        bump,
+    )]
+    pub collection_trait_config: Account<'info, CollectionTraitConfig>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCollectionTraitConfig<'info> {
+    pub authority: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        mut,
+        seeds = [b"trait_config", collection.key().as_ref()],
+        bump = collection_trait_config.bump,
+    )]
+    pub collection_trait_config: Account<'info, CollectionTraitConfig>,
+}
+
+#[derive(Accounts)]
+pub struct CommitTraitHash<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        constraint = project.key() == collection.project @ MarketplaceError::ProjectNotFound,
+        constraint = project.authority == authority.key() @ MarketplaceError::Unauthorized,
+    )]
+    pub project: Account<'info, Project>,
+    
+    #[account(
+        seeds = [b"nft_data", nft_data.mint.as_ref()],
+        bump = nft_data.bump,
+        constraint = nft_data.collection == collection.key() @ MarketplaceError::CollectionNotFound,
+    )]
+    pub nft_data: Account<'info, NftData>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<NftTraits>()
+            + MAX_NFT_TRAITS * (std::mem::size_of::<TraitAttribute>() + 2 * MAX_TRAIT_STRING_LEN),
+        seeds = [b"nft_traits", nft_data.mint.as_ref()],
+        bump,
+    )]
+    pub nft_traits: Account<'info, NftTraits>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyTraits<'info> {
+    #[account(
+        seeds = [b"nft_traits", nft_traits.nft_mint.as_ref()],
+        bump = nft_traits.bump,
+    )]
+    pub nft_traits: Account<'info, NftTraits>,
+}
+
+#[derive(Accounts)]
+#[instruction(provided_traits: Option<Vec<TraitAttribute>>, randomness_request_id: u64)]
+pub struct ValidateMintConfig<'info> {
+    // The wallet the mint would be for, which seeds auto-generated traits
+    pub user: Signer<'info>,
+    
+    pub collection: Account<'info, Collection>,
+    
+    #[account(
+        seeds = [b"trait_config", collection.key().as_ref()],
+        bump = collection_trait_config.bump,
+    )]
+    pub collection_trait_config: Account<'info, CollectionTraitConfig>,
+    
+    /// The user's randomness request, consumed when auto-generation takes randomness from an oracle
+    /// CHECK: Address is pinned by seeds; contents are checked in the instruction
+    #[account(
+        mut,
+        seeds = [b"randomness", user.key().as_ref(), randomness_request_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub randomness_request: UncheckedAccount<'info>,
+    
+    // The collection's trait type accounts follow in the remaining accounts
+}
+
+// Create the trait configuration for a collection
+pub fn initialize_collection_trait_config(
+    ctx: Context<InitializeCollectionTraitConfig>,
+    base_uri: String,
+    auto_generation_enabled: bool,
+    metadata_format: MetadataFormat,
+) -> Result<()> {
+    if base_uri.len() > MAX_BASE_URI_LEN {
+        return Err(MarketplaceError::InvalidMetadataUri.into());
+    }
+    
+    let config = &mut ctx.accounts.collection_trait_config;
+    config.collection = ctx.accounts.collection.key();
+    config.base_uri = base_uri;
+    config.auto_generation_enabled = auto_generation_enabled;
+    config.metadata_format = metadata_format;
+    config.trait_types = Vec::new();
+    config.price_modifier_mode = PriceModifierMode::Additive;
+    config.price_modifiers = Vec::new();
+    config.randomness_source = RandomnessSource::SlotHash;
+    config.combo_bonuses = Vec::new();
+    config.bump = *ctx.bumps.get("collection_trait_config").unwrap();
+    
+    msg!("Trait config created for collection: {}", ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Replace the trait-based mint price modifiers of a collection (an empty list disables them)
+pub fn set_trait_price_modifiers(
+    ctx: Context<UpdateCollectionTraitConfig>,
+    price_modifier_mode: PriceModifierMode,
+    price_modifiers: Vec<TraitPriceModifier>,
+) -> Result<()> {
+    validate_price_modifiers(&price_modifier_mode, &price_modifiers)?;
+    
+    let config = &mut ctx.accounts.collection_trait_config;
+    config.price_modifier_mode = price_modifier_mode;
+    config.price_modifiers = price_modifiers;
+    
+    msg!("{} trait price modifiers set for collection: {}", config.price_modifiers.len(), ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Replace the trait combination rarity bonuses of a collection (an empty list disables them)
+pub fn set_trait_combo_bonuses(
+    ctx: Context<UpdateCollectionTraitConfig>,
+    combo_bonuses: Vec<TraitComboBonus>,
+) -> Result<()> {
+    validate_combo_bonuses(&combo_bonuses)?;
+    
+    let config = &mut ctx.accounts.collection_trait_config;
+    config.combo_bonuses = combo_bonuses;
+    
+    msg!("{} trait combo bonuses set for collection: {}", config.combo_bonuses.len(), ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Choose where trait generation randomness comes from
+pub fn set_trait_randomness_source(
+    ctx: Context<UpdateCollectionTraitConfig>,
+    randomness_source: RandomnessSource,
+) -> Result<()> {
+    let config = &mut ctx.accounts.collection_trait_config;
+    config.randomness_source = randomness_source;
+    
+    msg!("Trait randomness source set to {:?} for collection: {}", randomness_source, ctx.accounts.collection.collection_id);
+    
+    Ok(())
+}
+
+// Create a trait type account for a collection
+pub fn initialize_trait_type(
+    ctx: Context<InitializeTraitType>,
+    name: String,
+    is_required: bool,
+    trait_values: Vec<TraitValue>,
+) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_TRAIT_STRING_LEN {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    let trait_type = create_trait_type(
+        &ctx.accounts.collection.key(),
+        name,
+        is_required,
+        trait_values,
+        *ctx.bumps.get("trait_type").unwrap(),
+    )?;
+    
+    msg!("Trait type created: {}", trait_type.name);
+    
+    ctx.accounts.trait_type.set_inner(trait_type);
+    
+    Ok(())
+}
+
+// Append a new value to an existing trait type
+pub fn add_trait_value(
+    ctx: Context<AddTraitValue>,
+    trait_value: TraitValue,
+) -> Result<()> {
+    let trait_type = &mut ctx.accounts.trait_type;
+    
+    if trait_type.trait_values.len() >= MAX_TRAIT_VALUES {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    validate_trait_value(&trait_value)?;
+    
+    if trait_type.trait_values.iter().any(|v| v.name == trait_value.name) {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    msg!("Trait value {} added to {}", trait_value.name, trait_type.name);
+    
+    trait_type.trait_values.push(trait_value);
+    
+    Ok(())
+}
+
+// Change the supply cap of a trait value after launch (None removes the cap)
+pub fn adjust_trait_supply_cap(
+    ctx: Context<AdjustTraitSupplyCap>,
+    value_name: String,
+    new_available_supply: Option<u64>,
+) -> Result<()> {
+    let trait_type = &mut ctx.accounts.trait_type;
+    
+    let trait_value = trait_type.trait_values
+        .iter_mut()
+        .find(|v| v.name == value_name)
+        .ok_or(MarketplaceError::TraitValueNotFound)?;
+    
+    // A cap can never drop below what has already been minted
+    if let Some(new_cap) = new_available_supply {
+        if new_cap < trait_value.used_supply {
+            return Err(MarketplaceError::TraitSupplyExceeded.into());
+        }
+    }
+    
+    let old_available_supply = trait_value.available_supply;
+    trait_value.available_supply = new_available_supply;
+    
+    emit!(TraitSupplyCapAdjusted {
+        trait_type: trait_type.key(),
+        value_name: value_name.clone(),
+        old_available_supply,
+        new_available_supply,
+    });
+    
+    msg!("Supply cap for {} in {} adjusted", value_name, trait_type.name);
+    
+    Ok(())
+}
+
+// Record an NFT's off-chain computed traits along with a hash of the canonical trait set.
+// The record is created once and cannot be rewritten
+pub fn commit_trait_hash(
+    ctx: Context<CommitTraitHash>,
+    trait_values: Vec<TraitAttribute>,
+) -> Result<()> {
+    if trait_values.is_empty() || trait_values.len() > MAX_NFT_TRAITS {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    for attribute in &trait_values {
+        if attribute.trait_type.is_empty()
+            || attribute.trait_type.len() > MAX_TRAIT_STRING_LEN
+            || attribute.value.len() > MAX_TRAIT_STRING_LEN
+        {
+            return Err(MarketplaceError::InvalidTraitConfig.into());
+        }
+    }
+    
+    let canonical = canonical_traits(trait_values)?;
+    let trait_hash = trait_set_hash(&canonical);
+    
+    let nft_traits = &mut ctx.accounts.nft_traits;
+    nft_traits.nft_mint = ctx.accounts.nft_data.mint;
+    nft_traits.collection = ctx.accounts.collection.key();
+    nft_traits.trait_values = canonical;
+    nft_traits.is_auto_generated = false;
+    nft_traits.generation_seed = None;
+    nft_traits.trait_hash = trait_hash;
+    nft_traits.bump = *ctx.bumps.get("nft_traits").unwrap();
+    
+    msg!("Trait hash committed for NFT: {}", nft_traits.nft_mint);
+    
+    Ok(())
+}
+
+// Check that a trait set matches an NFT's committed trait hash, in any order
+pub fn verify_traits(
+    ctx: Context<VerifyTraits>,
+    trait_values: Vec<TraitAttribute>,
+) -> Result<()> {
+    let canonical = canonical_traits(trait_values)
+        .map_err(|_| MarketplaceError::TraitValidationFailed)?;
+    
+    if trait_set_hash(&canonical) != ctx.accounts.nft_traits.trait_hash {
+        return Err(MarketplaceError::TraitValidationFailed.into());
+    }
+    
+    msg!("Traits verified for NFT: {}", ctx.accounts.nft_traits.nft_mint);
+    
+    Ok(())
+}
+
+// Dry-run a mint with traits: check the provided traits, or auto-generate them when none are
+// given, and build the metadata URI, without creating any account or using any trait supply.
+// The collection's trait types are passed as remaining accounts. Fails with the error the mint
+// would fail with; on success the traits and URI are emitted
+pub fn validate_mint_config<'info>(
+    ctx: Context<'_, '_, '_, 'info, ValidateMintConfig<'info>>,
+    provided_traits: Option<Vec<TraitAttribute>>,
+    _randomness_request_id: u64,
+) -> Result<()> {
+    let collection_key = ctx.accounts.collection.key();
+    let config = &ctx.accounts.collection_trait_config;
+    
+    let mut trait_types = Vec::with_capacity(ctx.remaining_accounts.len());
+    for account in ctx.remaining_accounts {
+        let trait_type = Account::<TraitType>::try_from(account)
+            .map_err(|_| MarketplaceError::TraitTypeNotFound)?;
+        if trait_type.collection != collection_key {
+            return Err(MarketplaceError::TraitTypeNotFound.into());
+        }
+        trait_types.push(Box::new(trait_type.into_inner()));
+    }
+    
+    let traits = match provided_traits {
+        Some(provided_traits) => {
+            let traits: Vec<(String, String)> = provided_traits
+                .into_iter()
+                .map(|attribute| (attribute.trait_type, attribute.value))
+                .collect();
+            validate_traits(&trait_types, &traits)?;
+            traits
+        }
+        None => {
+            if !config.auto_generation_enabled {
+                return Err(MarketplaceError::AutoGenerationDisabled.into());
+            }
+            let seed = committed_randomness(
+                config.randomness_source,
+                &ctx.accounts.randomness_request,
+                &ctx.accounts.user.key(),
+                &collection_key,
+                b"validate_mint_config",
+            )?;
+            auto_generate_traits(&trait_types, config, &seed)?
+        }
+    };
+    
+    let metadata_uri = generate_metadata_uri(config, &traits, &trait_types)?;
+    let rarity_score = calculate_rarity_score(&trait_types, &traits, &config.combo_bonuses);
+    
+    msg!("Mint config valid for collection {}: {}", ctx.accounts.collection.collection_id, metadata_uri);
+    
+    emit!(MintConfigValidated {
+        collection: collection_key,
+        traits: traits
+            .into_iter()
+            .map(|(trait_type, value)| TraitAttribute { trait_type, value })
+            .collect(),
+        metadata_uri,
+        rarity_score,
+    });
+    
+    Ok(())
+}
+
+// Sort a trait set by trait type, rejecting sets that name a trait type twice
+pub fn canonical_traits(mut trait_values: Vec<TraitAttribute>) -> Result<Vec<TraitAttribute>> {
+    trait_values.sort_by(|a, b| a.trait_type.cmp(&b.trait_type));
+    
+    if trait_values.windows(2).any(|pair| pair[0].trait_type == pair[1].trait_type) {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    Ok(trait_values)
+}
+
+// sha256 over the length-prefixed trait type and value names of a canonical trait set
+pub fn trait_set_hash(canonical: &[TraitAttribute]) -> [u8; 32] {
+    let mut data: Vec<u8> = Vec::new();
+    for attribute in canonical {
+        data.extend_from_slice(&(attribute.trait_type.len() as u32).to_le_bytes());
+        data.extend_from_slice(attribute.trait_type.as_bytes());
+        data.extend_from_slice(&(attribute.value.len() as u32).to_le_bytes());
+        data.extend_from_slice(attribute.value.as_bytes());
+    }
+    
+    hash(&data).to_bytes()
+}
+
+// Helper function to validate a single trait value definition
+// Zero weights are rejected: such a value could never be rolled, yet would score as maximally rare
+pub fn validate_trait_value(trait_value: &TraitValue) -> Result<()> {
+    if trait_value.rarity_weight == 0 {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    if trait_value.name.is_empty()
+        || trait_value.name.len() > MAX_TRAIT_STRING_LEN
+        || trait_value.uri_postfix.len() > MAX_TRAIT_STRING_LEN
+    {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    Ok(())
+}
+
+// Helper function to validate a price modifier list
+pub fn validate_price_modifiers(
+    mode: &PriceModifierMode,
+    price_modifiers: &[TraitPriceModifier],
+) -> Result<()> {
+    if price_modifiers.len() > MAX_PRICE_MODIFIERS {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    for (i, price_modifier) in price_modifiers.iter().enumerate() {
+        if price_modifier.trait_name.is_empty()
+            || price_modifier.trait_name.len() > MAX_TRAIT_STRING_LEN
+            || price_modifier.value_name.is_empty()
+            || price_modifier.value_name.len() > MAX_TRAIT_STRING_LEN
+        {
+            return Err(MarketplaceError::InvalidTraitConfig.into());
+        }
+        
+        // A zero multiplier would make the NFT free
+        if *mode == PriceModifierMode::Multiplicative && price_modifier.modifier == 0 {
+            return Err(MarketplaceError::InvalidTraitConfig.into());
+        }
+        
+        // Each trait value may only carry one modifier
+        if price_modifiers[..i].iter().any(|m| {
+            m.trait_name == price_modifier.trait_name && m.value_name == price_modifier.value_name
+        }) {
+            return Err(MarketplaceError::InvalidTraitConfig.into());
+        }
+    }
+    
+    Ok(())
+}
+
+// Validate a combination bonus table: bounded, and each combination names two or more
+// distinct trait types and carries a non-zero bonus
+pub fn validate_combo_bonuses(combo_bonuses: &[TraitComboBonus]) -> Result<()> {
+    if combo_bonuses.len() > MAX_COMBO_BONUSES {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    for combo in combo_bonuses {
+        if combo.traits.len() < 2 || combo.traits.len() > MAX_COMBO_TRAITS || combo.bonus == 0 {
+            return Err(MarketplaceError::InvalidTraitConfig.into());
+        }
+        
+        for (i, attribute) in combo.traits.iter().enumerate() {
+            if attribute.trait_type.is_empty()
+                || attribute.trait_type.len() > MAX_TRAIT_STRING_LEN
+                || attribute.value.is_empty()
+                || attribute.value.len() > MAX_TRAIT_STRING_LEN
+            {
+                return Err(MarketplaceError::InvalidTraitConfig.into());
+            }
+            
+            // An NFT has one value per trait type, so a combination repeating one could never match
+            if combo.traits[..i].iter().any(|a| a.trait_type == attribute.trait_type) {
+                return Err(MarketplaceError::InvalidTraitConfig.into());
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+// Combination bonuses of a collection's trait config, or none if the account is not a trait config
+pub fn load_combo_bonuses(info: &AccountInfo, collection: &Pubkey) -> Vec<TraitComboBonus> {
+    match Account::<CollectionTraitConfig>::try_from(info) {
+        Ok(config) if config.collection == *collection => config.into_inner().combo_bonuses,
+        _ => Vec::new(),
+    }
+}
+
 // Helper function to create a new trait type
 pub fn create_trait_type(
     collection: &Pubkey,
@@ -15,9 +608,18 @@ pub fn create_trait_type(
     trait_values: Vec<TraitValue>,
     bump: u8,
 ) -> Result<TraitType> {
-    if trait_values.is_empty() {
+    if trait_values.is_empty() || trait_values.len() > MAX_TRAIT_VALUES {
         return Err(MarketplaceError::InvalidTraitConfig.into());
     }
+    
+    for (i, trait_value) in trait_values.iter().enumerate() {
+        validate_trait_value(trait_value)?;
+        
+        // Value names must be unique within a trait type
+        if trait_values[..i].iter().any(|v| v.name == trait_value.name) {
+            return Err(MarketplaceError::InvalidTraitConfig.into());
+        }
+    }
 
     Ok(TraitType {
         collection: *collection,
@@ -115,6 +717,7 @@ where
     // (should only happen if most traits are supply-limited)
     trait_type.trait_values
         .iter()
+        .filter(|v| v.rarity_weight > 0)
         .find(|v| {
             if let Some(max_supply) = v.available_supply {
                 v.used_supply < max_supply
@@ -126,8 +729,8 @@ where
 }
 
 // Helper function to auto-generate traits for an NFT
-pub fn auto_generate_traits<'a, T>(
-    trait_types: &'a [T],
+pub fn auto_generate_traits<T>(
+    trait_types: &[T],
     _config: &CollectionTraitConfig,
     seed: &[u8; 32],
 ) -> Result<Vec<(String, String)>> 
@@ -160,8 +763,8 @@ where
 }
 
 // Helper function to validate manually provided traits
-pub fn validate_traits<'a, T>(
-    trait_types: &'a [T],
+pub fn validate_traits<T>(
+    trait_types: &[T],
     provided_traits: &[(String, String)],
 ) -> Result<()> 
 where
@@ -201,10 +804,10 @@ where
 }
 
 // Helper function to generate metadata URI with traits
-pub fn generate_metadata_uri<'a, T>(
+pub fn generate_metadata_uri<T>(
     config: &CollectionTraitConfig,
     trait_values: &[(String, String)],
-    trait_types: &'a [T],
+    trait_types: &[T],
 ) -> Result<String> 
 where
     T: AsRef<TraitType> + Deref<Target = TraitType>
@@ -273,7 +876,7 @@ pub fn update_trait_supply<T>(
     value_name: &str,
 ) -> Result<()> 
 where
-    T: AsMut<TraitType> + DerefMut<Target = TraitType>
+    T: DerefMut<Target = TraitType>
 {
     // Find the trait value and increment its used_supply
     let value = trait_type.trait_values
@@ -281,7 +884,9 @@ where
         .find(|v| v.name == value_name)
         .ok_or(MarketplaceError::TraitValueNotFound)?;
     
-    value.used_supply += 1;
+    value.used_supply = value.used_supply
+        .checked_add(1)
+        .ok_or(MarketplaceError::CalculationOverflow)?;
     
     // Check if we've exceeded available supply
     if let Some(max_supply) = value.available_supply {
@@ -291,4 +896,116 @@ where
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+// Traits chosen for a new NFT
+pub struct TraitSelection {
+    pub traits: Vec<(String, String)>, // Selected (trait type, trait value) names
+    pub rarity_score: u16,             // Rarity of the selected combination
+}
+
+// Record one use of each selected trait value; the i-th trait type account pairs with the i-th index
+pub fn apply_trait_selection<'info>(
+    collection: &Pubkey,
+    trait_type_accounts: &[AccountInfo<'info>],
+    traits_selection: &[u8],
+    combo_bonuses: &[TraitComboBonus],
+) -> Result<TraitSelection> {
+    if trait_type_accounts.len() != traits_selection.len() {
+        return Err(MarketplaceError::InvalidTraitsSelection.into());
+    }
+    
+    let mut selected_traits = Vec::with_capacity(traits_selection.len());
+    let mut trait_types = Vec::with_capacity(traits_selection.len());
+    let mut trait_type_keys = Vec::with_capacity(traits_selection.len());
+    
+    for (account, &value_index) in trait_type_accounts.iter().zip(traits_selection) {
+        let mut trait_type = Account::<TraitType>::try_from(account)
+            .map_err(|_| MarketplaceError::TraitTypeNotFound)?;
+        
+        if trait_type.collection != *collection {
+            return Err(MarketplaceError::TraitTypeNotFound.into());
+        }
+        
+        // Each trait type is picked once, so its supply and rarity aren't counted twice
+        if trait_type_keys.contains(&account.key())
+            || selected_traits.iter().any(|(name, _)| *name == trait_type.name)
+        {
+            return Err(MarketplaceError::DuplicateTraitType.into());
+        }
+        trait_type_keys.push(account.key());
+        
+        let value_name = trait_type.trait_values
+            .get(value_index as usize)
+            .map(|v| v.name.clone())
+            .ok_or(MarketplaceError::TraitValueNotFound)?;
+        
+        update_trait_supply(&mut trait_type, &value_name)?;
+        trait_type.exit(&crate::ID)?;
+        
+        selected_traits.push((trait_type.name.clone(), value_name));
+        trait_types.push(Box::new(trait_type.into_inner()));
+    }
+    
+    let rarity_score = calculate_rarity_score(&trait_types, &selected_traits, combo_bonuses);
+    
+    Ok(TraitSelection {
+        traits: selected_traits,
+        rarity_score,
+    })
+}
+
+// Adjust a base price by the modifiers matching the selected traits
+pub fn apply_trait_price_modifiers(
+    config: &CollectionTraitConfig,
+    selected_traits: &[(String, String)],
+    base_amount: u64,
+) -> Result<u64> {
+    let mut amount = base_amount;
+    
+    for (trait_name, value_name) in selected_traits {
+        let price_modifier = match config.price_modifiers
+            .iter()
+            .find(|m| &m.trait_name == trait_name && &m.value_name == value_name)
+        {
+            Some(price_modifier) => price_modifier,
+            None => continue,
+        };
+        
+        amount = match config.price_modifier_mode {
+            PriceModifierMode::Additive => amount.checked_add(price_modifier.modifier),
+            PriceModifierMode::Multiplicative => (amount as u128)
+                .checked_mul(price_modifier.modifier as u128)
+                .map(|v| v / PRICE_MODIFIER_BPS_DENOMINATOR as u128)
+                .and_then(|v| u64::try_from(v).ok()),
+        }
+        .ok_or(MarketplaceError::CalculationOverflow)?;
+    }
+    
+    Ok(amount)
+}
+
+// Record the selected traits and price them through the collection's modifiers.
+// The collection trait config comes first in the accounts, followed by the trait type accounts
+pub fn price_trait_selection<'info>(
+    collection: &Pubkey,
+    accounts: &[AccountInfo<'info>],
+    traits_selection: &[u8],
+    base_amount: u64,
+) -> Result<(u64, TraitSelection)> {
+    let (config_account, trait_type_accounts) = accounts
+        .split_first()
+        .ok_or(MarketplaceError::InvalidTraitsSelection)?;
+    
+    let config = Account::<CollectionTraitConfig>::try_from(config_account)
+        .map_err(|_| MarketplaceError::InvalidTraitConfig)?;
+    
+    if config.collection != *collection {
+        return Err(MarketplaceError::InvalidTraitConfig.into());
+    }
+    
+    let selection = apply_trait_selection(collection, trait_type_accounts, traits_selection, &config.combo_bonuses)?;
+    let amount = apply_trait_price_modifiers(&config, &selection.traits, base_amount)?;
+    
+    Ok((amount, selection))
+}
@@ -4,7 +4,14 @@ use anchor_lang::prelude::*;
 pub struct PlatformConfig {
     pub authority: Pubkey,
     pub platform_fee_basis_points: u16,  // In basis points (100 = 1%)
-    pub platform_treasury: Pubkey,
+    pub platform_treasury: Pubkey,       // Wallet owning the platform's fee token account for each pool's token
+    pub max_platform_fee_absolute: Option<u64>, // Optional ceiling on the platform fee per trade, in tokens
+    pub reclaim_split_bps: u16,          // Platform's share of liquidity reclaimed from inactive pools (10000 = all)
+    pub max_project_royalty_bps: u16,    // Highest royalty a project may set, in basis points
+    pub min_fee_transfer: u64,           // Platform/project fees below this accrue in the pool until they reach it
+    pub min_manual_price: Option<u64>,   // Lowest manual USD price accepted (scaled by 10^6)
+    pub max_manual_price: Option<u64>,   // Highest manual USD price accepted (scaled by 10^6)
+    pub swap_program: Option<Pubkey>,    // DEX program redemptions may swap through to SOL (None disables)
     pub bump: u8,
 }
 
@@ -17,6 +24,49 @@ pub struct Project {
     pub royalty_basis_points: u16, // Royalty fee in basis points
     pub last_activity_timestamp: i64, // Last activity timestamp for inactivity monitoring
     pub is_active: bool,          // Project active status
+    pub fee_discount_tiers: Vec<FeeDiscountTier>, // Volume-based fee discount schedule (ascending by volume)
+    pub royalty_vault: Option<Pubkey>, // Vault royalties accrue into when set (claimed in bulk)
+    pub accrued_royalties: u64,   // Royalties accrued in the vault and not yet claimed
+    pub max_escrows_per_user: u32, // Active escrows a user may hold in this project (0 = unlimited)
+    pub buyback_burn_bps: u16,    // Share of the project fee burned from the pool instead of paid out (10000 = all)
+    pub bump: u8,
+}
+
+// Split of a project's royalty across several creator wallets
+#[account]
+pub struct RoyaltyConfig {
+    pub project: Pubkey,                    // Project these royalty shares belong to
+    pub recipients: Vec<RoyaltyRecipient>,  // Royalty recipients (empty = single royalty wallet)
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RoyaltyRecipient {
+    pub wallet: Pubkey,          // Token account receiving this share
+    pub share_bps: u16,          // Share of the royalty fee (shares sum to 10000)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FeeDiscountTier {
+    pub min_volume: u64,         // Cumulative token volume required to reach this tier
+    pub discount_bps: u16,       // Discount on platform and project fees (in basis points)
+}
+
+// Number of active escrows a user holds within a project, capped by the project
+#[account]
+pub struct UserEscrowCount {
+    pub project: Pubkey,         // Project account this record belongs to
+    pub user: Pubkey,            // Escrow owner being tracked
+    pub active_escrows: u32,     // Escrows currently holding the user's tokens
+    pub bump: u8,
+}
+
+// Per-user trading volume within a project, used to resolve fee discount tiers
+#[account]
+pub struct UserTier {
+    pub project: Pubkey,         // Project account this record belongs to
+    pub user: Pubkey,            // User wallet being tracked
+    pub cumulative_volume: u64,  // Total tokens the user has traded in this project
     pub bump: u8,
 }
 
@@ -27,9 +77,50 @@ pub struct Collection {
     pub metadata_uri: String,      // Metadata URI for the collection
     pub token_mint: Option<Pubkey>, // Associated token mint (if any)
     pub is_compressed: bool,       // Whether this collection uses compressed NFTs
+    pub redeemable: bool,          // Whether NFTs in this collection can be redeemed for tokens
+    pub metadata_hash: Option<[u8; 32]>, // Pre-reveal commitment to the final metadata
+    pub revealed: bool,            // Whether the final metadata has been revealed
+    pub rarity_cooldown_curve: Vec<RarityCooldownTier>, // Cooldowns by rarity (ascending by score)
+    pub cooldown_bypass_fee_bps: u16, // Fee to redeem before cooldown ends (0 disables the bypass)
+    pub mint_start_ts: Option<i64>, // Minting opens at this time (unset = already open)
+    pub mint_end_ts: Option<i64>,   // Minting closes at this time (unset = never closes)
+    pub reset_cooldown_on_transfer: bool, // Whether a new holder's NFT starts with no cooldown
+    pub min_hold_period: i64,      // Seconds after mint before any NFT can be redeemed (0 disables)
+    pub fixed_mint_price: Option<u64>, // Flat token price for swaps into new NFTs (unset = oracle-gated price)
+    pub cooldown_clock: ClockSource, // Clock new cooldowns and vesting periods are measured on
+    pub loyalty_min_rarity_score: u16, // Rarity a held NFT needs to earn the loyalty cooldown reduction
+    pub loyalty_cooldown_reduction_percent: u8, // Cut to new mints' cooldowns for holders of such an NFT (0 disables)
+    pub discount_decay: bool,      // Whether redeeming inside a discount's cooldown forfeits the unserved share of the discount
+    pub redemption_floor_usd: Option<u64>, // Least a redemption pays in USD (scaled by 10^6), converted at the current price (unset = no floor)
+    pub escrow_during_cooldown: bool, // Whether an NFT still in cooldown can back a new escrow (redeeming it waits either way)
+    pub free_mint_allowed: bool,   // Whether a collection without a token mints for free through mint_free_nft
+    pub secondary_lock_until: Option<i64>, // Secondary sales of the collection's NFTs are blocked until this time (unset = never blocked)
+    pub redemption_value_usd: Option<u64>, // USD value a redemption pays (scaled by 10^6), converted at the current price (unset = the pool's value)
+    pub bump: u8,
+}
+
+// On-chain index of a project's collections, in creation order, so clients can page through
+// them without knowing collection IDs. Grown by one key per collection, up to a fixed cap
+#[account]
+pub struct CollectionRegistry {
+    pub project: Pubkey,         // Project whose collections are recorded
+    pub collections: Vec<Pubkey>, // Collection accounts, oldest first
     pub bump: u8,
 }
 
+// Clock a cooldown or vesting end is measured on
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ClockSource {
+    Timestamp,                   // Unix timestamp in seconds; validators can skew it slightly
+    Slot,                        // Slot height; periods are counted in slots
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RarityCooldownTier {
+    pub min_rarity_score: u16,   // Rarity score required to reach this tier
+    pub cooldown_seconds: i64,   // Cooldown applied to newly minted NFTs in this tier (slots on a slot clock)
+}
+
 #[account]
 pub struct LiquidityPool {
     pub project: Pubkey,          // Project account this LP belongs to
@@ -37,13 +128,41 @@ pub struct LiquidityPool {
     pub lp_token_account: Pubkey, // Token account holding LP tokens
     pub created_at: i64,          // Creation timestamp
     pub last_activity: i64,       // Last activity timestamp
-    pub oracle_price_usd: Option<u64>, // Latest oracle price in USD (scaled by 10^6)
+    pub oracle_price_usd: Option<u64>, // Latest oracle price in the quote asset (scaled by its decimals)
     pub oracle_price_last_update: i64, // Last oracle price update timestamp
     pub redemption_locked: bool,   // Whether redemption is locked due to oracle issues
+    pub redemption_locked_since: Option<i64>, // When the current redemption lock began (unset while unlocked)
+    pub auto_recover_redemption_lock: bool, // Whether a fresh price update lifts the redemption lock on its own
     pub price_source: crate::modules::oracle::PriceSource, // Source of price data
+    pub last_trade_price_usd: Option<u64>, // USD value of the most recent trade (scaled by 10^6)
+    pub last_trade_ts: i64,        // Timestamp of the most recent trade
+    pub reserved_liquidity: u64,   // Tokens earmarked for reserved redemptions
+    pub pending_platform_fees: u64, // Platform fees held in the pool until they reach the transfer threshold
+    pub pending_project_fees: u64,  // Project fees held in the pool until they reach the transfer threshold
+    pub quote_asset: crate::modules::oracle::QuoteAsset, // Asset the oracle price is quoted in
+    pub quote_price_usd: Option<u64>, // USD price of one whole quote asset unit (scaled by 10^6), for non-USD quotes
+    pub quote_price_last_update: i64, // Last quote asset price update timestamp
+    pub allow_partial_redemption: bool, // Whether short redemptions pay what is available and owe the rest
+    pub pending_claims: u64,       // Tokens owed to partially paid redemptions
+    pub pricing_mode: PricingMode, // How redemptions from this pool are valued
+    pub redemption_value_usd: u64, // USD value an oracle-priced redemption pays (scaled by 10^6)
+    pub redemption_lp_tax_bps: u16, // Share of each redemption kept in the pool instead of paid out (0 disables)
+    pub max_oracle_staleness: i64, // Seconds before the oracle price counts as stale (0 uses the default)
+    pub max_confidence_bps: u16,   // Widest Pyth confidence interval accepted, in bps of the price (0 disables)
+    pub quote_price_feed: Option<Pubkey>, // Pyth feed the quote asset's USD price is read from (unset blocks updates)
+    pub pyth_price_feed: Option<Pubkey>, // Pyth feed the token price is read from (unset blocks Pyth updates)
+    pub dex_token_account: Option<Pubkey>, // DEX reserve of the token the DEX price is read from
+    pub dex_base_account: Option<Pubkey>, // DEX reserve of the base asset the DEX price is read from
     pub bump: u8,
 }
 
+// How a pool values redemptions
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PricingMode {
+    Flat,                        // Legacy 1:1: every redemption pays the base token value
+    OraclePriced,                // Pays the pool's redemption USD value at the current oracle price
+}
+
 #[account]
 pub struct NftData {
     pub owner: Pubkey,           // Current owner of the NFT
@@ -51,11 +170,14 @@ pub struct NftData {
     pub mint: Pubkey,            // NFT mint address
     pub metadata_uri: String,    // Metadata URI for this specific NFT
     pub minted_at: i64,          // Mint timestamp
-    pub cooldown_end_timestamp: Option<i64>, // End of cooldown period (if any)
+    pub cooldown_end_timestamp: Option<i64>, // End of cooldown period (if any), on cooldown_clock
+    pub cooldown_clock: ClockSource, // Clock the cooldown end is measured on
     pub discount_percent: Option<u8>, // Discount percentage applied (if any)
     pub fusion_level: u8,        // Fusion level (0 for base NFTs, higher for fused NFTs)
     pub parent_nfts: Option<Vec<Pubkey>>, // Parent NFTs used in fusion process (if any)
     pub rarity_score: u16,       // Rarity score (higher is rarer)
+    pub reference_count: u16,    // Live references (e.g. an active escrow) that block closing or fusing this NFT
+    pub fused_into: Option<Pubkey>, // Child mint this NFT was consumed into as a fusion parent (if any)
     pub bump: u8,
 }
 
@@ -67,22 +189,89 @@ pub struct FusionConfig {
     pub max_nfts_allowed: u8,    // Maximum number of NFTs allowed for fusion
     pub base_success_rate: u8,   // Base success rate for fusion (0-100)
     pub token_burn_percent: u8,  // Percentage of input NFT value to burn (0-100)
-    pub cooldown_period: i64,    // Cooldown period after fusion (in seconds)
+    pub cooldown_period: i64,    // Child cooldown under the FixedPeriod policy (seconds, or slots on a slot clock)
     pub is_active: bool,         // Whether fusion is active for this collection
+    pub child_cooldown_policy: ChildCooldownPolicy, // How a fused NFT's cooldown is set
+    pub min_input_value_usd: Option<u64>, // Minimum combined parent value in USD (scaled by 10^6)
+    pub randomness_source: RandomnessSource, // Where fusion randomness comes from
+    pub burn_destination: BurnDestination, // Where the fusion burn amount goes
+    pub min_fusion_interval: i64, // Minimum seconds between fusions by one wallet (0 disables)
+    pub level_multipliers_bps: Vec<u16>, // Rarity multiplier per fusion level from 1, capping levels at its length (empty = default curve)
+    pub output_count: u8,        // Children minted by each fusion, which split the fused rarity
     pub bump: u8,
 }
 
+// Time of a wallet's last fusion in a collection, for the per-wallet fusion interval
+#[account]
+pub struct FusionCooldown {
+    pub collection: Pubkey,      // Collection the fusions happened in
+    pub user: Pubkey,            // Wallet that fused
+    pub last_fusion_at: i64,     // Timestamp of the wallet's last fusion (0 if none yet)
+    pub bump: u8,
+}
+
+// How a fused NFT's cooldown is set from its parents
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ChildCooldownPolicy {
+    None,                        // No cooldown; the child is tradeable at once
+    MaxParent,                   // Inherits the longest remaining parent cooldown
+    FixedPeriod,                 // Cools down for the config's cooldown_period, whatever the parents carried
+}
+
+// Where the token_burn_percent share of a fusion's input value goes
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum BurnDestination {
+    Burn,                        // Burned from supply
+    Treasury,                    // Sent to the project treasury
+    BuybackLp,                   // Deposited into the project's liquidity pool
+}
+
 #[account]
 pub struct TokenEscrow {
     pub owner: Pubkey,           // Original token owner
     pub nft_mint: Pubkey,        // Associated NFT mint 
+    pub project: Pubkey,         // Project the NFT belongs to
     pub token_mint: Pubkey,      // Token mint address
     pub token_amount: u64,       // Amount of tokens in escrow
     pub escrow_token_account: Pubkey, // Token account holding escrowed tokens
     pub discount_percent: Option<u8>,  // Discount on redemption (if any)
-    pub vesting_end_timestamp: Option<i64>, // End of vesting period (if any)
+    pub vesting_end_timestamp: Option<i64>, // End of vesting period (if any), on vesting_clock
+    pub vesting_clock: ClockSource, // Clock the vesting end is measured on
     pub is_active: bool,         // Whether this escrow is active
     pub created_at: i64,         // Creation timestamp
+    pub redeemed_amount: u64,    // Net tokens paid to the owner on redemption (for reversals)
+    pub claimed_amount: u64,     // Tokens of token_amount already paid out by vested claims
+    pub split_index: u16,        // 0 for the NFT's escrow, otherwise the index it was split off at
+    pub split_count: u16,        // Escrows split off this one so far
+    pub bump: u8,
+}
+
+// Liquidity earmarked in a project's pool to buy back a specific NFT at a fixed amount
+#[account]
+pub struct RedemptionReservation {
+    pub project: Pubkey,         // Project that funded the reservation
+    pub nft_mint: Pubkey,        // NFT mint the reservation pays out for
+    pub reserved_amount: u64,    // Tokens paid on redemption (0 once redeemed)
+    pub created_at: i64,         // Reservation timestamp
+    pub bump: u8,
+}
+
+// Marks a partner collection or market-maker wallet as exempt from the platform fee
+#[account]
+pub struct FeeExemption {
+    pub subject: Pubkey,         // Collection account or user wallet that is exempt
+    pub created_at: i64,         // When the exemption was granted
+    pub bump: u8,
+}
+
+// Tokens still owed for a redemption the pool could only partly pay
+#[account]
+pub struct PendingClaim {
+    pub claimant: Pubkey,        // Wallet the remainder is owed to
+    pub project: Pubkey,         // Project whose pool owes the claim
+    pub nft_mint: Pubkey,        // NFT whose redemption opened the claim
+    pub amount: u64,             // Tokens still owed
+    pub created_at: i64,         // Redemption timestamp
     pub bump: u8,
 }
 
@@ -97,6 +286,7 @@ pub struct NftListing {
     pub is_active: bool,         // Whether this listing is active
     pub created_at: i64,         // Creation timestamp
     pub collection: Pubkey,      // Collection account the NFT belongs to
+    pub price_updated_at: i64,   // When the asking price was last changed
     pub bump: u8,
 }
 
@@ -128,8 +318,8 @@ pub struct TraitValue {
     pub name: String,            // Name of the trait value (e.g., "Blue" for eye color)
     pub uri_postfix: String,     // Postfix to add to base URI (for asset loading)
     pub rarity_weight: u16,      // Weight for random selection (higher = more common)
-    pub available_supply: Option<u32>, // Optional limited supply for this trait
-    pub used_supply: u32,        // How many times this trait has been used
+    pub available_supply: Option<u64>, // Optional limited supply for this trait
+    pub used_supply: u64,        // How many times this trait has been used
 }
 
 // Collection traits configuration
@@ -140,9 +330,32 @@ pub struct CollectionTraitConfig {
     pub auto_generation_enabled: bool, // Whether auto-generation is enabled
     pub metadata_format: MetadataFormat, // Format of metadata (JSON, etc.)
     pub trait_types: Vec<Pubkey>, // List of trait type accounts
+    pub price_modifier_mode: PriceModifierMode, // How trait price modifiers are applied
+    pub price_modifiers: Vec<TraitPriceModifier>, // Mint price adjustments for specific trait values
+    pub randomness_source: RandomnessSource, // Where trait generation randomness comes from
+    pub combo_bonuses: Vec<TraitComboBonus>, // Extra rarity score for specific trait combinations
     pub bump: u8,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub enum PriceModifierMode {
+    Additive,                    // Modifiers are token amounts added to the price
+    Multiplicative,              // Modifiers are basis-point multipliers (10000 = unchanged)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TraitPriceModifier {
+    pub trait_name: String,      // Trait type the modifier applies to
+    pub value_name: String,      // Trait value that triggers the modifier
+    pub modifier: u64,           // Token amount or basis points, depending on the mode
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TraitComboBonus {
+    pub traits: Vec<TraitAttribute>, // Trait values that must all be present (distinct trait types)
+    pub bonus: u16,              // Rarity score added when the combination is present
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
 pub enum MetadataFormat {
     StandardJson,                // Standard JSON metadata format
@@ -155,8 +368,59 @@ pub enum MetadataFormat {
 pub struct NftTraits {
     pub nft_mint: Pubkey,        // NFT mint address
     pub collection: Pubkey,      // Collection account the NFT belongs to
-    pub trait_values: Vec<(String, String)>, // (trait type name, trait value name) pairs
+    pub trait_values: Vec<TraitAttribute>, // Trait type and value names, sorted by trait type
     pub is_auto_generated: bool, // Whether traits were auto-generated
     pub generation_seed: Option<[u8; 32]>, // Seed used for auto-generation if applicable
+    pub trait_hash: [u8; 32],    // sha256 of the canonical (sorted) trait set
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct TraitAttribute {
+    pub trait_type: String,      // Trait type name (e.g., "Eyes")
+    pub value: String,           // Trait value name (e.g., "Blue")
+}
+
+// Where randomness for trait rolls and fusion comes from
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum RandomnessSource {
+    SlotHash,                    // Hash of slot and keys; predictable within a slot
+    Oracle { oracle: Pubkey },   // Result delivered by the given oracle authority; trusted, not verified on-chain
+}
+
+// A pending or fulfilled request for randomness
+#[account]
+pub struct RandomnessRequest {
+    pub requester: Pubkey,       // Wallet the randomness is for
+    pub collection: Pubkey,      // Collection the randomness is used in
+    pub source: RandomnessSource, // Source the request was made against
+    pub request_id: u64,         // Requester-chosen id, part of the PDA seeds
+    pub requested_slot: u64,     // Slot the request was made in
+    pub result: Option<[u8; 32]>, // Random value once fulfilled
+    pub consumed: bool,          // Whether the result has been used
+    pub bump: u8,
+}
+
+// Rolling cap on how many NFTs a collection can mint per window
+#[account]
+pub struct MintRateLimit {
+    pub collection: Pubkey,      // Collection being throttled
+    pub max_mints: u64,          // Mints allowed per window (0 disables the cap)
+    pub window_seconds: i64,     // Window length (86400 for a daily cap)
+    pub window_start: i64,       // Start of the current window
+    pub minted_in_window: u64,   // Mints counted in the current window
+    pub bump: u8,
+}
+
+// Per-collection activity counters, updated by the instructions that act on the collection
+#[account]
+pub struct CollectionStats {
+    pub collection: Pubkey,      // Collection these stats belong to
+    pub mints: u64,              // NFTs minted or swapped into the collection
+    pub redemptions: u64,        // NFTs redeemed for tokens
+    pub fusions: u64,            // Fusions producing a child NFT in the collection
+    pub total_volume: u64,       // Tokens paid in swaps and listing sales for the collection's NFTs
+    pub floor_price: Option<u64>, // Lowest swap price paid for one of the collection's NFTs
+    pub last_updated: i64,       // Timestamp of the last recorded activity
     pub bump: u8,
 }
@@ -1,8 +1,9 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
-import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL } from '@solana/web3.js';
-import { TOKEN_PROGRAM_ID, createMint, getOrCreateAssociatedTokenAccount, mintTo } from '@solana/spl-token';
+import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL, Transaction } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID, NATIVE_MINT, createAccount, createMint, getAccount, getAssociatedTokenAddress, getMint, getOrCreateAssociatedTokenAccount, createSyncNativeInstruction, mintTo, transfer } from '@solana/spl-token';
 import { assert } from "chai";
+import { createHash } from "crypto";
 
 // Define the type structure for our program - normally this would be imported
 // from "../target/types/nft_fusion_marketplace" but we'll define it here
@@ -29,6 +30,22 @@ describe("nft-fusion-marketplace", () => {
   
   // Mock addresses for external programs
   const mockPythPriceAccount = Keypair.generate().publicKey;
+  // Pyth price account loaded from tests/fixtures, reporting an exponent of 30
+  const outOfRangeExpoPythAccount = new PublicKey("2XGCWcTA3cggXzDfKBV5Qy7TPGLpERydwFBy33keGe71");
+  // Pyth price account loaded from tests/fixtures, last published in 2020
+  const stalePythAccount = new PublicKey("8wz3C2wXDiaVjtz5mHqfc6mp8PA6jShZ2iEo6LgZb9w");
+  // Pyth price account loaded from tests/fixtures, quoting $1.00 with a $0.05 confidence interval
+  const wideConfidencePythAccount = new PublicKey("Ana9AVgUhwvrb6RxqR2ZwhPpgNqUpdYyEB2bGjQaHfzm");
+  // Escrow loaded from tests/fixtures whose token account's authority was moved off the escrow PDA.
+  // Its owner is derived from a fixed seed so the test can sign for it
+  const tamperedEscrowOwner = Keypair.fromSeed(createHash("sha256").update("tampered-escrow-owner").digest());
+  const tamperedEscrowNftMint = new PublicKey("G99eVWJGv7NqiMgFp6x3vhFzAedgH9UgCQ4oJydLRQhF");
+  const tamperedEscrowTokenMint = new PublicKey("HWy6kNG6XeYPfs1na68vMxyoyHQx3SYBjmzvwWtYsdC8");
+  const tamperedEscrowTokenAccount = new PublicKey("9RC5H9o57P63yzb9gicAvG9VpDeJNdQkRbUg3N6L6aGq");
+  const tamperedEscrowProject = new PublicKey("4a3JW5PFtKGqSFVJ1Ep75SAaDcvqdo55EbHuhLCPPb3V");
+  // Active listing loaded from tests/fixtures in the test collection, owned by a seeded keypair
+  const lockedListingOwner = Keypair.fromSeed(createHash("sha256").update("locked-listing-owner").digest());
+  const lockedListingPda = new PublicKey("ARRWgD3D2UbAk8krq2XBtdhTR4ExtWK7kz1v9HRvPiWy");
   
   // Test variables
   const projectId = "test-project";
@@ -36,6 +53,8 @@ describe("nft-fusion-marketplace", () => {
   const metadataUri = "https://arweave.net/test-metadata";
   let tokenMint: PublicKey;
   let userTokenAccount: PublicKey;
+  // The platform treasury wallet's token account for the test token, where platform fees land
+  let platformFeeAccount: PublicKey;
   let nftMint: PublicKey;
   
   // PDA addresses
@@ -44,6 +63,9 @@ describe("nft-fusion-marketplace", () => {
   let collectionPda: PublicKey;
   let liquidityPoolPda: PublicKey;
   let lpTokenAccountPda: PublicKey;
+  let userTierPda: PublicKey;
+  let royaltyConfigPda: PublicKey;
+  let fusionConfigPda: PublicKey;
   
   before(async () => {
     // Airdrop SOL to test accounts
@@ -77,6 +99,25 @@ describe("nft-fusion-marketplace", () => {
       1000 * 10**9 // 1000 tokens with 9 decimals
     );
     
+    // Create fee destination token accounts at the project treasury and royalty addresses
+    for (const treasury of [projectTreasury, royaltyWallet]) {
+      await createAccount(
+        provider.connection,
+        platformAuthority,
+        tokenMint,
+        platformAuthority.publicKey,
+        treasury
+      );
+    }
+    
+    // The platform treasury is a wallet; its fees land in its token account for the pool's token
+    platformFeeAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      platformAuthority,
+      tokenMint,
+      platformTreasury.publicKey
+    )).address;
+    
     // Derive PDAs
     [platformConfigPda] = PublicKey.findProgramAddressSync(
       [Buffer.from("platform_config")],
@@ -92,12 +133,27 @@ describe("nft-fusion-marketplace", () => {
       [Buffer.from("collection"), Buffer.from(collectionId)],
       program.programId
     );
+    
+    [fusionConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("fusion_config"), collectionPda.toBuffer()],
+      program.programId
+    );
+    
+    [userTierPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_tier"), projectPda.toBuffer(), user.publicKey.toBuffer()],
+      program.programId
+    );
+    
+    [royaltyConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("royalty_config"), projectPda.toBuffer()],
+      program.programId
+    );
   });
 
   it("Initializes the platform", async () => {
     try {
       await program.methods
-        .initialize(500, platformTreasury.publicKey) // 5% platform fee
+        .initializePlatform(500, platformTreasury.publicKey) // 5% platform fee
         .accounts({
           authority: platformAuthority.publicKey,
           platformConfig: platformConfigPda,
@@ -111,6 +167,9 @@ describe("nft-fusion-marketplace", () => {
       assert.equal(platformConfig.authority.toString(), platformAuthority.publicKey.toString());
       assert.equal(platformConfig.platformFeeBasisPoints, 500);
       assert.equal(platformConfig.platformTreasury.toString(), platformTreasury.publicKey.toString());
+      assert.equal(platformConfig.maxProjectRoyaltyBps, 10000);
+      assert.equal(platformConfig.reclaimSplitBps, 10000);
+      assert.isNull(platformConfig.maxPlatformFeeAbsolute);
       
       console.log("Platform initialized successfully");
     } catch (error) {
@@ -145,6 +204,7 @@ describe("nft-fusion-marketplace", () => {
       assert.equal(project.royaltyWallet.toString(), royaltyWallet.publicKey.toString());
       assert.equal(project.royaltyBasisPoints, 200);
       assert.isTrue(project.isActive);
+      assert.isAbove(project.lastActivityTimestamp.toNumber(), 0);
       
       console.log("Project created successfully");
     } catch (error) {
@@ -168,6 +228,7 @@ describe("nft-fusion-marketplace", () => {
           platformConfig: platformConfigPda,
           project: projectPda,
           collection: collectionPda,
+          collectionRegistry: collectionRegistryPda(projectPda),
           tokenMintAccount: tokenMint,
           systemProgram: SystemProgram.programId,
         })
@@ -181,6 +242,7 @@ describe("nft-fusion-marketplace", () => {
       assert.equal(collection.metadataUri, metadataUri);
       assert.equal(collection.tokenMint.toString(), tokenMint.toString());
       assert.isFalse(collection.isCompressed);
+      assert.isTrue(collection.redeemable);
       
       console.log("Collection created successfully");
     } catch (error) {
@@ -221,6 +283,8 @@ describe("nft-fusion-marketplace", () => {
           tokenMintAccount: tokenMint,
           lpTokenAccount: lpTokenAccountPda,
           authorityTokenAccount: userTokenAccount, // Using user's account for simplicity in test
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
           tokenProgram: TOKEN_PROGRAM_ID,
           associatedTokenProgram: new PublicKey("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL"),
           systemProgram: SystemProgram.programId,
@@ -264,6 +328,610 @@ describe("nft-fusion-marketplace", () => {
     return liquidityPool;
   }
 
+  // Helper function to swap tokens for a freshly minted NFT
+  async function swapTokenForNft(
+    tokenAmount: anchor.BN,
+    discountPercent: number | null = null,
+    cooldownPeriod: anchor.BN | null = null,
+    royaltyDestination: PublicKey = royaltyWallet.publicKey,
+    nftMintKeypair: Keypair = Keypair.generate(),
+    traitsSelection: Buffer | null = null,
+    traitAccounts: PublicKey[] = [],
+    royaltyRecipients: PublicKey[] = [],
+    loyaltyNft: { nftData: PublicKey; tokenAccount: PublicKey } | null = null,
+    platformTreasuryAccount: PublicKey = platformFeeAccount
+  ) {
+    const [nftDataPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+      program.programId
+    );
+    
+    const signature = await program.methods
+      .swapTokenForNft(collectionId, tokenAmount, discountPercent, cooldownPeriod, traitsSelection)
+      .accounts({
+        user: user.publicKey,
+        platformConfig: platformConfigPda,
+        collection: collectionPda,
+        collectionStats: collectionStatsPda(collectionPda),
+        mintRateLimit: mintRateLimitPda(collectionPda),
+        project: projectPda,
+        liquidityPool: liquidityPoolPda,
+        userTokenAccount: userTokenAccount,
+        lpTokenAccount: lpTokenAccountPda,
+        tokenMint: tokenMint,
+        platformTreasury: platformTreasuryAccount,
+        projectTreasury: projectTreasury.publicKey,
+        royaltyWallet: royaltyDestination,
+        royaltyConfig: royaltyConfigPda,
+        collectionFeeExemption: feeExemptionPda(collectionPda),
+        userFeeExemption: feeExemptionPda(user.publicKey),
+        nftMint: nftMintKeypair.publicKey,
+        nftData: nftDataPda,
+        loyaltyNftData: loyaltyNft ? loyaltyNft.nftData : SystemProgram.programId,
+        loyaltyTokenAccount: loyaltyNft ? loyaltyNft.tokenAccount : SystemProgram.programId,
+        userTier: userTierPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .remainingAccounts([...traitAccounts, ...royaltyRecipients].map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+      .signers([user, nftMintKeypair])
+      .rpc();
+    
+    return { nftMint: nftMintKeypair.publicKey, nftDataPda, signature };
+  }
+
+  // Helper function to derive the escrow PDAs for an NFT
+  function escrowPdas(nftMint: PublicKey) {
+    const [tokenEscrowPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("token_escrow"), nftMint.toBuffer()],
+      program.programId
+    );
+    const [escrowTokenAccountPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow_token_account"), nftMint.toBuffer()],
+      program.programId
+    );
+    return { tokenEscrowPda, escrowTokenAccountPda };
+  }
+
+  // Helper function to derive a collection's stats PDA
+  function collectionStatsPda(collection: PublicKey) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("collection_stats"), collection.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // Helper function to derive a project's collection registry PDA
+  function collectionRegistryPda(project: PublicKey) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("collection_registry"), project.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // Helper function to derive a wallet's fusion cooldown PDA in the main collection
+  function fusionCooldownPda(fuser: PublicKey) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("fusion_cooldown"), collectionPda.toBuffer(), fuser.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // Helper function to derive the platform fee exemption PDA for a collection or user
+  function feeExemptionPda(subject: PublicKey) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("fee_exemption"), subject.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  function mintRateLimitPda(collection: PublicKey) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("mint_rate_limit"), collection.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  function collectionTraitConfigPda(collection: PublicKey) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("trait_config"), collection.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  function userEscrowCountPda(owner: PublicKey = user.publicKey) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("user_escrow_count"), projectPda.toBuffer(), owner.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  function randomnessRequestPda(requester: PublicKey, requestId: anchor.BN) {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("randomness"), requester.toBuffer(), requestId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+  }
+
+  // Helper function to assert that an action fails with the named error
+  async function expectError(action: () => Promise<unknown>, errorName: string, message: string) {
+    let rejected = false;
+    try {
+      await action();
+    } catch (error) {
+      rejected = true;
+      assert.include(error.message, errorName);
+    }
+    assert.isTrue(rejected, message);
+  }
+
+  // Helper function to lock tokens in escrow against an NFT
+  async function createTokenEscrow(
+    nftMint: PublicKey,
+    tokenAmount: anchor.BN,
+    vestingPeriod: anchor.BN | null = null
+  ) {
+    const [nftDataPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("nft_data"), nftMint.toBuffer()],
+      program.programId
+    );
+    const { tokenEscrowPda, escrowTokenAccountPda } = escrowPdas(nftMint);
+    
+    await program.methods
+      .createTokenEscrow(nftMint, tokenAmount, vestingPeriod)
+      .accounts({
+        owner: user.publicKey,
+        platformConfig: platformConfigPda,
+        nftData: nftDataPda,
+        collection: collectionPda,
+        project: projectPda,
+        tokenEscrow: tokenEscrowPda,
+        userEscrowCount: userEscrowCountPda(),
+        tokenMint: tokenMint,
+        escrowTokenAccount: escrowTokenAccountPda,
+        ownerTokenAccount: userTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([user])
+      .rpc();
+    
+    return { nftDataPda, tokenEscrowPda, escrowTokenAccountPda };
+  }
+
+  // Helper function to redeem an NFT for its escrowed tokens
+  async function redeemEscrowToken(nftMint: PublicKey) {
+    const [nftDataPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("nft_data"), nftMint.toBuffer()],
+      program.programId
+    );
+    const { tokenEscrowPda, escrowTokenAccountPda } = escrowPdas(nftMint);
+    
+    await program.methods
+      .redeemEscrowToken(nftMint)
+      .accounts({
+        user: user.publicKey,
+        platformConfig: platformConfigPda,
+        nftData: nftDataPda,
+        tokenEscrow: tokenEscrowPda,
+        collection: collectionPda,
+        collectionStats: collectionStatsPda(collectionPda),
+        userEscrowCount: userEscrowCountPda(),
+        project: projectPda,
+        userTokenAccount: userTokenAccount,
+        escrowTokenAccount: escrowTokenAccountPda,
+        platformTreasury: platformFeeAccount,
+        projectTreasury: projectTreasury.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  // Helper function to fuse parent NFTs into a new child NFT
+  // Helper function to build a fusion of the given parents into a fresh child, unsent
+  function fuseNftsRequest(
+    parents: { nftDataPda: PublicKey; userNftAccount: PublicKey }[],
+    childMetadataUri = metadataUri,
+    fuser: Keypair = user,
+    extraChildCount = 0,
+    fuserTokenAccount: PublicKey = userTokenAccount,
+    randomnessRequestId = new anchor.BN(0)
+  ) {
+    const childMintKeypair = Keypair.generate();
+    const [childNftDataPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("nft_data"), childMintKeypair.publicKey.toBuffer()],
+      program.programId
+    );
+    const extraChildMintKeypairs = Array.from({ length: extraChildCount }, () => Keypair.generate());
+    const extraChildNftDataPdas = extraChildMintKeypairs.map((keypair) => PublicKey.findProgramAddressSync(
+      [Buffer.from("nft_data"), keypair.publicKey.toBuffer()],
+      program.programId
+    )[0]);
+    
+    const request = program.methods
+      .fuseNfts(collectionId, childMetadataUri, randomnessRequestId)
+      .accounts({
+        user: fuser.publicKey,
+        collection: collectionPda,
+        collectionStats: collectionStatsPda(collectionPda),
+        project: projectPda,
+        fusionConfig: fusionConfigPda,
+        liquidityPool: liquidityPoolPda,
+        childMint: childMintKeypair.publicKey,
+        childNftData: childNftDataPda,
+        fusionCooldown: fusionCooldownPda(fuser.publicKey),
+        randomnessRequest: randomnessRequestPda(fuser.publicKey, randomnessRequestId),
+        tokenMint: tokenMint,
+        userTokenAccount: fuserTokenAccount,
+        lpTokenAccount: lpTokenAccountPda,
+        projectTreasury: projectTreasury.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        ...parents.flatMap(({ nftDataPda, userNftAccount }) => [
+          { pubkey: nftDataPda, isWritable: true, isSigner: false },
+          { pubkey: userNftAccount, isWritable: false, isSigner: false },
+        ]),
+        ...extraChildMintKeypairs.flatMap((keypair, i) => [
+          { pubkey: keypair.publicKey, isWritable: false, isSigner: true },
+          { pubkey: extraChildNftDataPdas[i], isWritable: true, isSigner: false },
+        ]),
+      ]);
+    
+    return {
+      request,
+      signers: [fuser, childMintKeypair, ...extraChildMintKeypairs],
+      childMint: childMintKeypair.publicKey,
+      childNftDataPda,
+      extraChildNftDataPdas,
+    };
+  }
+
+  async function fuseNfts(
+    parents: { nftDataPda: PublicKey; userNftAccount: PublicKey }[],
+    childMetadataUri = metadataUri,
+    fuser: Keypair = user,
+    extraChildCount = 0,
+    randomnessRequestId = new anchor.BN(0)
+  ) {
+    const fuserTokenAccount = fuser === user
+      ? userTokenAccount
+      : (await getOrCreateAssociatedTokenAccount(provider.connection, fuser, tokenMint, fuser.publicKey)).address;
+    const { request, signers, childMint, childNftDataPda, extraChildNftDataPdas } =
+      fuseNftsRequest(parents, childMetadataUri, fuser, extraChildCount, fuserTokenAccount, randomnessRequestId);
+    
+    const signature = await request.signers(signers).rpc();
+    
+    return { childMint, childNftDataPda, extraChildNftDataPdas, signature };
+  }
+
+  // Helper function to create a real 0-decimal mint with one token held by the user
+  async function createHeldNftMint() {
+    const nftMintKeypair = Keypair.generate();
+    await createMint(
+      provider.connection,
+      user,
+      user.publicKey,
+      null,
+      0,
+      nftMintKeypair
+    );
+    const userNftAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      user,
+      nftMintKeypair.publicKey,
+      user.publicKey
+    )).address;
+    await mintTo(
+      provider.connection,
+      user,
+      nftMintKeypair.publicKey,
+      userNftAccount,
+      user,
+      1
+    );
+    
+    return { nftMintKeypair, userNftAccount };
+  }
+
+  // Helper function to swap for an NFT whose token the user actually holds
+  async function swapForHeldNft(
+    tokenAmount: anchor.BN,
+    discountPercent: number | null = null,
+    cooldownPeriod: anchor.BN | null = null
+  ) {
+    const { nftMintKeypair, userNftAccount } = await createHeldNftMint();
+    const { nftMint, nftDataPda } = await swapTokenForNft(
+      tokenAmount,
+      discountPercent,
+      cooldownPeriod,
+      royaltyWallet.publicKey,
+      nftMintKeypair
+    );
+    
+    return { nftMint, nftDataPda, userNftAccount };
+  }
+
+  // Helper function to build the accounts for redeeming an NFT from the liquidity pool
+  function redeemNftAccounts(nftMint: PublicKey, userNftAccount: PublicKey) {
+    const [nftDataPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("nft_data"), nftMint.toBuffer()],
+      program.programId
+    );
+    const { tokenEscrowPda } = escrowPdas(nftMint);
+    const [redemptionReservationPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("redemption_reservation"), nftMint.toBuffer()],
+      program.programId
+    );
+    const [pendingClaimPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_claim"), nftMint.toBuffer()],
+      program.programId
+    );
+    
+    return {
+      user: user.publicKey,
+      platformConfig: platformConfigPda,
+      nftData: nftDataPda,
+      collection: collectionPda,
+      collectionStats: collectionStatsPda(collectionPda),
+      project: projectPda,
+      liquidityPool: liquidityPoolPda,
+      nftMint: nftMint,
+      tokenEscrow: tokenEscrowPda,
+      redemptionReservation: redemptionReservationPda,
+      pendingClaim: pendingClaimPda,
+      userNftAccount: userNftAccount,
+      userTokenAccount: userTokenAccount,
+      lpTokenAccount: lpTokenAccountPda,
+      tokenMint: tokenMint,
+      projectTreasury: projectTreasury.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  // Helper function to redeem an NFT for tokens from the liquidity pool
+  async function redeemNftForToken(nftMint: PublicKey, userNftAccount: PublicKey, bypassCooldown = false) {
+    await program.methods
+      .redeemNftForToken(nftMint, bypassCooldown)
+      .accounts(redeemNftAccounts(nftMint, userNftAccount))
+      .signers([user])
+      .rpc();
+  }
+
+  // Helper function to pin the Pyth feed the main pool's token price is read from
+  async function pinPythPriceFeed(feed: PublicKey | null) {
+    await program.methods
+      .setPythPriceFeed(projectId, feed)
+      .accounts({
+        authority: platformAuthority.publicKey,
+        project: projectPda,
+        liquidityPool: liquidityPoolPda,
+      })
+      .signers([platformAuthority])
+      .rpc();
+  }
+
+  // Helper function to pin the DEX reserve accounts the main pool's token price is read from
+  async function pinDexPriceAccounts(dexTokenAccount: PublicKey | null, dexBaseAccount: PublicKey | null) {
+    await program.methods
+      .setDexPriceAccounts(projectId, dexTokenAccount, dexBaseAccount)
+      .accounts({
+        authority: platformAuthority.publicKey,
+        project: projectPda,
+        liquidityPool: liquidityPoolPda,
+      })
+      .signers([platformAuthority])
+      .rpc();
+  }
+
+  // Helper function to redeem an NFT from the pool while passing another collection in place of its own
+  function redeemWithCollection(
+    nftMint: PublicKey,
+    userNftAccount: PublicKey,
+    collection: PublicKey,
+    bypassCooldown = false
+  ) {
+    return program.methods
+      .redeemNftForToken(nftMint, bypassCooldown)
+      .accounts({
+        ...redeemNftAccounts(nftMint, userNftAccount),
+        collection,
+        collectionStats: collectionStatsPda(collection),
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  // Helper function to create a collection of the project's token under the given project
+  async function createCollectionUnder(id: string, ownerProjectId: string, ownerProjectPda: PublicKey) {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("collection"), Buffer.from(id)],
+      program.programId
+    );
+    await program.methods
+      .createCollection(id, ownerProjectId, metadataUri, tokenMint, false)
+      .accounts({
+        authority: platformAuthority.publicKey,
+        platformConfig: platformConfigPda,
+        project: ownerProjectPda,
+        collection: pda,
+        collectionRegistry: collectionRegistryPda(ownerProjectPda),
+        tokenMintAccount: tokenMint,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([platformAuthority])
+      .rpc();
+    return pda;
+  }
+
+  // Helper function to create a collection without a token under the given project, opted in to free mints
+  async function createFreeMintCollection(
+    id: string,
+    ownerProjectId = projectId,
+    ownerProjectPda = projectPda,
+    allowFreeMint = true
+  ) {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("collection"), Buffer.from(id)],
+      program.programId
+    );
+    await program.methods
+      .createCollection(id, ownerProjectId, metadataUri, null, false)
+      .accounts({
+        authority: platformAuthority.publicKey,
+        platformConfig: platformConfigPda,
+        project: ownerProjectPda,
+        collection: pda,
+        collectionRegistry: collectionRegistryPda(ownerProjectPda),
+        tokenMintAccount: SystemProgram.programId,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([platformAuthority])
+      .rpc();
+    if (allowFreeMint) {
+      await program.methods
+        .setFreeMintAllowed(true)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: ownerProjectPda,
+          collection: pda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    }
+    return pda;
+  }
+
+  // Helper function to mint through mint_nft into a fresh mint, with trait type accounts in selection order
+  async function mintNftInto(
+    mintCollectionId: string,
+    mintCollection: PublicKey,
+    traitsSelection: Buffer | null = null,
+    traitTypes: PublicKey[] = [],
+    mintProject: PublicKey = projectPda
+  ) {
+    const nftMintKeypair = Keypair.generate();
+    await createMint(provider.connection, user, user.publicKey, null, 0, nftMintKeypair);
+    const [nftDataPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+      program.programId
+    );
+    
+    await program.methods
+      .mintNft(mintCollectionId, metadataUri, traitsSelection)
+      .accounts({
+        user: user.publicKey,
+        platformConfig: platformConfigPda,
+        collection: mintCollection,
+        collectionStats: collectionStatsPda(mintCollection),
+        mintRateLimit: mintRateLimitPda(mintCollection),
+        collectionTraitConfig: collectionTraitConfigPda(mintCollection),
+        project: mintProject,
+        nftMint: nftMintKeypair.publicKey,
+        nftData: nftDataPda,
+        loyaltyNftData: SystemProgram.programId,
+        loyaltyTokenAccount: SystemProgram.programId,
+        metadataAccount: Keypair.generate().publicKey,
+        masterEdition: Keypair.generate().publicKey,
+        userTokenAccount: Keypair.generate().publicKey,
+        tokenMetadataProgram: new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .remainingAccounts(traitTypes.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+      .signers([user, nftMintKeypair])
+      .rpc();
+    
+    return nftDataPda;
+  }
+
+  // Helper function to create an unrelated project with an empty pool and one collection of its own
+  async function createRivalProject(rivalProjectId: string) {
+    const [rivalProjectPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("project"), Buffer.from(rivalProjectId)],
+      program.programId
+    );
+    const [rivalPoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("liquidity_pool"), rivalProjectPda.toBuffer()],
+      program.programId
+    );
+    const rivalLpTokenAccount = await getAssociatedTokenAddress(tokenMint, rivalPoolPda, true);
+    const authorityTokenAccount = (await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      platformAuthority,
+      tokenMint,
+      platformAuthority.publicKey
+    )).address;
+    
+    await program.methods
+      .createProject(rivalProjectId, projectTreasury.publicKey, royaltyWallet.publicKey, 200)
+      .accounts({
+        authority: platformAuthority.publicKey,
+        platformConfig: platformConfigPda,
+        project: rivalProjectPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([platformAuthority])
+      .rpc();
+    await program.methods
+      .setupLiquidityPool(rivalProjectId, tokenMint, new anchor.BN(0))
+      .accounts({
+        authority: platformAuthority.publicKey,
+        platformConfig: platformConfigPda,
+        project: rivalProjectPda,
+        liquidityPool: rivalPoolPda,
+        tokenMintAccount: tokenMint,
+        lpTokenAccount: rivalLpTokenAccount,
+        authorityTokenAccount,
+        platformTreasury: platformFeeAccount,
+        projectTreasury: projectTreasury.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([platformAuthority])
+      .rpc();
+    const rivalCollectionPda = await createCollectionUnder(`${rivalProjectId}-collection`, rivalProjectId, rivalProjectPda);
+    
+    return { rivalProjectPda, rivalPoolPda, rivalLpTokenAccount, rivalCollectionPda };
+  }
+
+  // Helper function to read a token account balance as a number
+  async function tokenBalance(address: PublicKey): Promise<number> {
+    return Number((await getAccount(provider.connection, address)).amount);
+  }
+
+  it("Reports a pending first price on a freshly set up pool", async () => {
+    try {
+      const liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isNull(liquidityPool.oraclePriceUsd);
+      
+      // Swaps need a price, and the error says the price is pending rather than stale
+      await expectError(
+        () => swapTokenForNft(new anchor.BN(10**9)),
+        "OracleNotInitialized",
+        "Swapping before the first price update should be rejected"
+      );
+      
+      console.log("Fresh pool reports oracle not initialized");
+    } catch (error) {
+      console.error("Error testing fresh pool oracle status:", error);
+      throw error;
+    }
+  });
+
   it("Updates price manually", async () => {
     try {
       // Set a manual price of $10.50 USD (scaled by 10^6)
@@ -272,7 +940,8 @@ describe("nft-fusion-marketplace", () => {
       await program.methods
         .setPriceManually(
           projectId,
-          priceUsd
+          priceUsd,
+          null
         )
         .accounts({
           authority: platformAuthority.publicKey,
@@ -344,23 +1013,34 @@ describe("nft-fusion-marketplace", () => {
         5000 * 10**6 // 5000 USDC with 6 decimals (represents a $5 token price)
       );
       
-      await program.methods
-        .updatePriceFromDex(
-          projectId
-        )
-        .accounts({
-          authority: platformAuthority.publicKey,
-          platformConfig: platformConfigPda,
-          project: projectPda,
-          liquidityPool: liquidityPoolPda,
-          dexTokenAccount: dexTokenAccount.address,
-          dexBaseAccount: dexBaseAccount.address,
-          tokenMint: tokenMint,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([platformAuthority])
-        .rpc();
+      const updateFromDex = () =>
+        program.methods
+          .updatePriceFromDex(
+            projectId
+          )
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            dexTokenAccount: dexTokenAccount.address,
+            dexBaseAccount: dexBaseAccount.address,
+            tokenMint: tokenMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Reserves the project hasn't pinned can't set the price
+      await expectError(
+        () => updateFromDex(),
+        "PriceFeedMismatch",
+        "A DEX update from unpinned reserves should be rejected"
+      );
+      
+      await pinDexPriceAccounts(dexTokenAccount.address, dexBaseAccount.address);
+      await updateFromDex();
       
       // Verify price was updated from DEX
       await checkOraclePrice('dexLiquidity', 1_000_000); // At least $1
@@ -398,9 +1078,9 @@ describe("nft-fusion-marketplace", () => {
           
         console.log("Pyth update succeeded unexpectedly");
       } catch (error) {
-        // We expect this to fail because we're not using a real Pyth account
+        // No feed is pinned on the pool yet, so the fake account is refused outright
         console.log("Pyth update failed as expected:", error.message);
-        assert.include(error.message, "Error"); // Should contain some error message
+        assert.include(error.message, "PriceFeedMismatch");
       }
       
       // Verify the previous price is still there
@@ -446,9 +1126,14 @@ describe("nft-fusion-marketplace", () => {
           user: user.publicKey,
           platformConfig: platformConfigPda,
           collection: collectionPda,
+          collectionStats: collectionStatsPda(collectionPda),
+          mintRateLimit: mintRateLimitPda(collectionPda),
+          collectionTraitConfig: collectionTraitConfigPda(collectionPda),
           project: projectPda,
           nftMint: nftMint,
           nftData: nftDataPda,
+          loyaltyNftData: SystemProgram.programId,
+          loyaltyTokenAccount: SystemProgram.programId,
           metadataAccount: metadataAccount,
           masterEdition: masterEdition,
           userTokenAccount: userTokenAccount,
@@ -651,4 +1336,6712 @@ describe("nft-fusion-marketplace", () => {
       throw error;
     }
   });
+
+  it("Sets a volume-based fee discount schedule", async () => {
+    try {
+      // 50% off platform and project fees once a user has traded 10 tokens
+      const tiers = [{ minVolume: new anchor.BN(10 * 10**9), discountBps: 5000 }];
+      
+      await program.methods
+        .setFeeDiscountTiers(projectId, tiers)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const project = await program.account.project.fetch(projectPda);
+      assert.equal(project.feeDiscountTiers.length, 1);
+      assert.equal(project.feeDiscountTiers[0].discountBps, 5000);
+      
+      // A discount above 100% would push fees negative and must be rejected
+      await expectError(
+        () => program.methods
+          .setFeeDiscountTiers(projectId, [{ minVolume: new anchor.BN(1), discountBps: 10001 }])
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidFeeDiscount",
+        "Discount above 10000 bps should be rejected"
+      );
+      
+      console.log("Fee discount schedule set successfully");
+    } catch (error) {
+      console.error("Error setting fee discount schedule:", error);
+      throw error;
+    }
+  });
+
+  it("Charges lower fees once a user crosses a volume threshold", async () => {
+    try {
+      const swapAmount = new anchor.BN(10 * 10**9);
+      
+      // First swap happens below the threshold and pays the full fee
+      const treasuryBefore = await tokenBalance(platformFeeAccount);
+      await swapTokenForNft(swapAmount);
+      const treasuryAfterFirst = await tokenBalance(platformFeeAccount);
+      const firstFee = treasuryAfterFirst - treasuryBefore;
+      
+      // The first swap brought the user to the 10 token tier
+      const userTier = await program.account.userTier.fetch(userTierPda);
+      assert.equal(userTier.cumulativeVolume.toString(), swapAmount.toString());
+      
+      // Second swap pays the discounted fee
+      await swapTokenForNft(swapAmount);
+      const treasuryAfterSecond = await tokenBalance(platformFeeAccount);
+      const secondFee = treasuryAfterSecond - treasuryAfterFirst;
+      
+      assert.isAbove(firstFee, 0);
+      assert.equal(secondFee, firstFee / 2);
+      
+      console.log(`Platform fee dropped from ${firstFee} to ${secondFee} after crossing the tier`);
+    } catch (error) {
+      console.error("Error testing fee discount tiers:", error);
+      throw error;
+    }
+  });
+
+  it("Returns a mistakenly redeemed NFT to its owner", async () => {
+    try {
+      const escrowAmount = new anchor.BN(5 * 10**9);
+      const { nftMint: escrowNftMint, nftDataPda } = await swapTokenForNft(new anchor.BN(10 * 10**9));
+      const { tokenEscrowPda, escrowTokenAccountPda } = await createTokenEscrow(escrowNftMint, escrowAmount);
+      
+      // Redeeming hands the NFT to the project and pays out the escrow
+      const balanceBeforeRedeem = await tokenBalance(userTokenAccount);
+      await redeemEscrowToken(escrowNftMint);
+      const payout = (await tokenBalance(userTokenAccount)) - balanceBeforeRedeem;
+      
+      let nftData = await program.account.nftData.fetch(nftDataPda);
+      assert.equal(nftData.owner.toString(), projectPda.toString());
+      
+      // The project reverses the redemption and the user hands the payout back
+      await program.methods
+        .returnRedeemedNft(escrowNftMint)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          user: user.publicKey,
+          nftData: nftDataPda,
+          tokenEscrow: tokenEscrowPda,
+          collection: collectionPda,
+          project: projectPda,
+          userTokenAccount: userTokenAccount,
+          escrowTokenAccount: escrowTokenAccountPda,
+          tokenMint: tokenMint,
+          userEscrowCount: userEscrowCountPda(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority, user])
+        .rpc();
+      
+      nftData = await program.account.nftData.fetch(nftDataPda);
+      assert.equal(nftData.owner.toString(), user.publicKey.toString());
+      
+      const tokenEscrow = await program.account.tokenEscrow.fetch(tokenEscrowPda);
+      assert.isTrue(tokenEscrow.isActive);
+      assert.equal(tokenEscrow.tokenAmount.toNumber(), payout);
+      assert.equal(await tokenBalance(escrowTokenAccountPda), payout);
+      assert.equal(await tokenBalance(userTokenAccount), balanceBeforeRedeem);
+      
+      // A second return is rejected since the NFT is no longer held by the project
+      await expectError(
+        () => program.methods
+          .returnRedeemedNft(escrowNftMint)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            user: user.publicKey,
+            nftData: nftDataPda,
+            tokenEscrow: tokenEscrowPda,
+            collection: collectionPda,
+            project: projectPda,
+            userTokenAccount: userTokenAccount,
+            escrowTokenAccount: escrowTokenAccountPda,
+            tokenMint: tokenMint,
+            userEscrowCount: userEscrowCountPda(),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([platformAuthority, user])
+          .rpc(),
+        "NftNotRedeemed",
+        "Returning an NFT the project does not hold should fail"
+      );
+      
+      console.log("Redeemed NFT returned to its owner");
+    } catch (error) {
+      console.error("Error returning redeemed NFT:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects zero-weight trait values", async () => {
+    try {
+      const traitName = "Background";
+      const [traitTypePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), collectionPda.toBuffer(), Buffer.from(traitName)],
+        program.programId
+      );
+      const traitValue = (name: string, rarityWeight: number) => ({
+        name,
+        uriPostfix: name.toLowerCase(),
+        rarityWeight,
+        availableSupply: null,
+        usedSupply: new anchor.BN(0),
+      });
+      
+      // A zero-weight value can never be rolled, so the trait type is rejected outright
+      await expectError(
+        () => program.methods
+          .initializeTraitType(traitName, true, [traitValue("Blue", 70), traitValue("Gold", 0)])
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            traitType: traitTypePda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidTraitConfig",
+        "Zero-weight trait value should be rejected at creation"
+      );
+      
+      await program.methods
+        .initializeTraitType(traitName, true, [traitValue("Blue", 70), traitValue("Gold", 5)])
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          traitType: traitTypePda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // Adding a zero-weight value later is rejected the same way
+      await expectError(
+        () => program.methods
+          .addTraitValue(traitValue("Void", 0))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            traitType: traitTypePda,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidTraitConfig",
+        "Zero-weight trait value should be rejected when added"
+      );
+      
+      await program.methods
+        .addTraitValue(traitValue("Red", 25))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          traitType: traitTypePda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const traitType = await program.account.traitType.fetch(traitTypePda);
+      assert.deepEqual(traitType.traitValues.map((v) => v.name), ["Blue", "Gold", "Red"]);
+      assert.isTrue(traitType.traitValues.every((v) => v.rarityWeight > 0));
+      
+      console.log("Zero-weight trait values rejected");
+    } catch (error) {
+      console.error("Error testing zero-weight trait values:", error);
+      throw error;
+    }
+  });
+
+  it("Caps fusion inputs at MAX_FUSION_INPUTS", async () => {
+    try {
+      const maxFusionInputs = 5;
+      const initializeFusionConfig = (maxNftsAllowed: number) =>
+        program.methods
+          .initializeFusionConfig(2, maxNftsAllowed, 100, 0, new anchor.BN(0), { maxParent: {} })
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            fusionConfig: fusionConfigPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // A config allowing more inputs than the hard cap is rejected
+      await expectError(
+        () => initializeFusionConfig(maxFusionInputs + 1),
+        "FusionInputLimitExceeded",
+        "max_nfts_allowed above the hard cap should be rejected"
+      );
+      
+      await initializeFusionConfig(maxFusionInputs);
+      
+      // Mint one more parent than the cap allows
+      const parents = [];
+      for (let i = 0; i <= maxFusionInputs; i++) {
+        parents.push(await swapForHeldNft(new anchor.BN(10**9)));
+      }
+      
+      await expectError(
+        () => fuseNfts(parents),
+        "FusionInputLimitExceeded",
+        "Fusing more than the cap should fail with a clear error"
+      );
+      
+      // Fusing exactly at the cap succeeds within the default compute budget
+      const { childNftDataPda, signature } = await fuseNfts(parents.slice(0, maxFusionInputs));
+      const child = await program.account.nftData.fetch(childNftDataPda);
+      assert.equal(child.parentNfts.length, maxFusionInputs);
+      assert.equal(child.fusionLevel, 1);
+      
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      assert.isBelow(tx.meta.computeUnitsConsumed, 200_000);
+      
+      console.log(`Fused ${maxFusionInputs} parents using ${tx.meta.computeUnitsConsumed} compute units`);
+    } catch (error) {
+      console.error("Error testing fusion input cap:", error);
+      throw error;
+    }
+  });
+
+  it("Accrues royalties in a vault and claims them in bulk", async () => {
+    try {
+      const [royaltyVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("royalty_vault"), projectPda.toBuffer()],
+        program.programId
+      );
+      
+      await program.methods
+        .setRoyaltyAccrual(projectId, true)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          tokenMint: tokenMint,
+          royaltyVault: royaltyVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // Swaps now route royalties to the vault instead of the royalty wallet
+      const royaltyWalletBefore = await tokenBalance(royaltyWallet.publicKey);
+      const swapAmount = new anchor.BN(10 * 10**9);
+      for (let i = 0; i < 3; i++) {
+        await swapTokenForNft(swapAmount, null, null, royaltyVaultPda);
+      }
+      
+      const project = await program.account.project.fetch(projectPda);
+      const accrued = project.accruedRoyalties.toNumber();
+      assert.isAbove(accrued, 0);
+      assert.equal(await tokenBalance(royaltyVaultPda), accrued);
+      assert.equal(await tokenBalance(royaltyWallet.publicKey), royaltyWalletBefore);
+      
+      // One claim pays out everything accrued across the swaps
+      await program.methods
+        .claimRoyalties()
+        .accounts({
+          claimer: platformAuthority.publicKey,
+          project: projectPda,
+          royaltyVault: royaltyVaultPda,
+          royaltyWallet: royaltyWallet.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      assert.equal(await tokenBalance(royaltyWallet.publicKey), royaltyWalletBefore + accrued);
+      assert.equal(await tokenBalance(royaltyVaultPda), 0);
+      assert.equal((await program.account.project.fetch(projectPda)).accruedRoyalties.toNumber(), 0);
+      
+      // Switch back to per-swap royalty transfers for the remaining tests
+      await program.methods
+        .setRoyaltyAccrual(projectId, false)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          tokenMint: tokenMint,
+          royaltyVault: royaltyVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      console.log(`Claimed ${accrued} accrued royalty tokens in a single transfer`);
+    } catch (error) {
+      console.error("Error testing royalty accrual:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects minting into a mint that already has supply", async () => {
+    try {
+      // An existing NFT-shaped mint that already has a token in circulation
+      const nftMintKeypair = Keypair.generate();
+      await createMint(
+        provider.connection,
+        platformAuthority,
+        platformAuthority.publicKey,
+        null,
+        0,
+        nftMintKeypair
+      );
+      const holderAccount = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        platformAuthority,
+        nftMintKeypair.publicKey,
+        user.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        platformAuthority,
+        nftMintKeypair.publicKey,
+        holderAccount.address,
+        platformAuthority,
+        1
+      );
+      
+      const [nftDataPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+        program.programId
+      );
+      
+      await expectError(
+        () => program.methods
+          .mintNft(collectionId, metadataUri, null)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            collection: collectionPda,
+            collectionStats: collectionStatsPda(collectionPda),
+            mintRateLimit: mintRateLimitPda(collectionPda),
+            collectionTraitConfig: collectionTraitConfigPda(collectionPda),
+            project: projectPda,
+            nftMint: nftMintKeypair.publicKey,
+            nftData: nftDataPda,
+            loyaltyNftData: SystemProgram.programId,
+            loyaltyTokenAccount: SystemProgram.programId,
+            metadataAccount: Keypair.generate().publicKey,
+            masterEdition: Keypair.generate().publicKey,
+            userTokenAccount: holderAccount.address,
+            tokenMetadataProgram: new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user, nftMintKeypair])
+          .rpc(),
+        "InvalidTokenMint",
+        "Minting into a pre-minted mint should be rejected"
+      );
+      
+      const nftData = await provider.connection.getAccountInfo(nftDataPda);
+      assert.isNull(nftData, "No NFT data should be created for a rejected mint");
+      
+      console.log("Pre-minted mint rejected");
+    } catch (error) {
+      console.error("Error testing pre-minted mint rejection:", error);
+      throw error;
+    }
+  });
+
+  it("Applies each fused NFT cooldown policy", async () => {
+    try {
+      const setChildCooldownPolicy = (policy: object, period: number = 0) =>
+        program.methods
+          .setChildCooldownPolicy(policy, new anchor.BN(period))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            fusionConfig: fusionConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Parents minted at a discount carry different cooldowns (parents are consumed, so each fusion gets fresh ones)
+      const cooldownParents = async () => [
+        await swapForHeldNft(new anchor.BN(10**9), 10, new anchor.BN(3600)),
+        await swapForHeldNft(new anchor.BN(10**9), 10, new anchor.BN(7200)),
+      ];
+      const childCooldownEnd = async (parents) => {
+        const { childNftDataPda } = await fuseNfts(parents);
+        return (await program.account.nftData.fetch(childNftDataPda)).cooldownEndTimestamp;
+      };
+      
+      // MaxParent: the child inherits the longest remaining parent cooldown
+      await setChildCooldownPolicy({ maxParent: {} });
+      const parents = await cooldownParents();
+      const longCooldownEnd = (await program.account.nftData.fetch(parents[1].nftDataPda))
+        .cooldownEndTimestamp.toNumber();
+      assert.equal((await childCooldownEnd(parents)).toNumber(), longCooldownEnd);
+      
+      // None: the child is immediately tradeable
+      await setChildCooldownPolicy({ none: {} });
+      assert.isNull(await childCooldownEnd(await cooldownParents()));
+      
+      // FixedPeriod: the child cools down for the configured period, shorter than either parent's
+      const fixedPeriod = 600;
+      await setChildCooldownPolicy({ fixedPeriod: {} }, fixedPeriod);
+      const fusedAt = await provider.connection.getBlockTime(await provider.connection.getSlot());
+      const fixedEnd = (await childCooldownEnd(await cooldownParents())).toNumber();
+      assert.approximately(fixedEnd, fusedAt + fixedPeriod, 30);
+      
+      // The fixed period can't be negative
+      await expectError(
+        () => setChildCooldownPolicy({ fixedPeriod: {} }, -1),
+        "InvalidFusionConfig",
+        "A negative fixed period should be rejected"
+      );
+      
+      await setChildCooldownPolicy({ maxParent: {} });
+      
+      console.log("Fused NFT cooldowns follow the configured policy");
+    } catch (error) {
+      console.error("Error testing fused NFT cooldown policy:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects pool redemption of an NFT with an active escrow", async () => {
+    try {
+      // Back the NFT with a real mint held by the user so the redemption accounts are valid
+      const { nftMintKeypair, userNftAccount } = await createHeldNftMint();
+      
+      const { nftMint: escrowedMint } = await swapTokenForNft(
+        new anchor.BN(10**9),
+        null,
+        null,
+        royaltyWallet.publicKey,
+        nftMintKeypair
+      );
+      await createTokenEscrow(escrowedMint, new anchor.BN(10**9));
+      
+      const lpBefore = await tokenBalance(lpTokenAccountPda);
+      await expectError(
+        () => redeemNftForToken(escrowedMint, userNftAccount),
+        "EscrowStillActive",
+        "Redeeming an NFT with an active escrow should be rejected"
+      );
+      assert.equal(await tokenBalance(lpTokenAccountPda), lpBefore);
+      
+      console.log("Escrowed NFT cannot be redeemed from the pool");
+    } catch (error) {
+      console.error("Error testing escrowed NFT redemption guard:", error);
+      throw error;
+    }
+  });
+
+  it("Caps the platform fee at an absolute token amount", async () => {
+    try {
+      const feeCap = 10**9; // 1 token
+      const setPlatformFeeCap = (cap: anchor.BN | null) =>
+        program.methods
+          .setPlatformFeeCap(cap)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      await setPlatformFeeCap(new anchor.BN(feeCap));
+      const platformConfig = await program.account.platformConfig.fetch(platformConfigPda);
+      assert.equal(platformConfig.maxPlatformFeeAbsolute.toNumber(), feeCap);
+      
+      // A large swap would owe more than the cap, so the cap binds
+      const beforeLarge = await tokenBalance(platformFeeAccount);
+      await swapTokenForNft(new anchor.BN(100 * 10**9));
+      const largeFee = await tokenBalance(platformFeeAccount) - beforeLarge;
+      assert.equal(largeFee, feeCap);
+      
+      // A small swap stays under the cap and pays its percentage fee
+      const beforeSmall = await tokenBalance(platformFeeAccount);
+      await swapTokenForNft(new anchor.BN(5 * 10**9));
+      const smallFee = await tokenBalance(platformFeeAccount) - beforeSmall;
+      assert.isAbove(smallFee, 0);
+      assert.isBelow(smallFee, feeCap);
+      
+      await setPlatformFeeCap(null);
+      
+      console.log(`Large swap fee clamped to ${largeFee}, small swap paid ${smallFee}`);
+    } catch (error) {
+      console.error("Error testing platform fee cap:", error);
+      throw error;
+    }
+  });
+
+  it("Records the last traded price after a swap", async () => {
+    try {
+      const swapAmount = new anchor.BN(4 * 10**9);
+      await swapTokenForNft(swapAmount);
+      
+      const liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      const expectedPriceUsd = swapAmount
+        .mul(liquidityPool.oraclePriceUsd)
+        .div(new anchor.BN(10**9));
+      assert.equal(liquidityPool.lastTradePriceUsd.toString(), expectedPriceUsd.toString());
+      assert.equal(liquidityPool.lastTradeTs.toString(), liquidityPool.lastActivity.toString());
+      
+      console.log(`Last trade recorded at ${liquidityPool.lastTradePriceUsd.toNumber() / 1_000_000} USD`);
+    } catch (error) {
+      console.error("Error testing last trade price:", error);
+      throw error;
+    }
+  });
+
+  it("Refuses to consume an NFT that backs an active escrow", async () => {
+    try {
+      const parents = [];
+      for (let i = 0; i < 2; i++) {
+        parents.push(await swapForHeldNft(new anchor.BN(10**9)));
+      }
+      
+      // The escrow holds a reference to its NFT while it is active
+      await createTokenEscrow(parents[0].nftMint, new anchor.BN(10**9));
+      const parentData = await program.account.nftData.fetch(parents[0].nftDataPda);
+      assert.equal(parentData.referenceCount, 1);
+      
+      await expectError(
+        () => fuseNfts(parents),
+        "NftStillReferenced",
+        "Fusing an NFT that backs an active escrow should be rejected"
+      );
+      
+      // The parent's data account is left intact for its escrow
+      assert.isNotNull(await provider.connection.getAccountInfo(parents[0].nftDataPda));
+      
+      // Closing the escrow releases the NFT
+      const { tokenEscrowPda, escrowTokenAccountPda } = escrowPdas(parents[0].nftMint);
+      await program.methods
+        .closeTokenEscrow(parents[0].nftMint)
+        .accounts({
+          owner: user.publicKey,
+          tokenEscrow: tokenEscrowPda,
+          escrowTokenAccount: escrowTokenAccountPda,
+          ownerTokenAccount: userTokenAccount,
+          userEscrowCount: userEscrowCountPda(),
+          nftData: parents[0].nftDataPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.equal((await program.account.nftData.fetch(parents[0].nftDataPda)).referenceCount, 0);
+      await fuseNfts(parents);
+      
+      console.log("NFT backing an active escrow cannot be consumed by a fusion");
+    } catch (error) {
+      console.error("Error testing referenced NFT close guard:", error);
+      throw error;
+    }
+  });
+
+  it("Carries raw amounts and decimals in event payloads", async () => {
+    try {
+      const priceEvents = [];
+      const swapEvents = [];
+      const priceListener = program.addEventListener("PriceUpdated", (event) => priceEvents.push(event));
+      const swapListener = program.addEventListener("NftSwapped", (event) => swapEvents.push(event));
+      
+      const priceUsd = new anchor.BN(10_500_000);
+      await program.methods
+        .setPriceManually(projectId, priceUsd, null)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const swapAmount = new anchor.BN(3 * 10**9);
+      const { nftMint } = await swapTokenForNft(swapAmount);
+      
+      // Give the websocket subscription time to deliver the logs
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+      await program.removeEventListener(priceListener);
+      await program.removeEventListener(swapListener);
+      
+      assert.isAbove(priceEvents.length, 0);
+      const priceEvent = priceEvents[priceEvents.length - 1];
+      assert.equal(priceEvent.priceUsd.toString(), priceUsd.toString());
+      assert.equal(priceEvent.usdDecimals, 6);
+      
+      const swapEvent = swapEvents.find((event) => event.nftMint.equals(nftMint));
+      assert.isDefined(swapEvent);
+      assert.equal(swapEvent.tokenAmount.toString(), swapAmount.toString());
+      assert.equal(swapEvent.tokenDecimals, 9);
+      assert.equal(swapEvent.usdDecimals, 6);
+      
+      console.log("Events carry raw amounts with their decimals");
+    } catch (error) {
+      console.error("Error testing event decimals:", error);
+      throw error;
+    }
+  });
+
+  it("Adjusts a trait value's supply cap after launch", async () => {
+    try {
+      const traitName = "Eyes";
+      const [traitTypePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), collectionPda.toBuffer(), Buffer.from(traitName)],
+        program.programId
+      );
+      
+      await program.methods
+        .initializeTraitType(traitName, false, [{
+          name: "Laser",
+          uriPostfix: "laser",
+          rarityWeight: 5,
+          availableSupply: new anchor.BN(10),
+          usedSupply: new anchor.BN(5),
+        }])
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          traitType: traitTypePda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const adjustTraitSupplyCap = (cap: number | null) =>
+        program.methods
+          .adjustTraitSupplyCap("Laser", cap === null ? null : new anchor.BN(cap))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            traitType: traitTypePda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Raising the cap is allowed
+      await adjustTraitSupplyCap(20);
+      let traitType = await program.account.traitType.fetch(traitTypePda);
+      assert.equal(traitType.traitValues[0].availableSupply.toNumber(), 20);
+      
+      // Lowering the cap below what has already been used is rejected
+      await expectError(
+        () => adjustTraitSupplyCap(3),
+        "TraitSupplyExceeded",
+        "Cap below used supply should be rejected"
+      );
+      
+      traitType = await program.account.traitType.fetch(traitTypePda);
+      assert.equal(traitType.traitValues[0].availableSupply.toNumber(), 20);
+      
+      console.log("Trait supply cap adjusted without dropping below used supply");
+    } catch (error) {
+      console.error("Error testing trait supply cap adjustment:", error);
+      throw error;
+    }
+  });
+
+  it("Blocks redemptions from a non-redeemable collection", async () => {
+    try {
+      const setCollectionRedeemable = (redeemable: boolean) =>
+        program.methods
+          .setCollectionRedeemable(redeemable)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      const { nftMintKeypair, userNftAccount } = await createHeldNftMint();
+      const { nftMint: poolNftMint } = await swapTokenForNft(
+        new anchor.BN(10**9),
+        null,
+        null,
+        royaltyWallet.publicKey,
+        nftMintKeypair
+      );
+      const { nftMint: escrowNftMint } = await swapTokenForNft(new anchor.BN(10**9));
+      await createTokenEscrow(escrowNftMint, new anchor.BN(10**9));
+      
+      await setCollectionRedeemable(false);
+      
+      for (const [label, redeem] of [
+        ["pool", () => redeemNftForToken(poolNftMint, userNftAccount)],
+        ["escrow", () => redeemEscrowToken(escrowNftMint)],
+      ] as const) {
+        await expectError(
+          () => redeem(),
+          "RedemptionLocked",
+          `${label} redemption should be rejected for a non-redeemable collection`
+        );
+      }
+      
+      // Nor can the NFT be redeemed by passing some other, redeemable collection in its place
+      const decoyCollectionPda = await createCollectionUnder("redeemable-decoy", projectId, projectPda);
+      await expectError(
+        () => redeemWithCollection(poolNftMint, userNftAccount, decoyCollectionPda),
+        "CollectionNotFound",
+        "Passing a redeemable collection for a non-redeemable NFT should be rejected"
+      );
+      
+      // Redemptions work again once the collection is re-enabled
+      await setCollectionRedeemable(true);
+      await redeemEscrowToken(escrowNftMint);
+      
+      console.log("Non-redeemable collection blocks redemptions");
+    } catch (error) {
+      console.error("Error testing non-redeemable collection:", error);
+      throw error;
+    }
+  });
+
+  it("Closes several finished escrows in one batch", async () => {
+    try {
+      // Two escrows are redeemed and empty, one is still vesting
+      const finished = [];
+      for (let i = 0; i < 2; i++) {
+        const { nftMint } = await swapTokenForNft(new anchor.BN(10**9));
+        await createTokenEscrow(nftMint, new anchor.BN(10**9));
+        await redeemEscrowToken(nftMint);
+        finished.push(escrowPdas(nftMint));
+      }
+      const { nftMint: vestingMint } = await swapTokenForNft(new anchor.BN(10**9));
+      await createTokenEscrow(vestingMint, new anchor.BN(10**9), new anchor.BN(3600));
+      const vesting = escrowPdas(vestingMint);
+      
+      const escrows = [...finished, vesting];
+      const ownerBalanceBefore = await provider.connection.getBalance(user.publicKey);
+      
+      await program.methods
+        .closeEscrowsBatch()
+        .accounts({
+          owner: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(escrows.flatMap(({ tokenEscrowPda, escrowTokenAccountPda }) => [
+          { pubkey: tokenEscrowPda, isWritable: true, isSigner: false },
+          { pubkey: escrowTokenAccountPda, isWritable: true, isSigner: false },
+        ]))
+        .signers([user])
+        .rpc();
+      
+      // Finished escrows and their token accounts are gone and their rent refunded
+      for (const { tokenEscrowPda, escrowTokenAccountPda } of finished) {
+        assert.isNull(await provider.connection.getAccountInfo(tokenEscrowPda));
+        assert.isNull(await provider.connection.getAccountInfo(escrowTokenAccountPda));
+      }
+      assert.isAbove(await provider.connection.getBalance(user.publicKey), ownerBalanceBefore);
+      
+      // The vesting escrow is skipped rather than failing the batch
+      const vestingEscrow = await program.account.tokenEscrow.fetch(vesting.tokenEscrowPda);
+      assert.isTrue(vestingEscrow.isActive);
+      assert.equal(await tokenBalance(vesting.escrowTokenAccountPda), 10**9);
+      
+      console.log("Closed finished escrows and skipped the vesting one");
+    } catch (error) {
+      console.error("Error testing batch escrow close:", error);
+      throw error;
+    }
+  });
+
+  it("Counts trait usage past u32 and stops cleanly at the counter limit", async () => {
+    try {
+      // Picking traits through mint_nft needs a collection that mints for free
+      const traitCollectionId = "trait-counter-collection";
+      const traitCollectionPda = await createFreeMintCollection(traitCollectionId);
+      const traitName = "Edition";
+      const [traitTypePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), traitCollectionPda.toBuffer(), Buffer.from(traitName)],
+        program.programId
+      );
+      const u32Max = new anchor.BN(4_294_967_295);
+      const u64Max = new anchor.BN("18446744073709551615");
+      
+      await program.methods
+        .initializeTraitType(traitName, false, [
+          { name: "Large", uriPostfix: "large", rarityWeight: 5, availableSupply: null, usedSupply: u32Max },
+          { name: "Full", uriPostfix: "full", rarityWeight: 5, availableSupply: null, usedSupply: u64Max },
+        ])
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: traitCollectionPda,
+          project: projectPda,
+          traitType: traitTypePda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const mintWithTrait = (valueIndex: number) =>
+        mintNftInto(traitCollectionId, traitCollectionPda, Buffer.from([valueIndex]), [traitTypePda]);
+      
+      // Crossing the old u32 boundary just keeps counting
+      await mintWithTrait(0);
+      let traitType = await program.account.traitType.fetch(traitTypePda);
+      assert.equal(traitType.traitValues[0].usedSupply.toString(), u32Max.addn(1).toString());
+      
+      // At the u64 limit the mint fails with an overflow error instead of panicking
+      await expectError(
+        () => mintWithTrait(1),
+        "CalculationOverflow",
+        "Incrementing a saturated counter should be rejected"
+      );
+      
+      traitType = await program.account.traitType.fetch(traitTypePda);
+      assert.equal(traitType.traitValues[1].usedSupply.toString(), u64Max.toString());
+      
+      console.log("Trait usage counter overflows cleanly");
+    } catch (error) {
+      console.error("Error testing trait usage counter overflow:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects a trait type selected more than once", async () => {
+    try {
+      const dupCollectionId = "duplicate-trait-collection";
+      const dupCollectionPda = await createFreeMintCollection(dupCollectionId);
+      const traitName = "Aura";
+      const [traitTypePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), dupCollectionPda.toBuffer(), Buffer.from(traitName)],
+        program.programId
+      );
+      await program.methods
+        .initializeTraitType(traitName, false, [
+          { name: "Dim", uriPostfix: "dim", rarityWeight: 5, availableSupply: null, usedSupply: new anchor.BN(0) },
+          { name: "Bright", uriPostfix: "bright", rarityWeight: 50, availableSupply: null, usedSupply: new anchor.BN(0) },
+        ])
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: dupCollectionPda,
+          project: projectPda,
+          traitType: traitTypePda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // Listing the same trait type twice would count its supply and rarity twice
+      await expectError(
+        () => mintNftInto(dupCollectionId, dupCollectionPda, Buffer.from([1, 1]), [traitTypePda, traitTypePda]),
+        "DuplicateTraitType",
+        "A trait type selected twice should be rejected"
+      );
+      
+      const traitType = await program.account.traitType.fetch(traitTypePda);
+      assert.equal(traitType.traitValues[1].usedSupply.toNumber(), 0);
+      
+      // Selected once, it mints
+      await mintNftInto(dupCollectionId, dupCollectionPda, Buffer.from([1]), [traitTypePda]);
+      
+      console.log("Repeated trait type rejected");
+    } catch (error) {
+      console.error("Error testing duplicate trait types:", error);
+      throw error;
+    }
+  });
+
+  it("Requires a minimum combined parent value for fusion", async () => {
+    try {
+      const setFusionMinInputValue = (minValueUsd: anchor.BN | null) =>
+        program.methods
+          .setFusionMinInputValue(minValueUsd)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            fusionConfig: fusionConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Freshly swapped parents have no rarity bonus, so each is worth one token
+      const parents = [];
+      for (let i = 0; i < 2; i++) {
+        parents.push(await swapForHeldNft(new anchor.BN(10**9)));
+      }
+      const liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      const combinedValueUsd = liquidityPool.oraclePriceUsd.muln(parents.length);
+      
+      // Just below the threshold is rejected
+      await setFusionMinInputValue(combinedValueUsd.addn(1));
+      await expectError(
+        () => fuseNfts(parents),
+        "TokenPriceTooLow",
+        "Fusion below the minimum input value should be rejected"
+      );
+      
+      // Exactly at the threshold succeeds
+      await setFusionMinInputValue(combinedValueUsd);
+      const { childNftDataPda } = await fuseNfts(parents);
+      const child = await program.account.nftData.fetch(childNftDataPda);
+      assert.equal(child.parentNfts.length, parents.length);
+      
+      await setFusionMinInputValue(null);
+      
+      console.log(`Fusion required ${combinedValueUsd.toString()} USD of input value`);
+    } catch (error) {
+      console.error("Error testing fusion minimum input value:", error);
+      throw error;
+    }
+  });
+
+  it("Quotes a swap that matches the amounts actually charged", async () => {
+    try {
+      const swapAmount = new anchor.BN(8 * 10**9);
+      const discountPercent = 25;
+      
+      const { events } = await program.methods
+        .simulateSwap(collectionId, swapAmount, discountPercent)
+        .accounts({
+          user: user.publicKey,
+          platformConfig: platformConfigPda,
+          collection: collectionPda,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          userTier: userTierPda,
+          collectionFeeExemption: feeExemptionPda(collectionPda),
+          userFeeExemption: feeExemptionPda(user.publicKey),
+        })
+        .simulate();
+      const quote = events.find((event) => event.name === "SwapQuote").data;
+      
+      const userBefore = await tokenBalance(userTokenAccount);
+      const platformBefore = await tokenBalance(platformFeeAccount);
+      const projectBefore = await tokenBalance(projectTreasury.publicKey);
+      const royaltyBefore = await tokenBalance(royaltyWallet.publicKey);
+      
+      await swapTokenForNft(swapAmount, discountPercent, new anchor.BN(60));
+      
+      assert.equal(quote.baseAmount.toString(), swapAmount.toString());
+      assert.equal(quote.totalAmount.toNumber(), userBefore - await tokenBalance(userTokenAccount));
+      assert.equal(quote.discountAmount.toNumber(), swapAmount.toNumber() - quote.totalAmount.toNumber());
+      assert.equal(quote.platformFee.toNumber(), await tokenBalance(platformFeeAccount) - platformBefore);
+      assert.equal(quote.projectFee.toNumber(), await tokenBalance(projectTreasury.publicKey) - projectBefore);
+      assert.equal(quote.royaltyFee.toNumber(), await tokenBalance(royaltyWallet.publicKey) - royaltyBefore);
+      
+      console.log(`Quoted ${quote.totalAmount.toString()} tokens, matching the executed swap`);
+    } catch (error) {
+      console.error("Error testing swap quote:", error);
+      throw error;
+    }
+  });
+
+  it("Charges more for a premium trait through price modifiers", async () => {
+    try {
+      const [traitConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_config"), collectionPda.toBuffer()],
+        program.programId
+      );
+      const [backgroundPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), collectionPda.toBuffer(), Buffer.from("Background")],
+        program.programId
+      );
+      const swapAmount = new anchor.BN(10 * 10**9);
+      const goldPremium = new anchor.BN(5 * 10**9);
+      
+      await program.methods
+        .initializeCollectionTraitConfig("https://example.com/traits", false, { standardJson: {} })
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          collectionTraitConfig: traitConfigPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      await program.methods
+        .setTraitPriceModifiers({ additive: {} }, [
+          { traitName: "Background", valueName: "Gold", modifier: goldPremium },
+        ])
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          collectionTraitConfig: traitConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // Blue (index 0) has no modifier, Gold (index 1) carries the premium
+      const traitAccounts = [traitConfigPda, backgroundPda];
+      
+      let balanceBefore = await tokenBalance(userTokenAccount);
+      await swapTokenForNft(swapAmount, null, null, royaltyWallet.publicKey, Keypair.generate(), Buffer.from([0]), traitAccounts);
+      const commonCost = balanceBefore - await tokenBalance(userTokenAccount);
+      
+      balanceBefore = await tokenBalance(userTokenAccount);
+      await swapTokenForNft(swapAmount, null, null, royaltyWallet.publicKey, Keypair.generate(), Buffer.from([1]), traitAccounts);
+      const premiumCost = balanceBefore - await tokenBalance(userTokenAccount);
+      
+      assert.equal(commonCost, swapAmount.toNumber());
+      assert.equal(premiumCost - commonCost, goldPremium.toNumber());
+      
+      const background = await program.account.traitType.fetch(backgroundPda);
+      assert.equal(background.traitValues[0].usedSupply.toNumber(), 1);
+      assert.equal(background.traitValues[1].usedSupply.toNumber(), 1);
+      
+      console.log(`Premium trait cost ${premiumCost} tokens vs ${commonCost} for a common one`);
+    } catch (error) {
+      console.error("Error testing trait price modifiers:", error);
+      throw error;
+    }
+  });
+
+  it("Force-unlocks a stuck redemption lock", async () => {
+    try {
+      const overrideAccounts = (authority: PublicKey) => ({
+        authority,
+        platformConfig: platformConfigPda,
+        project: projectPda,
+        liquidityPool: liquidityPoolPda,
+      });
+      
+      await program.methods
+        .lockRedemption(projectId)
+        .accounts(overrideAccounts(platformAuthority.publicKey))
+        .signers([platformAuthority])
+        .rpc();
+      assert.isTrue((await program.account.liquidityPool.fetch(liquidityPoolPda)).redemptionLocked);
+      
+      // Swaps stay blocked while the lock is set
+      await expectError(
+        () => swapTokenForNft(new anchor.BN(10**9)),
+        "RedemptionLocked",
+        "Swap should be rejected while redemption is locked"
+      );
+      
+      // Only the platform authority may clear the lock
+      await expectError(
+        () => program.methods
+          .forceUnlockRedemption(projectId)
+          .accounts(overrideAccounts(user.publicKey))
+          .signers([user])
+          .rpc(),
+        "Unauthorized",
+        "Non-authority unlock should be rejected"
+      );
+      
+      const { events } = await program.methods
+        .forceUnlockRedemption(projectId)
+        .accounts(overrideAccounts(platformAuthority.publicKey))
+        .signers([platformAuthority])
+        .simulate();
+      const overrideEvent = events.find((event) => event.name === "RedemptionLockOverridden").data;
+      assert.isTrue(overrideEvent.wasLocked);
+      assert.isFalse(overrideEvent.locked);
+      assert.isTrue(overrideEvent.authority.equals(platformAuthority.publicKey));
+      
+      await program.methods
+        .forceUnlockRedemption(projectId)
+        .accounts(overrideAccounts(platformAuthority.publicKey))
+        .signers([platformAuthority])
+        .rpc();
+      assert.isFalse((await program.account.liquidityPool.fetch(liquidityPoolPda)).redemptionLocked);
+      
+      await swapTokenForNft(new anchor.BN(10**9));
+      
+      console.log("Redemption lock cleared by the platform authority");
+    } catch (error) {
+      console.error("Error testing forced redemption unlock:", error);
+      throw error;
+    }
+  });
+
+  it("Configures how reclaimed liquidity is split with the project", async () => {
+    try {
+      const setReclaimSplit = (bps: number) =>
+        program.methods
+          .setReclaimSplit(bps)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      await setReclaimSplit(6000);
+      const platformConfig = await program.account.platformConfig.fetch(platformConfigPda);
+      assert.equal(platformConfig.reclaimSplitBps, 6000);
+      
+      // The platform share cannot exceed the whole pool
+      await expectError(
+        () => setReclaimSplit(10001),
+        "InvalidReclaimSplit",
+        "Reclaim split above 10000 bps should be rejected"
+      );
+      
+      // The six-month inactivity window cannot elapse on a local validator, so only check
+      // that an active pool with both treasuries passed is still left alone
+      const lpBefore = await tokenBalance(lpTokenAccountPda);
+      await expectError(
+        () => program.methods
+          .checkLpInactivity(projectId)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            lpTokenAccount: lpTokenAccountPda,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "LiquidityPoolNotInactive",
+        "Active pool should not be reclaimed"
+      );
+      assert.equal(await tokenBalance(lpTokenAccountPda), lpBefore);
+      
+      await setReclaimSplit(10000);
+      
+      console.log("Reclaim split configured and validated");
+    } catch (error) {
+      console.error("Error testing reclaim split:", error);
+      throw error;
+    }
+  });
+
+  it("Reveals a collection only against its committed metadata hash", async () => {
+    try {
+      const revealedUri = "https://example.com/revealed/";
+      const salt = Array.from(Keypair.generate().publicKey.toBytes());
+      const metadataHash = Array.from(
+        createHash("sha256").update(Buffer.from(revealedUri)).update(Buffer.from(salt)).digest()
+      );
+      const revealAccounts = {
+        authority: platformAuthority.publicKey,
+        project: projectPda,
+        collection: collectionPda,
+      };
+      const reveal = (uri: string) =>
+        program.methods
+          .revealCollection(uri, salt)
+          .accounts(revealAccounts)
+          .signers([platformAuthority])
+          .rpc();
+      
+      await expectError(() => reveal(revealedUri), "MetadataNotCommitted", "Reveal without a commitment should be rejected");
+      
+      await program.methods
+        .commitMetadataHash(metadataHash)
+        .accounts(revealAccounts)
+        .signers([platformAuthority])
+        .rpc();
+      
+      // A reveal that differs from the commitment is rejected
+      await expectError(() => reveal("https://example.com/tampered/"), "RevealMismatch", "Tampered reveal should be rejected");
+      
+      await reveal(revealedUri);
+      
+      const collection = await program.account.collection.fetch(collectionPda);
+      assert.isTrue(collection.revealed);
+      assert.equal(collection.metadataUri, revealedUri);
+      
+      await expectError(() => reveal(revealedUri), "CollectionAlreadyRevealed", "A second reveal should be rejected");
+      
+      console.log("Collection revealed against its commitment");
+    } catch (error) {
+      console.error("Error testing collection reveal:", error);
+      throw error;
+    }
+  });
+
+  it("Reports specific errors for mismatched swap accounts", async () => {
+    try {
+      const otherProjectId = "mismatch-project";
+      const [otherProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(otherProjectId)],
+        program.programId
+      );
+      const [otherPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), otherProjectPda.toBuffer()],
+        program.programId
+      );
+      const otherMint = await createMint(
+        provider.connection,
+        platformAuthority,
+        platformAuthority.publicKey,
+        null,
+        9
+      );
+      const authorityTokenAccount = (await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        platformAuthority,
+        tokenMint,
+        platformAuthority.publicKey
+      )).address;
+      
+      const createCollection = async (id: string, ownerProjectId: string, ownerProjectPda: PublicKey, mint: PublicKey) => {
+        const [pda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("collection"), Buffer.from(id)],
+          program.programId
+        );
+        await program.methods
+          .createCollection(id, ownerProjectId, metadataUri, mint, false)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: ownerProjectPda,
+            collection: pda,
+            collectionRegistry: collectionRegistryPda(ownerProjectPda),
+            tokenMintAccount: mint,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+        return pda;
+      };
+      
+      await program.methods
+        .createProject(otherProjectId, projectTreasury.publicKey, royaltyWallet.publicKey, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: otherProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      await program.methods
+        .setupLiquidityPool(otherProjectId, tokenMint, new anchor.BN(0))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: otherProjectPda,
+          liquidityPool: otherPoolPda,
+          tokenMintAccount: tokenMint,
+          lpTokenAccount: await getAssociatedTokenAddress(tokenMint, otherPoolPda, true),
+          authorityTokenAccount,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const otherMintCollectionId = "mismatch-mint";
+      const otherMintCollectionPda = await createCollection(otherMintCollectionId, projectId, projectPda, otherMint);
+      const otherProjectCollectionId = "mismatch-owner";
+      const otherProjectCollectionPda = await createCollection(otherProjectCollectionId, otherProjectId, otherProjectPda, tokenMint);
+      
+      const swapInto = (swapCollectionId: string, swapCollection: PublicKey, swapPool: PublicKey) => {
+        const nftMintKeypair = Keypair.generate();
+        const [nftDataPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+          program.programId
+        );
+        return program.methods
+          .swapTokenForNft(swapCollectionId, new anchor.BN(10**9), null, null, null)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            collection: swapCollection,
+            collectionStats: collectionStatsPda(swapCollection),
+            mintRateLimit: mintRateLimitPda(swapCollection),
+            project: projectPda,
+            liquidityPool: swapPool,
+            userTokenAccount: userTokenAccount,
+            lpTokenAccount: lpTokenAccountPda,
+            tokenMint: tokenMint,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            royaltyWallet: royaltyWallet.publicKey,
+            royaltyConfig: royaltyConfigPda,
+            collectionFeeExemption: feeExemptionPda(swapCollection),
+            userFeeExemption: feeExemptionPda(user.publicKey),
+            nftMint: nftMintKeypair.publicKey,
+            nftData: nftDataPda,
+            loyaltyNftData: SystemProgram.programId,
+            loyaltyTokenAccount: SystemProgram.programId,
+            userTier: userTierPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user, nftMintKeypair])
+          .rpc();
+      };
+      
+      await expectError(
+        () => swapInto(otherMintCollectionId, otherMintCollectionPda, liquidityPoolPda),
+        "TokenMintMismatch",
+        "A swap into a collection of another token should be rejected"
+      );
+      await expectError(
+        () => swapInto(otherProjectCollectionId, otherProjectCollectionPda, liquidityPoolPda),
+        "CollectionProjectMismatch",
+        "A swap into another project's collection should be rejected"
+      );
+      await expectError(
+        () => swapInto(collectionId, collectionPda, otherPoolPda),
+        "PoolProjectMismatch",
+        "A swap against another project's pool should be rejected"
+      );
+      
+      console.log("Mismatched swap accounts report specific errors");
+    } catch (error) {
+      console.error("Error testing granular swap errors:", error);
+      throw error;
+    }
+  });
+
+  it("Gives high-rarity NFTs the longer cooldown from the rarity curve", async () => {
+    try {
+      const [traitConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_config"), collectionPda.toBuffer()],
+        program.programId
+      );
+      const [backgroundPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), collectionPda.toBuffer(), Buffer.from("Background")],
+        program.programId
+      );
+      const rareCooldown = 3600;
+      const setRarityCooldownCurve = (curve: { minRarityScore: number; cooldownSeconds: anchor.BN }[]) =>
+        program.methods
+          .setRarityCooldownCurve(curve)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Tiers must ascend by rarity score
+      await expectError(
+        () => setRarityCooldownCurve([
+          { minRarityScore: 50, cooldownSeconds: new anchor.BN(rareCooldown) },
+          { minRarityScore: 20, cooldownSeconds: new anchor.BN(60) },
+        ]),
+        "InvalidCooldownPeriod",
+        "Descending rarity cooldown curve should be rejected"
+      );
+      
+      await setRarityCooldownCurve([{ minRarityScore: 50, cooldownSeconds: new anchor.BN(rareCooldown) }]);
+      
+      // Blue (index 0) is the common background, Gold (index 1) the rare one
+      const traitAccounts = [traitConfigPda, backgroundPda];
+      const swapAmount = new anchor.BN(10**9);
+      const { nftDataPda: commonNftData } = await swapTokenForNft(
+        swapAmount, null, null, royaltyWallet.publicKey, Keypair.generate(), Buffer.from([0]), traitAccounts
+      );
+      const { nftDataPda: rareNftData } = await swapTokenForNft(
+        swapAmount, null, null, royaltyWallet.publicKey, Keypair.generate(), Buffer.from([1]), traitAccounts
+      );
+      
+      const common = await program.account.nftData.fetch(commonNftData);
+      const rare = await program.account.nftData.fetch(rareNftData);
+      assert.isBelow(common.rarityScore, 50);
+      assert.isNull(common.cooldownEndTimestamp);
+      assert.isAtLeast(rare.rarityScore, 50);
+      assert.equal(rare.cooldownEndTimestamp.toNumber(), rare.mintedAt.toNumber() + rareCooldown);
+      
+      await setRarityCooldownCurve([]);
+      
+      console.log(`Rarity ${rare.rarityScore} NFT locked for ${rareCooldown}s, rarity ${common.rarityScore} NFT unlocked`);
+    } catch (error) {
+      console.error("Error testing rarity cooldown curve:", error);
+      throw error;
+    }
+  });
+
+  it("Fuses NFTs held by the signer despite a stale NftData owner", async () => {
+    try {
+      const holder = Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(holder.publicKey, 10 * LAMPORTS_PER_SOL)
+      );
+      
+      // The NFTs move to another wallet outside the program, leaving NftData.owner stale
+      const parents = [];
+      for (let i = 0; i < 2; i++) {
+        const { nftMint, nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+        const holderNftAccount = (await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          holder,
+          nftMint,
+          holder.publicKey
+        )).address;
+        await transfer(provider.connection, user, userNftAccount, holderNftAccount, user, 1);
+        parents.push({ nftDataPda, userNftAccount: holderNftAccount, staleAccount: userNftAccount });
+      }
+      assert.isTrue((await program.account.nftData.fetch(parents[0].nftDataPda)).owner.equals(user.publicKey));
+      
+      // The recorded owner no longer holds the tokens, so it cannot fuse them
+      await expectError(
+        () => fuseNfts(parents.map(({ nftDataPda, staleAccount }) => ({ nftDataPda, userNftAccount: staleAccount }))),
+        "NotNftOwner",
+        "Fusion by a wallet that no longer holds the NFTs should be rejected"
+      );
+      
+      // The actual holder can, and the fused NFT is theirs
+      const { childNftDataPda } = await fuseNfts(parents, metadataUri, holder);
+      const child = await program.account.nftData.fetch(childNftDataPda);
+      assert.isTrue(child.owner.equals(holder.publicKey));
+      
+      console.log("Fusion went to the token holder despite stale parent owners");
+    } catch (error) {
+      console.error("Error testing fusion holder reconciliation:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects project royalties above the platform cap", async () => {
+    try {
+      const setMaxProjectRoyalty = (bps: number) =>
+        program.methods
+          .setMaxProjectRoyalty(bps)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const setProjectRoyalty = (bps: number) =>
+        program.methods
+          .setProjectRoyalty(projectId, bps)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Cap royalties at 10%
+      await setMaxProjectRoyalty(1000);
+      
+      await expectError(
+        () => setProjectRoyalty(1500),
+        "InvalidRoyaltyFee",
+        "Royalty above the platform cap should be rejected"
+      );
+      assert.equal((await program.account.project.fetch(projectPda)).royaltyBasisPoints, 200);
+      
+      await setProjectRoyalty(1000);
+      assert.equal((await program.account.project.fetch(projectPda)).royaltyBasisPoints, 1000);
+      
+      await setProjectRoyalty(200);
+      await setMaxProjectRoyalty(10000);
+      
+      console.log("Project royalty capped by platform policy");
+    } catch (error) {
+      console.error("Error testing project royalty cap:", error);
+      throw error;
+    }
+  });
+
+  it("Serves randomness from the slot hash fallback and a trusted oracle", async () => {
+    const setFusionRandomnessSource = (source: object) =>
+      program.methods
+        .setFusionRandomnessSource(source)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          fusionConfig: fusionConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const [traitConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_config"), collectionPda.toBuffer()],
+        program.programId
+      );
+      const requestRandomness = async (requestId: anchor.BN, sourceConfig: PublicKey) => {
+        const randomnessRequest = randomnessRequestPda(user.publicKey, requestId);
+        await program.methods
+          .requestRandomness(requestId)
+          .accounts({
+            requester: user.publicKey,
+            collection: collectionPda,
+            sourceConfig,
+            randomnessRequest,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+        return randomnessRequest;
+      };
+      
+      // The trait config uses the slot hash fallback, which is fulfilled immediately and reproducible
+      const fallbackId = new anchor.BN(1);
+      const fallback = await program.account.randomnessRequest.fetch(await requestRandomness(fallbackId, traitConfigPda));
+      const expected = createHash("sha256")
+        .update(fallback.requestedSlot.toArrayLike(Buffer, "le", 8))
+        .update(collectionPda.toBuffer())
+        .update(user.publicKey.toBuffer())
+        .update(fallbackId.toArrayLike(Buffer, "le", 8))
+        .digest();
+      assert.deepEqual(Buffer.from(fallback.result), expected);
+      
+      // With an oracle source the request waits for its oracle
+      const oracle = Keypair.generate();
+      await setFusionRandomnessSource({ oracle: { oracle: oracle.publicKey } });
+      
+      const oracleRequestId = new anchor.BN(2);
+      const oracleRequest = await requestRandomness(oracleRequestId, fusionConfigPda);
+      assert.isNull((await program.account.randomnessRequest.fetch(oracleRequest)).result);
+      
+      const oracleResult = Array.from(Keypair.generate().publicKey.toBytes());
+      const fulfill = (signer: Keypair) =>
+        program.methods
+          .fulfillRandomness(oracleResult)
+          .accounts({ oracle: signer.publicKey, randomnessRequest: oracleRequest })
+          .signers([signer])
+          .rpc();
+      
+      await expectError(
+        () => fulfill(user),
+        "Unauthorized",
+        "Only the configured oracle may fulfill an oracle request"
+      );
+      
+      await fulfill(oracle);
+      assert.deepEqual((await program.account.randomnessRequest.fetch(oracleRequest)).result, oracleResult);
+      
+      await expectError(() => fulfill(oracle), "RandomnessAlreadyFulfilled", "A request can only be fulfilled once");
+      
+      // With an oracle source a fusion needs a fulfilled request, which it uses up
+      const parents = [];
+      for (let i = 0; i < 2; i++) {
+        parents.push(await swapForHeldNft(new anchor.BN(10**9)));
+      }
+      await expectError(
+        () => fuseNfts(parents, metadataUri, user, 0, new anchor.BN(3)),
+        "RandomnessNotFulfilled",
+        "An oracle-sourced fusion should need a fulfilled request"
+      );
+      
+      await fuseNfts(parents, metadataUri, user, 0, oracleRequestId);
+      assert.isTrue((await program.account.randomnessRequest.fetch(oracleRequest)).consumed);
+      
+      const nextParents = [];
+      for (let i = 0; i < 2; i++) {
+        nextParents.push(await swapForHeldNft(new anchor.BN(10**9)));
+      }
+      await expectError(
+        () => fuseNfts(nextParents, metadataUri, user, 0, oracleRequestId),
+        "RandomnessAlreadyConsumed",
+        "A request's result can only be used once"
+      );
+      
+      await setFusionRandomnessSource({ slotHash: {} });
+      
+      console.log("Randomness served from the slot hash fallback and the oracle");
+    } catch (error) {
+      await setFusionRandomnessSource({ slotHash: {} });
+      console.error("Error testing randomness sources:", error);
+      throw error;
+    }
+  });
+
+  it("Lets a paid bypass skip the redemption cooldown", async () => {
+    try {
+      const bypassFeeBps = 500; // 5% of the redemption value
+      const setCooldownBypassFee = (feeBps: number) =>
+        program.methods
+          .setCooldownBypassFee(feeBps)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const cooling = await swapForHeldNft(new anchor.BN(10**9), 10, new anchor.BN(3600));
+      
+      // Without the bypass the cooldown still applies
+      await expectError(
+        () => redeemNftForToken(cooling.nftMint, cooling.userNftAccount, false),
+        "NftInCooldown",
+        "Redeeming a cooling NFT without the bypass should be rejected"
+      );
+      
+      // The bypass is unavailable until the project sets a fee
+      await expectError(
+        () => redeemNftForToken(cooling.nftMint, cooling.userNftAccount, true),
+        "CooldownBypassDisabled",
+        "The bypass should be unavailable without a fee"
+      );
+      
+      await setCooldownBypassFee(bypassFeeBps);
+      
+      // An NFT with no cooldown has nothing to bypass
+      const unlocked = await swapForHeldNft(new anchor.BN(10**9));
+      await expectError(
+        () => redeemNftForToken(unlocked.nftMint, unlocked.userNftAccount, true),
+        "NftNotInCooldown",
+        "Bypassing a cooldown that isn't running should be rejected"
+      );
+      
+      // A collection with a token bypass fee can't stand in for the NFT's own to dodge the fee
+      const decoyCollectionPda = await createCollectionUnder("bypass-decoy", projectId, projectPda);
+      await program.methods
+        .setCooldownBypassFee(1)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          collection: decoyCollectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await expectError(
+        () => redeemWithCollection(cooling.nftMint, cooling.userNftAccount, decoyCollectionPda, true),
+        "CollectionNotFound",
+        "The bypass fee should come from the NFT's own collection"
+      );
+      
+      // Paying the fee redeems immediately and sends the fee to the project treasury
+      const treasuryBefore = await tokenBalance(projectTreasury.publicKey);
+      const userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(cooling.nftMint, cooling.userNftAccount, true);
+      
+      const expectedFee = (10**9 * bypassFeeBps) / 10000;
+      assert.equal(await tokenBalance(projectTreasury.publicKey), treasuryBefore + expectedFee);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + 10**9 - expectedFee);
+      
+      await setCooldownBypassFee(0);
+      
+      console.log(`Cooldown bypassed for a ${expectedFee} token fee`);
+    } catch (error) {
+      console.error("Error testing cooldown bypass fee:", error);
+      throw error;
+    }
+  });
+
+  it("Validates the collection PDA with its stored bump in both mint and swap", async () => {
+    try {
+      const [canonicalCollectionPda, canonicalBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(collectionId)],
+        program.programId
+      );
+      assert.isTrue(canonicalCollectionPda.equals(collectionPda));
+      const collection = await program.account.collection.fetch(collectionPda);
+      assert.equal(collection.bump, canonicalBump);
+      
+      // Mint against a collection that mints for free, which is stored with its canonical bump too
+      const freeCollectionId = "bump-free-collection";
+      const [canonicalFreeCollectionPda, canonicalFreeBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(freeCollectionId)],
+        program.programId
+      );
+      const freeCollectionPda = await createFreeMintCollection(freeCollectionId);
+      assert.isTrue(canonicalFreeCollectionPda.equals(freeCollectionPda));
+      const freeCollection = await program.account.collection.fetch(freeCollectionPda);
+      assert.equal(freeCollection.bump, canonicalFreeBump);
+      const mintedNftDataPda = await mintNftInto(freeCollectionId, freeCollectionPda);
+      
+      // Swap against the main collection
+      const { nftDataPda: swappedNftDataPda } = await swapTokenForNft(
+        new anchor.BN(10**9), null, null, royaltyWallet.publicKey, Keypair.generate()
+      );
+      
+      const minted = await program.account.nftData.fetch(mintedNftDataPda);
+      const swapped = await program.account.nftData.fetch(swappedNftDataPda);
+      assert.isTrue(minted.collection.equals(freeCollectionPda));
+      assert.isTrue(swapped.collection.equals(collectionPda));
+      
+      console.log(`Mint and swap both accept the collection at bump ${canonicalBump}`);
+    } catch (error) {
+      console.error("Error testing collection bump handling:", error);
+      throw error;
+    }
+  });
+
+  it("Enforces the collection mint window on swaps", async () => {
+    try {
+      const setMintWindow = (start: number | null, end: number | null) =>
+        program.methods
+          .setMintWindow(start === null ? null : new anchor.BN(start), end === null ? null : new anchor.BN(end))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const swap = () =>
+        swapTokenForNft(new anchor.BN(10**9), null, null, royaltyWallet.publicKey, Keypair.generate());
+      const now = await provider.connection.getBlockTime(await provider.connection.getSlot());
+      
+      // The window must end after it starts
+      await expectError(
+        () => setMintWindow(now + 3600, now),
+        "InvalidMintWindow",
+        "Inverted mint window should be rejected"
+      );
+      
+      // Before the window
+      await setMintWindow(now + 3600, now + 7200);
+      await expectError(swap, "MintNotStarted", "A swap before the mint window should be rejected");
+      
+      // During the window
+      await setMintWindow(now - 3600, now + 3600);
+      await swap();
+      
+      // After the window
+      await setMintWindow(now - 7200, now - 3600);
+      await expectError(swap, "MintEnded", "A swap after the mint window should be rejected");
+      
+      // Unset bounds leave minting open
+      await setMintWindow(null, null);
+      await swap();
+      
+      console.log("Swaps are only accepted inside the collection mint window");
+    } catch (error) {
+      console.error("Error testing mint window:", error);
+      throw error;
+    }
+  });
+
+  it("Closes the escrow token account when redeeming an escrow", async () => {
+    try {
+      const { nftMint } = await swapTokenForNft(new anchor.BN(10**9));
+      const { escrowTokenAccountPda } = await createTokenEscrow(nftMint, new anchor.BN(10**9));
+      const escrowRent = await provider.connection.getBalance(escrowTokenAccountPda);
+      assert.isAbove(escrowRent, 0);
+      
+      // The provider pays the transaction fee, so the user's lamports only change by the refund
+      const userLamportsBefore = await provider.connection.getBalance(user.publicKey);
+      await redeemEscrowToken(nftMint);
+      
+      assert.isNull(await provider.connection.getAccountInfo(escrowTokenAccountPda));
+      assert.equal(await provider.connection.getBalance(user.publicKey), userLamportsBefore + escrowRent);
+      
+      console.log(`Escrow token account closed, ${escrowRent} lamports refunded`);
+    } catch (error) {
+      console.error("Error testing escrow token account close:", error);
+      throw error;
+    }
+  });
+
+  it("Applies the collection's cooldown policy when a gifted NFT is synced", async () => {
+    try {
+      const setTransferCooldownPolicy = (reset: boolean) =>
+        program.methods
+          .setTransferCooldownPolicy(reset)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Gift a cooling NFT to a fresh wallet and record the new holder
+      const giftAndSync = async () => {
+        const { nftMint, nftDataPda, userNftAccount } = await swapForHeldNft(
+          new anchor.BN(10**9), 10, new anchor.BN(3600)
+        );
+        const recipient = Keypair.generate();
+        const recipientNftAccount = (await getOrCreateAssociatedTokenAccount(
+          provider.connection, user, nftMint, recipient.publicKey
+        )).address;
+        await transfer(provider.connection, user, userNftAccount, recipientNftAccount, user, 1);
+        
+        const cooldownBefore = (await program.account.nftData.fetch(nftDataPda)).cooldownEndTimestamp;
+        await program.methods
+          .syncNftOwner()
+          .accounts({
+            user: recipient.publicKey,
+            nftData: nftDataPda,
+            collection: collectionPda,
+            userNftAccount: recipientNftAccount,
+          })
+          .signers([recipient])
+          .rpc();
+        
+        const nftData = await program.account.nftData.fetch(nftDataPda);
+        assert.equal(nftData.owner.toString(), recipient.publicKey.toString());
+        return { cooldownBefore, cooldownAfter: nftData.cooldownEndTimestamp, nftDataPda, userNftAccount };
+      };
+      
+      // Inherit: the recipient keeps the sender's remaining cooldown
+      await setTransferCooldownPolicy(false);
+      const inherited = await giftAndSync();
+      assert.isNotNull(inherited.cooldownBefore);
+      assert.equal(inherited.cooldownAfter.toNumber(), inherited.cooldownBefore.toNumber());
+      
+      // Reset: the recipient starts without a cooldown
+      await setTransferCooldownPolicy(true);
+      const reset = await giftAndSync();
+      assert.isNotNull(reset.cooldownBefore);
+      assert.isNull(reset.cooldownAfter);
+      
+      // The previous holder can no longer claim the NFT
+      await expectError(
+        () => program.methods
+          .syncNftOwner()
+          .accounts({
+            user: user.publicKey,
+            nftData: reset.nftDataPda,
+            collection: collectionPda,
+            userNftAccount: reset.userNftAccount,
+          })
+          .signers([user])
+          .rpc(),
+        "NotNftOwner",
+        "Syncing without holding the NFT should be rejected"
+      );
+      
+      await setTransferCooldownPolicy(false);
+      
+      console.log("Gifted NFTs inherit or reset their cooldown per collection policy");
+    } catch (error) {
+      console.error("Error testing transfer cooldown policy:", error);
+      throw error;
+    }
+  });
+
+  it("Redeems an NFT for its reserved buyback amount", async () => {
+    try {
+      const reservedAmount = 3 * 10**9;
+      const { nftMint, nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+      const [redemptionReservationPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("redemption_reservation"), nftMint.toBuffer()],
+        program.programId
+      );
+      
+      // Fund the project authority so it can pre-fund the buyback
+      const authorityTokenAccount = (await getOrCreateAssociatedTokenAccount(
+        provider.connection, platformAuthority, tokenMint, platformAuthority.publicKey
+      )).address;
+      await transfer(provider.connection, user, userTokenAccount, authorityTokenAccount, user, 2 * reservedAmount);
+      
+      const reserveRedemption = () =>
+        program.methods
+          .reserveRedemption(nftMint, new anchor.BN(reservedAmount))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            nftData: nftDataPda,
+            collection: collectionPda,
+            redemptionReservation: redemptionReservationPda,
+            lpTokenAccount: lpTokenAccountPda,
+            authorityTokenAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      const reservedBefore = (await program.account.liquidityPool.fetch(liquidityPoolPda)).reservedLiquidity.toNumber();
+      await reserveRedemption();
+      let pool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(pool.reservedLiquidity.toNumber(), reservedBefore + reservedAmount);
+      
+      // A second reservation for the same NFT is rejected
+      await expectError(
+        () => reserveRedemption(),
+        "RedemptionAlreadyReserved",
+        "Double reservation should be rejected"
+      );
+      
+      // Redemption pays the reserved amount instead of the base value and releases the reservation
+      const userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(nftMint, userNftAccount);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + reservedAmount);
+      
+      const reservation = await program.account.redemptionReservation.fetch(redemptionReservationPda);
+      assert.equal(reservation.reservedAmount.toNumber(), 0);
+      pool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(pool.reservedLiquidity.toNumber(), reservedBefore);
+      
+      console.log(`NFT bought back for its reserved ${reservedAmount} tokens`);
+    } catch (error) {
+      console.error("Error testing redemption reservation:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects zero oracle prices with InvalidOraclePrice", async () => {
+    try {
+      const priceBefore = (await program.account.liquidityPool.fetch(liquidityPoolPda)).oraclePriceUsd.toString();
+      
+      // A manual price of zero
+      await expectError(
+        () => program.methods
+          .setPriceManually(projectId, new anchor.BN(0), null)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidOraclePrice",
+        "Manual zero price should be rejected"
+      );
+      
+      // DEX reserves so lopsided the price rounds down to zero
+      const dexMint = await createMint(provider.connection, platformAuthority, platformAuthority.publicKey, null, 6);
+      const dexOwner = Keypair.generate().publicKey;
+      const dexTokenAccount = (await getOrCreateAssociatedTokenAccount(
+        provider.connection, platformAuthority, tokenMint, dexOwner
+      )).address;
+      await transfer(provider.connection, user, userTokenAccount, dexTokenAccount, user, 2 * 10**9);
+      const dexBaseAccount = (await getOrCreateAssociatedTokenAccount(
+        provider.connection, platformAuthority, dexMint, dexOwner
+      )).address;
+      await mintTo(provider.connection, platformAuthority, dexMint, dexBaseAccount, platformAuthority, 1);
+      await pinDexPriceAccounts(dexTokenAccount, dexBaseAccount);
+      
+      await expectError(
+        () => program.methods
+          .updatePriceFromDex(projectId)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            dexTokenAccount,
+            dexBaseAccount,
+            tokenMint: tokenMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidOraclePrice",
+        "DEX zero price should be rejected"
+      );
+      
+      const priceAfter = (await program.account.liquidityPool.fetch(liquidityPoolPda)).oraclePriceUsd.toString();
+      assert.equal(priceAfter, priceBefore);
+      
+      console.log("Zero oracle prices are rejected and the stored price is kept");
+    } catch (error) {
+      console.error("Error testing invalid oracle prices:", error);
+      throw error;
+    }
+  });
+
+  it("Accrues dust fees in the pool until they reach the transfer threshold", async () => {
+    try {
+      const setMinFeeTransfer = (minFeeTransfer: number) =>
+        program.methods
+          .setMinFeeTransfer(new anchor.BN(minFeeTransfer))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const tinySwap = () => swapTokenForNft(new anchor.BN(10**6));
+      const pendingPlatformFees = async () =>
+        (await program.account.liquidityPool.fetch(liquidityPoolPda)).pendingPlatformFees.toNumber();
+      
+      // With an unreachable threshold a tiny swap's platform fee stays in the pool
+      await setMinFeeTransfer(Number.MAX_SAFE_INTEGER);
+      const treasuryBefore = await tokenBalance(platformFeeAccount);
+      await tinySwap();
+      const feePerSwap = await pendingPlatformFees();
+      assert.isAbove(feePerSwap, 0);
+      assert.equal(await tokenBalance(platformFeeAccount), treasuryBefore);
+      
+      // Once the pending balance reaches the threshold it is transferred in one go
+      await setMinFeeTransfer(3 * feePerSwap);
+      await tinySwap();
+      assert.equal(await pendingPlatformFees(), 2 * feePerSwap);
+      assert.equal(await tokenBalance(platformFeeAccount), treasuryBefore);
+      
+      await tinySwap();
+      assert.equal(await pendingPlatformFees(), 0);
+      assert.equal(await tokenBalance(platformFeeAccount), treasuryBefore + 3 * feePerSwap);
+      
+      await setMinFeeTransfer(0);
+      
+      console.log(`Three ${feePerSwap}-token fees accrued and transferred together`);
+    } catch (error) {
+      console.error("Error testing fee transfer threshold:", error);
+      throw error;
+    }
+  });
+
+  it("Tracks per-collection stats across mint, redeem and fuse", async () => {
+    try {
+      const statsPda = collectionStatsPda(collectionPda);
+      await program.methods
+        .initializeCollectionStats()
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          collection: collectionPda,
+          collectionStats: statsPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // Three swaps at two prices
+      const redeemed = await swapForHeldNft(new anchor.BN(2 * 10**9));
+      const parents = [
+        await swapForHeldNft(new anchor.BN(10**9)),
+        await swapForHeldNft(new anchor.BN(10**9)),
+      ];
+      
+      await redeemNftForToken(redeemed.nftMint, redeemed.userNftAccount);
+      await fuseNfts(parents);
+      
+      const stats = await program.account.collectionStats.fetch(statsPda);
+      assert.equal(stats.mints.toNumber(), 3);
+      assert.equal(stats.redemptions.toNumber(), 1);
+      assert.equal(stats.fusions.toNumber(), 1);
+      assert.equal(stats.totalVolume.toNumber(), 4 * 10**9);
+      assert.equal(stats.floorPrice.toNumber(), 10**9);
+      
+      // The same counters are reported through the stats event
+      const { events } = await program.methods
+        .getCollectionStats()
+        .accounts({ collectionStats: statsPda })
+        .simulate();
+      const reported = events.find((event) => event.name === "CollectionStatsReported").data;
+      assert.isTrue(reported.collection.equals(collectionPda));
+      assert.equal(reported.mints.toNumber(), 3);
+      assert.equal(reported.totalVolume.toNumber(), 4 * 10**9);
+      
+      console.log("Collection stats follow mints, redemptions and fusions");
+    } catch (error) {
+      console.error("Error testing collection stats:", error);
+      throw error;
+    }
+  });
+
+  it("Bounds manual prices and records the price they replace", async () => {
+    try {
+      const setManualPriceBounds = (min: number | null, max: number | null) =>
+        program.methods
+          .setManualPriceBounds(min === null ? null : new anchor.BN(min), max === null ? null : new anchor.BN(max))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const setPrice = (priceUsd: number) =>
+        program.methods
+          .setPriceManually(projectId, new anchor.BN(priceUsd), null)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority]);
+      
+      const previousPrice = (await program.account.liquidityPool.fetch(liquidityPoolPda)).oraclePriceUsd;
+      await setManualPriceBounds(1_000_000, 100_000_000); // $1 to $100
+      
+      // In bounds: accepted, with the previous price in the event
+      const inBounds = 20_000_000;
+      const { events } = await setPrice(inBounds).simulate();
+      const priceSet = events.find((event) => event.name === "ManualPriceSet").data;
+      assert.equal(priceSet.previousPriceUsd.toString(), previousPrice.toString());
+      assert.equal(priceSet.priceUsd.toNumber(), inBounds);
+      await setPrice(inBounds).rpc();
+      
+      // Out of bounds on either side: rejected and the stored price is kept
+      for (const outOfBounds of [500_000, 20_000_000_000]) {
+        await expectError(
+          () => setPrice(outOfBounds).rpc(),
+          "InvalidOraclePrice",
+          `Manual price ${outOfBounds} should be rejected`
+        );
+      }
+      const pool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(pool.oraclePriceUsd.toNumber(), inBounds);
+      
+      await setManualPriceBounds(null, null);
+      await setPrice(previousPrice.toNumber()).rpc();
+      
+      console.log("Manual prices outside the configured bounds are rejected");
+    } catch (error) {
+      console.error("Error testing manual price bounds:", error);
+      throw error;
+    }
+  });
+
+  it("Caps collection mints per rolling window", async () => {
+    try {
+      const rateLimitPda = mintRateLimitPda(collectionPda);
+      const windowSeconds = 20;
+      const setMintRateLimit = (maxMints: number, window: number) =>
+        program.methods
+          .setMintRateLimit(new anchor.BN(maxMints), new anchor.BN(window))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+            mintRateLimit: rateLimitPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const swap = () =>
+        swapTokenForNft(new anchor.BN(10**9), null, null, royaltyWallet.publicKey, Keypair.generate());
+      
+      // A window must have a positive length
+      await expectError(() => setMintRateLimit(2, 0), "InvalidMintWindow", "Zero-length window should be rejected");
+      
+      // Mint up to the cap, then the next mint in the same window is rejected.
+      // A short window stands in for a day, since the local validator clock can't be advanced
+      await setMintRateLimit(2, windowSeconds);
+      await swap();
+      await swap();
+      let rateLimit = await program.account.mintRateLimit.fetch(rateLimitPda);
+      assert.equal(rateLimit.mintedInWindow.toNumber(), 2);
+      await expectError(swap, "MintRateLimitExceeded", "Mint past the cap should be rejected");
+      
+      // Once the window has elapsed the counter resets
+      await new Promise((resolve) => setTimeout(resolve, (windowSeconds + 2) * 1000));
+      await swap();
+      rateLimit = await program.account.mintRateLimit.fetch(rateLimitPda);
+      assert.equal(rateLimit.mintedInWindow.toNumber(), 1);
+      
+      // Removing the cap leaves minting open
+      await setMintRateLimit(0, 86400);
+      await swap();
+      await swap();
+      
+      console.log("Collection mints are capped per window and reset when it rolls over");
+    } catch (error) {
+      console.error("Error testing mint rate limit:", error);
+      throw error;
+    }
+  });
+
+  it("Splits royalties across one or more recipients on swap", async () => {
+    try {
+      const setRoyaltyRecipients = (recipients: { wallet: PublicKey; shareBps: number }[]) =>
+        program.methods
+          .setRoyaltyRecipients(projectId, recipients)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            royaltyConfig: royaltyConfigPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const swapAmount = 10 * 10**9;
+      const swap = (recipients: PublicKey[] = []) =>
+        swapTokenForNft(new anchor.BN(swapAmount), null, null, royaltyWallet.publicKey, Keypair.generate(), null, [], recipients);
+      const project = await program.account.project.fetch(projectPda);
+      const royaltyFee = Math.floor(swapAmount * project.royaltyBasisPoints / 10000);
+      
+      // Single recipient: without a split the royalty wallet receives the whole royalty
+      let royaltyWalletBefore = await tokenBalance(royaltyWallet.publicKey);
+      await swap();
+      assert.equal(await tokenBalance(royaltyWallet.publicKey), royaltyWalletBefore + royaltyFee);
+      
+      // Shares must add up to the whole royalty
+      const creators = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+      const creatorAccounts: PublicKey[] = [];
+      for (const creator of creators) {
+        creatorAccounts.push(await createAccount(
+          provider.connection,
+          platformAuthority,
+          tokenMint,
+          creator.publicKey,
+          Keypair.generate()
+        ));
+      }
+      await expectError(
+        () => setRoyaltyRecipients([
+          { wallet: creatorAccounts[0], shareBps: 5000 },
+          { wallet: creatorAccounts[1], shareBps: 4000 },
+        ]),
+        "InvalidRoyaltySplit",
+        "Royalty shares that don't sum to 10000 should be rejected"
+      );
+      
+      // Multiple recipients: the royalty is split 50/30/20 and the royalty wallet gets nothing
+      const shares = [5000, 3000, 2000];
+      await setRoyaltyRecipients(creatorAccounts.map((wallet, i) => ({ wallet, shareBps: shares[i] })));
+      const creatorsBefore = await Promise.all(creatorAccounts.map(tokenBalance));
+      royaltyWalletBefore = await tokenBalance(royaltyWallet.publicKey);
+      await swap(creatorAccounts);
+      
+      const expected = [
+        Math.floor(royaltyFee * shares[0] / 10000),
+        Math.floor(royaltyFee * shares[1] / 10000),
+      ];
+      expected.push(royaltyFee - expected[0] - expected[1]);
+      for (let i = 0; i < creatorAccounts.length; i++) {
+        assert.equal(await tokenBalance(creatorAccounts[i]), creatorsBefore[i] + expected[i]);
+      }
+      assert.equal(await tokenBalance(royaltyWallet.publicKey), royaltyWalletBefore);
+      
+      // Recipients must be passed in the configured order
+      await expectError(
+        () => swap([...creatorAccounts].reverse()),
+        "RoyaltyRecipientMismatch",
+        "Out-of-order royalty recipients should be rejected"
+      );
+      
+      // Clearing the split goes back to the single royalty wallet
+      await setRoyaltyRecipients([]);
+      royaltyWalletBefore = await tokenBalance(royaltyWallet.publicKey);
+      await swap();
+      assert.equal(await tokenBalance(royaltyWallet.publicKey), royaltyWalletBefore + royaltyFee);
+      
+      console.log("Royalties are split across the configured recipients");
+    } catch (error) {
+      console.error("Error testing royalty recipients:", error);
+      throw error;
+    }
+  });
+
+  it("Caps the number of active escrows per user", async () => {
+    try {
+      const setMaxEscrowsPerUser = (max: number) =>
+        program.methods
+          .setMaxEscrowsPerUser(projectId, max)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const activeEscrows = async () =>
+        (await program.account.userEscrowCount.fetch(userEscrowCountPda())).activeEscrows;
+      const escrowNewNft = async () => {
+        const { nftMint } = await swapTokenForNft(new anchor.BN(10**9));
+        await createTokenEscrow(nftMint, new anchor.BN(10**9));
+        return nftMint;
+      };
+      
+      // Leave room for two more escrows on top of those held from earlier tests
+      const held = await activeEscrows();
+      await setMaxEscrowsPerUser(held + 2);
+      
+      const firstMint = await escrowNewNft();
+      await escrowNewNft();
+      assert.equal(await activeEscrows(), held + 2);
+      
+      // A third escrow is over the cap
+      const { nftMint: overLimitMint } = await swapTokenForNft(new anchor.BN(10**9));
+      await expectError(
+        () => createTokenEscrow(overLimitMint, new anchor.BN(10**9)),
+        "EscrowLimitReached",
+        "Escrow past the per-user cap should be rejected"
+      );
+      
+      // Closing an escrow frees a slot
+      const { tokenEscrowPda, escrowTokenAccountPda } = escrowPdas(firstMint);
+      await program.methods
+        .closeTokenEscrow(firstMint)
+        .accounts({
+          owner: user.publicKey,
+          tokenEscrow: tokenEscrowPda,
+          escrowTokenAccount: escrowTokenAccountPda,
+          ownerTokenAccount: userTokenAccount,
+          userEscrowCount: userEscrowCountPda(),
+          nftData: PublicKey.findProgramAddressSync(
+            [Buffer.from("nft_data"), firstMint.toBuffer()],
+            program.programId
+          )[0],
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.equal(await activeEscrows(), held + 1);
+      
+      await createTokenEscrow(overLimitMint, new anchor.BN(10**9));
+      assert.equal(await activeEscrows(), held + 2);
+      
+      // Redeeming an escrow frees a slot too
+      await redeemEscrowToken(overLimitMint);
+      assert.equal(await activeEscrows(), held + 1);
+      
+      await setMaxEscrowsPerUser(0);
+      
+      console.log("Active escrows are capped per user and freed on close and redeem");
+    } catch (error) {
+      console.error("Error testing escrow cap:", error);
+      throw error;
+    }
+  });
+
+  it("Splits a vesting escrow into two on the same schedule", async () => {
+    try {
+      const vestingSeconds = 5;
+      const escrowAmount = 4 * 10**9;
+      const { nftMint } = await swapTokenForNft(new anchor.BN(10**9));
+      const { tokenEscrowPda, escrowTokenAccountPda } = await createTokenEscrow(
+        nftMint, new anchor.BN(escrowAmount), new anchor.BN(vestingSeconds)
+      );
+      
+      const splitIndex = 1;
+      const indexBytes = Buffer.alloc(2);
+      indexBytes.writeUInt16LE(splitIndex);
+      const [splitEscrowPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token_escrow"), nftMint.toBuffer(), indexBytes],
+        program.programId
+      );
+      const [splitEscrowTokenAccountPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow_token_account"), nftMint.toBuffer(), indexBytes],
+        program.programId
+      );
+      const splitEscrow = (amount: number) =>
+        program.methods
+          .splitEscrow(nftMint, splitIndex, new anchor.BN(amount))
+          .accounts({
+            owner: user.publicKey,
+            project: projectPda,
+            tokenEscrow: tokenEscrowPda,
+            escrowTokenAccount: escrowTokenAccountPda,
+            splitEscrow: splitEscrowPda,
+            tokenMint: tokenMint,
+            splitEscrowTokenAccount: splitEscrowTokenAccountPda,
+            userEscrowCount: userEscrowCountPda(),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user])
+          .rpc();
+      const closeSplitEscrow = () =>
+        program.methods
+          .closeSplitEscrow(nftMint, splitIndex)
+          .accounts({
+            owner: user.publicKey,
+            splitEscrow: splitEscrowPda,
+            splitEscrowTokenAccount: splitEscrowTokenAccountPda,
+            ownerTokenAccount: userTokenAccount,
+            userEscrowCount: userEscrowCountPda(),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+      
+      // Splitting off more than the escrow holds is rejected
+      await expectError(
+        () => splitEscrow(escrowAmount + 1),
+        "InsufficientTokenBalance",
+        "Splitting more than the balance should be rejected"
+      );
+      
+      // A 50/50 split leaves both escrows on the original vesting schedule
+      await splitEscrow(escrowAmount / 2);
+      const original = await program.account.tokenEscrow.fetch(tokenEscrowPda);
+      const split = await program.account.tokenEscrow.fetch(splitEscrowPda);
+      assert.equal(original.tokenAmount.toNumber(), escrowAmount / 2);
+      assert.equal(split.tokenAmount.toNumber(), escrowAmount / 2);
+      assert.equal(await tokenBalance(escrowTokenAccountPda), escrowAmount / 2);
+      assert.equal(await tokenBalance(splitEscrowTokenAccountPda), escrowAmount / 2);
+      assert.equal(split.vestingEndTimestamp.toNumber(), original.vestingEndTimestamp.toNumber());
+      assert.isTrue(split.owner.equals(user.publicKey));
+      assert.equal(split.splitIndex, splitIndex);
+      assert.equal(original.splitCount, splitIndex);
+      
+      // Neither half can be withdrawn before the original vesting end
+      await expectError(() => closeSplitEscrow(), "VestingPeriodActive", "Split escrow should still be vesting");
+      
+      // Once vested, the split half is withdrawn and its accounts closed
+      await new Promise((resolve) => setTimeout(resolve, (vestingSeconds + 2) * 1000));
+      const balanceBefore = await tokenBalance(userTokenAccount);
+      await closeSplitEscrow();
+      assert.equal(await tokenBalance(userTokenAccount), balanceBefore + escrowAmount / 2);
+      assert.isNull(await provider.connection.getAccountInfo(splitEscrowPda));
+      assert.isNull(await provider.connection.getAccountInfo(splitEscrowTokenAccountPda));
+      
+      console.log("Escrow split 50/50 with both halves vesting on the original schedule");
+    } catch (error) {
+      console.error("Error testing escrow split:", error);
+      throw error;
+    }
+  });
+
+  it("Pays platform fees to the treasury wallet's token account and rejects system-account project treasuries", async () => {
+    try {
+      // A project whose treasury is a plain wallet can't set up a pool
+      const walletProjectId = "wallet-treasury-project";
+      const [walletProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(walletProjectId)],
+        program.programId
+      );
+      const [walletPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), walletProjectPda.toBuffer()],
+        program.programId
+      );
+      const walletTreasury = Keypair.generate().publicKey;
+      
+      await program.methods
+        .createProject(walletProjectId, walletTreasury, royaltyWallet.publicKey, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: walletProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      await expectError(
+        () => program.methods
+          .setupLiquidityPool(walletProjectId, tokenMint, new anchor.BN(0))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: walletProjectPda,
+            liquidityPool: walletPoolPda,
+            tokenMintAccount: tokenMint,
+            lpTokenAccount: await getAssociatedTokenAddress(tokenMint, walletPoolPda, true),
+            authorityTokenAccount: userTokenAccount,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: walletTreasury,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidTreasury",
+        "A system-account treasury should be rejected"
+      );
+      
+      // Platform fees go to a token account owned by the platform treasury wallet, not to any
+      // account of the right token
+      await expectError(
+        () => swapTokenForNft(
+          new anchor.BN(10**9), null, null, royaltyWallet.publicKey, Keypair.generate(), null, [], [], null, userTokenAccount
+        ),
+        "InvalidTreasury",
+        "A fee account the platform treasury doesn't own should be rejected"
+      );
+      
+      // Escrow redemption fees land in the same treasury token accounts as swap fees
+      const { nftMint } = await swapTokenForNft(new anchor.BN(10**9));
+      const escrowAmount = 10 * 10**9;
+      await createTokenEscrow(nftMint, new anchor.BN(escrowAmount));
+      const platformBefore = await tokenBalance(platformFeeAccount);
+      const projectBefore = await tokenBalance(projectTreasury.publicKey);
+      
+      await redeemEscrowToken(nftMint);
+      
+      const platformConfig = await program.account.platformConfig.fetch(platformConfigPda);
+      const project = await program.account.project.fetch(projectPda);
+      assert.equal(
+        await tokenBalance(platformFeeAccount),
+        platformBefore + Math.floor(escrowAmount * platformConfig.platformFeeBasisPoints / 10000)
+      );
+      assert.equal(
+        await tokenBalance(projectTreasury.publicKey),
+        projectBefore + Math.floor(escrowAmount * project.royaltyBasisPoints / 10000)
+      );
+      
+      console.log("Treasury fees land in the configured treasury token accounts");
+    } catch (error) {
+      console.error("Error testing treasury validation:", error);
+      throw error;
+    }
+  });
+
+  it("Routes the fusion burn to the configured destination", async () => {
+    try {
+      const burnPercent = 10;
+      const setFusionBurn = (percent: number, destination: object) =>
+        program.methods
+          .setFusionBurn(percent, destination)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            fusionConfig: fusionConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const heldParents = async () => {
+        const parents = [];
+        for (let i = 0; i < 2; i++) {
+          const { nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+          const { rarityScore } = await program.account.nftData.fetch(nftDataPda);
+          parents.push({ nftDataPda, userNftAccount, rarityScore });
+        }
+        return parents;
+      };
+      // Each parent is worth the base token value plus its rarity score as a percentage
+      const burnAmount = (parents: { rarityScore: number }[]) => {
+        const combinedValue = parents.reduce(
+          (total, { rarityScore }) => total + Math.floor(10**9 * (100 + rarityScore) / 100),
+          0
+        );
+        return Math.floor(combinedValue * burnPercent / 100);
+      };
+      
+      // Burn: the user's tokens leave the supply
+      await setFusionBurn(burnPercent, { burn: {} });
+      let parents = await heldParents();
+      let userBefore = await tokenBalance(userTokenAccount);
+      const supplyBefore = Number((await getMint(provider.connection, tokenMint)).supply);
+      await fuseNfts(parents);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore - burnAmount(parents));
+      assert.equal(Number((await getMint(provider.connection, tokenMint)).supply), supplyBefore - burnAmount(parents));
+      
+      // Treasury: the same amount goes to the project treasury instead
+      await setFusionBurn(burnPercent, { treasury: {} });
+      parents = await heldParents();
+      userBefore = await tokenBalance(userTokenAccount);
+      const treasuryBefore = await tokenBalance(projectTreasury.publicKey);
+      await fuseNfts(parents);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore - burnAmount(parents));
+      assert.equal(await tokenBalance(projectTreasury.publicKey), treasuryBefore + burnAmount(parents));
+      
+      // BuybackLp: the amount is deposited back into the liquidity pool
+      await setFusionBurn(burnPercent, { buybackLp: {} });
+      parents = await heldParents();
+      userBefore = await tokenBalance(userTokenAccount);
+      const lpBefore = await tokenBalance(lpTokenAccountPda);
+      await fuseNfts(parents);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore - burnAmount(parents));
+      assert.equal(await tokenBalance(lpTokenAccountPda), lpBefore + burnAmount(parents));
+      
+      await setFusionBurn(0, { burn: {} });
+      
+      console.log("Fusion burn is burned, sent to the treasury or deposited in the pool as configured");
+    } catch (error) {
+      console.error("Error testing fusion burn destination:", error);
+      throw error;
+    }
+  });
+
+  it("Pays the royalty with the project fee when there is no royalty wallet", async () => {
+    try {
+      const soloProjectId = "no-royalty-wallet";
+      const soloCollectionId = "no-royalty-wallet-collection";
+      const [soloProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(soloProjectId)],
+        program.programId
+      );
+      const [soloPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), soloProjectPda.toBuffer()],
+        program.programId
+      );
+      const [soloCollectionPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(soloCollectionId)],
+        program.programId
+      );
+      const [soloUserTierPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user_tier"), soloProjectPda.toBuffer(), user.publicKey.toBuffer()],
+        program.programId
+      );
+      const [soloRoyaltyConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("royalty_config"), soloProjectPda.toBuffer()],
+        program.programId
+      );
+      const soloLpTokenAccount = await getAssociatedTokenAddress(tokenMint, soloPoolPda, true);
+      
+      // A project without a royalty wallet, so royalties default to its treasury
+      await program.methods
+        .createProject(soloProjectId, projectTreasury.publicKey, null, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: soloProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setupLiquidityPool(soloProjectId, tokenMint, new anchor.BN(0))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: soloProjectPda,
+          liquidityPool: soloPoolPda,
+          tokenMintAccount: tokenMint,
+          lpTokenAccount: soloLpTokenAccount,
+          authorityTokenAccount: userTokenAccount,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setPriceManually(soloProjectId, new anchor.BN(1_000_000), null)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: soloProjectPda,
+          liquidityPool: soloPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .createCollection(soloCollectionId, soloProjectId, metadataUri, tokenMint, false)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: soloProjectPda,
+          collection: soloCollectionPda,
+          collectionRegistry: collectionRegistryPda(soloProjectPda),
+          tokenMintAccount: tokenMint,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const swapAmount = 10 * 10**9;
+      const treasuryBefore = await tokenBalance(projectTreasury.publicKey);
+      const nftMintKeypair = Keypair.generate();
+      const [nftDataPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+        program.programId
+      );
+      const signature = await program.methods
+        .swapTokenForNft(soloCollectionId, new anchor.BN(swapAmount), null, null, null)
+        .accounts({
+          user: user.publicKey,
+          platformConfig: platformConfigPda,
+          collection: soloCollectionPda,
+          collectionStats: collectionStatsPda(soloCollectionPda),
+          mintRateLimit: mintRateLimitPda(soloCollectionPda),
+          project: soloProjectPda,
+          liquidityPool: soloPoolPda,
+          userTokenAccount: userTokenAccount,
+          lpTokenAccount: soloLpTokenAccount,
+          tokenMint: tokenMint,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          royaltyWallet: projectTreasury.publicKey,
+          royaltyConfig: soloRoyaltyConfigPda,
+          collectionFeeExemption: feeExemptionPda(soloCollectionPda),
+          userFeeExemption: feeExemptionPda(user.publicKey),
+          nftMint: nftMintKeypair.publicKey,
+          nftData: nftDataPda,
+          loyaltyNftData: SystemProgram.programId,
+          loyaltyTokenAccount: SystemProgram.programId,
+          userTier: soloUserTierPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user, nftMintKeypair])
+        .rpc();
+      
+      // The treasury receives the project fee plus the royalty exactly once
+      const platformFeeBps = (await program.account.platformConfig.fetch(platformConfigPda)).platformFeeBasisPoints;
+      const royaltyBps = 200;
+      const projectFee = Math.floor(swapAmount * Math.floor((10000 - platformFeeBps - royaltyBps) / 2) / 10000);
+      const royaltyFee = Math.floor(swapAmount * royaltyBps / 10000);
+      assert.equal(await tokenBalance(projectTreasury.publicKey), treasuryBefore + projectFee + royaltyFee);
+      
+      // ...in a single transfer
+      await provider.connection.confirmTransaction(signature, "confirmed");
+      const tx = await provider.connection.getParsedTransaction(signature, "confirmed");
+      const treasuryTransfers = tx.meta.innerInstructions
+        .flatMap(({ instructions }) => instructions)
+        .filter((ix: any) => ix.parsed?.type === "transfer" && ix.parsed.info.destination === projectTreasury.publicKey.toString());
+      assert.equal(treasuryTransfers.length, 1);
+      
+      console.log("Royalty folded into the project fee when it defaults to the treasury");
+    } catch (error) {
+      console.error("Error testing royalty wallet default:", error);
+      throw error;
+    }
+  });
+
+  it("Prices a SOL-quoted pool and converts trades to USD with the SOL/USD price", async () => {
+    try {
+      const solProjectId = "sol-quoted";
+      const solCollectionId = "sol-quoted-collection";
+      const [solProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(solProjectId)],
+        program.programId
+      );
+      const [solPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), solProjectPda.toBuffer()],
+        program.programId
+      );
+      const [solCollectionPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(solCollectionId)],
+        program.programId
+      );
+      const [solUserTierPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user_tier"), solProjectPda.toBuffer(), user.publicKey.toBuffer()],
+        program.programId
+      );
+      const [solRoyaltyConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("royalty_config"), solProjectPda.toBuffer()],
+        program.programId
+      );
+      const solLpTokenAccount = await getAssociatedTokenAddress(tokenMint, solPoolPda, true);
+      
+      await program.methods
+        .createProject(solProjectId, projectTreasury.publicKey, royaltyWallet.publicKey, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: solProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setupLiquidityPool(solProjectId, tokenMint, new anchor.BN(0))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: solProjectPda,
+          liquidityPool: solPoolPda,
+          tokenMintAccount: tokenMint,
+          lpTokenAccount: solLpTokenAccount,
+          authorityTokenAccount: userTokenAccount,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .createCollection(solCollectionId, solProjectId, metadataUri, tokenMint, false)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: solProjectPda,
+          collection: solCollectionPda,
+          collectionRegistry: collectionRegistryPda(solProjectPda),
+          tokenMintAccount: tokenMint,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // Quote the pool in SOL: one token costs 0.01 SOL (10_000_000 lamports)
+      await program.methods
+        .setQuoteAsset(solProjectId, { sol: {} })
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: solProjectPda,
+          liquidityPool: solPoolPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setPriceManually(solProjectId, new anchor.BN(10_000_000), null)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: solProjectPda,
+          liquidityPool: solPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const swapAmount = 10 * 10**9;
+      const swapOnSolPool = async () => {
+        const nftMintKeypair = Keypair.generate();
+        const [nftDataPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .swapTokenForNft(solCollectionId, new anchor.BN(swapAmount), null, null, null)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            collection: solCollectionPda,
+            collectionStats: collectionStatsPda(solCollectionPda),
+            mintRateLimit: mintRateLimitPda(solCollectionPda),
+            project: solProjectPda,
+            liquidityPool: solPoolPda,
+            userTokenAccount: userTokenAccount,
+            lpTokenAccount: solLpTokenAccount,
+            tokenMint: tokenMint,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            royaltyWallet: royaltyWallet.publicKey,
+            royaltyConfig: solRoyaltyConfigPda,
+            collectionFeeExemption: feeExemptionPda(solCollectionPda),
+            userFeeExemption: feeExemptionPda(user.publicKey),
+            nftMint: nftMintKeypair.publicKey,
+            nftData: nftDataPda,
+            loyaltyNftData: SystemProgram.programId,
+            loyaltyTokenAccount: SystemProgram.programId,
+            userTier: solUserTierPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user, nftMintKeypair])
+          .rpc();
+      };
+      
+      // Without a SOL/USD price the pool cannot value anything in USD
+      await expectError(
+        () => swapOnSolPool(),
+        "OracleNotInitialized",
+        "Swapping before the SOL/USD price is set should be rejected"
+      );
+      
+      // SOL at 150 USD
+      await program.methods
+        .setQuotePriceManually(solProjectId, new anchor.BN(150_000_000))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: solProjectPda,
+          liquidityPool: solPoolPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await swapOnSolPool();
+      
+      // 10 tokens * 0.01 SOL * 150 USD = 15 USD
+      const solPool = await program.account.liquidityPool.fetch(solPoolPda);
+      assert.deepEqual(solPool.quoteAsset, { sol: {} });
+      assert.equal(solPool.oraclePriceUsd.toNumber(), 10_000_000);
+      assert.equal(solPool.lastTradePriceUsd.toNumber(), 15_000_000);
+      
+      // USD pools have no quote asset price to set
+      await expectError(
+        () => program.methods
+          .setQuotePriceManually(projectId, new anchor.BN(150_000_000))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidOraclePrice",
+        "Setting a quote price on a USD pool should be rejected"
+      );
+      
+      // Pyth quote updates read only the feed the project pinned, and only its authorities may run them
+      const setQuotePriceFeed = (feed: PublicKey | null) =>
+        program.methods
+          .setQuotePriceFeed(solProjectId, feed)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: solProjectPda,
+            liquidityPool: solPoolPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const updateQuoteFromPyth = (feed: PublicKey, authority: Keypair = platformAuthority) =>
+        program.methods
+          .updateQuotePriceFromPyth(solProjectId)
+          .accounts({
+            authority: authority.publicKey,
+            platformConfig: platformConfigPda,
+            project: solProjectPda,
+            liquidityPool: solPoolPda,
+            pythPriceAccount: feed,
+          })
+          .signers([authority])
+          .rpc();
+      
+      await expectError(
+        () => updateQuoteFromPyth(wideConfidencePythAccount),
+        "PriceFeedMismatch",
+        "A quote update without a pinned feed should be rejected"
+      );
+      await setQuotePriceFeed(wideConfidencePythAccount);
+      await expectError(
+        () => updateQuoteFromPyth(wideConfidencePythAccount, user),
+        "Unauthorized",
+        "Only the project or platform authority should update the quote price"
+      );
+      await expectError(
+        () => updateQuoteFromPyth(stalePythAccount),
+        "PriceFeedMismatch",
+        "A feed other than the pinned one should be rejected"
+      );
+      
+      // The pinned feed publishes SOL at $1.00
+      await updateQuoteFromPyth(wideConfidencePythAccount);
+      let quotedPool = await program.account.liquidityPool.fetch(solPoolPda);
+      assert.equal(quotedPool.quotePriceUsd.toNumber(), 1_000_000);
+      
+      // A stale pinned feed locks redemptions and keeps the last quote price
+      await setQuotePriceFeed(stalePythAccount);
+      await updateQuoteFromPyth(stalePythAccount);
+      quotedPool = await program.account.liquidityPool.fetch(solPoolPda);
+      assert.isTrue(quotedPool.redemptionLocked);
+      assert.equal(quotedPool.quotePriceUsd.toNumber(), 1_000_000);
+      
+      console.log(`SOL-quoted trade recorded at ${solPool.lastTradePriceUsd.toNumber() / 1_000_000} USD`);
+    } catch (error) {
+      console.error("Error testing SOL-quoted pool:", error);
+      throw error;
+    }
+  });
+
+  it("Commits an NFT's trait hash and verifies trait sets against it", async () => {
+    try {
+      const { nftMint, nftDataPda } = await swapTokenForNft(new anchor.BN(10**9));
+      const [nftTraitsPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("nft_traits"), nftMint.toBuffer()],
+        program.programId
+      );
+      const traits = [
+        { traitType: "Eyes", value: "Blue" },
+        { traitType: "Background", value: "Sunset" },
+        { traitType: "Hat", value: "Crown" },
+      ];
+      
+      await program.methods
+        .commitTraitHash(traits)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          nftData: nftDataPda,
+          nftTraits: nftTraitsPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const nftTraits = await program.account.nftTraits.fetch(nftTraitsPda);
+      assert.equal(nftTraits.nftMint.toString(), nftMint.toString());
+      assert.deepEqual(nftTraits.traitValues.map((t) => t.traitType), ["Background", "Eyes", "Hat"]);
+      
+      // The same trait set verifies in any order
+      const verify = (traitValues) =>
+        program.methods
+          .verifyTraits(traitValues)
+          .accounts({ nftTraits: nftTraitsPda })
+          .rpc();
+      await verify(traits);
+      await verify([traits[2], traits[0], traits[1]]);
+      
+      // Tampered, missing and duplicated traits are rejected
+      const tamperedSets = [
+        [{ traitType: "Eyes", value: "Red" }, traits[1], traits[2]],
+        [traits[0], traits[1]],
+        [traits[0], traits[1], traits[2], { traitType: "Eyes", value: "Blue" }],
+      ];
+      for (const tampered of tamperedSets) {
+        await expectError(
+          () => verify(tampered),
+          "TraitValidationFailed",
+          "A tampered trait set should fail verification"
+        );
+      }
+      
+      // The committed record cannot be rewritten
+      await expectError(
+        () => program.methods
+          .commitTraitHash([{ traitType: "Eyes", value: "Red" }])
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            nftData: nftDataPda,
+            nftTraits: nftTraitsPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "already in use",
+        "Committed traits should not be rewritable"
+      );
+      
+      console.log("Trait hash committed and verified");
+    } catch (error) {
+      console.error("Error testing trait hash verification:", error);
+      throw error;
+    }
+  });
+
+  it("Blocks redemption until the collection's minimum hold period has passed", async () => {
+    try {
+      const setMinHoldPeriod = (seconds: number) =>
+        program.methods
+          .setMinHoldPeriod(new anchor.BN(seconds))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      await setMinHoldPeriod(3600);
+      
+      // A freshly minted NFT cannot be redeemed from the pool, even when paying to bypass its cooldown
+      const { nftMint, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+      await expectError(
+        () => redeemNftForToken(nftMint, userNftAccount),
+        "NftInCooldown",
+        "Redeeming right after mint should be rejected"
+      );
+      await expectError(
+        () => redeemNftForToken(nftMint, userNftAccount, true),
+        "NftInCooldown",
+        "The cooldown bypass should not skip the hold period"
+      );
+      
+      // ...or by passing a collection without a hold period in place of its own
+      const decoyCollectionPda = await createCollectionUnder("hold-decoy", projectId, projectPda);
+      await expectError(
+        () => redeemWithCollection(nftMint, userNftAccount, decoyCollectionPda),
+        "CollectionNotFound",
+        "A collection without a hold period should not stand in for the NFT's own"
+      );
+      
+      // ...nor through its escrow
+      const { nftMint: escrowedMint } = await swapTokenForNft(new anchor.BN(10**9));
+      await createTokenEscrow(escrowedMint, new anchor.BN(10**9));
+      await expectError(
+        () => redeemEscrowToken(escrowedMint),
+        "NftInCooldown",
+        "Redeeming an escrow right after mint should be rejected"
+      );
+      
+      // With the hold period removed both paths work again
+      await setMinHoldPeriod(0);
+      await redeemEscrowToken(escrowedMint);
+      await redeemNftForToken(nftMint, userNftAccount);
+      
+      console.log("Minimum hold period enforced on both redemption paths");
+    } catch (error) {
+      console.error("Error testing minimum hold period:", error);
+      throw error;
+    }
+  });
+
+  it("Pauses and resumes fusion for a collection", async () => {
+    try {
+      const setFusionActive = (isActive: boolean) =>
+        program.methods
+          .setFusionActive(isActive)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            fusionConfig: fusionConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const heldParents = async () => {
+        const parents = [];
+        for (let i = 0; i < 2; i++) {
+          const { nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+          parents.push({ nftDataPda, userNftAccount });
+        }
+        return parents;
+      };
+      
+      // Paused fusion rejects attempts but keeps the rest of the config
+      const configBefore = await program.account.fusionConfig.fetch(fusionConfigPda);
+      await setFusionActive(false);
+      const parents = await heldParents();
+      await expectError(() => fuseNfts(parents), "FusionInactive", "Fusing while paused should be rejected");
+      
+      const pausedConfig = await program.account.fusionConfig.fetch(fusionConfigPda);
+      assert.isFalse(pausedConfig.isActive);
+      assert.equal(pausedConfig.minNftsRequired, configBefore.minNftsRequired);
+      assert.equal(pausedConfig.baseSuccessRate, configBefore.baseSuccessRate);
+      
+      // Only the project authority can pause or resume fusion
+      await expectError(
+        () => program.methods
+          .setFusionActive(true)
+          .accounts({
+            authority: user.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            fusionConfig: fusionConfigPda,
+          })
+          .signers([user])
+          .rpc(),
+        "Unauthorized",
+        "Non-authorities should not resume fusion"
+      );
+      
+      // Reactivated fusion works with the same parents
+      await setFusionActive(true);
+      const { childNftDataPda } = await fuseNfts(parents);
+      const child = await program.account.nftData.fetch(childNftDataPda);
+      assert.equal(child.fusionLevel, 1);
+      
+      console.log("Fusion paused and resumed");
+    } catch (error) {
+      console.error("Error testing fusion pause:", error);
+      throw error;
+    }
+  });
+
+  it("Scores a recognized trait combination above its individual trait bonuses", async () => {
+    try {
+      const traitConfigPda = collectionTraitConfigPda(collectionPda);
+      const traitTypePda = (name: string) => PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), collectionPda.toBuffer(), Buffer.from(name)],
+        program.programId
+      )[0];
+      const traitValue = (name: string, rarityWeight: number) => ({
+        name,
+        uriPostfix: name.toLowerCase(),
+        rarityWeight,
+        availableSupply: null,
+        usedSupply: new anchor.BN(0),
+      });
+      
+      // Two trait types whose rare values score the same on their own
+      for (const [name, rare, common] of [["Hat", "Crown", "Cap"], ["Aura", "Glow", "Dull"]]) {
+        await program.methods
+          .initializeTraitType(name, false, [traitValue(rare, 10), traitValue(common, 90)])
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            traitType: traitTypePda(name),
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      }
+      
+      const setComboBonuses = (comboBonuses: { traits: { traitType: string; value: string }[]; bonus: number }[]) =>
+        program.methods
+          .setTraitComboBonuses(comboBonuses)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            collection: collectionPda,
+            project: projectPda,
+            collectionTraitConfig: traitConfigPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // A combination must name at least two distinct trait types
+      await expectError(
+        () => setComboBonuses([{ traits: [{ traitType: "Hat", value: "Crown" }, { traitType: "Hat", value: "Cap" }], bonus: 30 }]),
+        "InvalidTraitConfig",
+        "A combination repeating a trait type should be rejected"
+      );
+      
+      const comboBonus = 30;
+      await setComboBonuses([
+        { traits: [{ traitType: "Hat", value: "Crown" }, { traitType: "Aura", value: "Glow" }], bonus: comboBonus },
+      ]);
+      
+      const rarityFor = async (selection: number[], traitNames: string[]) => {
+        const { nftDataPda } = await swapTokenForNft(
+          new anchor.BN(10**9),
+          null,
+          null,
+          royaltyWallet.publicKey,
+          Keypair.generate(),
+          Buffer.from(selection),
+          [traitConfigPda, ...traitNames.map(traitTypePda)]
+        );
+        return (await program.account.nftData.fetch(nftDataPda)).rarityScore;
+      };
+      
+      // Every score starts from the same base
+      const baseScore = 10;
+      const crownScore = await rarityFor([0], ["Hat"]);
+      const glowScore = await rarityFor([0], ["Aura"]);
+      const comboScore = await rarityFor([0, 0], ["Hat", "Aura"]);
+      const partialScore = await rarityFor([0, 1], ["Hat", "Aura"]);
+      const dullScore = await rarityFor([1], ["Aura"]);
+      
+      const individualBonuses = (crownScore - baseScore) + (glowScore - baseScore);
+      assert.isAbove(comboScore - baseScore, individualBonuses);
+      assert.equal(comboScore, baseScore + individualBonuses + comboBonus);
+      
+      // Only part of the combination earns no combination bonus
+      assert.equal(partialScore, baseScore + (crownScore - baseScore) + (dullScore - baseScore));
+      
+      await setComboBonuses([]);
+      
+      console.log(`Combination scored ${comboScore} vs ${baseScore + individualBonuses} from its traits alone`);
+    } catch (error) {
+      console.error("Error testing trait combination bonuses:", error);
+      throw error;
+    }
+  });
+
+  it("Breaks fees down consistently for any amount", async () => {
+    try {
+      const platformConfig = await program.account.platformConfig.fetch(platformConfigPda);
+      const project = await program.account.project.fetch(projectPda);
+      const platformBps = platformConfig.platformFeeBasisPoints;
+      const royaltyBps = project.royaltyBasisPoints;
+      const projectBps = Math.floor((10000 - platformBps - royaltyBps) / 2);
+      const cap: anchor.BN | null = platformConfig.maxPlatformFeeAbsolute;
+      
+      // Each fee rounds down on its own, then the volume discount comes off the platform and project fees
+      const fee = (amount: anchor.BN, bps: number) => amount.muln(bps).divn(10000);
+      const discounted = (amount: anchor.BN, discountBps: number) => amount.sub(fee(amount, discountBps));
+      
+      const amounts = [1, 9_999, 10_000, 10_001, 33_333, 123_456_789, 8 * 10**9 + 7, 999_999_999_999];
+      for (const amount of amounts) {
+        for (const discountPercent of [null, 33]) {
+          const { events } = await program.methods
+            .simulateSwap(collectionId, new anchor.BN(amount), discountPercent)
+            .accounts({
+              user: user.publicKey,
+              platformConfig: platformConfigPda,
+              collection: collectionPda,
+              project: projectPda,
+              liquidityPool: liquidityPoolPda,
+              userTier: userTierPda,
+              collectionFeeExemption: feeExemptionPda(collectionPda),
+              userFeeExemption: feeExemptionPda(user.publicKey),
+            })
+            .simulate();
+          const quote = events.find((event) => event.name === "SwapQuote").data;
+          const total: anchor.BN = quote.totalAmount;
+          
+          // The components always add back up to the amount paid
+          const sum = quote.platformFee.add(quote.projectFee).add(quote.royaltyFee).add(quote.netAmount);
+          assert.equal(sum.toString(), total.toString(), `sum for ${amount}`);
+          
+          // ...and each fee matches the per-component rounding
+          let expectedPlatformFee = discounted(fee(total, platformBps), quote.feeDiscountBps);
+          if (cap !== null && expectedPlatformFee.gt(cap)) {
+            expectedPlatformFee = cap;
+          }
+          assert.equal(quote.platformFee.toString(), expectedPlatformFee.toString(), `platform fee for ${amount}`);
+          assert.equal(
+            quote.projectFee.toString(),
+            discounted(fee(total, projectBps), quote.feeDiscountBps).toString(),
+            `project fee for ${amount}`
+          );
+          assert.equal(quote.royaltyFee.toString(), fee(total, royaltyBps).toString(), `royalty fee for ${amount}`);
+        }
+      }
+      
+      console.log(`Fee breakdown checked for ${amounts.length * 2} amounts`);
+    } catch (error) {
+      console.error("Error testing fee breakdown:", error);
+      throw error;
+    }
+  });
+
+  it("Recovers an NFT stuck in a program-owned token account", async () => {
+    try {
+      const nftPdas = (nftMint: PublicKey) => ({
+        nftData: PublicKey.findProgramAddressSync([Buffer.from("nft_data"), nftMint.toBuffer()], program.programId)[0],
+        tokenEscrow: escrowPdas(nftMint).tokenEscrowPda,
+        listing: PublicKey.findProgramAddressSync([Buffer.from("listing"), nftMint.toBuffer()], program.programId)[0],
+      });
+      // Simulate a failed transfer that leaves the NFT with the liquidity pool
+      const depositToPool = async (nftMint: PublicKey, userNftAccount: PublicKey) => {
+        const poolNftAccount = (await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          user,
+          nftMint,
+          liquidityPoolPda,
+          true
+        )).address;
+        await transfer(provider.connection, user, userNftAccount, poolNftAccount, user, 1);
+        return poolNftAccount;
+      };
+      const recoverNft = (
+        nftMint: PublicKey,
+        custodyTokenAccount: PublicKey,
+        destinationTokenAccount: PublicKey,
+        authority: Keypair = platformAuthority,
+        projectAuthority: Keypair = platformAuthority
+      ) =>
+        program.methods
+          .recoverNft(projectId)
+          .accounts({
+            authority: authority.publicKey,
+            platformConfig: platformConfigPda,
+            projectAuthority: projectAuthority.publicKey,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            nftMint,
+            custodyTokenAccount,
+            destinationTokenAccount,
+            ...nftPdas(nftMint),
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([authority, projectAuthority])
+          .rpc();
+      
+      const { nftMint, nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+      const poolNftAccount = await depositToPool(nftMint, userNftAccount);
+      assert.equal(await tokenBalance(userNftAccount), 0);
+      
+      // Only the platform authority can recover NFTs, together with the project authority
+      await expectError(
+        () => recoverNft(nftMint, poolNftAccount, userNftAccount, user),
+        "Unauthorized",
+        "Non-authorities should not recover NFTs"
+      );
+      
+      // Nor can the platform authority move a project's NFT without the project authority
+      await expectError(
+        () => recoverNft(nftMint, poolNftAccount, userNftAccount, platformAuthority, user),
+        "Unauthorized",
+        "Recovery without the project authority should be rejected"
+      );
+      
+      await recoverNft(nftMint, poolNftAccount, userNftAccount);
+      assert.equal(await tokenBalance(userNftAccount), 1);
+      assert.equal(await tokenBalance(poolNftAccount), 0);
+      assert.isTrue((await program.account.nftData.fetch(nftDataPda)).owner.equals(user.publicKey));
+      
+      // An NFT still backing an active escrow stays in custody
+      const escrowed = await swapForHeldNft(new anchor.BN(10**9));
+      await createTokenEscrow(escrowed.nftMint, new anchor.BN(10**9));
+      const escrowedPoolAccount = await depositToPool(escrowed.nftMint, escrowed.userNftAccount);
+      await expectError(
+        () => recoverNft(escrowed.nftMint, escrowedPoolAccount, escrowed.userNftAccount),
+        "EscrowStillActive",
+        "Recovering an escrowed NFT should be rejected"
+      );
+      assert.equal(await tokenBalance(escrowedPoolAccount), 1);
+      
+      console.log("Stuck NFT recovered to its owner");
+    } catch (error) {
+      console.error("Error testing NFT recovery:", error);
+      throw error;
+    }
+  });
+
+  it("Charges exactly the fixed mint price whatever the oracle state", async () => {
+    try {
+      const fixedProjectId = "fixed-price";
+      const fixedCollectionId = "fixed-price-collection";
+      const [fixedProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(fixedProjectId)],
+        program.programId
+      );
+      const [fixedPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), fixedProjectPda.toBuffer()],
+        program.programId
+      );
+      const [fixedCollectionPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(fixedCollectionId)],
+        program.programId
+      );
+      const [fixedUserTierPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user_tier"), fixedProjectPda.toBuffer(), user.publicKey.toBuffer()],
+        program.programId
+      );
+      const [fixedRoyaltyConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("royalty_config"), fixedProjectPda.toBuffer()],
+        program.programId
+      );
+      const fixedLpTokenAccount = await getAssociatedTokenAddress(tokenMint, fixedPoolPda, true);
+      
+      await program.methods
+        .createProject(fixedProjectId, projectTreasury.publicKey, royaltyWallet.publicKey, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: fixedProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setupLiquidityPool(fixedProjectId, tokenMint, new anchor.BN(0))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: fixedProjectPda,
+          liquidityPool: fixedPoolPda,
+          tokenMintAccount: tokenMint,
+          lpTokenAccount: fixedLpTokenAccount,
+          authorityTokenAccount: userTokenAccount,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .createCollection(fixedCollectionId, fixedProjectId, metadataUri, tokenMint, false)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: fixedProjectPda,
+          collection: fixedCollectionPda,
+          collectionRegistry: collectionRegistryPda(fixedProjectPda),
+          tokenMintAccount: tokenMint,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const swapOnFixedPool = async (tokenAmount: number) => {
+        const nftMintKeypair = Keypair.generate();
+        const [nftDataPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .swapTokenForNft(fixedCollectionId, new anchor.BN(tokenAmount), null, null, null)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            collection: fixedCollectionPda,
+            collectionStats: collectionStatsPda(fixedCollectionPda),
+            mintRateLimit: mintRateLimitPda(fixedCollectionPda),
+            project: fixedProjectPda,
+            liquidityPool: fixedPoolPda,
+            userTokenAccount: userTokenAccount,
+            lpTokenAccount: fixedLpTokenAccount,
+            tokenMint: tokenMint,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            royaltyWallet: royaltyWallet.publicKey,
+            royaltyConfig: fixedRoyaltyConfigPda,
+            collectionFeeExemption: feeExemptionPda(fixedCollectionPda),
+            userFeeExemption: feeExemptionPda(user.publicKey),
+            nftMint: nftMintKeypair.publicKey,
+            nftData: nftDataPda,
+            loyaltyNftData: SystemProgram.programId,
+            loyaltyTokenAccount: SystemProgram.programId,
+            userTier: fixedUserTierPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user, nftMintKeypair])
+          .rpc();
+      };
+      // The pool has never been priced, so oracle-priced swaps are refused
+      await expectError(() => swapOnFixedPool(5 * 10**9), "OracleNotInitialized", "Oracle-priced swaps need a price");
+      
+      const setFixedMintPrice = (price: anchor.BN | null) =>
+        program.methods
+          .setFixedMintPrice(price)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: fixedProjectPda,
+            collection: fixedCollectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const fixedPrice = 3 * 10**9;
+      await setFixedMintPrice(new anchor.BN(fixedPrice));
+      
+      // The quote and the swap both use the fixed price, without an oracle price
+      const { events } = await program.methods
+        .simulateSwap(fixedCollectionId, new anchor.BN(5 * 10**9), null)
+        .accounts({
+          user: user.publicKey,
+          platformConfig: platformConfigPda,
+          collection: fixedCollectionPda,
+          project: fixedProjectPda,
+          liquidityPool: fixedPoolPda,
+          userTier: fixedUserTierPda,
+          collectionFeeExemption: feeExemptionPda(fixedCollectionPda),
+          userFeeExemption: feeExemptionPda(user.publicKey),
+        })
+        .simulate();
+      assert.equal(events.find((event) => event.name === "SwapQuote").data.totalAmount.toNumber(), fixedPrice);
+      
+      const balanceBefore = await tokenBalance(userTokenAccount);
+      await swapOnFixedPool(5 * 10**9);
+      assert.equal(balanceBefore - await tokenBalance(userTokenAccount), fixedPrice);
+      
+      // Offering less than the fixed price is refused rather than topped up
+      await expectError(
+        () => swapOnFixedPool(2 * 10**9),
+        "InsufficientTokenAmount",
+        "Offers below the fixed price should be rejected"
+      );
+      
+      // Clearing the fixed price returns the collection to oracle-gated pricing
+      await setFixedMintPrice(null);
+      await expectError(
+        () => swapOnFixedPool(5 * 10**9),
+        "OracleNotInitialized",
+        "Oracle-priced swaps need a price again"
+      );
+      
+      console.log(`Fixed-price mint charged ${fixedPrice} tokens without an oracle price`);
+    } catch (error) {
+      console.error("Error testing fixed mint price:", error);
+      throw error;
+    }
+  });
+
+  it("Expires slot-based cooldowns and vesting by slot height", async () => {
+    try {
+      const cooldownSlots = 20;
+      const setCooldownClock = (cooldownClock: object) =>
+        program.methods
+          .setCooldownClock(cooldownClock)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const waitForSlot = async (slot: number) => {
+        while ((await provider.connection.getSlot()) < slot) {
+          await new Promise((resolve) => setTimeout(resolve, 400));
+        }
+      };
+      await setCooldownClock({ slot: {} });
+      
+      // A discounted swap's cooldown is recorded as a slot height
+      const slotBefore = await provider.connection.getSlot();
+      const cooling = await swapForHeldNft(new anchor.BN(10**9), 10, new anchor.BN(cooldownSlots));
+      const nftData = await program.account.nftData.fetch(cooling.nftDataPda);
+      assert.deepEqual(nftData.cooldownClock, { slot: {} });
+      const cooldownEnd = nftData.cooldownEndTimestamp.toNumber();
+      assert.isAtLeast(cooldownEnd, slotBefore + cooldownSlots);
+      assert.isBelow(cooldownEnd, slotBefore + cooldownSlots + 100);
+      
+      // Escrow vesting is counted in slots as well
+      const { nftMint: escrowedMint } = await swapTokenForNft(new anchor.BN(10**9));
+      const { tokenEscrowPda } = await createTokenEscrow(
+        escrowedMint, new anchor.BN(10**9), new anchor.BN(cooldownSlots)
+      );
+      const escrow = await program.account.tokenEscrow.fetch(tokenEscrowPda);
+      assert.deepEqual(escrow.vestingClock, { slot: {} });
+      
+      await expectError(
+        () => redeemNftForToken(cooling.nftMint, cooling.userNftAccount),
+        "NftInCooldown",
+        "Redeeming before the cooldown slot should be rejected"
+      );
+      
+      await expectError(
+        () => redeemEscrowToken(escrowedMint),
+        "VestingPeriodActive",
+        "Redeeming before the vesting slot should be rejected"
+      );
+      
+      // Once the slot height passes both ends, both redemptions go through
+      await waitForSlot(Math.max(cooldownEnd, escrow.vestingEndTimestamp.toNumber()) + 1);
+      await redeemNftForToken(cooling.nftMint, cooling.userNftAccount);
+      await redeemEscrowToken(escrowedMint);
+      
+      await setCooldownClock({ timestamp: {} });
+      
+      console.log(`Slot-based cooldown expired at slot ${cooldownEnd}`);
+    } catch (error) {
+      console.error("Error testing slot-based cooldowns:", error);
+      throw error;
+    }
+  });
+
+  it("Shortens a new mint's cooldown for holders of a rare NFT from the collection", async () => {
+    try {
+      const cooldownSeconds = 1000;
+      const setLoyaltyCooldownPerk = (minRarityScore: number, reductionPercent: number) =>
+        program.methods
+          .setLoyaltyCooldownPerk(minRarityScore, reductionPercent)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const mintedCooldown = async (loyaltyNft: { nftData: PublicKey; tokenAccount: PublicKey } | null) => {
+        const { nftDataPda } = await swapTokenForNft(
+          new anchor.BN(10**9), 10, new anchor.BN(cooldownSeconds),
+          royaltyWallet.publicKey, Keypair.generate(), null, [], [], loyaltyNft
+        );
+        const nftData = await program.account.nftData.fetch(nftDataPda);
+        return nftData.cooldownEndTimestamp.toNumber() - nftData.mintedAt.toNumber();
+      };
+      
+      const held = await swapForHeldNft(new anchor.BN(10**9));
+      const heldRarity = (await program.account.nftData.fetch(held.nftDataPda)).rarityScore;
+      const loyaltyNft = { nftData: held.nftDataPda, tokenAccount: held.userNftAccount };
+      
+      // A held NFT below the rarity threshold earns nothing
+      await setLoyaltyCooldownPerk(heldRarity + 1, 50);
+      assert.equal(await mintedCooldown(loyaltyNft), cooldownSeconds);
+      
+      // At the threshold the cooldown is halved, but only when the NFT is presented
+      await setLoyaltyCooldownPerk(heldRarity, 50);
+      assert.equal(await mintedCooldown(null), cooldownSeconds);
+      assert.equal(await mintedCooldown(loyaltyNft), cooldownSeconds / 2);
+      
+      // The presented NFT must be held in the given token account
+      await expectError(
+        () => mintedCooldown({ nftData: held.nftDataPda, tokenAccount: userTokenAccount }),
+        "InvalidTokenAccount",
+        "An NFT presented without its token account should be rejected"
+      );
+      
+      // Reductions are percentages
+      await expectError(
+        () => setLoyaltyCooldownPerk(0, 101),
+        "InvalidDiscountPercentage",
+        "A reduction above 100% should be rejected"
+      );
+      
+      await setLoyaltyCooldownPerk(0, 0);
+      
+      console.log(`Loyal holder's cooldown cut from ${cooldownSeconds}s to ${cooldownSeconds / 2}s`);
+    } catch (error) {
+      console.error("Error testing loyalty cooldown perk:", error);
+      throw error;
+    }
+  });
+
+  it("Pays a partial redemption from what the pool has and settles the rest as a claim", async () => {
+    try {
+      const partialProjectId = "partial-redeem";
+      const partialCollectionId = "partial-redeem-collection";
+      const [partialProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(partialProjectId)],
+        program.programId
+      );
+      const [partialPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), partialProjectPda.toBuffer()],
+        program.programId
+      );
+      const [partialCollectionPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(partialCollectionId)],
+        program.programId
+      );
+      const [partialUserTierPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user_tier"), partialProjectPda.toBuffer(), user.publicKey.toBuffer()],
+        program.programId
+      );
+      const [partialRoyaltyConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("royalty_config"), partialProjectPda.toBuffer()],
+        program.programId
+      );
+      const partialLpTokenAccount = await getAssociatedTokenAddress(tokenMint, partialPoolPda, true);
+      
+      await program.methods
+        .createProject(partialProjectId, projectTreasury.publicKey, royaltyWallet.publicKey, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: partialProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setupLiquidityPool(partialProjectId, tokenMint, new anchor.BN(0))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: partialProjectPda,
+          liquidityPool: partialPoolPda,
+          tokenMintAccount: tokenMint,
+          lpTokenAccount: partialLpTokenAccount,
+          authorityTokenAccount: userTokenAccount,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .createCollection(partialCollectionId, partialProjectId, metadataUri, tokenMint, false)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: partialProjectPda,
+          collection: partialCollectionPda,
+          collectionRegistry: collectionRegistryPda(partialProjectPda),
+          tokenMintAccount: tokenMint,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setPriceManually(partialProjectId, new anchor.BN(1_000_000), null)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: partialProjectPda,
+          liquidityPool: partialPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // A cheap fixed-price mint leaves the pool well short of the redemption value
+      await program.methods
+        .setFixedMintPrice(new anchor.BN(10**8))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: partialProjectPda,
+          collection: partialCollectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      const { nftMintKeypair, userNftAccount } = await createHeldNftMint();
+      const nftMint = nftMintKeypair.publicKey;
+      const [nftDataPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("nft_data"), nftMint.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .swapTokenForNft(partialCollectionId, new anchor.BN(10**8), null, null, null)
+        .accounts({
+          user: user.publicKey,
+          platformConfig: platformConfigPda,
+          collection: partialCollectionPda,
+          collectionStats: collectionStatsPda(partialCollectionPda),
+          mintRateLimit: mintRateLimitPda(partialCollectionPda),
+          project: partialProjectPda,
+          liquidityPool: partialPoolPda,
+          userTokenAccount: userTokenAccount,
+          lpTokenAccount: partialLpTokenAccount,
+          tokenMint: tokenMint,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          royaltyWallet: royaltyWallet.publicKey,
+          royaltyConfig: partialRoyaltyConfigPda,
+          collectionFeeExemption: feeExemptionPda(partialCollectionPda),
+          userFeeExemption: feeExemptionPda(user.publicKey),
+          nftMint: nftMint,
+          nftData: nftDataPda,
+          loyaltyNftData: SystemProgram.programId,
+          loyaltyTokenAccount: SystemProgram.programId,
+          userTier: partialUserTierPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user, nftMintKeypair])
+        .rpc();
+      
+      const [pendingClaimPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_claim"), nftMint.toBuffer()],
+        program.programId
+      );
+      const redeemOnPartialPool = () =>
+        program.methods
+          .redeemNftForToken(nftMint, false)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            nftData: nftDataPda,
+            collection: partialCollectionPda,
+            collectionStats: collectionStatsPda(partialCollectionPda),
+            project: partialProjectPda,
+            liquidityPool: partialPoolPda,
+            nftMint: nftMint,
+            tokenEscrow: escrowPdas(nftMint).tokenEscrowPda,
+            redemptionReservation: PublicKey.findProgramAddressSync(
+              [Buffer.from("redemption_reservation"), nftMint.toBuffer()],
+              program.programId
+            )[0],
+            pendingClaim: pendingClaimPda,
+            userNftAccount: userNftAccount,
+            userTokenAccount: userTokenAccount,
+            lpTokenAccount: partialLpTokenAccount,
+            tokenMint: tokenMint,
+            projectTreasury: projectTreasury.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+      const claimPendingRedemption = () =>
+        program.methods
+          .claimPendingRedemption(nftMint)
+          .accounts({
+            claimant: user.publicKey,
+            pendingClaim: pendingClaimPda,
+            project: partialProjectPda,
+            liquidityPool: partialPoolPda,
+            lpTokenAccount: partialLpTokenAccount,
+            claimantTokenAccount: userTokenAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc();
+      
+      // Without the pool flag a short redemption fails outright
+      await expectError(redeemOnPartialPool, "InsufficientLiquidity", "A short redemption should fail by default");
+      
+      await program.methods
+        .setPartialRedemption(partialProjectId, true)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: partialProjectPda,
+          liquidityPool: partialPoolPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // With it, the user gets everything the pool can spare and a claim for the rest
+      let pool = await program.account.liquidityPool.fetch(partialPoolPda);
+      const available = await tokenBalance(partialLpTokenAccount)
+        - pool.pendingPlatformFees.toNumber()
+        - pool.pendingProjectFees.toNumber()
+        - pool.reservedLiquidity.toNumber();
+      assert.isAbove(available, 0);
+      assert.isBelow(available, 10**9);
+      
+      const balanceBefore = await tokenBalance(userTokenAccount);
+      await redeemOnPartialPool();
+      assert.equal(await tokenBalance(userTokenAccount), balanceBefore + available);
+      
+      const owed = 10**9 - available;
+      const claim = await program.account.pendingClaim.fetch(pendingClaimPda);
+      assert.equal(claim.amount.toNumber(), owed);
+      assert.isTrue(claim.claimant.equals(user.publicKey));
+      pool = await program.account.liquidityPool.fetch(partialPoolPda);
+      assert.equal(pool.pendingClaims.toNumber(), owed);
+      
+      // Nothing can be claimed until liquidity comes back
+      await expectError(claimPendingRedemption, "InsufficientLiquidity", "An empty pool should not pay claims");
+      
+      // Once the pool is topped up the claim is paid in full and closed
+      await transfer(provider.connection, user, userTokenAccount, partialLpTokenAccount, user, owed + 10**8);
+      const balanceBeforeClaim = await tokenBalance(userTokenAccount);
+      await claimPendingRedemption();
+      assert.equal(await tokenBalance(userTokenAccount), balanceBeforeClaim + owed);
+      assert.isNull(await provider.connection.getAccountInfo(pendingClaimPda));
+      pool = await program.account.liquidityPool.fetch(partialPoolPda);
+      assert.equal(pool.pendingClaims.toNumber(), 0);
+      
+      console.log(`Partial redemption paid ${available} tokens up front and ${owed} on claim`);
+    } catch (error) {
+      console.error("Error testing partial redemption:", error);
+      throw error;
+    }
+  });
+
+  it("Skips the platform fee for exempt collections and users but still charges royalties", async () => {
+    try {
+      const swapAmount = 4 * 10**9;
+      const quoteSwap = async () => {
+        const { events } = await program.methods
+          .simulateSwap(collectionId, new anchor.BN(swapAmount), null)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            collection: collectionPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            userTier: userTierPda,
+            collectionFeeExemption: feeExemptionPda(collectionPda),
+            userFeeExemption: feeExemptionPda(user.publicKey),
+          })
+          .simulate();
+        return events.find((event) => event.name === "SwapQuote").data;
+      };
+      const platformFeeCharged = async () => {
+        const before = await tokenBalance(platformFeeAccount);
+        await swapTokenForNft(new anchor.BN(swapAmount));
+        return await tokenBalance(platformFeeAccount) - before;
+      };
+      const addFeeExemption = (subject: PublicKey) =>
+        program.methods
+          .addFeeExemption(subject)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            feeExemption: feeExemptionPda(subject),
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const removeFeeExemption = (subject: PublicKey) =>
+        program.methods
+          .removeFeeExemption(subject)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            feeExemption: feeExemptionPda(subject),
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      // Only the platform authority manages the registry
+      await expectError(
+        () => program.methods
+          .addFeeExemption(user.publicKey)
+          .accounts({
+            authority: user.publicKey,
+            platformConfig: platformConfigPda,
+            feeExemption: feeExemptionPda(user.publicKey),
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc(),
+        "Unauthorized",
+        "Users should not be able to exempt themselves"
+      );
+      
+      const standard = await quoteSwap();
+      assert.isAbove(standard.platformFee.toNumber(), 0);
+      assert.equal(await platformFeeCharged(), standard.platformFee.toNumber());
+      
+      // An exempt user pays no platform fee; the royalty is unchanged and the saving stays in the pool
+      await addFeeExemption(user.publicKey);
+      const userExempt = await quoteSwap();
+      assert.equal(userExempt.platformFee.toNumber(), 0);
+      assert.equal(userExempt.royaltyFee.toNumber(), standard.royaltyFee.toNumber());
+      assert.equal(userExempt.netAmount.toNumber(), standard.netAmount.toNumber() + standard.platformFee.toNumber());
+      assert.equal(await platformFeeCharged(), 0);
+      await removeFeeExemption(user.publicKey);
+      
+      // So does anyone trading a partner collection
+      await addFeeExemption(collectionPda);
+      const collectionExempt = await quoteSwap();
+      assert.equal(collectionExempt.platformFee.toNumber(), 0);
+      assert.equal(collectionExempt.royaltyFee.toNumber(), standard.royaltyFee.toNumber());
+      assert.equal(await platformFeeCharged(), 0);
+      await removeFeeExemption(collectionPda);
+      
+      // With the exemptions withdrawn the platform fee is charged again
+      assert.isNull(await provider.connection.getAccountInfo(feeExemptionPda(collectionPda)));
+      assert.equal(await platformFeeCharged(), standard.platformFee.toNumber());
+      
+      console.log(`Exempt swaps skipped a ${standard.platformFee.toNumber()} token platform fee`);
+    } catch (error) {
+      console.error("Error testing fee exemptions:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects duplicate and already-consumed fusion parents", async () => {
+    try {
+      const [first, second, third] = [
+        await swapForHeldNft(new anchor.BN(10**9)),
+        await swapForHeldNft(new anchor.BN(10**9)),
+        await swapForHeldNft(new anchor.BN(10**9)),
+      ];
+      
+      // The same NFT can't fill two parent slots
+      await expectError(
+        () => fuseNfts([first, first]),
+        "DuplicateFusionParent",
+        "A parent listed twice should be rejected"
+      );
+      
+      // A successful fusion consumes both parents...
+      const { childMint } = await fuseNfts([first, second]);
+      
+      // ...so neither can be fused again
+      await expectError(
+        () => fuseNfts([first, third]),
+        "InvalidNftForFusion",
+        "A consumed parent should not fuse again"
+      );
+      const unused = await program.account.nftData.fetch(third.nftDataPda);
+      assert.isNull(unused.fusedInto);
+      
+      console.log(`Parents consumed into ${childMint.toBase58()} cannot be fused again`);
+    } catch (error) {
+      console.error("Error testing fusion parent reuse:", error);
+      throw error;
+    }
+  });
+
+  it("Keeps a fused parent consumed when its rent is refunded in the same transaction", async () => {
+    try {
+      const [first, second, third] = [
+        await swapForHeldNft(new anchor.BN(10**9)),
+        await swapForHeldNft(new anchor.BN(10**9)),
+        await swapForHeldNft(new anchor.BN(10**9)),
+      ];
+      const rent = await provider.connection.getMinimumBalanceForRentExemption(
+        (await provider.connection.getAccountInfo(first.nftDataPda)).data.length
+      );
+      
+      // Fuse, pay the consumed parent's rent back, then try to fuse it again in one transaction
+      const fusion = fuseNftsRequest([first, second]);
+      const refusion = fuseNftsRequest([first, third]);
+      const tx = new Transaction()
+        .add(await fusion.request.instruction())
+        .add(SystemProgram.transfer({ fromPubkey: user.publicKey, toPubkey: first.nftDataPda, lamports: rent }))
+        .add(await refusion.request.instruction());
+      
+      await expectError(
+        () => provider.sendAndConfirm(tx, [...fusion.signers, ...refusion.signers]),
+        "InvalidNftForFusion",
+        "A refunded parent should not fuse a second time"
+      );
+      
+      // Nothing from the rejected transaction landed
+      assert.isNull(await provider.connection.getAccountInfo(fusion.childNftDataPda));
+      const unused = await program.account.nftData.fetch(third.nftDataPda);
+      assert.isNull(unused.fusedInto);
+      
+      console.log("A fused parent stays consumed after its rent is refunded");
+    } catch (error) {
+      console.error("Error testing fused parent revival:", error);
+      throw error;
+    }
+  });
+
+  it("Throttles a wallet that fuses again before the fusion interval passes", async () => {
+    const intervalSeconds = 3;
+    const setFusionMinInterval = (interval: number) =>
+      program.methods
+        .setFusionMinInterval(new anchor.BN(interval))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          fusionConfig: fusionConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const heldParents = async () => [
+      await swapForHeldNft(new anchor.BN(10**9)),
+      await swapForHeldNft(new anchor.BN(10**9)),
+    ];
+    
+    try {
+      await setFusionMinInterval(intervalSeconds);
+      const config = await program.account.fusionConfig.fetch(fusionConfigPda);
+      assert.equal(config.minFusionInterval.toNumber(), intervalSeconds);
+      
+      await fuseNfts(await heldParents());
+      const cooldown = await program.account.fusionCooldown.fetch(fusionCooldownPda(user.publicKey));
+      assert.isTrue(cooldown.user.equals(user.publicKey));
+      assert.isAbove(cooldown.lastFusionAt.toNumber(), 0);
+      
+      // A second fusion straight away is throttled, even with fresh parents
+      const nextParents = await heldParents();
+      await expectError(
+        () => fuseNfts(nextParents),
+        "FusionRateLimited",
+        "A fusion inside the interval should be throttled"
+      );
+      
+      // Once the interval has passed the same parents fuse normally
+      await new Promise((resolve) => setTimeout(resolve, (intervalSeconds + 2) * 1000));
+      await fuseNfts(nextParents);
+      const refreshed = await program.account.fusionCooldown.fetch(fusionCooldownPda(user.publicKey));
+      assert.isAtLeast(refreshed.lastFusionAt.toNumber() - cooldown.lastFusionAt.toNumber(), intervalSeconds);
+      
+      await setFusionMinInterval(0);
+      
+      console.log(`Fusions throttled to one per ${intervalSeconds}s per wallet`);
+    } catch (error) {
+      console.error("Error testing the fusion interval:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects oracle updates against another project's pool", async () => {
+    try {
+      // The pool set up for the mismatched-swap test belongs to a different project
+      const [otherProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from("mismatch-project")],
+        program.programId
+      );
+      const [otherPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), otherProjectPda.toBuffer()],
+        program.programId
+      );
+      const poolBefore = await program.account.liquidityPool.fetch(otherPoolPda);
+      
+      await expectError(
+        () => program.methods
+          .setPriceManually(projectId, new anchor.BN(50_000_000), null)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: otherPoolPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "PoolProjectMismatch",
+        "A manual price update on a foreign pool should be rejected"
+      );
+      
+      await expectError(
+        () => program.methods
+          .updatePriceFromDex(projectId)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: otherPoolPda,
+            dexTokenAccount: userTokenAccount,
+            dexBaseAccount: userTokenAccount,
+            tokenMint: tokenMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "PoolProjectMismatch",
+        "A DEX price update on a foreign pool should be rejected"
+      );
+      
+      await expectError(
+        () => program.methods
+          .updatePriceFromPyth(projectId)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: otherPoolPda,
+            pythPriceAccount: SystemProgram.programId,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "PoolProjectMismatch",
+        "A Pyth price update on a foreign pool should be rejected"
+      );
+      
+      const poolAfter = await program.account.liquidityPool.fetch(otherPoolPda);
+      assert.equal(poolAfter.oraclePriceUsd.toString(), poolBefore.oraclePriceUsd.toString());
+      
+      console.log("Oracle updates are bound to the project's own pool");
+    } catch (error) {
+      console.error("Error testing oracle pool binding:", error);
+      throw error;
+    }
+  });
+
+  it("Pays at least the collection's redemption floor when the price dips", async () => {
+    const setRedemptionFloor = (floorUsd: anchor.BN | null) =>
+      program.methods
+        .setRedemptionFloor(floorUsd)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          collection: collectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const setPrice = (priceUsd: anchor.BN) =>
+      program.methods
+        .setPriceManually(projectId, priceUsd, null)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const baseValue = 10**9;
+    
+    try {
+      const previousPrice = (await program.account.liquidityPool.fetch(liquidityPoolPda)).oraclePriceUsd;
+      
+      // A $0.30 floor is worth 3 tokens once the price drops to $0.10
+      const lowPrice = new anchor.BN(100_000);
+      const floorUsd = new anchor.BN(300_000);
+      await setRedemptionFloor(floorUsd);
+      const collection = await program.account.collection.fetch(collectionPda);
+      assert.equal(collection.redemptionFloorUsd.toNumber(), floorUsd.toNumber());
+      
+      // At the regular price the floor is worth less than the base value, which is paid as usual
+      const atRegularPrice = await swapForHeldNft(new anchor.BN(10**9));
+      let userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(atRegularPrice.nftMint, atRegularPrice.userNftAccount);
+      const floorAtRegularPrice = floorUsd.mul(new anchor.BN(baseValue)).div(previousPrice).toNumber();
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + Math.max(baseValue, floorAtRegularPrice));
+      
+      // After the dip the floor binds and the pool pays its token equivalent
+      const atLowPrice = await swapForHeldNft(new anchor.BN(10**9));
+      await setPrice(lowPrice);
+      userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(atLowPrice.nftMint, atLowPrice.userNftAccount);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + 3 * baseValue);
+      
+      // A floor the pool can't fund is refused rather than paid in part
+      await setPrice(previousPrice);
+      const unfunded = await swapForHeldNft(new anchor.BN(10**9));
+      await setPrice(lowPrice);
+      const lpBalance = new anchor.BN(await tokenBalance(lpTokenAccountPda));
+      await setRedemptionFloor(lpBalance.add(new anchor.BN(baseValue)).mul(lowPrice).div(new anchor.BN(baseValue)));
+      await expectError(
+        () => redeemNftForToken(unfunded.nftMint, unfunded.userNftAccount),
+        "InsufficientLiquidity",
+        "A floor payout beyond the pool's liquidity should be rejected"
+      );
+      
+      await setRedemptionFloor(null);
+      await setPrice(previousPrice);
+      await redeemNftForToken(unfunded.nftMint, unfunded.userNftAccount);
+      
+      console.log("Redemptions paid the USD floor when the token price dipped");
+    } catch (error) {
+      console.error("Error testing the redemption floor:", error);
+      throw error;
+    }
+  });
+
+  it("Ignores another project's redemption floor", async () => {
+    try {
+      const { rivalProjectPda, rivalCollectionPda } = await createRivalProject("rival-floor");
+      
+      // A floor worth far more than the pool holds, set on a collection outside this project
+      await program.methods
+        .setRedemptionFloor(new anchor.BN(1_000_000_000_000))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: rivalProjectPda,
+          collection: rivalCollectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const held = await swapForHeldNft(new anchor.BN(10**9));
+      const lpBefore = await tokenBalance(lpTokenAccountPda);
+      await expectError(
+        () => redeemWithCollection(held.nftMint, held.userNftAccount, rivalCollectionPda),
+        "CollectionNotFound",
+        "Redeeming under another project's floor should be rejected"
+      );
+      assert.equal(await tokenBalance(lpTokenAccountPda), lpBefore);
+      
+      await redeemNftForToken(held.nftMint, held.userNftAccount);
+      
+      console.log("Another project's redemption floor can't be borrowed");
+    } catch (error) {
+      console.error("Error testing a borrowed redemption floor:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects a Pyth price with an out-of-range exponent", async () => {
+    try {
+      const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      await pinPythPriceFeed(outOfRangeExpoPythAccount);
+      
+      await expectError(
+        () => program.methods
+          .updatePriceFromPyth(projectId)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            pythPriceAccount: outOfRangeExpoPythAccount,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc(),
+        "InvalidOraclePrice",
+        "A feed with an exponent of 30 should be rejected"
+      );
+      
+      const poolAfter = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(poolAfter.oraclePriceUsd.toString(), poolBefore.oraclePriceUsd.toString());
+      
+      console.log("Pyth feed with an out-of-range exponent rejected before scaling");
+    } catch (error) {
+      console.error("Error testing the Pyth exponent bound:", error);
+      throw error;
+    }
+  });
+
+  it("Decays a discount over its cooldown when the NFT is redeemed early", async () => {
+    const bypassFeeBps = 500;
+    const discountPercent = 50;
+    const cooldownSeconds = 20;
+    const baseValue = 10**9;
+    const discountValue = (baseValue * discountPercent) / 100;
+    const bypassFee = (baseValue * bypassFeeBps) / 10000;
+    const collectionAccounts = {
+      authority: platformAuthority.publicKey,
+      project: projectPda,
+      collection: collectionPda,
+    };
+    const setCooldownBypassFee = (feeBps: number) =>
+      program.methods.setCooldownBypassFee(feeBps).accounts(collectionAccounts).signers([platformAuthority]).rpc();
+    const setDiscountDecay = (discountDecay: boolean) =>
+      program.methods.setDiscountDecay(discountDecay).accounts(collectionAccounts).signers([platformAuthority]).rpc();
+    // Tokens withheld from a bypassed redemption beyond the bypass fee
+    const clawbackOnEarlyRedeem = async (nft: { nftMint: PublicKey; userNftAccount: PublicKey }) => {
+      const userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(nft.nftMint, nft.userNftAccount, true);
+      return baseValue - bypassFee - (await tokenBalance(userTokenAccount) - userBefore);
+    };
+    
+    try {
+      await setCooldownBypassFee(bypassFeeBps);
+      
+      // Without decay an early exit only costs the bypass fee
+      const undecayed = await swapForHeldNft(new anchor.BN(baseValue), discountPercent, new anchor.BN(cooldownSeconds));
+      assert.equal(await clawbackOnEarlyRedeem(undecayed), 0);
+      
+      await setDiscountDecay(true);
+      assert.isTrue((await program.account.collection.fetch(collectionPda)).discountDecay);
+      
+      // A collection with the same bypass fee but no decay can't stand in for the NFT's own
+      const decoyCollectionPda = await createCollectionUnder("decay-decoy", projectId, projectPda);
+      await program.methods
+        .setCooldownBypassFee(bypassFeeBps)
+        .accounts({ ...collectionAccounts, collection: decoyCollectionPda })
+        .signers([platformAuthority])
+        .rpc();
+      const decoyed = await swapForHeldNft(new anchor.BN(baseValue), discountPercent, new anchor.BN(cooldownSeconds));
+      await expectError(
+        () => redeemWithCollection(decoyed.nftMint, decoyed.userNftAccount, decoyCollectionPda, true),
+        "CollectionNotFound",
+        "The decay schedule should come from the NFT's own collection"
+      );
+      
+      // Right after the purchase almost none of the discount has been earned
+      const immediate = await swapForHeldNft(new anchor.BN(baseValue), discountPercent, new anchor.BN(cooldownSeconds));
+      const immediateClawback = await clawbackOnEarlyRedeem(immediate);
+      assert.isAtLeast(immediateClawback, discountValue * 0.75);
+      assert.isAtMost(immediateClawback, discountValue);
+      
+      // Halfway through the cooldown about half the discount is honored
+      const halfway = await swapForHeldNft(new anchor.BN(baseValue), discountPercent, new anchor.BN(cooldownSeconds));
+      await new Promise((resolve) => setTimeout(resolve, (cooldownSeconds / 2) * 1000));
+      const halfwayClawback = await clawbackOnEarlyRedeem(halfway);
+      assert.isAtLeast(halfwayClawback, discountValue * 0.25);
+      assert.isAtMost(halfwayClawback, discountValue * 0.75);
+      assert.isBelow(halfwayClawback, immediateClawback);
+      
+      // Once the cooldown has ended the full value is paid
+      const shortCooldown = 3;
+      const served = await swapForHeldNft(new anchor.BN(baseValue), discountPercent, new anchor.BN(shortCooldown));
+      await new Promise((resolve) => setTimeout(resolve, (shortCooldown + 2) * 1000));
+      const userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(served.nftMint, served.userNftAccount);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + baseValue);
+      
+      await setDiscountDecay(false);
+      await setCooldownBypassFee(0);
+      
+      console.log(`Early redemptions forfeited ${immediateClawback} then ${halfwayClawback} of a ${discountValue} token discount`);
+    } catch (error) {
+      console.error("Error testing discount decay:", error);
+      throw error;
+    }
+  });
+
+  it("Closes consumed fusion parents and refunds their rent to the holder", async () => {
+    try {
+      const parents = [
+        await swapForHeldNft(new anchor.BN(10**9)),
+        await swapForHeldNft(new anchor.BN(10**9)),
+      ];
+      const lamports = async (address: PublicKey) => {
+        const info = await provider.connection.getAccountInfo(address);
+        return info ? info.lamports : 0;
+      };
+      
+      const parentRent = (await lamports(parents[0].nftDataPda)) + (await lamports(parents[1].nftDataPda));
+      assert.isAbove(parentRent, 0);
+      const cooldownRentBefore = await lamports(fusionCooldownPda(user.publicKey));
+      const userBefore = await lamports(user.publicKey);
+      
+      const { childNftDataPda } = await fuseNfts(parents);
+      
+      // Both parent data accounts are gone
+      for (const { nftDataPda } of parents) {
+        assert.isNull(await provider.connection.getAccountInfo(nftDataPda));
+      }
+      
+      // The user got the parents' rent back, less the rent for the accounts this fusion opened
+      const openedRent = (await lamports(childNftDataPda))
+        + (await lamports(fusionCooldownPda(user.publicKey))) - cooldownRentBefore;
+      assert.equal(await lamports(user.publicKey), userBefore + parentRent - openedRent);
+      
+      console.log(`Fusion refunded ${parentRent} lamports of parent rent`);
+    } catch (error) {
+      console.error("Error testing fusion parent closure:", error);
+      throw error;
+    }
+  });
+
+  it("Records a project's collections in its registry in creation order", async () => {
+    try {
+      const registryProjectId = "registry-project";
+      const [registryProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(registryProjectId)],
+        program.programId
+      );
+      const registryPda = collectionRegistryPda(registryProjectPda);
+      
+      await program.methods
+        .createProject(registryProjectId, projectTreasury.publicKey, null, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: registryProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const registered: PublicKey[] = [];
+      for (let i = 0; i < 3; i++) {
+        const id = "registry-collection-" + i;
+        const [pda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("collection"), Buffer.from(id)],
+          program.programId
+        );
+        await program.methods
+          .createCollection(id, registryProjectId, metadataUri, tokenMint, false)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: registryProjectPda,
+            collection: pda,
+            collectionRegistry: registryPda,
+            tokenMintAccount: tokenMint,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+        registered.push(pda);
+      }
+      
+      const registry = await program.account.collectionRegistry.fetch(registryPda);
+      assert.equal(registry.project.toString(), registryProjectPda.toString(), "Registry should belong to the project");
+      assert.deepEqual(
+        registry.collections.map((key: PublicKey) => key.toString()),
+        registered.map((key) => key.toString()),
+        "Registry should list the project's collections in creation order"
+      );
+      
+      // The account grows with each collection and stays rent exempt
+      const registryInfo = await provider.connection.getAccountInfo(registryPda);
+      const rentExempt = await provider.connection.getMinimumBalanceForRentExemption(registryInfo.data.length);
+      assert.isAtLeast(registryInfo.data.length, 8 + 32 + 4 + 32 * registered.length + 1, "Registry should have room for every key");
+      assert.isAtLeast(registryInfo.lamports, rentExempt, "Registry should stay rent exempt");
+      
+      console.log("Collection registry verified");
+    } catch (error) {
+      console.error("Error testing collection registry:", error);
+      throw error;
+    }
+  });
+
+  it("Makes no fee transfers for a swap whose fees round to zero", async () => {
+    try {
+      const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      
+      // A single base unit leaves every fee share below one token unit
+      const { signature } = await swapTokenForNft(new anchor.BN(1));
+      
+      await provider.connection.confirmTransaction(signature, "confirmed");
+      const tx = await provider.connection.getParsedTransaction(signature, "confirmed");
+      const feeTransfers = tx.meta.innerInstructions
+        .flatMap(({ instructions }) => instructions)
+        .filter((ix: any) => ix.parsed && ix.parsed.type === "transfer" && ix.parsed.info.source === lpTokenAccountPda.toString());
+      assert.equal(feeTransfers.length, 0, "A zero-fee swap should not transfer out of the pool");
+      
+      const poolAfter = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(poolAfter.pendingPlatformFees.toString(), poolBefore.pendingPlatformFees.toString());
+      assert.equal(poolAfter.pendingProjectFees.toString(), poolBefore.pendingProjectFees.toString());
+      
+      console.log("Zero-fee swap made no fee transfers");
+    } catch (error) {
+      console.error("Error testing zero-fee swap:", error);
+      throw error;
+    }
+  });
+
+  it("Redeems an NFT straight to SOL through the approved swap program", async () => {
+    const mockSwap = anchor.workspace.MockSwap as Program;
+    const setSwapProgram = (swapProgram: PublicKey | null) =>
+      program.methods
+        .setSwapProgram(swapProgram)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const baseValue = 10**9;
+    // The mock pool pays 0.01 SOL per token
+    const rate = 10_000_000;
+    
+    try {
+      const [swapPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pool"), tokenMint.toBuffer()],
+        mockSwap.programId
+      );
+      await mockSwap.methods
+        .initializePool(new anchor.BN(rate))
+        .accounts({
+          authority: user.publicKey,
+          inputMint: tokenMint,
+          pool: swapPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      const poolInputVault = await createAccount(provider.connection, user, tokenMint, swapPoolPda, Keypair.generate());
+      const poolOutputVault = await createAccount(provider.connection, user, NATIVE_MINT, swapPoolPda, Keypair.generate());
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({ fromPubkey: user.publicKey, toPubkey: poolOutputVault, lamports: LAMPORTS_PER_SOL }),
+          createSyncNativeInstruction(poolOutputVault)
+        ),
+        [user]
+      );
+      const userSolAccount = await createAccount(provider.connection, user, NATIVE_MINT, user.publicKey, Keypair.generate());
+      
+      const held = await swapForHeldNft(new anchor.BN(10**9));
+      const redeemForSol = async (swapProgram: PublicKey, amountIn: number, minSolOut: number) => {
+        const swapIx = await mockSwap.methods
+          .swap(new anchor.BN(amountIn))
+          .accounts({
+            user: user.publicKey,
+            pool: swapPoolPda,
+            userSource: userTokenAccount,
+            poolInputVault,
+            poolOutputVault,
+            userDestination: userSolAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .instruction();
+        await program.methods
+          .redeemNftForSol(held.nftMint, false, swapIx.data, new anchor.BN(minSolOut))
+          .accounts(redeemNftAccounts(held.nftMint, held.userNftAccount))
+          .remainingAccounts([
+            { pubkey: swapProgram, isWritable: false, isSigner: false },
+            { pubkey: userSolAccount, isWritable: true, isSigner: false },
+            ...swapIx.keys,
+          ])
+          .signers([user])
+          .rpc();
+      };
+      const solOut = baseValue * rate / 10**9;
+      
+      // Only the platform's approved swap program may be used
+      await expectError(
+        () => redeemForSol(mockSwap.programId, baseValue, solOut),
+        "SwapProgramNotApproved",
+        "Redeeming to SOL should fail before a swap program is approved"
+      );
+      await setSwapProgram(mockSwap.programId);
+      await expectError(
+        () => redeemForSol(TOKEN_PROGRAM_ID, baseValue, solOut),
+        "SwapProgramNotApproved",
+        "Redeeming to SOL should refuse any other swap program"
+      );
+      
+      // The swap must return the minimum SOL and may only spend the redemption payout
+      await expectError(
+        () => redeemForSol(mockSwap.programId, baseValue, solOut + 1),
+        "SlippageExceeded",
+        "Redeeming to SOL should fail when the swap returns less than the minimum"
+      );
+      await expectError(
+        () => redeemForSol(mockSwap.programId, baseValue + 1, 0),
+        "SwapOverspent",
+        "Redeeming to SOL should not swap tokens the user already held"
+      );
+      
+      const tokensBefore = await tokenBalance(userTokenAccount);
+      const solBefore = await tokenBalance(userSolAccount);
+      await redeemForSol(mockSwap.programId, baseValue, solOut);
+      
+      assert.equal(await tokenBalance(userTokenAccount), tokensBefore, "The whole payout should be swapped");
+      assert.equal(await tokenBalance(userSolAccount), solBefore + solOut, "The user should receive the swapped SOL");
+      assert.isNull(await provider.connection.getAccountInfo(held.nftDataPda), "The redeemed NFT's data should be closed");
+      
+      await setSwapProgram(null);
+      
+      console.log("NFT redeemed straight to SOL");
+    } catch (error) {
+      console.error("Error testing redemption to SOL:", error);
+      throw error;
+    }
+  });
+
+  it("Keeps legacy pools on flat redemptions until oracle pricing is enabled", async () => {
+    try {
+      const pricedProjectId = "oracle-redeem";
+      const pricedCollectionId = "oracle-redeem-collection";
+      const [pricedProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from(pricedProjectId)],
+        program.programId
+      );
+      const [pricedPoolPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_pool"), pricedProjectPda.toBuffer()],
+        program.programId
+      );
+      const [pricedCollectionPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(pricedCollectionId)],
+        program.programId
+      );
+      const [pricedUserTierPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user_tier"), pricedProjectPda.toBuffer(), user.publicKey.toBuffer()],
+        program.programId
+      );
+      const [pricedRoyaltyConfigPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("royalty_config"), pricedProjectPda.toBuffer()],
+        program.programId
+      );
+      const pricedLpTokenAccount = await getAssociatedTokenAddress(tokenMint, pricedPoolPda, true);
+      
+      await program.methods
+        .createProject(pricedProjectId, projectTreasury.publicKey, royaltyWallet.publicKey, 200)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: pricedProjectPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .setupLiquidityPool(pricedProjectId, tokenMint, new anchor.BN(0))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: pricedProjectPda,
+          liquidityPool: pricedPoolPda,
+          tokenMintAccount: tokenMint,
+          lpTokenAccount: pricedLpTokenAccount,
+          authorityTokenAccount: userTokenAccount,
+          platformTreasury: platformFeeAccount,
+          projectTreasury: projectTreasury.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await program.methods
+        .createCollection(pricedCollectionId, pricedProjectId, metadataUri, tokenMint, false)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: pricedProjectPda,
+          collection: pricedCollectionPda,
+          collectionRegistry: collectionRegistryPda(pricedProjectPda),
+          tokenMintAccount: tokenMint,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const setPrice = (priceUsd: number) =>
+        program.methods
+          .setPriceManually(pricedProjectId, new anchor.BN(priceUsd), null)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            platformConfig: platformConfigPda,
+            project: pricedProjectPda,
+            liquidityPool: pricedPoolPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const enableOracleRedemption = () =>
+        program.methods
+          .enableOracleRedemption(pricedProjectId)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: pricedProjectPda,
+            liquidityPool: pricedPoolPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const swapOnPricedPool = async () => {
+        const { nftMintKeypair, userNftAccount } = await createHeldNftMint();
+        const nftMint = nftMintKeypair.publicKey;
+        const [nftDataPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("nft_data"), nftMint.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .swapTokenForNft(pricedCollectionId, new anchor.BN(10**9), null, null, null)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            collection: pricedCollectionPda,
+            collectionStats: collectionStatsPda(pricedCollectionPda),
+            mintRateLimit: mintRateLimitPda(pricedCollectionPda),
+            project: pricedProjectPda,
+            liquidityPool: pricedPoolPda,
+            userTokenAccount: userTokenAccount,
+            lpTokenAccount: pricedLpTokenAccount,
+            tokenMint: tokenMint,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            royaltyWallet: royaltyWallet.publicKey,
+            royaltyConfig: pricedRoyaltyConfigPda,
+            collectionFeeExemption: feeExemptionPda(pricedCollectionPda),
+            userFeeExemption: feeExemptionPda(user.publicKey),
+            nftMint: nftMint,
+            nftData: nftDataPda,
+            loyaltyNftData: SystemProgram.programId,
+            loyaltyTokenAccount: SystemProgram.programId,
+            userTier: pricedUserTierPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user, nftMintKeypair])
+          .rpc();
+        return { nftMint, nftDataPda, userNftAccount };
+      };
+      const redeemOnPricedPool = (held: { nftMint: PublicKey; nftDataPda: PublicKey; userNftAccount: PublicKey }) =>
+        program.methods
+          .redeemNftForToken(held.nftMint, false)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            nftData: held.nftDataPda,
+            collection: pricedCollectionPda,
+            collectionStats: collectionStatsPda(pricedCollectionPda),
+            project: pricedProjectPda,
+            liquidityPool: pricedPoolPda,
+            nftMint: held.nftMint,
+            tokenEscrow: escrowPdas(held.nftMint).tokenEscrowPda,
+            redemptionReservation: PublicKey.findProgramAddressSync(
+              [Buffer.from("redemption_reservation"), held.nftMint.toBuffer()],
+              program.programId
+            )[0],
+            pendingClaim: PublicKey.findProgramAddressSync(
+              [Buffer.from("pending_claim"), held.nftMint.toBuffer()],
+              program.programId
+            )[0],
+            userNftAccount: held.userNftAccount,
+            userTokenAccount: userTokenAccount,
+            lpTokenAccount: pricedLpTokenAccount,
+            tokenMint: tokenMint,
+            projectTreasury: projectTreasury.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+      
+      // Migrating needs a price to fix the redemption value at
+      await expectError(
+        enableOracleRedemption,
+        "OracleNotInitialized",
+        "Enabling oracle pricing should need an oracle price"
+      );
+      
+      await setPrice(1_000_000);
+      const flatNft = await swapOnPricedPool();
+      const pricedNft = await swapOnPricedPool();
+      await transfer(provider.connection, user, userTokenAccount, pricedLpTokenAccount, user, 5 * 10**9);
+      
+      // A legacy pool keeps paying the flat base value whatever the price does
+      let pool = await program.account.liquidityPool.fetch(pricedPoolPda);
+      assert.deepEqual(pool.pricingMode, { flat: {} });
+      await setPrice(500_000);
+      let balanceBefore = await tokenBalance(userTokenAccount);
+      await redeemOnPricedPool(flatNft);
+      assert.equal(await tokenBalance(userTokenAccount), balanceBefore + 10**9, "A flat pool should pay the base value");
+      
+      // Enabling fixes the USD value of the base tokens at the current $1 price, once
+      await setPrice(1_000_000);
+      await enableOracleRedemption();
+      pool = await program.account.liquidityPool.fetch(pricedPoolPda);
+      assert.deepEqual(pool.pricingMode, { oraclePriced: {} });
+      assert.equal(pool.redemptionValueUsd.toNumber(), 1_000_000);
+      await expectError(
+        enableOracleRedemption,
+        "OracleRedemptionAlreadyEnabled",
+        "Oracle pricing should only be enabled once"
+      );
+      
+      // From then on the payout follows the price: at $0.50 the $1 value is two tokens
+      await setPrice(500_000);
+      balanceBefore = await tokenBalance(userTokenAccount);
+      await redeemOnPricedPool(pricedNft);
+      assert.equal(await tokenBalance(userTokenAccount), balanceBefore + 2 * 10**9, "An oracle-priced pool should pay the USD value");
+      
+      console.log("Redemption pricing migrated from flat to oracle-priced");
+    } catch (error) {
+      console.error("Error testing oracle-priced redemption migration:", error);
+      throw error;
+    }
+  });
+
+  it("Burns the project's buyback share of swap fees from the pool", async () => {
+    const setBuybackBurn = (buybackBurnBps: number) =>
+      program.methods
+        .setBuybackBurn(projectId, buybackBurnBps)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const tokenSupply = async () => Number((await getMint(provider.connection, tokenMint)).supply);
+    const swapAmount = 10 * 10**9;
+    
+    try {
+      await expectError(
+        () => setBuybackBurn(10001),
+        "InvalidBuybackBurnShare",
+        "A buyback share above 10000 bps should be rejected"
+      );
+      
+      // Without a buyback the whole project fee reaches the treasury and supply is untouched
+      let supplyBefore = await tokenSupply();
+      let treasuryBefore = await tokenBalance(projectTreasury.publicKey);
+      await swapTokenForNft(new anchor.BN(swapAmount));
+      const projectFee = await tokenBalance(projectTreasury.publicKey) - treasuryBefore;
+      assert.isAbove(projectFee, 0);
+      assert.equal(await tokenSupply(), supplyBefore);
+      
+      // With half routed to the buyback, that half is burned out of supply instead
+      await setBuybackBurn(5000);
+      const project = await program.account.project.fetch(projectPda);
+      assert.equal(project.buybackBurnBps, 5000);
+      supplyBefore = await tokenSupply();
+      treasuryBefore = await tokenBalance(projectTreasury.publicKey);
+      await swapTokenForNft(new anchor.BN(swapAmount));
+      const burned = Math.floor(projectFee * 5000 / 10000);
+      assert.equal(await tokenSupply(), supplyBefore - burned, "Supply should fall by the buyback share");
+      assert.equal(await tokenBalance(projectTreasury.publicKey), treasuryBefore + projectFee - burned);
+      
+      await setBuybackBurn(0);
+      
+      console.log(`Buyback burned ${burned} of a ${projectFee} token project fee`);
+    } catch (error) {
+      console.error("Error testing buyback burn:", error);
+      throw error;
+    }
+  });
+
+  it("Moves platform fees to a new treasury of the same token", async () => {
+    const setPlatformTreasury = (newTreasury: PublicKey, newTreasuryAccount: PublicKey) =>
+      program.methods
+        .setPlatformTreasury()
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          liquidityPool: liquidityPoolPda,
+          newTreasury,
+          newTreasuryAccount,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const newTreasury = Keypair.generate().publicKey;
+      const newTreasuryAccount = await createAccount(provider.connection, user, tokenMint, newTreasury, Keypair.generate());
+      
+      // A treasury for another token than the pool's would never receive these fees
+      const otherMint = await createMint(provider.connection, user, user.publicKey, null, 9);
+      const otherMintAccount = await createAccount(provider.connection, user, otherMint, newTreasury, Keypair.generate());
+      await expectError(
+        () => setPlatformTreasury(newTreasury, otherMintAccount),
+        "InvalidTreasury",
+        "A treasury of another mint should be rejected"
+      );
+      
+      // Nor can the new treasury point at a token account someone else owns
+      await expectError(
+        () => setPlatformTreasury(newTreasury, userTokenAccount),
+        "InvalidTreasury",
+        "A treasury must own the token account it shows"
+      );
+      
+      await setPlatformTreasury(newTreasury, newTreasuryAccount);
+      const platformConfig = await program.account.platformConfig.fetch(platformConfigPda);
+      assert.isTrue(platformConfig.platformTreasury.equals(newTreasury));
+      
+      // Later swaps pay the new treasury and leave the old balance where it was
+      const oldBalance = await tokenBalance(platformFeeAccount);
+      await swapTokenForNft(
+        new anchor.BN(10 * 10**9), null, null, royaltyWallet.publicKey, Keypair.generate(), null, [], [], null, newTreasuryAccount
+      );
+      assert.isAbove(await tokenBalance(newTreasuryAccount), 0, "Platform fees should reach the new treasury");
+      assert.equal(await tokenBalance(platformFeeAccount), oldBalance, "The old treasury should receive nothing more");
+      
+      await setPlatformTreasury(platformTreasury.publicKey, platformFeeAccount);
+      
+      console.log("Platform fees moved to the new treasury");
+    } catch (error) {
+      console.error("Error testing platform treasury change:", error);
+      throw error;
+    }
+  });
+
+  it("Lets a cooling NFT back an escrow but not redeem it", async () => {
+    const setEscrowCooldownPolicy = (allow: boolean) =>
+      program.methods
+        .setEscrowCooldownPolicy(allow)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          collection: collectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const collection = await program.account.collection.fetch(collectionPda);
+      assert.isTrue(collection.escrowDuringCooldown, "Collections allow escrow during cooldown by default");
+      
+      // Locking tokens against a discounted NFT is allowed while it cools down...
+      const { nftMint, nftDataPda } = await swapTokenForNft(new anchor.BN(10**9), 10, new anchor.BN(3600));
+      const { tokenEscrowPda, escrowTokenAccountPda } = await createTokenEscrow(nftMint, new anchor.BN(10**9));
+      assert.isTrue((await program.account.tokenEscrow.fetch(tokenEscrowPda)).isActive);
+      
+      // ...but taking the value back out has to wait
+      await expectError(
+        () => redeemEscrowToken(nftMint),
+        "NftInCooldown",
+        "Redeeming a cooling NFT's escrow should be rejected"
+      );
+      
+      await program.methods
+        .closeTokenEscrow(nftMint)
+        .accounts({
+          owner: user.publicKey,
+          tokenEscrow: tokenEscrowPda,
+          escrowTokenAccount: escrowTokenAccountPda,
+          ownerTokenAccount: userTokenAccount,
+          userEscrowCount: userEscrowCountPda(),
+          nftData: nftDataPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      
+      // A stricter collection blocks escrow creation during cooldown as well
+      await setEscrowCooldownPolicy(false);
+      const { nftMint: strictMint } = await swapTokenForNft(new anchor.BN(10**9), 10, new anchor.BN(3600));
+      await expectError(
+        () => createTokenEscrow(strictMint, new anchor.BN(10**9)),
+        "NftInCooldown",
+        "Escrowing a cooling NFT should be rejected when the collection disallows it"
+      );
+      
+      await setEscrowCooldownPolicy(true);
+      
+      console.log("Cooldown blocks escrow redemption but not escrow creation");
+    } catch (error) {
+      console.error("Error testing escrow cooldown policy:", error);
+      throw error;
+    }
+  });
+
+  it("Reports each item of a mixed batch close and processes the valid ones", async () => {
+    try {
+      const { nftMint: finishedMint } = await swapTokenForNft(new anchor.BN(10**9));
+      await createTokenEscrow(finishedMint, new anchor.BN(10**9));
+      await redeemEscrowToken(finishedMint);
+      const finished = escrowPdas(finishedMint);
+      
+      const { nftMint: vestingMint } = await swapTokenForNft(new anchor.BN(10**9));
+      await createTokenEscrow(vestingMint, new anchor.BN(10**9), new anchor.BN(3600));
+      const vesting = escrowPdas(vestingMint);
+      
+      // An account that isn't an escrow at all sits between the two
+      const notAnEscrow = { tokenEscrowPda: platformConfigPda, escrowTokenAccountPda: userTokenAccount };
+      
+      const batch = program.methods
+        .closeEscrowsBatch()
+        .accounts({
+          owner: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([vesting, notAnEscrow, finished].flatMap(({ tokenEscrowPda, escrowTokenAccountPda }) => [
+          { pubkey: tokenEscrowPda, isWritable: true, isSigner: false },
+          { pubkey: escrowTokenAccountPda, isWritable: true, isSigner: false },
+        ]))
+        .signers([user]);
+      
+      const errorCode = (name: string) => program.idl.errors.find((error) => error.name === name).code;
+      const { events } = await batch.simulate();
+      const results = events.filter((event) => event.name === "BatchItemResult").map((event) => event.data);
+      assert.deepEqual(
+        results.map((result) => [result.index, result.ok, result.errorCode]),
+        [
+          [0, false, errorCode("VestingPeriodActive")],
+          [1, false, errorCode("InvalidTokenEscrow")],
+          [2, true, null],
+        ]
+      );
+      
+      await batch.rpc();
+      
+      // The invalid items didn't stop the finished escrow from closing
+      assert.isNull(await provider.connection.getAccountInfo(finished.tokenEscrowPda));
+      assert.isTrue((await program.account.tokenEscrow.fetch(vesting.tokenEscrowPda)).isActive);
+      
+      console.log("Mixed batch reported per item and closed the finished escrow");
+    } catch (error) {
+      console.error("Error testing mixed batch close:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects withdrawing from an escrow token account the escrow no longer controls", async () => {
+    try {
+      const { tokenEscrowPda } = escrowPdas(tamperedEscrowNftMint);
+      const tamperedEscrow = await program.account.tokenEscrow.fetch(tokenEscrowPda);
+      assert.isTrue(tamperedEscrow.escrowTokenAccount.equals(tamperedEscrowTokenAccount));
+      assert.isFalse((await getAccount(provider.connection, tamperedEscrowTokenAccount)).owner.equals(tokenEscrowPda));
+      
+      const ownerTokenAccount = await createAccount(
+        provider.connection, user, tamperedEscrowTokenMint, tamperedEscrowOwner.publicKey, Keypair.generate()
+      );
+      const userEscrowCount = PublicKey.findProgramAddressSync(
+        [Buffer.from("user_escrow_count"), tamperedEscrowProject.toBuffer(), tamperedEscrowOwner.publicKey.toBuffer()],
+        program.programId
+      )[0];
+      
+      await expectError(
+        () => program.methods
+          .closeTokenEscrow(tamperedEscrowNftMint)
+          .accounts({
+            owner: tamperedEscrowOwner.publicKey,
+            tokenEscrow: tokenEscrowPda,
+            escrowTokenAccount: tamperedEscrowTokenAccount,
+            ownerTokenAccount,
+            userEscrowCount,
+            nftData: PublicKey.findProgramAddressSync(
+              [Buffer.from("nft_data"), tamperedEscrowNftMint.toBuffer()],
+              program.programId
+            )[0],
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([tamperedEscrowOwner])
+          .rpc(),
+        "InvalidEscrowAuthority",
+        "Closing an escrow through a token account it doesn't control should be rejected"
+      );
+      
+      // A batch close reports the escrow as failed instead of signing for it
+      const { events } = await program.methods
+        .closeEscrowsBatch()
+        .accounts({
+          owner: tamperedEscrowOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: tokenEscrowPda, isWritable: true, isSigner: false },
+          { pubkey: tamperedEscrowTokenAccount, isWritable: true, isSigner: false },
+        ])
+        .signers([tamperedEscrowOwner])
+        .simulate();
+      const result = events.find((event) => event.name === "BatchItemResult").data;
+      assert.isFalse(result.ok);
+      assert.equal(result.errorCode, program.idl.errors.find((error) => error.name === "InvalidEscrowAuthority").code);
+      
+      console.log("Escrow withdrawals require the escrow to control its token account");
+    } catch (error) {
+      console.error("Error testing escrow token account authority:", error);
+      throw error;
+    }
+  });
+
+  it("Scores fused NFTs with a custom fusion level curve", async () => {
+    const setLevelMultipliers = (levelMultipliersBps: number[]) =>
+      program.methods
+        .setFusionLevelMultipliers(levelMultipliersBps)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          fusionConfig: fusionConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    // Fuse two fresh base NFTs, returning the parents' scores and the child's
+    const fuseFreshPair = async () => {
+      const parents = [
+        await swapForHeldNft(new anchor.BN(10**9)),
+        await swapForHeldNft(new anchor.BN(10**9)),
+      ];
+      const parentScores = [];
+      for (const parent of parents) {
+        parentScores.push((await program.account.nftData.fetch(parent.nftDataPda)).rarityScore);
+      }
+      const { childNftDataPda } = await fuseNfts(parents);
+      const child = await program.account.nftData.fetch(childNftDataPda);
+      assert.equal(child.fusionLevel, 1);
+      return { parentScores, childScore: child.rarityScore };
+    };
+    
+    try {
+      for (const invalid of [[12000, 11000], [9000], [60000], new Array(11).fill(10000)]) {
+        await expectError(
+          () => setLevelMultipliers(invalid),
+          "InvalidFusionConfig",
+          `Level curve ${invalid.slice(0, 3)} should be rejected`
+        );
+      }
+      
+      // Fused NFTs carry no traits, so their score is the 10-point base times the level multiplier,
+      // plus a boost from the parents
+      const defaults = await fuseFreshPair();
+      await setLevelMultipliers([20000, 30000]);
+      const custom = await fuseFreshPair();
+      assert.deepEqual(custom.parentScores, defaults.parentScores);
+      assert.equal(defaults.childScore - Math.floor(10 * 11000 / 10000), custom.childScore - Math.floor(10 * 20000 / 10000));
+      assert.isAbove(custom.childScore, defaults.childScore);
+      
+      const fusionConfig = await program.account.fusionConfig.fetch(fusionConfigPda);
+      assert.deepEqual(fusionConfig.levelMultipliersBps, [20000, 30000]);
+      
+      await setLevelMultipliers([]);
+      
+      console.log("Fused NFT rarity follows the configured level curve");
+    } catch (error) {
+      console.error("Error testing fusion level curve:", error);
+      throw error;
+    }
+  });
+
+  it("Reclaims rent from the data of an NFT redeemed to the project", async () => {
+    const closeProjectOwnedNft = (nftMint: PublicKey, nftData: PublicKey) =>
+      program.methods
+        .closeProjectOwnedNft(nftMint)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          nftData,
+          listing: PublicKey.findProgramAddressSync(
+            [Buffer.from("listing"), nftMint.toBuffer()],
+            program.programId
+          )[0],
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const { nftMint, nftDataPda } = await swapTokenForNft(new anchor.BN(10**9));
+      await createTokenEscrow(nftMint, new anchor.BN(10**9));
+      
+      // The user's NFT can't be closed by the project
+      await expectError(
+        () => closeProjectOwnedNft(nftMint, nftDataPda),
+        "NftNotRedeemed",
+        "Closing an NFT the project doesn't own should be rejected"
+      );
+      
+      // Redeeming the escrow hands the NFT data to the project
+      await redeemEscrowToken(nftMint);
+      const nftData = await program.account.nftData.fetch(nftDataPda);
+      assert.isTrue(nftData.owner.equals(projectPda));
+      
+      const rent = await provider.connection.getBalance(nftDataPda);
+      const projectBalanceBefore = await provider.connection.getBalance(projectPda);
+      await closeProjectOwnedNft(nftMint, nftDataPda);
+      
+      assert.isNull(await provider.connection.getAccountInfo(nftDataPda));
+      assert.equal(await provider.connection.getBalance(projectPda), projectBalanceBefore + rent);
+      
+      console.log("Project-owned NFT data closed and its rent returned to the project");
+    } catch (error) {
+      console.error("Error testing project-owned NFT cleanup:", error);
+      throw error;
+    }
+  });
+
+  it("Normalizes manual prices entered with their own decimals", async () => {
+    const setPrice = (price: number, priceDecimals: number | null) =>
+      program.methods
+        .setPriceManually(projectId, new anchor.BN(price), priceDecimals)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const storedPrice = async () =>
+      (await program.account.liquidityPool.fetch(liquidityPoolPda)).oraclePriceUsd.toNumber();
+    const setManualPriceBounds = (min: anchor.BN | null, max: anchor.BN | null) =>
+      program.methods
+        .setManualPriceBounds(min, max)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const previousPrice = await storedPrice();
+      
+      // $5 as whole dollars, as cents and pre-scaled all store the same price
+      await setPrice(5, 0);
+      assert.equal(await storedPrice(), 5_000_000);
+      await setPrice(500, 2);
+      assert.equal(await storedPrice(), 5_000_000);
+      await setPrice(5_000_000, null);
+      assert.equal(await storedPrice(), 5_000_000);
+      
+      // Extra decimals are scaled down
+      await setPrice(5_250_000_000, 9);
+      assert.equal(await storedPrice(), 5_250_000);
+      
+      // Bounds apply after normalization: an unscaled 5 passed without decimals is $0.000005
+      await setManualPriceBounds(new anchor.BN(1_000_000), null);
+      await expectError(
+        () => setPrice(5, null),
+        "InvalidOraclePrice",
+        "An unscaled price below the bounds should be rejected"
+      );
+      await setPrice(5, 0);
+      assert.equal(await storedPrice(), 5_000_000);
+      
+      await setManualPriceBounds(null, null);
+      await setPrice(previousPrice, null);
+      
+      console.log("Manual prices are normalized to the USD scale before bounds checks");
+    } catch (error) {
+      console.error("Error testing manual price normalization:", error);
+      throw error;
+    }
+  });
+
+  it("Keeps the redemption LP tax in the pool", async () => {
+    const baseValue = 10**9;
+    
+    async function setRedemptionLpTax(bps: number) {
+      await program.methods
+        .setRedemptionLpTax(projectId, bps)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    }
+    
+    try {
+      // A 10% tax holds back a tenth of the base value from the redeemer
+      await setRedemptionLpTax(1000);
+      const liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(liquidityPool.redemptionLpTaxBps, 1000);
+      
+      const taxed = await swapForHeldNft(new anchor.BN(10**9));
+      const userBefore = await tokenBalance(userTokenAccount);
+      const lpBefore = await tokenBalance(lpTokenAccountPda);
+      await redeemNftForToken(taxed.nftMint, taxed.userNftAccount);
+      
+      const tax = baseValue / 10;
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + baseValue - tax);
+      assert.equal(await tokenBalance(lpTokenAccountPda), lpBefore - (baseValue - tax));
+      
+      // The tax is a share of the redemption, so it can't exceed 100%
+      await expectError(
+        () => setRedemptionLpTax(10001),
+        "InvalidRedemptionTax",
+        "A redemption tax above 100% should be rejected"
+      );
+      
+      await setRedemptionLpTax(0);
+      
+      console.log("Redemption LP tax stayed in the pool");
+    } catch (error) {
+      console.error("Error testing the redemption LP tax:", error);
+      throw error;
+    }
+  });
+
+  it("Dry-runs a mint with traits without creating anything", async () => {
+    try {
+      const traitConfigPda = collectionTraitConfigPda(collectionPda);
+      const [framePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("trait_type"), collectionPda.toBuffer(), Buffer.from("Frame")],
+        program.programId
+      );
+      
+      // A required trait whose Steel value has no supply left
+      await program.methods
+        .initializeTraitType("Frame", true, [
+          { name: "Oak", uriPostfix: "oak", rarityWeight: 80, availableSupply: null, usedSupply: new anchor.BN(0) },
+          { name: "Steel", uriPostfix: "steel", rarityWeight: 20, availableSupply: new anchor.BN(0), usedSupply: new anchor.BN(0) },
+        ])
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          traitType: framePda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const validateMintConfig = (providedTraits: { traitType: string; value: string }[] | null) =>
+        program.methods
+          .validateMintConfig(providedTraits, new anchor.BN(0))
+          .accounts({
+            user: user.publicKey,
+            collection: collectionPda,
+            collectionTraitConfig: traitConfigPda,
+            randomnessRequest: randomnessRequestPda(user.publicKey, new anchor.BN(0)),
+          })
+          .remainingAccounts([{ pubkey: framePda, isWritable: false, isSigner: false }])
+          .signers([user]);
+      
+      // A valid selection reports the URI the mint would get
+      const { events } = await validateMintConfig([{ traitType: "Frame", value: "Oak" }]).simulate();
+      const validated = events.find((event) => event.name === "MintConfigValidated").data;
+      assert.equal(validated.metadataUri, "https://example.com/traits/oak");
+      assert.deepEqual(validated.traits.map((attribute) => attribute.value), ["Oak"]);
+      
+      // Running it for real uses no supply
+      await validateMintConfig([{ traitType: "Frame", value: "Oak" }]).rpc();
+      const frame = await program.account.traitType.fetch(framePda);
+      assert.equal(frame.traitValues[0].usedSupply.toNumber(), 0);
+      
+      // Each misconfiguration fails with the error the mint would
+      const failures: [{ traitType: string; value: string }[] | null, string][] = [
+        [[], "RequiredTraitMissing"],
+        [[{ traitType: "Frame", value: "Steel" }], "TraitSupplyExceeded"],
+        [[{ traitType: "Frame", value: "Glass" }], "TraitValueNotFound"],
+        [[{ traitType: "Frame", value: "Oak" }, { traitType: "Mood", value: "Calm" }], "TraitTypeNotFound"],
+        [null, "AutoGenerationDisabled"],
+      ];
+      for (const [providedTraits, expectedError] of failures) {
+        await expectError(
+          () => validateMintConfig(providedTraits).rpc(),
+          expectedError,
+          `Dry run should fail with ${expectedError}`
+        );
+      }
+      
+      console.log("Mint config dry run surfaced each misconfiguration");
+    } catch (error) {
+      console.error("Error testing the mint config dry run:", error);
+      throw error;
+    }
+  });
+
+  it("Mints for free only from collections without a token", async () => {
+    try {
+      const freeCollectionId = "free-collection";
+      const [freeCollectionPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collection"), Buffer.from(freeCollectionId)],
+        program.programId
+      );
+      
+      await program.methods
+        .createCollection(freeCollectionId, projectId, metadataUri, null, false)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: projectPda,
+          collection: freeCollectionPda,
+          collectionRegistry: collectionRegistryPda(projectPda),
+          tokenMintAccount: SystemProgram.programId,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      const setFreeMintAllowed = (collection: PublicKey, allowed: boolean) =>
+        program.methods
+          .setFreeMintAllowed(allowed)
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      
+      const mintFreeNft = async (mintCollectionId: string, mintCollection: PublicKey) => {
+        const nftMintKeypair = Keypair.generate();
+        await createMint(provider.connection, user, user.publicKey, null, 0, nftMintKeypair);
+        const [nftDataPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("nft_data"), nftMintKeypair.publicKey.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .mintFreeNft(mintCollectionId, metadataUri)
+          .accounts({
+            user: user.publicKey,
+            collection: mintCollection,
+            collectionStats: collectionStatsPda(mintCollection),
+            mintRateLimit: mintRateLimitPda(mintCollection),
+            project: projectPda,
+            nftMint: nftMintKeypair.publicKey,
+            nftData: nftDataPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user, nftMintKeypair])
+          .rpc();
+        return nftDataPda;
+      };
+      
+      // A tokenless collection still has to opt in to free mints
+      await expectError(
+        () => mintFreeNft(freeCollectionId, freeCollectionPda),
+        "FreeMintNotAllowed",
+        "A collection that hasn't allowed free mints should refuse them"
+      );
+      
+      await setFreeMintAllowed(freeCollectionPda, true);
+      const nftDataPda = await mintFreeNft(freeCollectionId, freeCollectionPda);
+      const nftData = await program.account.nftData.fetch(nftDataPda);
+      assert.equal(nftData.owner.toString(), user.publicKey.toString());
+      assert.equal(nftData.collection.toString(), freeCollectionPda.toString());
+      
+      // Swapping is a token operation, which a tokenless collection can't serve
+      const swapMintKeypair = Keypair.generate();
+      await expectError(
+        () => program.methods
+          .swapTokenForNft(freeCollectionId, new anchor.BN(10**9), null, null, null)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            collection: freeCollectionPda,
+            collectionStats: collectionStatsPda(freeCollectionPda),
+            mintRateLimit: mintRateLimitPda(freeCollectionPda),
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            userTokenAccount: userTokenAccount,
+            lpTokenAccount: lpTokenAccountPda,
+            tokenMint: tokenMint,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            royaltyWallet: royaltyWallet.publicKey,
+            royaltyConfig: royaltyConfigPda,
+            collectionFeeExemption: feeExemptionPda(freeCollectionPda),
+            userFeeExemption: feeExemptionPda(user.publicKey),
+            nftMint: swapMintKeypair.publicKey,
+            nftData: PublicKey.findProgramAddressSync(
+              [Buffer.from("nft_data"), swapMintKeypair.publicKey.toBuffer()],
+              program.programId
+            )[0],
+            loyaltyNftData: SystemProgram.programId,
+            loyaltyTokenAccount: SystemProgram.programId,
+            userTier: userTierPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user, swapMintKeypair])
+          .rpc(),
+        "NoTokenMintSpecified",
+        "Swaps from a tokenless collection should be rejected"
+      );
+      
+      // A token-gated collection can neither opt in nor mint for free, and still mints through swaps
+      await expectError(
+        () => setFreeMintAllowed(collectionPda, true),
+        "FreeMintNotAllowed",
+        "A collection with a token should not allow free mints"
+      );
+      await expectError(
+        () => mintFreeNft(collectionId, collectionPda),
+        "FreeMintNotAllowed",
+        "A collection with a token should refuse free mints"
+      );
+      const { nftDataPda: swappedNftDataPda } = await swapTokenForNft(new anchor.BN(10**9));
+      const swapped = await program.account.nftData.fetch(swappedNftDataPda);
+      assert.equal(swapped.collection.toString(), collectionPda.toString());
+      
+      console.log("Free mints served only the tokenless collection");
+    } catch (error) {
+      console.error("Error testing free mints:", error);
+      throw error;
+    }
+  });
+
+  it("Refuses mint_nft for token collections, ones not opted in and other projects' collections", async () => {
+    try {
+      
+      // mint_nft takes no payment, so a collection with a token can't be minted from through it
+      await expectError(
+        () => mintNftInto(collectionId, collectionPda),
+        "FreeMintNotAllowed",
+        "mint_nft on a collection with a token should be rejected"
+      );
+      
+      // A tokenless collection must still opt in to free mints
+      const closedCollectionId = "mint-nft-closed-collection";
+      const closedCollectionPda = await createFreeMintCollection(closedCollectionId, projectId, projectPda, false);
+      await expectError(
+        () => mintNftInto(closedCollectionId, closedCollectionPda),
+        "FreeMintNotAllowed",
+        "mint_nft on a collection that hasn't allowed free mints should be rejected"
+      );
+      
+      // Another project's collection can't be minted from under this project
+      const rivalProjectId = "mint-nft-rival-project";
+      const { rivalProjectPda } = await createRivalProject(rivalProjectId);
+      const rivalCollectionId = "mint-nft-rival-collection";
+      const rivalCollectionPda = await createFreeMintCollection(rivalCollectionId, rivalProjectId, rivalProjectPda);
+      await expectError(
+        () => mintNftInto(rivalCollectionId, rivalCollectionPda, null, [], projectPda),
+        "CollectionProjectMismatch",
+        "mint_nft should reject a collection from another project"
+      );
+      
+      // An opted-in tokenless collection of the project mints
+      const openCollectionId = "mint-nft-open-collection";
+      const openCollectionPda = await createFreeMintCollection(openCollectionId);
+      const nftData = await program.account.nftData.fetch(await mintNftInto(openCollectionId, openCollectionPda));
+      assert.equal(nftData.owner.toString(), user.publicKey.toString());
+      assert.equal(nftData.collection.toString(), openCollectionPda.toString());
+      
+      console.log("mint_nft only mints from the project's opted-in tokenless collections");
+    } catch (error) {
+      console.error("Error testing mint_nft collection checks:", error);
+      throw error;
+    }
+  });
+
+  it("Lifts a redemption lock on the next fresh price update", async () => {
+    const lockAccounts = {
+      authority: platformAuthority.publicKey,
+      platformConfig: platformConfigPda,
+      project: projectPda,
+      liquidityPool: liquidityPoolPda,
+    };
+    const setAutoRecovery = (enabled: boolean) =>
+      program.methods
+        .setRedemptionAutoRecovery(projectId, enabled)
+        .accounts(lockAccounts)
+        .signers([platformAuthority]);
+    
+    try {
+      const previousPrice = (await program.account.liquidityPool.fetch(liquidityPoolPda)).oraclePriceUsd;
+      const setPrice = () =>
+        program.methods
+          .setPriceManually(projectId, previousPrice, null)
+          .accounts({ ...lockAccounts, systemProgram: SystemProgram.programId })
+          .signers([platformAuthority]);
+      const lock = () =>
+        program.methods
+          .lockRedemption(projectId)
+          .accounts(lockAccounts)
+          .signers([platformAuthority])
+          .rpc();
+      
+      // The lock records when it began
+      await lock();
+      let liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isTrue(liquidityPool.redemptionLocked);
+      assert.isTrue(liquidityPool.autoRecoverRedemptionLock);
+      const lockedSince = liquidityPool.redemptionLockedSince.toNumber();
+      assert.isAbove(lockedSince, 0);
+      
+      // A fresh update lifts it and reports how long it held
+      const { events } = await setPrice().simulate();
+      const recovered = events.find((event) => event.name === "RedemptionLockRecovered").data;
+      assert.equal(recovered.lockedSince.toNumber(), lockedSince);
+      assert.isAtLeast(recovered.recoveredAt.toNumber(), lockedSince);
+      
+      await setPrice().rpc();
+      liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isFalse(liquidityPool.redemptionLocked);
+      assert.isNull(liquidityPool.redemptionLockedSince);
+      
+      // With auto-recovery off the lock outlasts fresh updates until it is forced open
+      await setAutoRecovery(false).rpc();
+      await lock();
+      await setPrice().rpc();
+      liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isTrue(liquidityPool.redemptionLocked);
+      assert.isNotNull(liquidityPool.redemptionLockedSince);
+      
+      await program.methods
+        .forceUnlockRedemption(projectId)
+        .accounts(lockAccounts)
+        .signers([platformAuthority])
+        .rpc();
+      liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isFalse(liquidityPool.redemptionLocked);
+      assert.isNull(liquidityPool.redemptionLockedSince);
+      
+      await setAutoRecovery(true).rpc();
+      
+      console.log("Fresh price update lifted the redemption lock");
+    } catch (error) {
+      console.error("Error testing redemption lock auto-recovery:", error);
+      throw error;
+    }
+  });
+
+  it("Mints the configured number of children from one fusion", async () => {
+    const setOutputCount = (outputCount: number) =>
+      program.methods
+        .setFusionOutputCount(outputCount)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          collection: collectionPda,
+          project: projectPda,
+          fusionConfig: fusionConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const freshParents = async () => [
+      await swapForHeldNft(new anchor.BN(10**9)),
+      await swapForHeldNft(new anchor.BN(10**9)),
+    ];
+    
+    try {
+      for (const invalid of [0, 5]) {
+        await expectError(
+          () => setOutputCount(invalid),
+          "InvalidFusionConfig",
+          `Output count ${invalid} should be rejected`
+        );
+      }
+      
+      // A single-output fusion mints one child carrying the whole fused rarity
+      const single = await fuseNfts(await freshParents());
+      const singleChild = await program.account.nftData.fetch(single.childNftDataPda);
+      assert.equal(single.extraChildNftDataPdas.length, 0);
+      
+      // Three outputs split that rarity between three distinct children of the same parents
+      await setOutputCount(3);
+      const parents = await freshParents();
+      const multi = await fuseNfts(parents, metadataUri, user, 2);
+      const children = [];
+      for (const childNftDataPda of [multi.childNftDataPda, ...multi.extraChildNftDataPdas]) {
+        children.push(await program.account.nftData.fetch(childNftDataPda));
+      }
+      assert.equal(new Set(children.map((child) => child.mint.toString())).size, 3);
+      for (const child of children) {
+        assert.equal(child.owner.toString(), user.publicKey.toString());
+        assert.equal(child.collection.toString(), collectionPda.toString());
+        assert.equal(child.fusionLevel, singleChild.fusionLevel);
+        assert.equal(child.parentNfts.length, parents.length);
+      }
+      const scores = children.map((child) => child.rarityScore);
+      assert.equal(scores.reduce((sum, score) => sum + score, 0), singleChild.rarityScore);
+      assert.isAtMost(Math.max(...scores) - Math.min(...scores), 1);
+      
+      // A fusion that leaves out a configured child's accounts is rejected
+      await expectError(
+        () => fuseNfts(await freshParents(), metadataUri, user, 1),
+        "FusionOutputMismatch",
+        "A fusion missing child accounts should be rejected"
+      );
+      
+      await setOutputCount(1);
+      
+      console.log(`Fusion minted children with rarity ${scores.join(", ")}`);
+    } catch (error) {
+      console.error("Error testing multi-output fusion:", error);
+      throw error;
+    }
+  });
+
+  it("Locks secondary sales of a collection until the lock window ends", async () => {
+    const setSecondaryLock = (lockUntil: number | null) =>
+      program.methods
+        .setSecondaryLock(lockUntil === null ? null : new anchor.BN(lockUntil))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          collection: collectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const reprice = (askingPrice: number) =>
+      program.methods
+        .updateListingPrice(new anchor.BN(askingPrice))
+        .accounts({
+          owner: lockedListingOwner.publicKey,
+          listing: lockedListingPda,
+          collection: collectionPda,
+        })
+        .signers([lockedListingOwner])
+        .rpc();
+    
+    try {
+      const now = await provider.connection.getBlockTime(await provider.connection.getSlot());
+      await setSecondaryLock(now + 3600);
+      
+      // Only the project authority can lock a collection
+      await expectError(
+        () => program.methods
+          .setSecondaryLock(new anchor.BN(now + 3600))
+          .accounts({
+            authority: user.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([user])
+          .rpc(),
+        "Unauthorized",
+        "Non-authority lock should be rejected"
+      );
+      
+      // Listings can't be repriced during the lock
+      await expectError(
+        () => reprice(2 * 10**9),
+        "SecondaryMarketLocked",
+        "Repricing during the lock should be rejected"
+      );
+      
+      // Primary swaps and redemptions are unaffected
+      const { nftMint, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+      await redeemNftForToken(nftMint, userNftAccount);
+      
+      // A lock that has already ended doesn't block trading
+      await setSecondaryLock(now - 60);
+      await reprice(2 * 10**9);
+      
+      await setSecondaryLock(null);
+      await reprice(3 * 10**9);
+      const listing = await program.account.nftListing.fetch(lockedListingPda);
+      assert.equal(listing.askingPrice.toString(), (3 * 10**9).toString());
+      assert.isNull((await program.account.collection.fetch(collectionPda)).secondaryLockUntil);
+    } catch (error) {
+      await setSecondaryLock(null);
+      console.error("Error testing the secondary-sale lock:", error);
+      throw error;
+    }
+  });
+
+  it("Rolls fusion success from the base success rate", async () => {
+    const burnPercent = 10;
+    const updateFusionAccounts = {
+      authority: platformAuthority.publicKey,
+      collection: collectionPda,
+      project: projectPda,
+      fusionConfig: fusionConfigPda,
+    };
+    const setSuccessRate = (rate: number) =>
+      program.methods
+        .setFusionSuccessRate(rate)
+        .accounts(updateFusionAccounts)
+        .signers([platformAuthority])
+        .rpc();
+    const setFusionBurn = (percent: number) =>
+      program.methods
+        .setFusionBurn(percent, { burn: {} })
+        .accounts(updateFusionAccounts)
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      await expectError(
+        () => setSuccessRate(101),
+        "InvalidFusionConfig",
+        "A success rate above 100 should be rejected"
+      );
+      
+      const parents = [];
+      let combinedValue = 0;
+      for (let i = 0; i < 2; i++) {
+        const { nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+        const { rarityScore } = await program.account.nftData.fetch(nftDataPda);
+        combinedValue += Math.floor(10**9 * (100 + rarityScore) / 100);
+        parents.push({ nftDataPda, userNftAccount });
+      }
+      const burnAmount = Math.floor(combinedValue * burnPercent / 100);
+      
+      // A fusion that can't succeed still takes the burn, but keeps the parents and mints nothing
+      await setFusionBurn(burnPercent);
+      await setSuccessRate(0);
+      const failedEvents = [];
+      const listener = program.addEventListener("FusionFailed", (event) => failedEvents.push(event));
+      let userBefore = await tokenBalance(userTokenAccount);
+      const failed = await fuseNfts(parents);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore - burnAmount);
+      assert.isNull(await provider.connection.getAccountInfo(failed.childNftDataPda));
+      for (const { nftDataPda } of parents) {
+        assert.isNull((await program.account.nftData.fetch(nftDataPda)).fusedInto);
+      }
+      
+      // Give the websocket subscription time to deliver the logs
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+      await program.removeEventListener(listener);
+      assert.equal(failedEvents.length, 1);
+      assert.equal(failedEvents[0].burnAmount.toString(), burnAmount.toString());
+      assert.equal(failedEvents[0].baseSuccessRate, 0);
+      assert.equal(failedEvents[0].parentNfts.length, 2);
+      
+      // The kept parents can fuse again, and at a rate of 100 the fusion always succeeds
+      await setSuccessRate(100);
+      userBefore = await tokenBalance(userTokenAccount);
+      const fused = await fuseNfts(parents);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore - burnAmount);
+      assert.equal((await program.account.nftData.fetch(fused.childNftDataPda)).parentNfts.length, 2);
+      for (const { nftDataPda } of parents) {
+        assert.isNull(await provider.connection.getAccountInfo(nftDataPda));
+      }
+      
+      await setFusionBurn(0);
+    } catch (error) {
+      await setSuccessRate(100);
+      await setFusionBurn(0);
+      console.error("Error testing the fusion success roll:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects duplicate projects and royalties of 100% or more", async () => {
+    const createProject = (id: string, royaltyBasisPoints: number) =>
+      program.methods
+        .createProject(id, projectTreasury.publicKey, null, royaltyBasisPoints)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: PublicKey.findProgramAddressSync(
+            [Buffer.from("project"), Buffer.from(id)],
+            program.programId
+          )[0],
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      // A project id can only be created once
+      await expectError(
+        () => createProject(projectId, 200),
+        "already in use",
+        "Creating an existing project should be rejected"
+      );
+      
+      await expectError(
+        () => createProject("full-royalty-project", 10000),
+        "InvalidRoyaltyFee",
+        "A 100% royalty should be rejected"
+      );
+      
+      await createProject("fresh-project", 9999);
+      const [freshProjectPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("project"), Buffer.from("fresh-project")],
+        program.programId
+      );
+      const project = await program.account.project.fetch(freshProjectPda);
+      assert.equal(project.authority.toString(), platformAuthority.publicKey.toString());
+      assert.isNull(project.royaltyWallet);
+      assert.equal(project.feeDiscountTiers.length, 0);
+      assert.equal(project.accruedRoyalties.toNumber(), 0);
+    } catch (error) {
+      console.error("Error testing project creation:", error);
+      throw error;
+    }
+  });
+
+  it("Updates the platform fee under the platform authority", async () => {
+    const updatePlatformFee = (bps: number, authority: Keypair = platformAuthority) =>
+      program.methods
+        .updatePlatformFee(bps)
+        .accounts({
+          authority: authority.publicKey,
+          platformConfig: platformConfigPda,
+        })
+        .signers([authority])
+        .rpc();
+    
+    try {
+      await expectError(
+        () => updatePlatformFee(300, user),
+        "Unauthorized",
+        "Only the platform authority can change the fee"
+      );
+      
+      await expectError(() => updatePlatformFee(10000), "InvalidPlatformFee", "A 100% platform fee should be rejected");
+      
+      await updatePlatformFee(300);
+      assert.equal((await program.account.platformConfig.fetch(platformConfigPda)).platformFeeBasisPoints, 300);
+      
+      // The platform config can only be initialized once
+      await expectError(
+        () => program.methods
+          .initializePlatform(100, user.publicKey)
+          .accounts({
+            authority: user.publicKey,
+            platformConfig: platformConfigPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc(),
+        "already in use",
+        "Re-initializing the platform should be rejected"
+      );
+      
+      await updatePlatformFee(500);
+    } catch (error) {
+      await updatePlatformFee(500);
+      console.error("Error testing platform fee updates:", error);
+      throw error;
+    }
+  });
+
+  it("Lists, sells and cancels NFT listings", async () => {
+    try {
+      const buyer = Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(buyer.publicKey, 10 * LAMPORTS_PER_SOL)
+      );
+      const buyerTokenAccount = (await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        buyer,
+        tokenMint,
+        buyer.publicKey
+      )).address;
+      await transfer(provider.connection, user, userTokenAccount, buyerTokenAccount, user, 10 * 10**9);
+      
+      const { nftMint, nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+      const buyerNftAccount = (await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        buyer,
+        nftMint,
+        buyer.publicKey
+      )).address;
+      const [listingPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("listing"), nftMint.toBuffer()],
+        program.programId
+      );
+      const [listingVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("listing_vault"), nftMint.toBuffer()],
+        program.programId
+      );
+      const [buyerTierPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user_tier"), projectPda.toBuffer(), buyer.publicKey.toBuffer()],
+        program.programId
+      );
+      
+      const createListing = (owner: Keypair, ownerNftAccount: PublicKey, askingPrice: number) =>
+        program.methods
+          .createListing(new anchor.BN(askingPrice), null, null)
+          .accounts({
+            owner: owner.publicKey,
+            collection: collectionPda,
+            nftMint,
+            nftData: nftDataPda,
+            listing: listingPda,
+            listingVault: listingVaultPda,
+            ownerNftAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .signers([owner])
+          .rpc();
+      const cancelListing = (owner: Keypair, ownerNftAccount: PublicKey) =>
+        program.methods
+          .cancelListing()
+          .accounts({
+            owner: owner.publicKey,
+            listing: listingPda,
+            nftData: nftDataPda,
+            listingVault: listingVaultPda,
+            ownerNftAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([owner])
+          .rpc();
+      const buyListing = () =>
+        program.methods
+          .buyListing()
+          .accounts({
+            buyer: buyer.publicKey,
+            platformConfig: platformConfigPda,
+            listing: listingPda,
+            collection: collectionPda,
+            collectionStats: collectionStatsPda(collectionPda),
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            nftData: nftDataPda,
+            listingVault: listingVaultPda,
+            buyerNftAccount,
+            buyerTokenAccount,
+            sellerTokenAccount: userTokenAccount,
+            seller: user.publicKey,
+            lpTokenAccount: lpTokenAccountPda,
+            tokenMint,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            royaltyWallet: royaltyWallet.publicKey,
+            royaltyConfig: royaltyConfigPda,
+            collectionFeeExemption: feeExemptionPda(collectionPda),
+            userFeeExemption: feeExemptionPda(buyer.publicKey),
+            userTier: buyerTierPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer]);
+      
+      // Listing moves the NFT into the listing's vault and marks it as referenced
+      await createListing(user, userNftAccount, 10**9);
+      assert.equal(await tokenBalance(listingVaultPda), 1);
+      assert.equal(await tokenBalance(userNftAccount), 0);
+      assert.equal((await program.account.nftData.fetch(nftDataPda)).referenceCount, 1);
+      
+      await expectError(
+        () => createListing(user, userNftAccount, 10**9),
+        "ListingAlreadyExists",
+        "A second listing of the same NFT should be rejected"
+      );
+      
+      await expectError(
+        () => cancelListing(buyer, buyerNftAccount),
+        "UnauthorizedListingOperation",
+        "Only the seller can cancel a listing"
+      );
+      
+      // Buying charges the current asking price, split between fees and the seller
+      const price = 2 * 10**9;
+      await program.methods
+        .updateListingPrice(new anchor.BN(price))
+        .accounts({ owner: user.publicKey, listing: listingPda, collection: collectionPda })
+        .signers([user])
+        .rpc();
+      const { events } = await buyListing().simulate();
+      const sale = events.find((event) => event.name === "ListingSold").data;
+      assert.equal(sale.price.toNumber(), price);
+      assert.equal(sale.platformFee.toNumber() + sale.royaltyFee.toNumber() + sale.sellerAmount.toNumber(), price);
+      assert.isTrue(sale.buyer.equals(buyer.publicKey));
+      
+      const buyerBefore = await tokenBalance(buyerTokenAccount);
+      const sellerBefore = await tokenBalance(userTokenAccount);
+      await buyListing().rpc();
+      assert.equal(await tokenBalance(buyerTokenAccount), buyerBefore - price);
+      assert.equal(await tokenBalance(userTokenAccount), sellerBefore + sale.sellerAmount.toNumber());
+      assert.equal(await tokenBalance(buyerNftAccount), 1);
+      assert.isNull(await provider.connection.getAccountInfo(listingVaultPda));
+      
+      const nftData = await program.account.nftData.fetch(nftDataPda);
+      assert.isTrue(nftData.owner.equals(buyer.publicKey));
+      assert.equal(nftData.referenceCount, 0);
+      assert.isFalse((await program.account.nftListing.fetch(listingPda)).isActive);
+      
+      // The new owner can list the NFT again, and cancelling returns it
+      await createListing(buyer, buyerNftAccount, 3 * 10**9);
+      assert.isTrue((await program.account.nftListing.fetch(listingPda)).owner.equals(buyer.publicKey));
+      await cancelListing(buyer, buyerNftAccount);
+      assert.equal(await tokenBalance(buyerNftAccount), 1);
+      assert.equal((await program.account.nftData.fetch(nftDataPda)).referenceCount, 0);
+      assert.isFalse((await program.account.nftListing.fetch(listingPda)).isActive);
+      
+      // No listings while the collection's secondary market is locked
+      const setSecondaryLock = (lockUntil: number | null) =>
+        program.methods
+          .setSecondaryLock(lockUntil === null ? null : new anchor.BN(lockUntil))
+          .accounts({
+            authority: platformAuthority.publicKey,
+            project: projectPda,
+            collection: collectionPda,
+          })
+          .signers([platformAuthority])
+          .rpc();
+      const now = await provider.connection.getBlockTime(await provider.connection.getSlot());
+      await setSecondaryLock(now + 3600);
+      await expectError(
+        () => createListing(buyer, buyerNftAccount, 10**9),
+        "SecondaryMarketLocked",
+        "Listing during the secondary-sale lock should be rejected"
+      );
+      await setSecondaryLock(null);
+    } catch (error) {
+      console.error("Error testing listings:", error);
+      throw error;
+    }
+  });
+
+  it("Locks redemptions when the Pyth feed's publish time is stale", async () => {
+    const priceAccounts = {
+      authority: platformAuthority.publicKey,
+      platformConfig: platformConfigPda,
+      project: projectPda,
+      liquidityPool: liquidityPoolPda,
+      systemProgram: SystemProgram.programId,
+    };
+    
+    try {
+      const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isFalse(poolBefore.redemptionLocked);
+      
+      await pinPythPriceFeed(stalePythAccount);
+      await program.methods
+        .updatePriceFromPyth(projectId)
+        .accounts({ ...priceAccounts, pythPriceAccount: stalePythAccount })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // The stale price is not taken, and redemptions stay locked until a fresh one arrives
+      let liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isTrue(liquidityPool.redemptionLocked);
+      assert.isNotNull(liquidityPool.redemptionLockedSince);
+      assert.equal(liquidityPool.oraclePriceUsd.toString(), poolBefore.oraclePriceUsd.toString());
+      assert.equal(
+        liquidityPool.oraclePriceLastUpdate.toString(),
+        poolBefore.oraclePriceLastUpdate.toString()
+      );
+      
+      await program.methods
+        .setPriceManually(projectId, poolBefore.oraclePriceUsd, null)
+        .accounts(priceAccounts)
+        .signers([platformAuthority])
+        .rpc();
+      liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.isFalse(liquidityPool.redemptionLocked);
+      
+      console.log("Stale Pyth feed locked redemptions without replacing the price");
+    } catch (error) {
+      console.error("Error testing Pyth staleness:", error);
+      throw error;
+    }
+  });
+
+  it("Ages oracle prices against the pool's own staleness window", async () => {
+    const setStaleness = (maxOracleStaleness: anchor.BN, authority: Keypair = platformAuthority) =>
+      program.methods
+        .setOracleStaleness(projectId, maxOracleStaleness)
+        .accounts({
+          authority: authority.publicKey,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+        })
+        .signers([authority])
+        .rpc();
+    
+    try {
+      const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(poolBefore.maxOracleStaleness.toNumber(), 3600);
+      
+      // Only the project authority picks the window, and it can't be negative
+      await expectError(
+        () => setStaleness(new anchor.BN(60), user),
+        "Unauthorized",
+        "Only the project authority should set the staleness window"
+      );
+      
+      await expectError(
+        () => setStaleness(new anchor.BN(-1)),
+        "InvalidOracleStaleness",
+        "A negative staleness window should be rejected"
+      );
+      
+      // A one-second window turns the current price stale almost at once
+      await program.methods
+        .setPriceManually(projectId, poolBefore.oraclePriceUsd, null)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      await setStaleness(new anchor.BN(1));
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      
+      await expectError(
+        () => swapTokenForNft(new anchor.BN(10**9)),
+        "StaleOracleFeed",
+        "A price older than the pool's window should be stale"
+      );
+      
+      // Zero falls back to the one-hour default, as for pools that predate the setting
+      await setStaleness(new anchor.BN(0));
+      await swapTokenForNft(new anchor.BN(10**9));
+      
+      await setStaleness(new anchor.BN(3600));
+      
+      console.log("Oracle staleness followed the pool's window");
+    } catch (error) {
+      console.error("Error testing the oracle staleness window:", error);
+      throw error;
+    }
+  });
+
+  it("Rejects Pyth prices with a confidence interval wider than the pool allows", async () => {
+    const priceAccounts = {
+      authority: platformAuthority.publicKey,
+      platformConfig: platformConfigPda,
+      project: projectPda,
+      liquidityPool: liquidityPoolPda,
+      systemProgram: SystemProgram.programId,
+    };
+    const setConfidence = (maxConfidenceBps: number) =>
+      program.methods
+        .setOracleConfidence(projectId, maxConfidenceBps)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const updateFromPyth = () =>
+      program.methods
+        .updatePriceFromPyth(projectId)
+        .accounts({ ...priceAccounts, pythPriceAccount: wideConfidencePythAccount })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(poolBefore.maxConfidenceBps, 0);
+      
+      // Only the feed pinned on the pool is read
+      await pinPythPriceFeed(stalePythAccount);
+      await expectError(
+        () => updateFromPyth(),
+        "PriceFeedMismatch",
+        "A feed other than the pinned one should be rejected"
+      );
+      
+      // The feed's band is 500 bps of its price, wider than a 100 bps bound
+      await pinPythPriceFeed(wideConfidencePythAccount);
+      await setConfidence(100);
+      await expectError(
+        () => updateFromPyth(),
+        "OracleConfidenceTooWide",
+        "A price less certain than the pool's bound should be rejected"
+      );
+      
+      let liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(liquidityPool.oraclePriceUsd.toString(), poolBefore.oraclePriceUsd.toString());
+      
+      // A looser bound takes the price
+      await setConfidence(1000);
+      await updateFromPyth();
+      liquidityPool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      assert.equal(liquidityPool.oraclePriceUsd.toNumber(), 1_000_000);
+      
+      await setConfidence(0);
+      await program.methods
+        .setPriceManually(projectId, poolBefore.oraclePriceUsd, null)
+        .accounts(priceAccounts)
+        .signers([platformAuthority])
+        .rpc();
+      
+      console.log("Pyth price with a wide confidence interval rejected");
+    } catch (error) {
+      console.error("Error testing the Pyth confidence bound:", error);
+      throw error;
+    }
+  });
+
+  it("Pays a collection's USD redemption value at the current price", async () => {
+    const setRedemptionValue = (valueUsd: anchor.BN | null) =>
+      program.methods
+        .setRedemptionValue(valueUsd)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: projectPda,
+          collection: collectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const setPrice = (priceUsd: anchor.BN) =>
+      program.methods
+        .setPriceManually(projectId, priceUsd, null)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: projectPda,
+          liquidityPool: liquidityPoolPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const previousPrice = (await program.account.liquidityPool.fetch(liquidityPoolPda)).oraclePriceUsd;
+      
+      await expectError(
+        () => setRedemptionValue(new anchor.BN(0)),
+        "InvalidTokenAmount",
+        "A zero redemption value should be rejected"
+      );
+      
+      // A $1.00 redemption value is worth half a token at $2.00
+      const held = await swapForHeldNft(new anchor.BN(10**9));
+      await setRedemptionValue(new anchor.BN(1_000_000));
+      const collection = await program.account.collection.fetch(collectionPda);
+      assert.equal(collection.redemptionValueUsd.toNumber(), 1_000_000);
+      
+      await setPrice(new anchor.BN(2_000_000));
+      const userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(held.nftMint, held.userNftAccount);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + 5 * 10**8);
+      
+      await setRedemptionValue(null);
+      await setPrice(previousPrice);
+      
+      console.log("Redemption paid the collection's USD value at the oracle price");
+    } catch (error) {
+      console.error("Error testing the collection redemption value:", error);
+      throw error;
+    }
+  });
+
+  it("Refuses to redeem against another project's collection", async () => {
+    try {
+      const { rivalProjectPda, rivalPoolPda, rivalLpTokenAccount, rivalCollectionPda } = await createRivalProject("rival-value");
+      
+      // The rival's authority prices its own collection far above anything the pool should pay
+      await program.methods
+        .setRedemptionValue(new anchor.BN(1_000_000_000_000))
+        .accounts({
+          authority: platformAuthority.publicKey,
+          project: rivalProjectPda,
+          collection: rivalCollectionPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+      
+      // Passing the rival collection for an NFT of this project's collection is refused
+      const held = await swapForHeldNft(new anchor.BN(10**9));
+      const userBefore = await tokenBalance(userTokenAccount);
+      await expectError(
+        () => redeemWithCollection(held.nftMint, held.userNftAccount, rivalCollectionPda),
+        "CollectionNotFound",
+        "Redeeming with another project's collection should be rejected"
+      );
+      assert.equal(await tokenBalance(userTokenAccount), userBefore);
+      
+      // So is redeeming the NFT with its own collection against another project's pool
+      await expectError(
+        () => program.methods
+          .redeemNftForToken(held.nftMint, false)
+          .accounts({
+            ...redeemNftAccounts(held.nftMint, held.userNftAccount),
+            project: rivalProjectPda,
+            liquidityPool: rivalPoolPda,
+            lpTokenAccount: rivalLpTokenAccount,
+          })
+          .signers([user])
+          .rpc(),
+        "CollectionProjectMismatch",
+        "Redeeming against a pool outside the collection's project should be rejected"
+      );
+      
+      // The NFT still redeems normally from its own project
+      await redeemNftForToken(held.nftMint, held.userNftAccount);
+      
+      console.log("Redemptions are bound to the NFT's collection and its project");
+    } catch (error) {
+      console.error("Error testing cross-project redemption:", error);
+      throw error;
+    }
+  });
+
+  it("Claims an escrow linearly as it vests", async () => {
+    const escrowAmount = 6 * 10**9;
+    const vestingSeconds = 6;
+    
+    try {
+      const { nftMint, nftDataPda } = await swapTokenForNft(new anchor.BN(10 * 10**9));
+      const { tokenEscrowPda, escrowTokenAccountPda } = await createTokenEscrow(
+        nftMint,
+        new anchor.BN(escrowAmount),
+        new anchor.BN(vestingSeconds)
+      );
+      const claim = () =>
+        program.methods
+          .claimVestedEscrow(nftMint)
+          .accounts({
+            user: user.publicKey,
+            platformConfig: platformConfigPda,
+            nftData: nftDataPda,
+            tokenEscrow: tokenEscrowPda,
+            collection: collectionPda,
+            collectionStats: collectionStatsPda(collectionPda),
+            userEscrowCount: userEscrowCountPda(),
+            project: projectPda,
+            userTokenAccount: userTokenAccount,
+            escrowTokenAccount: escrowTokenAccountPda,
+            platformTreasury: platformFeeAccount,
+            projectTreasury: projectTreasury.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user]);
+      const balances = async () =>
+        (await tokenBalance(userTokenAccount))
+        + (await tokenBalance(platformFeeAccount))
+        + (await tokenBalance(projectTreasury.publicKey));
+      
+      // Part way through, a claim pays only the vested share, fees included
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      let before = await balances();
+      await claim().rpc();
+      let tokenEscrow = await program.account.tokenEscrow.fetch(tokenEscrowPda);
+      const claimed = tokenEscrow.claimedAmount.toNumber();
+      assert.isAbove(claimed, 0);
+      assert.isBelow(claimed, escrowAmount);
+      assert.isTrue(tokenEscrow.isActive);
+      assert.equal((await balances()) - before, claimed);
+      
+      // A second claim at the same moment finds nothing newly vested and pays nothing
+      const tx = new Transaction()
+        .add(await claim().instruction())
+        .add(await claim().instruction());
+      await provider.sendAndConfirm(tx, [user]);
+      tokenEscrow = await program.account.tokenEscrow.fetch(tokenEscrowPda);
+      const afterPair = tokenEscrow.claimedAmount.toNumber();
+      assert.isAtLeast(afterPair, claimed);
+      
+      // Once vesting ends the last claim takes the rest and redeems the NFT
+      await new Promise((resolve) => setTimeout(resolve, (vestingSeconds + 1) * 1000));
+      before = await balances();
+      await claim().rpc();
+      tokenEscrow = await program.account.tokenEscrow.fetch(tokenEscrowPda);
+      assert.equal(tokenEscrow.claimedAmount.toNumber(), escrowAmount);
+      assert.isFalse(tokenEscrow.isActive);
+      assert.equal((await balances()) - before, escrowAmount - afterPair);
+      
+      const nftData = await program.account.nftData.fetch(nftDataPda);
+      assert.equal(nftData.owner.toString(), projectPda.toString());
+      
+      await expectError(
+        () => claim().rpc(),
+        "EscrowNotActive",
+        "A fully claimed escrow should have nothing left to claim"
+      );
+      
+      console.log(`Escrow claimed in vested parts: ${claimed}, ${afterPair - claimed}, ${escrowAmount - afterPair}`);
+    } catch (error) {
+      console.error("Error testing vested escrow claims:", error);
+      throw error;
+    }
+  });
+
+  it("Refuses a platform fee and royalty that together exceed the trade", async () => {
+    const updatePlatformFee = (bps: number) =>
+      program.methods
+        .updatePlatformFee(bps)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    const setProjectRoyalty = (bps: number) =>
+      program.methods
+        .setProjectRoyalty(projectId, bps)
+        .accounts({
+          authority: platformAuthority.publicKey,
+          platformConfig: platformConfigPda,
+          project: projectPda,
+        })
+        .signers([platformAuthority])
+        .rpc();
+    
+    try {
+      const previousFee = (await program.account.platformConfig.fetch(platformConfigPda)).platformFeeBasisPoints;
+      const royaltyBps = (await program.account.project.fetch(projectPda)).royaltyBasisPoints;
+      
+      // A 99% platform fee on top of the 2% royalty leaves less than nothing for the project
+      await updatePlatformFee(9900);
+      await expectError(
+        () => swapTokenForNft(new anchor.BN(10**9)),
+        "FeeCalculationError",
+        "A swap whose fees exceed the trade should be refused"
+      );
+      
+      // Setting the royalty under such a fee is refused up front
+      await expectError(
+        () => setProjectRoyalty(royaltyBps),
+        "InvalidRoyaltyFee",
+        "A royalty pushing fees past 100% should be rejected"
+      );
+      
+      await updatePlatformFee(previousFee);
+      await swapTokenForNft(new anchor.BN(10**9));
+      
+      console.log("Fees over 100% were refused instead of underflowing");
+    } catch (error) {
+      console.error("Error testing the fee total bound:", error);
+      throw error;
+    }
+  });
+
+  it("Splits escrow redemption fees between the platform and project", async () => {
+    try {
+      const escrowAmount = 10**9;
+      const { nftMint } = await swapTokenForNft(new anchor.BN(10**9));
+      await createTokenEscrow(nftMint, new anchor.BN(escrowAmount));
+      
+      const platformFeeBps = (await program.account.platformConfig.fetch(platformConfigPda)).platformFeeBasisPoints;
+      const royaltyBps = (await program.account.project.fetch(projectPda)).royaltyBasisPoints;
+      const userBefore = await tokenBalance(userTokenAccount);
+      const platformBefore = await tokenBalance(platformFeeAccount);
+      const projectBefore = await tokenBalance(projectTreasury.publicKey);
+      
+      await redeemEscrowToken(nftMint);
+      
+      // The platform takes its fee, the project the royalty rate, and the owner the rest
+      const platformFee = Math.floor(escrowAmount * platformFeeBps / 10000);
+      const projectFee = Math.floor(escrowAmount * royaltyBps / 10000);
+      assert.equal(await tokenBalance(platformFeeAccount), platformBefore + platformFee);
+      assert.equal(await tokenBalance(projectTreasury.publicKey), projectBefore + projectFee);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + escrowAmount - platformFee - projectFee);
+      
+      console.log(`Escrow redemption paid ${platformFee} to the platform and ${projectFee} to the project`);
+    } catch (error) {
+      console.error("Error testing escrow redemption fees:", error);
+      throw error;
+    }
+  });
+
+  it("Lets the project authority withdraw free pool liquidity", async () => {
+    try {
+      const authorityTokenAccount = (await getOrCreateAssociatedTokenAccount(
+        provider.connection, platformAuthority, tokenMint, platformAuthority.publicKey
+      )).address;
+      const withdraw = (amount: anchor.BN, authority: Keypair = platformAuthority, destination = authorityTokenAccount) =>
+        program.methods
+          .withdrawLiquidity(projectId, amount)
+          .accounts({
+            authority: authority.publicKey,
+            project: projectPda,
+            liquidityPool: liquidityPoolPda,
+            lpTokenAccount: lpTokenAccountPda,
+            authorityTokenAccount: destination,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([authority])
+          .rpc();
+      
+      await expectError(
+        () => withdraw(new anchor.BN(10**9), user, userTokenAccount),
+        "Unauthorized",
+        "Only the project authority should withdraw liquidity"
+      );
+      
+      // Pending fees, reservations and owed claims are not the authority's to take
+      const pool = await program.account.liquidityPool.fetch(liquidityPoolPda);
+      const lpBalance = await tokenBalance(lpTokenAccountPda);
+      const withdrawable = lpBalance
+        - pool.pendingPlatformFees.toNumber()
+        - pool.pendingProjectFees.toNumber()
+        - pool.reservedLiquidity.toNumber()
+        - pool.pendingClaims.toNumber();
+      await expectError(
+        () => withdraw(new anchor.BN(withdrawable + 1)),
+        "InsufficientLiquidity",
+        "Withdrawing past the free balance should be rejected"
+      );
+      
+      const authorityBefore = await tokenBalance(authorityTokenAccount);
+      await withdraw(new anchor.BN(10**9));
+      assert.equal(await tokenBalance(authorityTokenAccount), authorityBefore + 10**9);
+      assert.equal(await tokenBalance(lpTokenAccountPda), lpBalance - 10**9);
+      
+      // Put the liquidity back for the tests that follow
+      await transfer(provider.connection, platformAuthority, authorityTokenAccount, lpTokenAccountPda, platformAuthority, 10**9);
+      
+      console.log("Project authority withdrew free pool liquidity");
+    } catch (error) {
+      console.error("Error testing liquidity withdrawal:", error);
+      throw error;
+    }
+  });
+
+  it("Emits events for redemptions and fusions", async () => {
+    try {
+      // A pool redemption reports what it paid, and nothing owed when the pool covers it
+      const held = await swapForHeldNft(new anchor.BN(10**9));
+      const { events } = await program.methods
+        .redeemNftForToken(held.nftMint, false)
+        .accounts(redeemNftAccounts(held.nftMint, held.userNftAccount))
+        .signers([user])
+        .simulate();
+      const redeemed = events.find((event) => event.name === "NftRedeemed").data;
+      assert.isTrue(redeemed.user.equals(user.publicKey));
+      assert.isTrue(redeemed.nftMint.equals(held.nftMint));
+      assert.isTrue(redeemed.collection.equals(collectionPda));
+      assert.equal(redeemed.pendingAmount.toNumber(), 0);
+      assert.isFalse(redeemed.fromEscrow);
+      
+      const userBefore = await tokenBalance(userTokenAccount);
+      await redeemNftForToken(held.nftMint, held.userNftAccount);
+      assert.equal(await tokenBalance(userTokenAccount), userBefore + redeemed.tokenAmount.toNumber());
+      
+      // A fusion reports its parents and the child it minted
+      const parents = [];
+      for (let i = 0; i < 2; i++) {
+        const { nftDataPda, userNftAccount } = await swapForHeldNft(new anchor.BN(10**9));
+        parents.push({ nftDataPda, userNftAccount });
+      }
+      const fusedEvents = [];
+      const listener = program.addEventListener("NftFused", (event) => fusedEvents.push(event));
+      const fused = await fuseNfts(parents);
+      
+      // Give the websocket subscription time to deliver the logs
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+      await program.removeEventListener(listener);
+      assert.equal(fusedEvents.length, 1);
+      const child = await program.account.nftData.fetch(fused.childNftDataPda);
+      assert.isTrue(fusedEvents[0].owner.equals(user.publicKey));
+      assert.isTrue(fusedEvents[0].collection.equals(collectionPda));
+      assert.equal(fusedEvents[0].childMints.length, 1);
+      assert.isTrue(fusedEvents[0].childMints[0].equals(fused.childMint));
+      assert.equal(fusedEvents[0].parentNfts.length, 2);
+      assert.equal(fusedEvents[0].fusionLevel, child.fusionLevel);
+      
+      console.log("Redemptions and fusions emitted their events");
+    } catch (error) {
+      console.error("Error testing redemption and fusion events:", error);
+      throw error;
+    }
+  });
 });